@@ -0,0 +1,263 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use nalgebra_glm::Vec3;
+use spree::{
+    bounds::{
+        bounds_distance_squared, bounds_in_frustum, bounds_intersect, frustum_from_matrix,
+        ray_bounds_intersection, BoundingBox,
+    },
+    bvh::{
+        bvh_insert, bvh_nearest, bvh_query_bounds, bvh_query_frustum, bvh_query_sphere,
+        bvh_ray_cast_nearest, bvh_update, Bvh,
+    },
+    world::{EntityId, Ray},
+};
+
+const SIZES: [usize; 4] = [100, 1_000, 10_000, 250_000];
+
+/// Unit boxes scattered through a cube that grows with the count, the same on every run
+fn scene(count: usize) -> Vec<(EntityId, BoundingBox)> {
+    let mut seed = 0x2545_f491_u32;
+    let mut random = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        seed as f32 / u32::MAX as f32
+    };
+    let extent = (count as f32).cbrt() * 4.0;
+    (0..count)
+        .map(|index| {
+            let center =
+                Vec3::new(random(), random(), random()) * extent * 2.0 - Vec3::repeat(extent);
+            let entity = EntityId {
+                id: index as u32,
+                generation: 0,
+            };
+            let bounds = BoundingBox {
+                min: center - Vec3::repeat(0.5),
+                max: center + Vec3::repeat(0.5),
+            };
+            (entity, bounds)
+        })
+        .collect()
+}
+
+fn build(scene: &[(EntityId, BoundingBox)]) -> Bvh {
+    let mut bvh = Bvh::default();
+    for (entity, bounds) in scene.iter() {
+        bvh_insert(&mut bvh, *entity, *bounds);
+    }
+    bvh
+}
+
+fn moved(scene: &[(EntityId, BoundingBox)], offset: f32) -> Vec<(EntityId, BoundingBox)> {
+    scene
+        .iter()
+        .map(|(entity, bounds)| {
+            let offset = Vec3::new(offset, 0.0, 0.0);
+            let bounds = BoundingBox {
+                min: bounds.min + offset,
+                max: bounds.max + offset,
+            };
+            (*entity, bounds)
+        })
+        .collect()
+}
+
+fn insert(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("bvh_insert");
+    for size in SIZES {
+        let scene = scene(size);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(size),
+            &scene,
+            |bencher, scene| bencher.iter(|| build(black_box(scene))),
+        );
+    }
+    group.finish();
+}
+
+/// Moves within the margin only update leaves, larger moves reinsert them
+fn update(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("bvh_update");
+    for size in SIZES {
+        let scene = scene(size);
+        for (label, offset) in [("within_margin", 0.05), ("reinsert", 2.0)] {
+            let target = moved(&scene, offset);
+            group.bench_with_input(BenchmarkId::new(label, size), &target, |bencher, target| {
+                bencher.iter_batched(
+                    || build(&scene),
+                    |mut bvh| {
+                        for (entity, bounds) in target.iter() {
+                            bvh_update(&mut bvh, *entity, *bounds);
+                        }
+                        bvh
+                    },
+                    criterion::BatchSize::LargeInput,
+                )
+            });
+        }
+    }
+    group.finish();
+}
+
+fn ray_cast(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("ray_cast_nearest");
+    let ray = Ray {
+        origin: Vec3::new(-1000.0, 0.3, 0.2),
+        direction: Vec3::x(),
+    };
+    for size in SIZES {
+        let scene = scene(size);
+        let bvh = build(&scene);
+        group.bench_with_input(BenchmarkId::new("bvh", size), &bvh, |bencher, bvh| {
+            bencher.iter(|| bvh_ray_cast_nearest(bvh, black_box(&ray), f32::MAX))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("linear", size),
+            &scene,
+            |bencher, scene| {
+                bencher.iter(|| {
+                    scene
+                        .iter()
+                        .filter_map(|(entity, bounds)| {
+                            Some((*entity, ray_bounds_intersection(black_box(&ray), bounds)?))
+                        })
+                        .min_by(|a, b| a.1.total_cmp(&b.1))
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn frustum_query(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("frustum_query");
+    let projection = nalgebra_glm::perspective_zo(16.0 / 9.0, 60_f32.to_radians(), 0.1, 100.0);
+    let view = nalgebra_glm::look_at(&Vec3::zeros(), &Vec3::new(1.0, 0.2, 0.5), &Vec3::y());
+    let frustum = frustum_from_matrix(&(projection * view));
+    for size in SIZES {
+        let scene = scene(size);
+        let bvh = build(&scene);
+        group.bench_with_input(BenchmarkId::new("bvh", size), &bvh, |bencher, bvh| {
+            bencher.iter(|| bvh_query_frustum(bvh, black_box(&frustum)))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("linear", size),
+            &scene,
+            |bencher, scene| {
+                bencher.iter(|| {
+                    scene
+                        .iter()
+                        .filter(|(_, bounds)| bounds_in_frustum(black_box(&frustum), bounds))
+                        .map(|(entity, _)| *entity)
+                        .collect::<Vec<_>>()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn bounds_query(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("bounds_query");
+    let region = BoundingBox {
+        min: Vec3::new(-5.0, -5.0, -5.0),
+        max: Vec3::new(5.0, 5.0, 5.0),
+    };
+    for size in SIZES {
+        let scene = scene(size);
+        let bvh = build(&scene);
+        group.bench_with_input(BenchmarkId::new("bvh", size), &bvh, |bencher, bvh| {
+            bencher.iter(|| bvh_query_bounds(bvh, black_box(&region)))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("linear", size),
+            &scene,
+            |bencher, scene| {
+                bencher.iter(|| {
+                    scene
+                        .iter()
+                        .filter(|(_, bounds)| bounds_intersect(bounds, black_box(&region)))
+                        .map(|(entity, _)| *entity)
+                        .collect::<Vec<_>>()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn sphere_query(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("sphere_query");
+    let center = Vec3::new(1.0, 2.0, 3.0);
+    let radius_squared = 8.0 * 8.0;
+    for size in SIZES {
+        let scene = scene(size);
+        let bvh = build(&scene);
+        group.bench_with_input(BenchmarkId::new("bvh", size), &bvh, |bencher, bvh| {
+            bencher.iter(|| bvh_query_sphere(bvh, black_box(&center), 8.0))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("linear", size),
+            &scene,
+            |bencher, scene| {
+                bencher.iter(|| {
+                    scene
+                        .iter()
+                        .filter(|(_, bounds)| {
+                            bounds_distance_squared(bounds, black_box(&center)) <= radius_squared
+                        })
+                        .map(|(entity, _)| *entity)
+                        .collect::<Vec<_>>()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+/// The eight nearest entities, the linear version partially sorting every distance
+fn nearest(criterion: &mut Criterion) {
+    const COUNT: usize = 8;
+    let mut group = criterion.benchmark_group("nearest");
+    let point = Vec3::new(1.0, 2.0, 3.0);
+    for size in SIZES {
+        let scene = scene(size);
+        let bvh = build(&scene);
+        group.bench_with_input(BenchmarkId::new("bvh", size), &bvh, |bencher, bvh| {
+            bencher.iter(|| bvh_nearest(bvh, black_box(&point), COUNT))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("linear", size),
+            &scene,
+            |bencher, scene| {
+                bencher.iter(|| {
+                    let mut distances = scene
+                        .iter()
+                        .map(|(entity, bounds)| {
+                            (*entity, bounds_distance_squared(bounds, black_box(&point)))
+                        })
+                        .collect::<Vec<_>>();
+                    let count = COUNT.min(distances.len());
+                    distances.select_nth_unstable_by(count - 1, |a, b| a.1.total_cmp(&b.1));
+                    distances.truncate(count);
+                    distances.sort_by(|a, b| a.1.total_cmp(&b.1));
+                    distances
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    insert,
+    update,
+    ray_cast,
+    frustum_query,
+    bounds_query,
+    sphere_query,
+    nearest
+);
+criterion_main!(benches);
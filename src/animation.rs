@@ -0,0 +1,742 @@
+use crate::{
+    asset::{get_asset, Assets, Handle},
+    world::{
+        get_component, get_component_mut, query_entities, Camera, Color, EntityId, LocalTransform,
+        Name, Parent, Projection, World, ANIMATION_PLAYER, CAMERA, COLOR, LOCAL_TRANSFORM, NAME,
+        PARENT,
+    },
+};
+use nalgebra_glm::{Quat, Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A property animations can drive. Values are packed into a `Vec4`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AnimatedProperty {
+    /// `LocalTransform` translation, in xyz
+    Translation,
+    /// `LocalTransform` rotation, as a quaternion in xyzw
+    Rotation,
+    /// `LocalTransform` scale, in xyz
+    Scale,
+    /// The `Color` component, in rgba
+    Color,
+    /// Vertical field of view of a perspective `Camera` in radians, in x
+    FieldOfView,
+}
+
+pub fn read_property(world: &World, entity: EntityId, property: AnimatedProperty) -> Option<Vec4> {
+    match property {
+        AnimatedProperty::Translation => {
+            get_component::<LocalTransform>(world, entity, LOCAL_TRANSFORM)
+                .map(|transform| transform.translation.push(0.0))
+        }
+        AnimatedProperty::Rotation => {
+            get_component::<LocalTransform>(world, entity, LOCAL_TRANSFORM)
+                .map(|transform| transform.rotation.coords)
+        }
+        AnimatedProperty::Scale => get_component::<LocalTransform>(world, entity, LOCAL_TRANSFORM)
+            .map(|transform| transform.scale.push(0.0)),
+        AnimatedProperty::Color => {
+            get_component::<Color>(world, entity, COLOR).map(|Color(color)| *color)
+        }
+        AnimatedProperty::FieldOfView => {
+            get_component::<Camera>(world, entity, CAMERA).and_then(|camera| {
+                match &camera.projection {
+                    Projection::Perspective(perspective) => {
+                        Some(Vec4::new(perspective.y_fov_rad, 0.0, 0.0, 0.0))
+                    }
+                    Projection::Orthographic(_) => None,
+                }
+            })
+        }
+    }
+}
+
+/// Writes the property if the entity has the component holding it
+pub fn write_property(
+    world: &mut World,
+    entity: EntityId,
+    property: AnimatedProperty,
+    value: Vec4,
+) {
+    match property {
+        AnimatedProperty::Translation => {
+            if let Some(transform) =
+                get_component_mut::<LocalTransform>(world, entity, LOCAL_TRANSFORM)
+            {
+                transform.translation = value.xyz();
+            }
+        }
+        AnimatedProperty::Rotation => {
+            if let Some(transform) =
+                get_component_mut::<LocalTransform>(world, entity, LOCAL_TRANSFORM)
+            {
+                transform.rotation = Quat::from(value).normalize();
+            }
+        }
+        AnimatedProperty::Scale => {
+            if let Some(transform) =
+                get_component_mut::<LocalTransform>(world, entity, LOCAL_TRANSFORM)
+            {
+                transform.scale = value.xyz();
+            }
+        }
+        AnimatedProperty::Color => {
+            if let Some(Color(color)) = get_component_mut::<Color>(world, entity, COLOR) {
+                *color = value;
+            }
+        }
+        AnimatedProperty::FieldOfView => {
+            if let Some(Camera {
+                projection: Projection::Perspective(perspective),
+                ..
+            }) = get_component_mut::<Camera>(world, entity, CAMERA)
+            {
+                perspective.y_fov_rad = value.x;
+            }
+        }
+    }
+}
+
+/// Interpolates between two property values, along the shortest arc for rotations
+pub fn mix_property(property: AnimatedProperty, from: &Vec4, to: &Vec4, factor: f32) -> Vec4 {
+    match property {
+        AnimatedProperty::Rotation => slerp(from, to, factor),
+        _ => from.lerp(to, factor),
+    }
+}
+
+fn slerp(from: &Vec4, to: &Vec4, factor: f32) -> Vec4 {
+    let mut to = *to;
+    let mut cosine = from.dot(&to);
+    if cosine < 0.0 {
+        to = -to;
+        cosine = -cosine;
+    }
+    // Nearly parallel rotations divide by a vanishing sine, so lerp instead
+    if cosine > 0.9995 {
+        return from.lerp(&to, factor).normalize();
+    }
+    let angle = cosine.acos();
+    (from * ((1.0 - factor) * angle).sin() + to * (factor * angle).sin()) / angle.sin()
+}
+
+/// How values between keyframes are computed, as defined by glTF
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Interpolation {
+    /// Holds each keyframe's value until the next
+    Step,
+    /// Linear, or spherical linear for rotations
+    #[default]
+    Linear,
+    /// Hermite spline through the keyframes using their tangents
+    CubicSpline,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnimationTrack {
+    /// Name of the descendant entity to animate, or `None` for the player's own entity
+    pub target: Option<String>,
+    pub property: AnimatedProperty,
+    pub interpolation: Interpolation,
+    /// Keyframe times in seconds, ascending
+    pub times: Vec<f32>,
+    /// One value per keyframe, or for cubic splines an in-tangent,
+    /// value and out-tangent per keyframe
+    pub values: Vec<Vec4>,
+}
+
+/// A named marker that `AnimationPlayer` reports when playback passes its time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnimationEvent {
+    pub time: f32,
+    pub name: String,
+}
+
+/// Animation clips are RON assets
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnimationClip {
+    pub tracks: Vec<AnimationTrack>,
+    pub events: Vec<AnimationEvent>,
+}
+
+impl crate::asset::LoadAsset for AnimationClip {
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        ron::de::from_bytes(bytes)
+            .map_err(|error| format!("Failed to parse animation clip: {error}"))
+    }
+}
+
+/// The time of the last keyframe or event
+pub fn clip_duration(clip: &AnimationClip) -> f32 {
+    clip.tracks
+        .iter()
+        .filter_map(|track| track.times.last())
+        .chain(clip.events.iter().map(|event| &event.time))
+        .fold(0.0, |duration, time| duration.max(*time))
+}
+
+/// The track's value at a time. Times outside the keyframes hold the nearest one.
+pub fn sample_track(track: &AnimationTrack, time: f32) -> Option<Vec4> {
+    let times = &track.times;
+    let cubic = track.interpolation == Interpolation::CubicSpline;
+    let stride = if cubic { 3 } else { 1 };
+    if times.is_empty() || track.values.len() < times.len() * stride {
+        return None;
+    }
+    let value = |key: usize| track.values[key * stride + cubic as usize];
+    if time <= times[0] {
+        return Some(value(0));
+    }
+    if time >= times[times.len() - 1] {
+        return Some(value(times.len() - 1));
+    }
+
+    let next = times.partition_point(|key_time| *key_time <= time);
+    let previous = next - 1;
+    let span = times[next] - times[previous];
+    let factor = if span > 0.0 {
+        (time - times[previous]) / span
+    } else {
+        0.0
+    };
+    Some(match track.interpolation {
+        Interpolation::Step => value(previous),
+        Interpolation::Linear => {
+            mix_property(track.property, &value(previous), &value(next), factor)
+        }
+        Interpolation::CubicSpline => {
+            let (t, t2, t3) = (factor, factor * factor, factor * factor * factor);
+            let out_tangent = track.values[previous * 3 + 2];
+            let in_tangent = track.values[next * 3];
+            let result = value(previous) * (2.0 * t3 - 3.0 * t2 + 1.0)
+                + out_tangent * span * (t3 - 2.0 * t2 + t)
+                + value(next) * (-2.0 * t3 + 3.0 * t2)
+                + in_tangent * span * (t3 - t2);
+            if track.property == AnimatedProperty::Rotation {
+                result.normalize()
+            } else {
+                result
+            }
+        }
+    })
+}
+
+/// Plays animation clips on its entity and named descendants
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnimationPlayer {
+    pub clip: Option<Handle<AnimationClip>>,
+    /// Playback position in seconds
+    pub time: f32,
+    /// Playback rate, negative to play backwards
+    pub speed: f32,
+    pub looping: bool,
+    pub paused: bool,
+    /// Names of the events passed during the last update, in order
+    pub events: Vec<String>,
+    fade: Option<Crossfade>,
+}
+
+impl Default for AnimationPlayer {
+    fn default() -> Self {
+        Self {
+            clip: None,
+            time: 0.0,
+            speed: 1.0,
+            looping: true,
+            paused: false,
+            events: Vec::new(),
+            fade: None,
+        }
+    }
+}
+
+/// The clip being faded out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Crossfade {
+    clip: Handle<AnimationClip>,
+    time: f32,
+    duration: f32,
+    elapsed: f32,
+}
+
+/// Switches to a clip immediately, from its start
+pub fn play_animation(player: &mut AnimationPlayer, clip: Handle<AnimationClip>) {
+    player.clip = Some(clip);
+    player.time = 0.0;
+    player.fade = None;
+}
+
+/// Starts a clip and blends to it from the current one over `duration` seconds
+pub fn crossfade_animation(
+    player: &mut AnimationPlayer,
+    clip: Handle<AnimationClip>,
+    duration: f32,
+) {
+    player.fade = match player.clip.take() {
+        Some(previous) if duration > 0.0 => Some(Crossfade {
+            clip: previous,
+            time: player.time,
+            duration,
+            elapsed: 0.0,
+        }),
+        _ => None,
+    };
+    player.clip = Some(clip);
+    player.time = 0.0;
+}
+
+/// Advances every `AnimationPlayer` by the frame's delta time and applies its clips
+pub fn update_animation_system(world: &mut World) {
+    let delta_time = world.resources.delta_time;
+    let players = query_entities(world, ANIMATION_PLAYER);
+    if players.is_empty() {
+        return;
+    }
+    let children = children_by_parent(world);
+    for entity in players {
+        let mut player = get_component::<AnimationPlayer>(world, entity, ANIMATION_PLAYER)
+            .cloned()
+            .expect("Queried entity has no animation player!");
+        let clips = &world.resources.assets.animation_clips;
+        advance_animation(&mut player, clips, delta_time);
+
+        let targets = named_descendants(world, entity, &children);
+        let mut samples = Vec::new();
+        if let Some(fade) = &player.fade {
+            sample_clip(clips, &fade.clip, fade.time, 1.0, &mut samples);
+        }
+        if let Some(clip) = &player.clip {
+            let weight = player
+                .fade
+                .as_ref()
+                .map_or(1.0, |fade| (fade.elapsed / fade.duration).clamp(0.0, 1.0));
+            sample_clip(clips, clip, player.time, weight, &mut samples);
+        }
+        for (target, property, value, weight) in samples {
+            let target = match target {
+                Some(name) => match targets.get(&name) {
+                    Some(target) => *target,
+                    None => continue,
+                },
+                None => entity,
+            };
+            let value = if weight < 1.0 {
+                match read_property(world, target, property) {
+                    Some(current) => mix_property(property, &current, &value, weight),
+                    None => continue,
+                }
+            } else {
+                value
+            };
+            write_property(world, target, property, value);
+        }
+
+        if let Some(component) =
+            get_component_mut::<AnimationPlayer>(world, entity, ANIMATION_PLAYER)
+        {
+            *component = player;
+        }
+    }
+}
+
+/// Moves the playhead, collecting the events passed and finishing crossfades
+fn advance_animation(player: &mut AnimationPlayer, clips: &Assets<AnimationClip>, delta_time: f32) {
+    player.events.clear();
+    if player.paused {
+        return;
+    }
+    let step = delta_time * player.speed;
+    if let Some(fade) = player.fade.as_mut() {
+        fade.elapsed += delta_time;
+        if let Some(clip) = get_asset(clips, &fade.clip) {
+            fade.time = advance_time(fade.time, step, clip_duration(clip), player.looping);
+        }
+        if fade.elapsed >= fade.duration {
+            player.fade = None;
+        }
+    }
+    let Some(clip) = player.clip.as_ref().and_then(|clip| get_asset(clips, clip)) else {
+        return;
+    };
+    let duration = clip_duration(clip);
+    let start = player.time;
+    let end = if player.looping {
+        start + step
+    } else {
+        (start + step).clamp(0.0, duration)
+    };
+    player.events = passed_events(clip, start, end, duration, player.looping);
+    player.time = advance_time(start, step, duration, player.looping);
+}
+
+fn advance_time(time: f32, step: f32, duration: f32, looping: bool) -> f32 {
+    if duration <= 0.0 {
+        0.0
+    } else if looping {
+        (time + step).rem_euclid(duration)
+    } else {
+        (time + step).clamp(0.0, duration)
+    }
+}
+
+/// Events the playhead swept past, in playback order. The range includes where
+/// the playhead started and excludes where it ended, so each pass fires an event
+/// once. Looping ranges may extend past the clip's ends.
+fn passed_events(
+    clip: &AnimationClip,
+    start: f32,
+    end: f32,
+    duration: f32,
+    looping: bool,
+) -> Vec<String> {
+    let (low, high) = if end >= start {
+        (start, end)
+    } else {
+        (end, start)
+    };
+    let mut passed = Vec::new();
+    for event in &clip.events {
+        // Laps around the candidates, checked exactly below
+        let (first, last) = if looping && duration > 0.0 {
+            (
+                ((low - event.time) / duration).floor() as i32,
+                ((high - event.time) / duration).ceil() as i32,
+            )
+        } else {
+            (0, 0)
+        };
+        for lap in first..=last {
+            let time = event.time + lap as f32 * duration;
+            let reached = if end >= start {
+                low <= time && time < high
+            } else {
+                low < time && time <= high
+            };
+            // A clip that stops at either end fires the events there too
+            let stopped_on =
+                !looping && start != end && time == end && (end == 0.0 || end == duration);
+            if reached || stopped_on {
+                passed.push((time, event.name.clone()));
+            }
+        }
+    }
+    passed.sort_by(|a, b| a.0.total_cmp(&b.0));
+    if end < start {
+        passed.reverse();
+    }
+    passed.into_iter().map(|(_, name)| name).collect()
+}
+
+type Sample = (Option<String>, AnimatedProperty, Vec4, f32);
+
+fn sample_clip(
+    clips: &Assets<AnimationClip>,
+    clip: &Handle<AnimationClip>,
+    time: f32,
+    weight: f32,
+    samples: &mut Vec<Sample>,
+) {
+    let Some(clip) = get_asset(clips, clip) else {
+        return;
+    };
+    for track in &clip.tracks {
+        if let Some(value) = sample_track(track, time) {
+            samples.push((track.target.clone(), track.property, value, weight));
+        }
+    }
+}
+
+fn children_by_parent(world: &World) -> HashMap<EntityId, Vec<EntityId>> {
+    let mut children = HashMap::<EntityId, Vec<EntityId>>::new();
+    for entity in query_entities(world, PARENT) {
+        if let Some(Parent(parent)) = get_component::<Parent>(world, entity, PARENT) {
+            children.entry(*parent).or_default().push(entity);
+        }
+    }
+    children
+}
+
+/// Descendants by name, the nearest winning when names repeat
+fn named_descendants(
+    world: &World,
+    root: EntityId,
+    children: &HashMap<EntityId, Vec<EntityId>>,
+) -> HashMap<String, EntityId> {
+    let mut named = HashMap::new();
+    let mut queue = std::collections::VecDeque::from([root]);
+    let mut visited = std::collections::HashSet::from([root]);
+    while let Some(entity) = queue.pop_front() {
+        for child in children.get(&entity).into_iter().flatten() {
+            if !visited.insert(*child) {
+                continue;
+            }
+            if let Some(Name(name)) = get_component::<Name>(world, *child, NAME) {
+                named.entry(name.clone()).or_insert(*child);
+            }
+            queue.push_back(*child);
+        }
+    }
+    named
+}
+
+/// A linearly interpolated track, a convenience for clips built in code
+pub fn linear_track(
+    target: Option<String>,
+    property: AnimatedProperty,
+    times: Vec<f32>,
+    values: Vec<Vec4>,
+) -> AnimationTrack {
+    AnimationTrack {
+        target,
+        property,
+        interpolation: Interpolation::Linear,
+        times,
+        values,
+    }
+}
+
+/// Packs a translation or scale for a track
+pub fn vec3_value(value: &Vec3) -> Vec4 {
+    value.push(0.0)
+}
+
+/// Packs a rotation for a track
+pub fn quat_value(value: &Quat) -> Vec4 {
+    value.coords
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        asset::add_asset,
+        world::{spawn_entities, World},
+    };
+
+    fn scalar(value: f32) -> Vec4 {
+        Vec4::new(value, 0.0, 0.0, 0.0)
+    }
+
+    fn track(interpolation: Interpolation, times: Vec<f32>, values: Vec<Vec4>) -> AnimationTrack {
+        AnimationTrack {
+            target: None,
+            property: AnimatedProperty::Translation,
+            interpolation,
+            times,
+            values,
+        }
+    }
+
+    fn sample(track: &AnimationTrack, time: f32) -> f32 {
+        sample_track(track, time)
+            .expect("The track has keyframes!")
+            .x
+    }
+
+    fn translation_clip(from: f32, to: f32, events: &[(f32, &str)]) -> AnimationClip {
+        AnimationClip {
+            tracks: vec![linear_track(
+                None,
+                AnimatedProperty::Translation,
+                vec![0.0, 1.0],
+                vec![scalar(from), scalar(to)],
+            )],
+            events: events
+                .iter()
+                .map(|(time, name)| AnimationEvent {
+                    time: *time,
+                    name: name.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    fn player_world(clip: AnimationClip) -> (World, EntityId, Handle<AnimationClip>) {
+        let mut world = World::default();
+        let clip = add_asset(&mut world.resources.assets.animation_clips, clip);
+        let entity = spawn_entities(&mut world, ANIMATION_PLAYER | LOCAL_TRANSFORM, 1)[0];
+        play_animation(player_mut(&mut world, entity), clip.clone());
+        (world, entity, clip)
+    }
+
+    fn player_mut(world: &mut World, entity: EntityId) -> &mut AnimationPlayer {
+        get_component_mut::<AnimationPlayer>(world, entity, ANIMATION_PLAYER)
+            .expect("No animation player!")
+    }
+
+    fn update(world: &mut World, delta_time: f32) {
+        world.resources.delta_time = delta_time;
+        update_animation_system(world);
+    }
+
+    fn translation_x(world: &World, entity: EntityId) -> f32 {
+        get_component::<LocalTransform>(world, entity, LOCAL_TRANSFORM)
+            .expect("No local transform!")
+            .translation
+            .x
+    }
+
+    fn events(world: &World, entity: EntityId) -> Vec<String> {
+        get_component::<AnimationPlayer>(world, entity, ANIMATION_PLAYER)
+            .expect("No animation player!")
+            .events
+            .clone()
+    }
+
+    #[test]
+    fn linear_and_step_tracks_interpolate_between_keyframes() {
+        let times = vec![0.0, 1.0, 3.0];
+        let values = vec![scalar(0.0), scalar(10.0), scalar(-10.0)];
+        let linear = track(Interpolation::Linear, times.clone(), values.clone());
+        assert_eq!(sample(&linear, 0.5), 5.0);
+        assert_eq!(sample(&linear, 2.0), 0.0);
+        let step = track(Interpolation::Step, times, values);
+        assert_eq!(sample(&step, 0.99), 0.0);
+        assert_eq!(sample(&step, 1.0), 10.0);
+        assert_eq!(sample(&step, 2.5), 10.0);
+
+        // Outside the keyframes the nearest one holds
+        assert_eq!(sample(&linear, -1.0), 0.0);
+        assert_eq!(sample(&linear, 5.0), -10.0);
+    }
+
+    #[test]
+    fn cubic_splines_pass_through_keyframes_with_their_tangents() {
+        // In-tangent, value and out-tangent per keyframe
+        let spline = track(
+            Interpolation::CubicSpline,
+            vec![0.0, 2.0],
+            vec![
+                scalar(0.0),
+                scalar(0.0),
+                scalar(1.0),
+                scalar(1.0),
+                scalar(2.0),
+                scalar(0.0),
+            ],
+        );
+        assert_eq!(sample(&spline, 0.0), 0.0);
+        assert_eq!(sample(&spline, 2.0), 2.0);
+        // A slope of one at both ends between 0 and 2 over 2 seconds is a straight line
+        assert!((sample(&spline, 0.5) - 0.5).abs() < 1e-5);
+        assert!((sample(&spline, 1.5) - 1.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn malformed_tracks_have_no_value() {
+        assert!(sample_track(&track(Interpolation::Linear, vec![], vec![]), 0.0).is_none());
+        let short = track(Interpolation::Linear, vec![0.0, 1.0], vec![scalar(1.0)]);
+        assert!(sample_track(&short, 0.5).is_none());
+        let short_spline = track(
+            Interpolation::CubicSpline,
+            vec![0.0],
+            vec![scalar(0.0), scalar(1.0)],
+        );
+        assert!(sample_track(&short_spline, 0.0).is_none());
+    }
+
+    #[test]
+    fn rotations_blend_along_the_shortest_arc() {
+        let from = quat_value(&Quat::identity());
+        let half_turn = nalgebra_glm::quat_angle_axis(std::f32::consts::PI * 0.9, &Vec3::y());
+        // The same rotation with the opposite sign must not spin the long way around
+        let to = -quat_value(&half_turn);
+        let halfway = Quat::from(mix_property(AnimatedProperty::Rotation, &from, &to, 0.5));
+        let expected = nalgebra_glm::quat_angle_axis(std::f32::consts::PI * 0.45, &Vec3::y());
+        assert!(nalgebra_glm::quat_dot(&halfway, &expected).abs() > 0.9999);
+        assert!((halfway.norm() - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn duration_covers_keyframes_and_events() {
+        let mut clip = translation_clip(0.0, 1.0, &[(1.5, "late")]);
+        assert_eq!(clip_duration(&clip), 1.5);
+        clip.events.clear();
+        assert_eq!(clip_duration(&clip), 1.0);
+        assert_eq!(clip_duration(&AnimationClip::default()), 0.0);
+    }
+
+    #[test]
+    fn players_apply_clips_and_loop() {
+        let (mut world, entity, _) = player_world(translation_clip(0.0, 4.0, &[]));
+        update(&mut world, 0.25);
+        assert_eq!(translation_x(&world, entity), 1.0);
+        update(&mut world, 1.0);
+        assert!((translation_x(&world, entity) - 1.0).abs() < 1e-5);
+
+        player_mut(&mut world, entity).looping = false;
+        update(&mut world, 2.0);
+        assert_eq!(translation_x(&world, entity), 4.0);
+        assert_eq!(player_mut(&mut world, entity).time, 1.0);
+
+        player_mut(&mut world, entity).paused = true;
+        player_mut(&mut world, entity).time = 0.5;
+        update(&mut world, 0.25);
+        assert_eq!(player_mut(&mut world, entity).time, 0.5);
+    }
+
+    #[test]
+    fn events_fire_once_per_pass_in_either_direction() {
+        let clip = translation_clip(0.0, 1.0, &[(0.25, "step"), (0.75, "land")]);
+        let (mut world, entity, _) = player_world(clip);
+        update(&mut world, 0.5);
+        assert_eq!(events(&world, entity), ["step"]);
+        update(&mut world, 0.5);
+        assert_eq!(events(&world, entity), ["land"]);
+        // Wrapping around the loop passes both, in playback order
+        player_mut(&mut world, entity).time = 0.7;
+        update(&mut world, 0.6);
+        assert_eq!(events(&world, entity), ["land", "step"]);
+        update(&mut world, 0.0);
+        assert!(events(&world, entity).is_empty());
+
+        player_mut(&mut world, entity).speed = -1.0;
+        player_mut(&mut world, entity).time = 0.5;
+        update(&mut world, 0.5);
+        assert_eq!(events(&world, entity), ["step"]);
+    }
+
+    #[test]
+    fn crossfades_blend_from_the_previous_clip() {
+        let (mut world, entity, _) = player_world(translation_clip(0.0, 0.0, &[]));
+        let target = add_asset(
+            &mut world.resources.assets.animation_clips,
+            translation_clip(10.0, 10.0, &[]),
+        );
+        update(&mut world, 0.1);
+        assert_eq!(translation_x(&world, entity), 0.0);
+
+        crossfade_animation(player_mut(&mut world, entity), target.clone(), 1.0);
+        update(&mut world, 0.5);
+        assert!((translation_x(&world, entity) - 5.0).abs() < 1e-4);
+        update(&mut world, 0.5);
+        assert_eq!(translation_x(&world, entity), 10.0);
+        assert!(player_mut(&mut world, entity).fade.is_none());
+
+        // Without a duration the switch is immediate
+        let back = add_asset(
+            &mut world.resources.assets.animation_clips,
+            translation_clip(-1.0, -1.0, &[]),
+        );
+        crossfade_animation(player_mut(&mut world, entity), back, 0.0);
+        update(&mut world, 0.1);
+        assert_eq!(translation_x(&world, entity), -1.0);
+    }
+
+    #[test]
+    fn tracks_target_named_descendants() {
+        let mut clip = translation_clip(3.0, 3.0, &[]);
+        clip.tracks[0].target = Some("Hand".to_string());
+        let (mut world, entity, _) = player_world(clip);
+        let hand = spawn_entities(&mut world, NAME | PARENT | LOCAL_TRANSFORM, 1)[0];
+        *get_component_mut::<Name>(&mut world, hand, NAME).expect("No name!") =
+            Name("Hand".to_string());
+        *get_component_mut::<Parent>(&mut world, hand, PARENT).expect("No parent!") =
+            Parent(entity);
+        update(&mut world, 0.1);
+        assert!((translation_x(&world, hand) - 3.0).abs() < 1e-5);
+        assert_eq!(translation_x(&world, entity), 0.0);
+    }
+}
@@ -1,16 +1,37 @@
 use crate::{
+    frame_stats::{entity_count, update_frame_stats},
     graphics::{self, create_renderer_resources, render_frame, resize_renderer},
-    world::World,
+    input::{apply_input_event, end_mouse_frame, input_event, update_input_system},
+    profiler::{begin_frame, end_frame, profile},
+    render_graph::RenderGraph,
+    replay::{
+        begin_replay, begin_replay_frame, create_input_recorder, create_input_replay,
+        end_replay_frame, record_frame, record_input_event, save_recording, InputRecorder,
+        InputRecording, InputReplay,
+    },
+    ui::{create_ui, handle_ui_event, run_ui, Ui},
+    world::{advance_fixed_time, begin_fixed_update, run_fixed_systems, run_systems, World},
 };
-use std::{sync::Arc, time::Instant};
+use std::{path::PathBuf, sync::Arc, time::Instant};
 use winit::{
-    application::ApplicationHandler, dpi::PhysicalSize, event::WindowEvent, window::Window,
+    application::ApplicationHandler,
+    dpi::PhysicalSize,
+    event::{ElementState, KeyEvent, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+    window::Window,
 };
 
 pub trait State {
     fn initialize(&mut self, _world: &mut World) {}
     fn receive_event(&mut self, _world: &mut World, _event: &WindowEvent) {}
     fn update(&mut self, _world: &mut World) {}
+    /// Called zero or more times per frame at `FixedTime::timestep` intervals,
+    /// before the fixed systems such as physics
+    fn fixed_update(&mut self, _world: &mut World) {}
+    /// Called each frame after `update`, to build egui windows
+    fn ui(&mut self, _context: &egui::Context, _world: &mut World) {}
+    /// Called once the renderer exists, to add custom passes and textures
+    fn configure_render_graph(&mut self, _world: &mut World, _render_graph: &mut RenderGraph) {}
 }
 
 #[derive(Default)]
@@ -20,7 +41,15 @@ pub struct App {
     window: Option<Arc<Window>>,
     last_render_time: Option<Instant>,
     graphics: Option<graphics::Graphics<'static>>,
+    ui: Option<Ui>,
     last_size: (u32, u32),
+    #[cfg(feature = "gamepad")]
+    gamepad_input: Option<crate::input::GamepadInput>,
+    #[cfg(feature = "audio-device")]
+    audio_output: Option<crate::audio::AudioOutput>,
+    record_path: Option<PathBuf>,
+    recorder: Option<InputRecorder>,
+    replay: Option<InputReplay>,
 }
 
 impl App {
@@ -30,6 +59,41 @@ impl App {
             ..Default::default()
         }
     }
+
+    /// Records input and frame times, saving them to `path` on exit
+    pub fn record_input(mut self, path: impl Into<PathBuf>) -> Self {
+        self.record_path = Some(path.into());
+        self
+    }
+
+    /// Drives the app from a recording instead of live input until it ends,
+    /// logging the first frame whose component data differs from it
+    pub fn replay_input(mut self, recording: InputRecording) -> Self {
+        self.replay = Some(create_input_replay(recording));
+        self
+    }
+}
+
+/// Runs one frame of input, gameplay and systems, without rendering.
+/// `ui` runs after `State::update`, before the fixed steps.
+pub fn update_frame<T>(
+    world: &mut World,
+    state: &mut dyn State,
+    ui: impl FnOnce(&mut World, &mut dyn State) -> T,
+) -> T {
+    profile(world, "Input", update_input_system);
+    profile(world, "Update", |world| state.update(world));
+    let ui_frame = profile(world, "UI", |world| ui(world, state));
+    let steps = advance_fixed_time(&mut world.resources.fixed_time, world.resources.delta_time);
+    profile(world, "Fixed Update", |world| {
+        begin_fixed_update(world);
+        for _ in 0..steps {
+            state.fixed_update(world);
+            run_fixed_systems(world);
+        }
+    });
+    profile(world, "Systems", run_systems);
+    ui_frame
 }
 
 impl ApplicationHandler for App {
@@ -52,11 +116,44 @@ impl ApplicationHandler for App {
             window_handle.inner_size().width,
             window_handle.inner_size().height,
         );
-        let graphics = pollster::block_on(async move {
+        self.world.resources.viewport_width = width;
+        self.world.resources.viewport_height = height;
+        if let Some(replay) = self.replay.as_ref() {
+            begin_replay(replay, &mut self.world);
+        }
+        if self.record_path.is_some() {
+            self.recorder = Some(create_input_recorder(&self.world));
+        }
+        let mut graphics = pollster::block_on(async move {
             create_renderer_resources(window_handle.clone(), width, height).await
         });
+        if let Some(state) = self.state.as_mut() {
+            state.configure_render_graph(&mut self.world, &mut graphics.render_graph);
+        }
+        let max_texture_side = graphics.device.limits().max_texture_dimension_2d as usize;
+        self.ui = Some(create_ui(
+            self.window.as_ref().expect("Window was just created!"),
+            max_texture_side,
+        ));
         self.graphics = Some(graphics);
 
+        #[cfg(feature = "audio-device")]
+        {
+            // Without a device the game still runs, silently
+            match crate::audio::start_audio_output(&self.world.resources.audio) {
+                Ok(output) => self.audio_output = Some(output),
+                Err(error) => log::warn!("Failed to start audio output: {error}"),
+            }
+        }
+
+        #[cfg(feature = "gamepad")]
+        {
+            match crate::input::start_gamepad_input() {
+                Ok(input) => self.gamepad_input = Some(input),
+                Err(error) => log::warn!("Failed to start gamepad input: {error}"),
+            }
+        }
+
         self.last_render_time = Some(Instant::now());
     }
 
@@ -66,29 +163,61 @@ impl ApplicationHandler for App {
         _window_id: winit::window::WindowId,
         event: winit::event::WindowEvent,
     ) {
-        let (Some(window), Some(last_render_time), Some(graphics), Some(state), world) = (
+        let (Some(window), Some(last_render_time), Some(graphics), Some(state), Some(ui), world) = (
             self.window.as_ref(),
             self.last_render_time.as_mut(),
             self.graphics.as_mut(),
             self.state.as_mut(),
+            self.ui.as_mut(),
             &mut self.world,
         ) else {
             return;
         };
 
+        // Events egui consumes, like typing into a text field, never reach gameplay input.
+        // Releases always do, so keys and buttons pressed before the UI took focus do not stick.
+        let consumed_by_ui = handle_ui_event(ui, window, &event);
+        let released = matches!(
+            event,
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state: ElementState::Released,
+                    ..
+                },
+                ..
+            } | WindowEvent::MouseInput {
+                state: ElementState::Released,
+                ..
+            }
+        );
+        // While replaying, live input is ignored so the recording alone drives the world
+        let replaying = self.replay.is_some();
+        let live_input = matches!(
+            event,
+            WindowEvent::KeyboardInput { .. }
+                | WindowEvent::MouseInput { .. }
+                | WindowEvent::CursorMoved { .. }
+                | WindowEvent::MouseWheel { .. }
+        );
+        if let (Some(input), false) = (input_event(&event, consumed_by_ui), replaying) {
+            apply_input_event(world, &input);
+            if let Some(recorder) = self.recorder.as_mut() {
+                record_input_event(recorder, input);
+            }
+        }
+
         match event {
+            // Exit by pressing the escape key
             WindowEvent::KeyboardInput {
                 event:
-                    winit::event::KeyEvent {
-                        physical_key: winit::keyboard::PhysicalKey::Code(key_code),
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::Escape),
+                        state: ElementState::Pressed,
                         ..
                     },
                 ..
-            } => {
-                // Exit by pressing the escape key
-                if matches!(key_code, winit::keyboard::KeyCode::Escape) {
-                    event_loop.exit();
-                }
+            } if !consumed_by_ui => {
+                event_loop.exit();
             }
             WindowEvent::Resized(PhysicalSize { width, height }) => {
                 let (width, height) = ((width).max(1), (height).max(1));
@@ -101,13 +230,49 @@ impl ApplicationHandler for App {
             }
             WindowEvent::RedrawRequested => {
                 let now = Instant::now();
-                let _delta_time = now - *last_render_time;
+                world.resources.delta_time = (now - *last_render_time).as_secs_f32();
                 *last_render_time = now;
+                if let Some(replay) = self.replay.as_ref() {
+                    if !begin_replay_frame(replay, world) {
+                        log::info!("Replay finished after {} frames", replay.frame);
+                        self.replay = None;
+                    }
+                }
+                let entities = entity_count(world);
+                update_frame_stats(
+                    &mut world.resources.frame_stats,
+                    world.resources.delta_time,
+                    entities,
+                );
 
-                render_frame(graphics);
-
-                state.update(world);
+                begin_frame(&mut world.resources.profiler);
+                #[cfg(feature = "gamepad")]
+                if let (Some(gamepad_input), None) =
+                    (self.gamepad_input.as_mut(), self.replay.as_ref())
+                {
+                    crate::input::poll_gamepads(gamepad_input, &mut world.resources.gamepads);
+                }
+                let ui_frame = update_frame(world, state.as_mut(), |world, state| {
+                    run_ui(ui, window, |context| state.ui(context, world))
+                });
+                if let Some(recorder) = self.recorder.as_mut() {
+                    record_frame(recorder, world);
+                }
+                if let Some(divergence) = self
+                    .replay
+                    .as_mut()
+                    .and_then(|replay| end_replay_frame(replay, world))
+                {
+                    log::error!("{divergence}");
+                }
+                profile(world, "Render", |world| {
+                    render_frame(graphics, world, &ui_frame)
+                });
+                end_mouse_frame(&mut world.resources.mouse);
+                end_frame(&mut world.resources.profiler);
             }
+            _ if replaying && live_input => {}
+            _ if consumed_by_ui && !released => {}
             _ => {
                 state.receive_event(world, &event);
             }
@@ -115,4 +280,19 @@ impl ApplicationHandler for App {
 
         window.request_redraw();
     }
+
+    fn exiting(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop) {
+        let (Some(path), Some(recorder)) = (self.record_path.as_ref(), self.recorder.as_ref())
+        else {
+            return;
+        };
+        match save_recording(&recorder.recording, path) {
+            Ok(()) => log::info!(
+                "Saved {} recorded frames to {}",
+                recorder.recording.frames.len(),
+                path.display()
+            ),
+            Err(error) => log::error!("Failed to save input recording: {error}"),
+        }
+    }
 }
@@ -0,0 +1,986 @@
+use crate::{
+    animation::AnimationClip,
+    audio::AudioClip,
+    image_loader::decode_image_bytes,
+    world::{
+        get_component_mut, spawn_entities, EntityId, Image, LocalTransform, Material, Mesh, Name,
+        Parent, RenderMesh, Scene, Shader, World, BOUNDING_BOX, GLOBAL_TRANSFORM, HIERARCHY_BOUNDS,
+        LOCAL_TRANSFORM, MATERIAL, NAME, PARENT, RENDER_MESH, WORLD_BOUNDS,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Weak},
+};
+
+/// Identifies an asset within its `Assets` collection. Ids are never reused.
+#[derive(
+    Default, Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub struct AssetId(pub u64);
+
+/// A typed, reference-counted reference to an asset.
+/// The asset is unloaded by `update_assets` once every handle to it has dropped.
+/// Handles serialize as their id and the path they were loaded from. Inside
+/// `deserialize_with_assets` a handle with a path loads it again, otherwise
+/// deserialized handles are detached: they resolve by id but do not keep the asset loaded.
+pub struct Handle<T> {
+    pub id: AssetId,
+    reference: Option<Arc<()>>,
+    path: Option<Arc<Path>>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    /// A handle that refers to an asset without keeping it loaded
+    pub fn detached(id: AssetId) -> Self {
+        Self {
+            id,
+            reference: None,
+            path: None,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Default for Handle<T> {
+    fn default() -> Self {
+        Self::detached(AssetId::default())
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            reference: self.reference.clone(),
+            path: self.path.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Handle").field(&self.id.0).finish()
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> std::hash::Hash for Handle<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SerializedHandle {
+    id: AssetId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    path: Option<PathBuf>,
+}
+
+impl<T> Serialize for Handle<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializedHandle {
+            id: self.id,
+            path: self.path.as_deref().map(Path::to_path_buf),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, T: ServerAsset> Deserialize<'de> for Handle<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let SerializedHandle { id, path } = SerializedHandle::deserialize(deserializer)?;
+        let Some(path) = path else {
+            return Ok(Self::detached(id));
+        };
+        let loaded = DESERIALIZING_ASSETS.with(|server| {
+            server
+                .borrow_mut()
+                .as_mut()
+                .map(|server| load_asset(T::server_assets(server), &path))
+        });
+        Ok(loaded.unwrap_or_else(|| Self {
+            path: Some(path.into()),
+            ..Self::detached(id)
+        }))
+    }
+}
+
+thread_local! {
+    /// The server that handles load their paths from while `deserialize_with_assets` runs
+    static DESERIALIZING_ASSETS: RefCell<Option<AssetServer>> = const { RefCell::new(None) };
+}
+
+/// Runs a deserialization in which handles that saved a path load it through the server,
+/// so a saved world or scene keeps its references to assets loaded from files
+pub fn deserialize_with_assets<R>(server: &mut AssetServer, deserialize: impl FnOnce() -> R) -> R {
+    /// Puts the server back even if the deserialization panics
+    struct Restore<'a>(&'a mut AssetServer);
+    impl Drop for Restore<'_> {
+        fn drop(&mut self) {
+            if let Some(server) = DESERIALIZING_ASSETS.with(|server| server.borrow_mut().take()) {
+                *self.0 = server;
+            }
+        }
+    }
+
+    let taken = std::mem::take(server);
+    DESERIALIZING_ASSETS.with(|server| *server.borrow_mut() = Some(taken));
+    let _restore = Restore(server);
+    deserialize()
+}
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LoadState {
+    /// No asset exists for the handle, it was never added or has been unloaded
+    #[default]
+    Missing,
+    Loading,
+    Loaded,
+    Failed(String),
+}
+
+/// Changes made by the last `update_assets`, readable until the next one
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AssetEvent {
+    Loaded(AssetId),
+    /// The asset was replaced by a hot reload
+    Modified(AssetId),
+    Removed(AssetId),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AssetEntry<T> {
+    pub asset: Option<T>,
+    pub state: LoadState,
+    pub path: Option<PathBuf>,
+    /// Tracks the handles to this asset. Entries without one,
+    /// such as deserialized entries, are never unloaded automatically.
+    #[serde(skip)]
+    references: Option<Weak<()>>,
+}
+
+/// Assets of one type, with the path index used for deduplication
+#[derive(Serialize, Deserialize)]
+#[serde(bound(serialize = "T: Serialize", deserialize = "T: Deserialize<'de>"))]
+pub struct Assets<T> {
+    entries: HashMap<AssetId, AssetEntry<T>>,
+    paths: HashMap<PathBuf, AssetId>,
+    next_id: u64,
+    #[serde(skip)]
+    loads: LoadQueue<T>,
+    #[serde(skip)]
+    events: Vec<AssetEvent>,
+}
+
+impl<T> Default for Assets<T> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            paths: HashMap::new(),
+            next_id: 1,
+            loads: LoadQueue::default(),
+            events: Vec::new(),
+        }
+    }
+}
+
+/// Finished background loads, waiting to be stored by `update_assets`
+struct LoadQueue<T> {
+    sender: mpsc::Sender<(AssetId, Result<T, String>)>,
+    receiver: mpsc::Receiver<(AssetId, Result<T, String>)>,
+}
+
+impl<T> Default for LoadQueue<T> {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self { sender, receiver }
+    }
+}
+
+/// Assets that can be decoded from the bytes of a file
+pub trait LoadAsset: Sized + Send + 'static {
+    fn decode(bytes: &[u8]) -> Result<Self, String>;
+}
+
+impl LoadAsset for Image {
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        decode_image_bytes(bytes).map_err(|error| error.to_string())
+    }
+}
+
+/// Shaders are validated once composed by a `ShaderLibrary`, since they may include other files
+impl LoadAsset for Shader {
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let source = std::str::from_utf8(bytes)
+            .map_err(|error| format!("Shader is not valid UTF-8: {error}"))?;
+        Ok(Shader {
+            source: source.to_string(),
+        })
+    }
+}
+
+/// Mesh files are RON, and are checked for indices past the last vertex
+impl LoadAsset for Mesh {
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let mesh: Mesh =
+            ron::de::from_bytes(bytes).map_err(|error| format!("Failed to parse mesh: {error}"))?;
+        if let Some(index) = mesh
+            .indices
+            .iter()
+            .find(|index| **index as usize >= mesh.vertices.len())
+        {
+            return Err(format!(
+                "Mesh index {index} is out of range for {} vertices",
+                mesh.vertices.len()
+            ));
+        }
+        if !mesh.skin_weights.is_empty() && mesh.skin_weights.len() != mesh.vertices.len() {
+            return Err(format!(
+                "Mesh has {} skin weights for {} vertices",
+                mesh.skin_weights.len(),
+                mesh.vertices.len()
+            ));
+        }
+        Ok(mesh)
+    }
+}
+
+/// Material files are RON, and the images they refer to by path load once the material is stored.
+/// Entities copy a material when spawned, so a reload applies to entities spawned after it.
+impl LoadAsset for Material {
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        ron::de::from_bytes(bytes).map_err(|error| format!("Failed to parse material: {error}"))
+    }
+}
+
+/// Scene files are RON, and the assets they refer to by path load once the scene is stored
+impl LoadAsset for Scene {
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        ron::de::from_bytes(bytes).map_err(|error| format!("Failed to parse scene: {error}"))
+    }
+}
+
+/// Asset types the `AssetServer` stores, which deserialized handles can load by path
+pub trait ServerAsset: LoadAsset {
+    fn server_assets(server: &mut AssetServer) -> &mut Assets<Self>;
+}
+
+macro_rules! server_assets {
+    ($($asset:ty => $field:ident),* $(,)?) => {
+        $(
+            impl ServerAsset for $asset {
+                fn server_assets(server: &mut AssetServer) -> &mut Assets<Self> {
+                    &mut server.$field
+                }
+            }
+        )*
+    };
+}
+
+server_assets! {
+    AnimationClip => animation_clips,
+    AudioClip => audio_clips,
+    Image => images,
+    Mesh => meshes,
+    Material => materials,
+    Scene => scenes,
+    Shader => shaders,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct AssetServer {
+    pub animation_clips: Assets<AnimationClip>,
+    pub audio_clips: Assets<AudioClip>,
+    pub images: Assets<Image>,
+    pub meshes: Assets<Mesh>,
+    pub materials: Assets<Material>,
+    pub scenes: Assets<Scene>,
+    pub shaders: Assets<Shader>,
+    /// The directory relative asset paths are resolved against, see `asset_root_path`
+    #[serde(skip)]
+    pub root: PathBuf,
+    #[serde(skip)]
+    watcher: Option<FileWatcher>,
+}
+
+/// Watches the directories of loaded files, since editors often save by replacing the file
+struct FileWatcher {
+    watcher: notify::RecommendedWatcher,
+    changes: mpsc::Receiver<notify::Result<notify::Event>>,
+    directories: HashSet<PathBuf>,
+}
+
+fn insert_entry<T>(
+    assets: &mut Assets<T>,
+    asset: Option<T>,
+    state: LoadState,
+    path: Option<PathBuf>,
+) -> Handle<T> {
+    let id = AssetId(assets.next_id);
+    assets.next_id += 1;
+    let reference = Arc::new(());
+    assets.entries.insert(
+        id,
+        AssetEntry {
+            asset,
+            state,
+            path,
+            references: Some(Arc::downgrade(&reference)),
+        },
+    );
+    Handle {
+        id,
+        reference: Some(reference),
+        path: assets.entries[&id].path.as_deref().map(Arc::from),
+        marker: PhantomData,
+    }
+}
+
+/// Resolves a path against the asset root, leaving absolute paths as they are
+pub fn asset_root_path(server: &AssetServer, path: impl AsRef<Path>) -> PathBuf {
+    server.root.join(path)
+}
+
+/// Stores an asset created in code
+pub fn add_asset<T>(assets: &mut Assets<T>, asset: T) -> Handle<T> {
+    insert_entry(assets, Some(asset), LoadState::Loaded, None)
+}
+
+/// Starts loading an asset on a rayon thread, returning a handle to it immediately.
+/// Loading a path that is already loaded or loading returns a handle to the same asset.
+pub fn load_asset<T: LoadAsset>(assets: &mut Assets<T>, path: impl AsRef<Path>) -> Handle<T> {
+    let path = normalize_path(path.as_ref());
+    if let Some(handle) = assets
+        .paths
+        .get(&path)
+        .copied()
+        .and_then(|id| strong_handle(assets, id))
+    {
+        return handle;
+    }
+
+    let handle = insert_entry(assets, None, LoadState::Loading, Some(path.clone()));
+    assets.paths.insert(path.clone(), handle.id);
+    spawn_load(assets, handle.id, path);
+    handle
+}
+
+fn spawn_load<T: LoadAsset>(assets: &Assets<T>, id: AssetId, path: PathBuf) {
+    let sender = assets.loads.sender.clone();
+    rayon::spawn(move || {
+        let result = std::fs::read(&path)
+            .map_err(|error| format!("Failed to read {}: {error}", path.display()))
+            .and_then(|bytes| T::decode(&bytes))
+            .map_err(|error| format!("{}: {error}", path.display()));
+        // The receiver only disappears with the asset collection
+        let _ = sender.send((id, result));
+    });
+}
+
+/// Loads an asset's file again in the background. Until the new version
+/// is ready the previous one stays in place, and it is kept if the reload fails.
+pub fn reload_asset<T: LoadAsset>(assets: &Assets<T>, id: AssetId) {
+    if let Some(path) = assets.entries.get(&id).and_then(|entry| entry.path.clone()) {
+        spawn_load(assets, id, path);
+    }
+}
+
+/// Returns a counted handle to an existing asset, making a detached one count again
+pub fn strong_handle<T>(assets: &mut Assets<T>, id: AssetId) -> Option<Handle<T>> {
+    let entry = assets.entries.get_mut(&id)?;
+    let reference = entry
+        .references
+        .as_ref()
+        .and_then(Weak::upgrade)
+        .unwrap_or_else(|| {
+            let reference = Arc::new(());
+            entry.references = Some(Arc::downgrade(&reference));
+            reference
+        });
+    Some(Handle {
+        id,
+        reference: Some(reference),
+        path: entry.path.as_deref().map(Arc::from),
+        marker: PhantomData,
+    })
+}
+
+pub fn get_asset<'a, T>(assets: &'a Assets<T>, handle: &Handle<T>) -> Option<&'a T> {
+    get_asset_by_id(assets, handle.id)
+}
+
+pub fn get_asset_by_id<T>(assets: &Assets<T>, id: AssetId) -> Option<&T> {
+    assets.entries.get(&id)?.asset.as_ref()
+}
+
+pub fn get_asset_mut<'a, T>(assets: &'a mut Assets<T>, handle: &Handle<T>) -> Option<&'a mut T> {
+    assets.entries.get_mut(&handle.id)?.asset.as_mut()
+}
+
+pub fn load_state<T>(assets: &Assets<T>, handle: &Handle<T>) -> LoadState {
+    assets
+        .entries
+        .get(&handle.id)
+        .map(|entry| entry.state.clone())
+        .unwrap_or_default()
+}
+
+pub fn asset_path<'a, T>(assets: &'a Assets<T>, handle: &Handle<T>) -> Option<&'a Path> {
+    assets.entries.get(&handle.id)?.path.as_deref()
+}
+
+pub fn asset_events<T>(assets: &Assets<T>) -> &[AssetEvent] {
+    &assets.events
+}
+
+/// Iterates over every loaded asset
+pub fn iter_assets<T>(assets: &Assets<T>) -> impl Iterator<Item = (AssetId, &T)> {
+    assets
+        .entries
+        .iter()
+        .filter_map(|(id, entry)| Some((*id, entry.asset.as_ref()?)))
+}
+
+/// Stores finished background loads and unloads assets that no handle refers to anymore
+pub fn update_assets<T>(assets: &mut Assets<T>) {
+    assets.events.clear();
+    while let Ok((id, result)) = assets.loads.receiver.try_recv() {
+        let Some(entry) = assets.entries.get_mut(&id) else {
+            continue;
+        };
+        let reloaded = entry.asset.is_some();
+        match result {
+            Ok(asset) => {
+                entry.asset = Some(asset);
+                entry.state = LoadState::Loaded;
+                assets.events.push(if reloaded {
+                    AssetEvent::Modified(id)
+                } else {
+                    AssetEvent::Loaded(id)
+                });
+            }
+            Err(error) if reloaded => {
+                log::error!("Failed to reload, keeping the previous version: {error}");
+            }
+            Err(error) => {
+                log::warn!("{error}");
+                entry.state = LoadState::Failed(error);
+            }
+        }
+    }
+
+    let unreferenced = assets
+        .entries
+        .iter()
+        .filter(|(_, entry)| {
+            entry
+                .references
+                .as_ref()
+                .is_some_and(|references| references.strong_count() == 0)
+        })
+        .map(|(id, _)| *id)
+        .collect::<Vec<_>>();
+    for id in unreferenced {
+        let entry = assets.entries.remove(&id);
+        if let Some(path) = entry.and_then(|entry| entry.path) {
+            assets.paths.remove(&path);
+        }
+        assets.events.push(AssetEvent::Removed(id));
+    }
+}
+
+pub fn update_asset_server(server: &mut AssetServer) {
+    if server.watcher.is_some() {
+        reload_changed_files(server);
+    }
+    update_assets(&mut server.animation_clips);
+    update_assets(&mut server.audio_clips);
+    update_assets(&mut server.images);
+    update_assets(&mut server.meshes);
+    update_assets(&mut server.materials);
+    update_assets(&mut server.scenes);
+    update_assets(&mut server.shaders);
+    load_referenced_assets(server);
+}
+
+/// Materials and scenes decode without the server, so the assets they
+/// refer to by path are loaded once they are stored
+fn load_referenced_assets(server: &mut AssetServer) {
+    let AssetServer {
+        images,
+        meshes,
+        materials,
+        scenes,
+        ..
+    } = server;
+    for id in stored_assets(materials) {
+        let Some(material) = materials
+            .entries
+            .get_mut(&id)
+            .and_then(|entry| entry.asset.as_mut())
+        else {
+            continue;
+        };
+        for texture in [
+            &mut material.base_color_texture,
+            &mut material.metallic_roughness_texture,
+            &mut material.normal_texture,
+            &mut material.occlusion_texture,
+            &mut material.emissive_texture,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            load_handle_path(images, &mut texture.image);
+        }
+    }
+    for id in stored_assets(scenes) {
+        let Some(scene) = scenes
+            .entries
+            .get_mut(&id)
+            .and_then(|entry| entry.asset.as_mut())
+        else {
+            continue;
+        };
+        for node in &mut scene.nodes {
+            if let Some(mesh) = &mut node.mesh {
+                load_handle_path(meshes, mesh);
+            }
+            if let Some(material) = &mut node.material {
+                load_handle_path(materials, material);
+            }
+        }
+    }
+}
+
+/// Assets loaded or reloaded by the last `update_assets`
+fn stored_assets<T>(assets: &Assets<T>) -> Vec<AssetId> {
+    assets
+        .events
+        .iter()
+        .filter_map(|event| match event {
+            AssetEvent::Loaded(id) | AssetEvent::Modified(id) => Some(*id),
+            AssetEvent::Removed(_) => None,
+        })
+        .collect()
+}
+
+/// Replaces a detached handle that saved a path with a handle loading that path
+fn load_handle_path<T: LoadAsset>(assets: &mut Assets<T>, handle: &mut Handle<T>) {
+    if handle.reference.is_none() {
+        if let Some(path) = handle.path.clone() {
+            *handle = load_asset(assets, path);
+        }
+    }
+}
+
+/// Starts watching the files of every loaded asset, reloading them when they change
+pub fn enable_hot_reload(server: &mut AssetServer) -> notify::Result<()> {
+    if server.watcher.is_some() {
+        return Ok(());
+    }
+    let (sender, changes) = mpsc::channel();
+    let watcher = notify::recommended_watcher(sender)?;
+    server.watcher = Some(FileWatcher {
+        watcher,
+        changes,
+        directories: HashSet::new(),
+    });
+    Ok(())
+}
+
+pub fn disable_hot_reload(server: &mut AssetServer) {
+    server.watcher = None;
+}
+
+fn reload_changed_files(server: &mut AssetServer) {
+    let AssetServer {
+        animation_clips,
+        audio_clips,
+        images,
+        meshes,
+        materials,
+        scenes,
+        shaders,
+        watcher: Some(watcher),
+        ..
+    } = server
+    else {
+        return;
+    };
+
+    let directories = [
+        &animation_clips.paths,
+        &audio_clips.paths,
+        &images.paths,
+        &meshes.paths,
+        &materials.paths,
+        &scenes.paths,
+        &shaders.paths,
+    ]
+    .into_iter()
+    .flat_map(HashMap::keys)
+    .filter_map(|path| path.parent())
+    .filter(|directory| !watcher.directories.contains(*directory))
+    .map(Path::to_path_buf)
+    .collect::<HashSet<_>>();
+    for directory in directories {
+        use notify::Watcher;
+        if let Err(error) = watcher
+            .watcher
+            .watch(&directory, notify::RecursiveMode::NonRecursive)
+        {
+            log::warn!("Failed to watch {}: {error}", directory.display());
+        }
+        watcher.directories.insert(directory);
+    }
+
+    // Editors report several events per save, so each file reloads once per update
+    let mut changed = HashSet::new();
+    while let Ok(event) = watcher.changes.try_recv() {
+        match event {
+            Ok(event) if event.kind.is_modify() || event.kind.is_create() => {
+                changed.extend(event.paths);
+            }
+            Ok(_) => {}
+            Err(error) => log::warn!("File watcher error: {error}"),
+        }
+    }
+    for path in changed {
+        let path = normalize_path(&path);
+        if let Some(id) = animation_clips.paths.get(&path) {
+            reload_asset(animation_clips, *id);
+        }
+        if let Some(id) = audio_clips.paths.get(&path) {
+            reload_asset(audio_clips, *id);
+        }
+        if let Some(id) = images.paths.get(&path) {
+            reload_asset(images, *id);
+        }
+        if let Some(id) = meshes.paths.get(&path) {
+            reload_asset(meshes, *id);
+        }
+        if let Some(id) = materials.paths.get(&path) {
+            reload_asset(materials, *id);
+        }
+        if let Some(id) = scenes.paths.get(&path) {
+            reload_asset(scenes, *id);
+        }
+        if let Some(id) = shaders.paths.get(&path) {
+            reload_asset(shaders, *id);
+        }
+    }
+}
+
+fn normalize_path(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Spawns an entity for every node of a loaded scene, returning them in node order
+pub fn spawn_scene(world: &mut World, scene: &Handle<Scene>) -> Vec<EntityId> {
+    let Some(scene) = get_asset(&world.resources.assets.scenes, scene) else {
+        return Vec::new();
+    };
+    let nodes = scene.nodes.clone();
+    let entities = nodes
+        .iter()
+        .map(|node| {
+            let mut mask = LOCAL_TRANSFORM | GLOBAL_TRANSFORM | NAME | HIERARCHY_BOUNDS;
+            if node.parent.is_some() {
+                mask |= PARENT;
+            }
+            if node.mesh.is_some() {
+                mask |= RENDER_MESH | MATERIAL | BOUNDING_BOX | WORLD_BOUNDS;
+            }
+            spawn_entities(world, mask, 1)[0]
+        })
+        .collect::<Vec<_>>();
+
+    for (node, entity) in nodes.into_iter().zip(entities.iter()) {
+        if let Some(transform) =
+            get_component_mut::<LocalTransform>(world, *entity, LOCAL_TRANSFORM)
+        {
+            *transform = node.transform;
+        }
+        if let Some(name) = get_component_mut::<Name>(world, *entity, NAME) {
+            *name = Name(node.name);
+        }
+        if let Some(parent) = node.parent.and_then(|parent| entities.get(parent)) {
+            if let Some(component) = get_component_mut::<Parent>(world, *entity, PARENT) {
+                *component = Parent(*parent);
+            }
+        }
+        if let Some(mesh) = node.mesh {
+            let material = node
+                .material
+                .and_then(|material| {
+                    get_asset(&world.resources.assets.materials, &material).cloned()
+                })
+                .unwrap_or_default();
+            if let Some(component) = get_component_mut::<RenderMesh>(world, *entity, RENDER_MESH) {
+                *component = RenderMesh(mesh);
+            }
+            if let Some(component) = get_component_mut::<Material>(world, *entity, MATERIAL) {
+                *component = material;
+            }
+        }
+    }
+    entities
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::cube_mesh;
+
+    /// Writes a file into a directory unique to the test
+    fn write_file(test: &str, name: &str, contents: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("spree-{}-{test}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// Updates until no load is pending, failing after a few seconds
+    fn finish_loading<T>(assets: &mut Assets<T>, handle: &Handle<T>) -> LoadState {
+        for _ in 0..500 {
+            update_assets(assets);
+            let state = load_state(assets, handle);
+            if state != LoadState::Loading {
+                return state;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        panic!("Asset never finished loading");
+    }
+
+    /// Waits for the next background load to finish and stores it
+    fn finish_next_load<T>(assets: &mut Assets<T>) {
+        let load = assets
+            .loads
+            .receiver
+            .recv_timeout(std::time::Duration::from_secs(5))
+            .expect("Asset never finished loading!");
+        assets.loads.sender.send(load).unwrap();
+        update_assets(assets);
+    }
+
+    #[test]
+    fn meshes_load_from_ron_files() {
+        let mesh = cube_mesh();
+        let path = write_file("mesh", "cube.ron", &ron::to_string(&mesh).unwrap());
+        let mut meshes = Assets::<Mesh>::default();
+        let handle = load_asset(&mut meshes, &path);
+        assert_eq!(finish_loading(&mut meshes, &handle), LoadState::Loaded);
+        let loaded = get_asset(&meshes, &handle).unwrap();
+        assert_eq!(loaded.indices, mesh.indices);
+        assert_eq!(loaded.vertices.len(), mesh.vertices.len());
+        assert_eq!(asset_events(&meshes), &[AssetEvent::Loaded(handle.id)]);
+    }
+
+    #[test]
+    fn meshes_with_out_of_range_indices_fail() {
+        let mut mesh = cube_mesh();
+        mesh.indices.push(mesh.vertices.len() as u32);
+        let path = write_file("bad-mesh", "cube.ron", &ron::to_string(&mesh).unwrap());
+        let mut meshes = Assets::<Mesh>::default();
+        let handle = load_asset(&mut meshes, &path);
+        assert!(matches!(
+            finish_loading(&mut meshes, &handle),
+            LoadState::Failed(error) if error.contains("out of range")
+        ));
+        assert!(get_asset(&meshes, &handle).is_none());
+    }
+
+    #[test]
+    fn materials_load_from_ron_files() {
+        let material = Material {
+            metallic_factor: 0.25,
+            roughness_factor: 0.75,
+            ..Default::default()
+        };
+        let path = write_file("material", "metal.ron", &ron::to_string(&material).unwrap());
+        let mut materials = Assets::<Material>::default();
+        let handle = load_asset(&mut materials, &path);
+        assert_eq!(finish_loading(&mut materials, &handle), LoadState::Loaded);
+        let loaded = get_asset(&materials, &handle).unwrap();
+        assert_eq!(loaded.metallic_factor, 0.25);
+        assert_eq!(loaded.roughness_factor, 0.75);
+        assert!(loaded.base_color_texture.is_none());
+    }
+
+    #[test]
+    fn loading_a_path_twice_shares_the_asset() {
+        let path = write_file(
+            "dedup",
+            "metal.ron",
+            &ron::to_string(&Material::default()).unwrap(),
+        );
+        let mut materials = Assets::<Material>::default();
+        let first = load_asset(&mut materials, &path);
+        let second = load_asset(&mut materials, path.parent().unwrap().join("./metal.ron"));
+        assert_eq!(first, second);
+        assert_eq!(finish_loading(&mut materials, &first), LoadState::Loaded);
+        assert_eq!(iter_assets(&materials).count(), 1);
+    }
+
+    #[test]
+    fn assets_unload_when_the_last_handle_drops() {
+        let mut meshes = Assets::<Mesh>::default();
+        let handle = add_asset(&mut meshes, cube_mesh());
+        let id = handle.id;
+        let detached = Handle::<Mesh>::detached(id);
+        let clone = handle.clone();
+        drop(handle);
+        update_assets(&mut meshes);
+        assert_eq!(load_state(&meshes, &clone), LoadState::Loaded);
+        drop(clone);
+        update_assets(&mut meshes);
+        assert_eq!(load_state(&meshes, &detached), LoadState::Missing);
+        assert_eq!(asset_events(&meshes), &[AssetEvent::Removed(id)]);
+    }
+
+    #[test]
+    fn missing_files_fail_to_load() {
+        let mut images = Assets::<Image>::default();
+        let handle = load_asset(&mut images, "/nonexistent/image.png");
+        assert!(matches!(
+            finish_loading(&mut images, &handle),
+            LoadState::Failed(_)
+        ));
+    }
+
+    #[test]
+    fn reloads_replace_the_asset_behind_existing_handles() {
+        let path = write_file("reload", "cube.ron", &ron::to_string(&cube_mesh()).unwrap());
+        let mut meshes = Assets::<Mesh>::default();
+        let handle = load_asset(&mut meshes, &path);
+        finish_next_load(&mut meshes);
+        assert_eq!(asset_events(&meshes), &[AssetEvent::Loaded(handle.id)]);
+
+        let mut triangle = cube_mesh();
+        triangle.indices.truncate(3);
+        write_file("reload", "cube.ron", &ron::to_string(&triangle).unwrap());
+        reload_asset(&meshes, handle.id);
+        finish_next_load(&mut meshes);
+        assert_eq!(asset_events(&meshes), &[AssetEvent::Modified(handle.id)]);
+        assert_eq!(
+            get_asset(&meshes, &handle).unwrap().indices,
+            triangle.indices
+        );
+        assert_eq!(load_state(&meshes, &handle), LoadState::Loaded);
+    }
+
+    #[test]
+    fn failed_reloads_keep_the_previous_version() {
+        let mesh = cube_mesh();
+        let path = write_file("failed-reload", "cube.ron", &ron::to_string(&mesh).unwrap());
+        let mut meshes = Assets::<Mesh>::default();
+        let handle = load_asset(&mut meshes, &path);
+        finish_next_load(&mut meshes);
+
+        write_file("failed-reload", "cube.ron", "not a mesh");
+        reload_asset(&meshes, handle.id);
+        finish_next_load(&mut meshes);
+        assert!(asset_events(&meshes).is_empty());
+        assert_eq!(get_asset(&meshes, &handle).unwrap().indices, mesh.indices);
+        assert_eq!(load_state(&meshes, &handle), LoadState::Loaded);
+    }
+
+    #[test]
+    fn handles_deserialize_by_path_inside_an_asset_server() {
+        let path = write_file(
+            "handle-path",
+            "cube.ron",
+            &ron::to_string(&cube_mesh()).unwrap(),
+        );
+        let mut server = AssetServer::default();
+        let handle = load_asset(&mut server.meshes, &path);
+        let serialized = ron::to_string(&handle).unwrap();
+
+        let detached: Handle<Mesh> = ron::from_str(&serialized).unwrap();
+        assert_eq!(detached, handle);
+        assert!(detached.reference.is_none());
+
+        let mut restarted = AssetServer::default();
+        restarted.meshes.next_id = 100;
+        let loaded: Handle<Mesh> =
+            deserialize_with_assets(&mut restarted, || ron::from_str(&serialized).unwrap());
+        assert_eq!(loaded.id, AssetId(100));
+        assert_eq!(
+            finish_loading(&mut restarted.meshes, &loaded),
+            LoadState::Loaded
+        );
+        assert_eq!(
+            asset_path(&restarted.meshes, &loaded),
+            asset_path(&server.meshes, &handle)
+        );
+    }
+
+    #[test]
+    fn handles_without_a_path_stay_detached() {
+        let mut server = AssetServer::default();
+        let handle = add_asset(&mut server.meshes, cube_mesh());
+        let serialized = ron::to_string(&handle).unwrap();
+        let loaded: Handle<Mesh> =
+            deserialize_with_assets(&mut server, || ron::from_str(&serialized).unwrap());
+        assert_eq!(loaded, handle);
+        assert!(loaded.reference.is_none());
+        assert!(get_asset(&server.meshes, &loaded).is_some());
+    }
+
+    #[test]
+    fn scenes_load_the_assets_they_refer_to_by_path() {
+        let mesh_path = write_file(
+            "scene-path",
+            "cube.ron",
+            &ron::to_string(&cube_mesh()).unwrap(),
+        );
+        let mut saving = AssetServer::default();
+        let mesh = load_asset(&mut saving.meshes, &mesh_path);
+        let scene = Scene {
+            nodes: vec![crate::world::SceneNode {
+                mesh: Some(mesh),
+                ..Default::default()
+            }],
+        };
+        let scene_path = write_file("scene-path", "scene.ron", &ron::to_string(&scene).unwrap());
+
+        let mut server = AssetServer::default();
+        let scene = load_asset(&mut server.scenes, &scene_path);
+        for _ in 0..500 {
+            update_asset_server(&mut server);
+            let mesh =
+                get_asset(&server.scenes, &scene).and_then(|scene| scene.nodes[0].mesh.clone());
+            if mesh.is_some_and(|mesh| load_state(&server.meshes, &mesh) == LoadState::Loaded) {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let mesh = get_asset(&server.scenes, &scene).unwrap().nodes[0]
+            .mesh
+            .clone()
+            .unwrap();
+        assert_eq!(load_state(&server.meshes, &mesh), LoadState::Loaded);
+        assert_eq!(
+            asset_path(&server.meshes, &mesh),
+            Some(normalize_path(&mesh_path).as_path())
+        );
+    }
+}
@@ -0,0 +1,655 @@
+use crate::{
+    asset::{asset_events, get_asset, AssetEvent, AssetId, Handle, LoadAsset},
+    world::{
+        get_component, get_component_mut, query_entities, EntityId, GlobalTransform, World,
+        ACTIVE_CAMERA, AUDIO_LISTENER, AUDIO_SOURCE, GLOBAL_TRANSFORM,
+    },
+};
+use nalgebra_glm::{Mat4, Vec3};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Decoded audio, as interleaved samples. Clones share the samples.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioClip {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub samples: Arc<[f32]>,
+}
+
+/// Decodes WAV, OGG Vorbis and FLAC files
+impl LoadAsset for AudioClip {
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        decode_audio_bytes(bytes).map_err(|error| format!("Failed to decode audio clip: {error}"))
+    }
+}
+
+pub fn decode_audio_bytes(bytes: &[u8]) -> Result<AudioClip, symphonia::core::errors::Error> {
+    use symphonia::core::{
+        audio::SampleBuffer, codecs::DecoderOptions, errors::Error, formats::FormatOptions,
+        io::MediaSourceStream, meta::MetadataOptions, probe::Hint,
+    };
+
+    let source = MediaSourceStream::new(
+        Box::new(std::io::Cursor::new(bytes.to_vec())),
+        Default::default(),
+    );
+    let mut format = symphonia::default::get_probe()
+        .format(
+            &Hint::new(),
+            source,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )?
+        .format;
+    let track = format
+        .default_track()
+        .ok_or(Error::Unsupported("no audio track"))?;
+    let track_id = track.id;
+    let mut decoder =
+        symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+    let mut sample_rate = track.codec_params.sample_rate.unwrap_or(0);
+    let mut channels = track
+        .codec_params
+        .channels
+        .map_or(0, |channels| channels.count() as u16);
+    let mut samples = Vec::new();
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(Error::IoError(error)) if error.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break
+            }
+            Err(error) => return Err(error),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // Corrupt packets are skipped, as players do
+            Err(Error::DecodeError(error)) => {
+                log::warn!("Skipping an undecodable audio packet: {error}");
+                continue;
+            }
+            Err(error) => return Err(error),
+        };
+        let spec = *decoded.spec();
+        sample_rate = spec.rate;
+        channels = spec.channels.count() as u16;
+        let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        buffer.copy_interleaved_ref(decoded);
+        samples.extend_from_slice(buffer.samples());
+    }
+    if channels == 0 || sample_rate == 0 {
+        return Err(Error::Unsupported("unknown channel layout or sample rate"));
+    }
+    Ok(AudioClip {
+        sample_rate,
+        channels,
+        samples: samples.into(),
+    })
+}
+
+/// Plays an `AudioClip`. Spatial sources are attenuated and panned
+/// by their `GlobalTransform` relative to the listener.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioSource {
+    pub clip: Option<Handle<AudioClip>>,
+    pub volume: f32,
+    /// Playback rate, which also shifts the pitch
+    pub pitch: f32,
+    pub looping: bool,
+    pub spatial: bool,
+    /// Cleared when a clip that does not loop reaches its end, set again to replay it
+    pub playing: bool,
+}
+
+impl Default for AudioSource {
+    fn default() -> Self {
+        Self {
+            clip: None,
+            volume: 1.0,
+            pitch: 1.0,
+            looping: false,
+            spatial: false,
+            playing: true,
+        }
+    }
+}
+
+/// Marks the entity spatial audio is heard from, instead of the active camera
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct AudioListener;
+
+/// Mixer settings and state
+#[derive(Serialize, Deserialize)]
+pub struct Audio {
+    pub volume: f32,
+    /// Distance within which spatial sources play at full volume
+    pub reference_distance: f32,
+    /// Distance beyond which spatial sources get no quieter
+    pub max_distance: f32,
+    /// How quickly spatial sources fade past the reference distance
+    pub rolloff: f32,
+    /// Shared with the output device's thread when one is playing
+    #[serde(skip)]
+    pub mixer: Arc<Mutex<Mixer>>,
+    #[serde(skip)]
+    clips: HashMap<AssetId, Arc<AudioClip>>,
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            reference_distance: 1.0,
+            max_distance: 50.0,
+            rolloff: 1.0,
+            mixer: Arc::new(Mutex::new(Mixer::default())),
+            clips: HashMap::new(),
+        }
+    }
+}
+
+/// Mixes the playing sources to interleaved stereo
+pub struct Mixer {
+    pub sample_rate: u32,
+    /// In the order sources started, so mixing is deterministic
+    voices: Vec<(EntityId, Voice)>,
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Self {
+            sample_rate: 48000,
+            voices: Vec::new(),
+        }
+    }
+}
+
+struct Voice {
+    clip: Arc<AudioClip>,
+    /// Position in the clip, in frames
+    cursor: f64,
+    pitch: f32,
+    looping: bool,
+    spatial: bool,
+    /// Left and right gains reached at the end of the last mix
+    gains: [f32; 2],
+    /// Gains to ramp to over the next mix, avoiding clicks when sources move
+    target_gains: [f32; 2],
+    finished: bool,
+}
+
+/// Left and right gains for a source. Spatial sources fade with the inverse of
+/// their clamped distance and are panned with constant power.
+pub fn spatial_gains(audio: &Audio, listener: &Mat4, position: &Vec3, volume: f32) -> [f32; 2] {
+    let listener_position = listener.column(3).xyz();
+    let offset = position - listener_position;
+    let distance = offset.norm().clamp(
+        audio.reference_distance,
+        audio.max_distance.max(audio.reference_distance),
+    );
+    let attenuation = audio.reference_distance
+        / (audio.reference_distance + audio.rolloff * (distance - audio.reference_distance));
+    let right = listener.column(0).xyz();
+    let pan = if offset.norm() > f32::EPSILON && right.norm() > f32::EPSILON {
+        offset.normalize().dot(&right.normalize()).clamp(-1.0, 1.0)
+    } else {
+        0.0
+    };
+    let angle = (pan + 1.0) * std::f32::consts::FRAC_PI_4;
+    let gain = volume * audio.volume * attenuation;
+    [gain * angle.cos(), gain * angle.sin()]
+}
+
+/// The listener's global transform, from an `AudioListener` or else the active camera
+pub fn audio_listener_transform(world: &World) -> Mat4 {
+    query_entities(world, AUDIO_LISTENER | GLOBAL_TRANSFORM)
+        .into_iter()
+        .chain(query_entities(world, ACTIVE_CAMERA | GLOBAL_TRANSFORM))
+        .find_map(|entity| get_component::<GlobalTransform>(world, entity, GLOBAL_TRANSFORM))
+        .copied()
+        .unwrap_or_else(Mat4::identity)
+}
+
+/// Starts, updates and stops mixer voices to match the `AudioSource` components
+pub fn update_audio_system(world: &mut World) {
+    let (assets, audio) = (&world.resources.assets, &mut world.resources.audio);
+    for event in asset_events(&assets.audio_clips) {
+        if let AssetEvent::Modified(id) | AssetEvent::Removed(id) = event {
+            audio.clips.remove(id);
+        }
+    }
+
+    let listener = audio_listener_transform(world);
+    let mut sources = Vec::new();
+    for entity in query_entities(world, AUDIO_SOURCE) {
+        let Some(source) = get_component::<AudioSource>(world, entity, AUDIO_SOURCE).cloned()
+        else {
+            continue;
+        };
+        let Some(handle) = source.clip.as_ref().filter(|_| source.playing) else {
+            continue;
+        };
+        let Some(clip) = get_asset(&world.resources.assets.audio_clips, handle) else {
+            continue;
+        };
+        let clip = world
+            .resources
+            .audio
+            .clips
+            .entry(handle.id)
+            // Cheap, the samples are shared with the asset
+            .or_insert_with(|| Arc::new(clip.clone()))
+            .clone();
+        let gains = if source.spatial {
+            let position = get_component::<GlobalTransform>(world, entity, GLOBAL_TRANSFORM)
+                .map_or_else(Vec3::zeros, |transform| transform.column(3).xyz());
+            spatial_gains(&world.resources.audio, &listener, &position, source.volume)
+        } else {
+            [source.volume * world.resources.audio.volume; 2]
+        };
+        sources.push((entity, source, clip, gains));
+    }
+
+    let finished = {
+        let mut mixer = world
+            .resources
+            .audio
+            .mixer
+            .lock()
+            .expect("Audio mixer lock was poisoned!");
+        let finished = mixer
+            .voices
+            .iter()
+            .filter(|(_, voice)| voice.finished)
+            .map(|(entity, _)| *entity)
+            .collect::<Vec<_>>();
+        mixer.voices.retain(|(entity, voice)| {
+            !voice.finished && sources.iter().any(|(source, ..)| source == entity)
+        });
+        for (entity, source, clip, gains) in sources {
+            if finished.contains(&entity) {
+                continue;
+            }
+            match mixer.voices.iter_mut().find(|(voice, _)| *voice == entity) {
+                Some((_, voice)) => {
+                    if !Arc::ptr_eq(&voice.clip, &clip) {
+                        voice.clip = clip;
+                        voice.cursor = 0.0;
+                    }
+                    voice.pitch = source.pitch;
+                    voice.looping = source.looping;
+                    voice.spatial = source.spatial;
+                    voice.target_gains = gains;
+                }
+                None => mixer.voices.push((
+                    entity,
+                    Voice {
+                        clip,
+                        cursor: 0.0,
+                        pitch: source.pitch,
+                        looping: source.looping,
+                        spatial: source.spatial,
+                        gains,
+                        target_gains: gains,
+                        finished: false,
+                    },
+                )),
+            }
+        }
+        finished
+    };
+    for entity in finished {
+        if let Some(source) = get_component_mut::<AudioSource>(world, entity, AUDIO_SOURCE) {
+            source.playing = false;
+        }
+    }
+}
+
+/// Adds the playing voices into interleaved stereo `output`, which is cleared first
+pub fn mix_audio(mixer: &mut Mixer, output: &mut [f32]) {
+    output.fill(0.0);
+    let frames = output.len() / 2;
+    let sample_rate = mixer.sample_rate.max(1) as f64;
+    for (_, voice) in mixer.voices.iter_mut() {
+        if voice.finished {
+            continue;
+        }
+        let channels = voice.clip.channels.max(1) as usize;
+        let clip_frames = voice.clip.samples.len() / channels;
+        if clip_frames == 0 {
+            voice.finished = true;
+            continue;
+        }
+        let step = voice.pitch.max(0.0) as f64 * voice.clip.sample_rate as f64 / sample_rate;
+        for frame in 0..frames {
+            if voice.cursor >= clip_frames as f64 {
+                if voice.looping {
+                    voice.cursor %= clip_frames as f64;
+                } else {
+                    voice.finished = true;
+                    break;
+                }
+            }
+            let ramp = (frame + 1) as f32 / frames as f32;
+            let [left, right] = clip_frame(voice, channels, clip_frames);
+            let [left_gain, right_gain] = [0, 1].map(|side| {
+                voice.gains[side] + (voice.target_gains[side] - voice.gains[side]) * ramp
+            });
+            output[frame * 2] += left * left_gain;
+            output[frame * 2 + 1] += right * right_gain;
+            voice.cursor += step;
+        }
+        voice.gains = voice.target_gains;
+    }
+}
+
+/// The clip's left and right samples at the cursor, interpolated between frames.
+/// Spatial voices hear a mono mix, which their gains pan.
+fn clip_frame(voice: &Voice, channels: usize, clip_frames: usize) -> [f32; 2] {
+    let index = voice.cursor.floor() as usize;
+    let next = if index + 1 < clip_frames {
+        index + 1
+    } else if voice.looping {
+        0
+    } else {
+        index
+    };
+    let fraction = (voice.cursor - index as f64) as f32;
+    let sample = |channel: usize| {
+        let samples = &voice.clip.samples;
+        let from = samples[index * channels + channel];
+        from + (samples[next * channels + channel] - from) * fraction
+    };
+    let (left, right) = if channels == 1 {
+        (sample(0), sample(0))
+    } else {
+        (sample(0), sample(1))
+    };
+    if voice.spatial {
+        let mono = (left + right) / 2.0;
+        [mono, mono]
+    } else {
+        [left, right]
+    }
+}
+
+/// Mixes the next `frames` frames without an output device, as interleaved stereo
+pub fn render_audio(audio: &Audio, frames: usize) -> Vec<f32> {
+    let mut output = vec![0.0; frames * 2];
+    mix_audio(
+        &mut audio.mixer.lock().expect("Audio mixer lock was poisoned!"),
+        &mut output,
+    );
+    output
+}
+
+/// Keeps the output device playing while alive
+#[cfg(feature = "audio-device")]
+pub struct AudioOutput {
+    _stream: cpal::Stream,
+}
+
+/// Plays the mixer on the default output device, at the device's sample rate
+#[cfg(feature = "audio-device")]
+pub fn start_audio_output(audio: &Audio) -> Result<AudioOutput, String> {
+    use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+    let device = cpal::default_host()
+        .default_output_device()
+        .ok_or("No audio output device")?;
+    let config = device
+        .default_output_config()
+        .map_err(|error| error.to_string())?;
+    audio
+        .mixer
+        .lock()
+        .expect("Audio mixer lock was poisoned!")
+        .sample_rate = config.sample_rate().0;
+    let stream = match config.sample_format() {
+        cpal::SampleFormat::F32 => build_output_stream::<f32>(&device, &config.into(), audio),
+        cpal::SampleFormat::I16 => build_output_stream::<i16>(&device, &config.into(), audio),
+        cpal::SampleFormat::U16 => build_output_stream::<u16>(&device, &config.into(), audio),
+        format => return Err(format!("Unsupported audio sample format {format}")),
+    }
+    .map_err(|error| error.to_string())?;
+    stream.play().map_err(|error| error.to_string())?;
+    Ok(AudioOutput { _stream: stream })
+}
+
+#[cfg(feature = "audio-device")]
+fn build_output_stream<T: cpal::SizedSample + cpal::FromSample<f32>>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    audio: &Audio,
+) -> Result<cpal::Stream, cpal::BuildStreamError> {
+    use cpal::traits::DeviceTrait;
+
+    let mixer = audio.mixer.clone();
+    let channels = config.channels as usize;
+    let mut stereo = Vec::new();
+    device.build_output_stream(
+        config,
+        move |output: &mut [T], _| {
+            stereo.resize(output.len() / channels * 2, 0.0);
+            match mixer.lock() {
+                Ok(mut mixer) => mix_audio(&mut mixer, &mut stereo),
+                Err(_) => stereo.fill(0.0),
+            }
+            for (frame, samples) in output.chunks_mut(channels).zip(stereo.chunks(2)) {
+                // Mono devices get both sides, extra channels stay silent
+                for (channel, sample) in frame.iter_mut().enumerate() {
+                    let value = match (channels, channel) {
+                        (1, _) => (samples[0] + samples[1]) / 2.0,
+                        (_, 0 | 1) => samples[channel],
+                        _ => 0.0,
+                    };
+                    *sample = T::from_sample(value);
+                }
+            }
+        },
+        |error| log::error!("Audio output error: {error}"),
+        None,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{asset::add_asset, world::spawn_entities};
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    fn assert_samples(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (index, (a, b)) in actual.iter().zip(expected).enumerate() {
+            assert!(close(*a, *b), "sample {index}: {actual:?} != {expected:?}");
+        }
+    }
+
+    fn clip(channels: u16, samples: &[f32]) -> AudioClip {
+        AudioClip {
+            sample_rate: 48000,
+            channels,
+            samples: samples.into(),
+        }
+    }
+
+    /// A world playing the clip from one source
+    fn playing(clip: AudioClip, source: AudioSource) -> (World, EntityId) {
+        let mut world = World::default();
+        let clip = add_asset(&mut world.resources.assets.audio_clips, clip);
+        let entity = spawn_entities(&mut world, AUDIO_SOURCE, 1)[0];
+        *source_mut(&mut world, entity) = AudioSource {
+            clip: Some(clip),
+            ..source
+        };
+        update_audio_system(&mut world);
+        (world, entity)
+    }
+
+    fn source_mut(world: &mut World, entity: EntityId) -> &mut AudioSource {
+        get_component_mut::<AudioSource>(world, entity, AUDIO_SOURCE).expect("No audio source!")
+    }
+
+    fn power([left, right]: [f32; 2]) -> f32 {
+        left * left + right * right
+    }
+
+    #[test]
+    fn nearby_sources_play_centered_at_full_volume() {
+        let audio = Audio::default();
+        let gains = spatial_gains(&audio, &Mat4::identity(), &Vec3::new(0.0, 0.0, -0.5), 0.5);
+        assert!(close(gains[0], gains[1]));
+        assert!(close(power(gains), 0.25));
+    }
+
+    #[test]
+    fn sources_pan_toward_their_side_with_constant_power() {
+        let audio = Audio::default();
+        let [left, right] = spatial_gains(&audio, &Mat4::identity(), &Vec3::x(), 1.0);
+        assert!(close(left, 0.0));
+        assert!(close(right, 1.0));
+        let gains = spatial_gains(&audio, &Mat4::identity(), &Vec3::new(-1.0, 0.0, -1.0), 1.0);
+        assert!(gains[0] > gains[1]);
+        assert!(close(power(gains), 0.5));
+    }
+
+    #[test]
+    fn panning_follows_the_listener_rotation() {
+        let audio = Audio::default();
+        // Turned to face +x, so +z is on the right
+        let listener = nalgebra_glm::rotation(-std::f32::consts::FRAC_PI_2, &Vec3::y());
+        let [left, right] = spatial_gains(&audio, &listener, &Vec3::z(), 1.0);
+        assert!(close(left, 0.0));
+        assert!(close(right, 1.0));
+    }
+
+    #[test]
+    fn distant_sources_fade_until_the_max_distance() {
+        let audio = Audio {
+            max_distance: 10.0,
+            ..Default::default()
+        };
+        let gain = |distance: f32| {
+            power(spatial_gains(
+                &audio,
+                &Mat4::identity(),
+                &Vec3::new(0.0, 0.0, -distance),
+                1.0,
+            ))
+            .sqrt()
+        };
+        assert!(close(gain(2.0), 0.5));
+        assert!(close(gain(4.0), 0.25));
+        assert!(close(gain(10.0), 0.1));
+        assert!(close(gain(100.0), 0.1));
+    }
+
+    #[test]
+    fn sources_mix_at_their_volume_times_the_master_volume() {
+        let (mut world, _) = playing(
+            clip(2, &[0.25, 0.75, 0.25, 0.75]),
+            AudioSource {
+                volume: 0.5,
+                ..Default::default()
+            },
+        );
+        world.resources.audio.volume = 0.5;
+        update_audio_system(&mut world);
+        // Skips the ramp to the new master volume
+        render_audio(&world.resources.audio, 0);
+        assert_samples(
+            &render_audio(&world.resources.audio, 2),
+            &[0.0625, 0.1875, 0.0625, 0.1875],
+        );
+    }
+
+    #[test]
+    fn pitched_voices_interpolate_between_frames() {
+        let (world, _) = playing(
+            clip(1, &[0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]),
+            AudioSource {
+                pitch: 0.5,
+                ..Default::default()
+            },
+        );
+        assert_samples(
+            &render_audio(&world.resources.audio, 4),
+            &[0.0, 0.0, 0.5, 0.5, 1.0, 1.0, 1.5, 1.5],
+        );
+    }
+
+    #[test]
+    fn finished_clips_fall_silent_and_stop_their_source() {
+        let (mut world, entity) = playing(clip(1, &[1.0, 1.0]), AudioSource::default());
+        assert_samples(
+            &render_audio(&world.resources.audio, 4),
+            &[1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0],
+        );
+        update_audio_system(&mut world);
+        assert!(!source_mut(&mut world, entity).playing);
+        assert_samples(&render_audio(&world.resources.audio, 2), &[0.0; 4]);
+    }
+
+    #[test]
+    fn looping_clips_wrap_around() {
+        let (world, _) = playing(
+            clip(1, &[0.0, 1.0]),
+            AudioSource {
+                looping: true,
+                ..Default::default()
+            },
+        );
+        assert_samples(
+            &render_audio(&world.resources.audio, 4),
+            &[0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 1.0],
+        );
+    }
+
+    #[test]
+    fn volume_changes_ramp_over_the_next_mix() {
+        let (mut world, entity) = playing(
+            clip(1, &[1.0]),
+            AudioSource {
+                looping: true,
+                ..Default::default()
+            },
+        );
+        assert_samples(&render_audio(&world.resources.audio, 2), &[1.0; 4]);
+        source_mut(&mut world, entity).volume = 0.0;
+        update_audio_system(&mut world);
+        assert_samples(
+            &render_audio(&world.resources.audio, 4),
+            &[0.75, 0.75, 0.5, 0.5, 0.25, 0.25, 0.0, 0.0],
+        );
+    }
+
+    #[test]
+    fn spatial_sources_play_a_panned_mono_mix() {
+        let (world, _) = playing(
+            clip(2, &[1.0, 0.0]),
+            AudioSource {
+                spatial: true,
+                ..Default::default()
+            },
+        );
+        let gain = std::f32::consts::FRAC_1_SQRT_2 * 0.5;
+        assert_samples(&render_audio(&world.resources.audio, 1), &[gain, gain]);
+    }
+
+    #[test]
+    fn clones_share_their_samples() {
+        let clip = clip(1, &[0.0; 16]);
+        assert!(Arc::ptr_eq(&clip.samples, &clip.clone().samples));
+    }
+}
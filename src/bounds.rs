@@ -0,0 +1,389 @@
+use crate::{
+    asset::AssetId,
+    world::{Mesh, Ray},
+};
+use nalgebra_glm::{Mat4, Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Local bounds of each mesh asset, computed once and dropped when the mesh changes
+pub type MeshBoundsCache = HashMap<AssetId, BoundingBox>;
+
+/// An axis-aligned box. The default box is empty and encloses nothing.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BoundingBox {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl BoundingBox {
+    pub const EMPTY: Self = Self {
+        min: Vec3::new(f32::MAX, f32::MAX, f32::MAX),
+        max: Vec3::new(f32::MIN, f32::MIN, f32::MIN),
+    };
+}
+
+impl Default for BoundingBox {
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}
+
+pub fn bounds_from_points(points: impl IntoIterator<Item = Vec3>) -> BoundingBox {
+    points
+        .into_iter()
+        .fold(BoundingBox::EMPTY, |bounds, point| {
+            merge_point(&bounds, &point)
+        })
+}
+
+/// The local-space box around a mesh's vertices
+pub fn mesh_bounds(mesh: &Mesh) -> BoundingBox {
+    bounds_from_points(mesh.vertices.iter().map(|vertex| vertex.position))
+}
+
+pub fn bounds_empty(bounds: &BoundingBox) -> bool {
+    bounds.min.x > bounds.max.x || bounds.min.y > bounds.max.y || bounds.min.z > bounds.max.z
+}
+
+pub fn bounds_center(bounds: &BoundingBox) -> Vec3 {
+    (bounds.min + bounds.max) * 0.5
+}
+
+pub fn bounds_half_extents(bounds: &BoundingBox) -> Vec3 {
+    (bounds.max - bounds.min) * 0.5
+}
+
+/// The eight corners, with bit 0 of the index choosing max x, bit 1 max y and bit 2 max z
+pub fn bounds_corners(bounds: &BoundingBox) -> [Vec3; 8] {
+    std::array::from_fn(|corner| {
+        Vec3::new(
+            if corner & 1 == 0 {
+                bounds.min.x
+            } else {
+                bounds.max.x
+            },
+            if corner & 2 == 0 {
+                bounds.min.y
+            } else {
+                bounds.max.y
+            },
+            if corner & 4 == 0 {
+                bounds.min.z
+            } else {
+                bounds.max.z
+            },
+        )
+    })
+}
+
+/// The smallest box enclosing both boxes
+pub fn merge_bounds(a: &BoundingBox, b: &BoundingBox) -> BoundingBox {
+    BoundingBox {
+        min: nalgebra_glm::min2(&a.min, &b.min),
+        max: nalgebra_glm::max2(&a.max, &b.max),
+    }
+}
+
+pub fn merge_point(bounds: &BoundingBox, point: &Vec3) -> BoundingBox {
+    BoundingBox {
+        min: nalgebra_glm::min2(&bounds.min, point),
+        max: nalgebra_glm::max2(&bounds.max, point),
+    }
+}
+
+/// Whether the boxes overlap, touching counts
+pub fn bounds_intersect(a: &BoundingBox, b: &BoundingBox) -> bool {
+    !bounds_empty(a)
+        && !bounds_empty(b)
+        && (0..3).all(|axis| a.min[axis] <= b.max[axis] && b.min[axis] <= a.max[axis])
+}
+
+/// The region both boxes cover, if they overlap
+pub fn bounds_intersection(a: &BoundingBox, b: &BoundingBox) -> Option<BoundingBox> {
+    bounds_intersect(a, b).then(|| BoundingBox {
+        min: nalgebra_glm::max2(&a.min, &b.min),
+        max: nalgebra_glm::min2(&a.max, &b.max),
+    })
+}
+
+pub fn bounds_contain_point(bounds: &BoundingBox, point: &Vec3) -> bool {
+    (0..3).all(|axis| bounds.min[axis] <= point[axis] && point[axis] <= bounds.max[axis])
+}
+
+/// Whether `inner` lies entirely within `outer`. Empty boxes contain nothing.
+pub fn bounds_contain(outer: &BoundingBox, inner: &BoundingBox) -> bool {
+    !bounds_empty(inner)
+        && bounds_contain_point(outer, &inner.min)
+        && bounds_contain_point(outer, &inner.max)
+}
+
+/// The axis-aligned box around a transformed box. Each output axis sums the
+/// extremes of the matrix entries scaled by the box, so rotation, non-uniform
+/// scale and shear are all enclosed tightly.
+pub fn transform_bounds(bounds: &BoundingBox, transform: &Mat4) -> BoundingBox {
+    if bounds_empty(bounds) {
+        return BoundingBox::EMPTY;
+    }
+    let translation = transform.column(3).xyz();
+    let mut min = translation;
+    let mut max = translation;
+    for row in 0..3 {
+        for column in 0..3 {
+            let a = transform[(row, column)] * bounds.min[column];
+            let b = transform[(row, column)] * bounds.max[column];
+            min[row] += a.min(b);
+            max[row] += a.max(b);
+        }
+    }
+    BoundingBox { min, max }
+}
+
+/// Distance along the ray to where it enters the box, zero when it starts inside
+pub fn ray_bounds_intersection(ray: &Ray, bounds: &BoundingBox) -> Option<f32> {
+    if bounds_empty(bounds) {
+        return None;
+    }
+    let mut near = 0.0_f32;
+    let mut far = f32::MAX;
+    for axis in 0..3 {
+        let origin = ray.origin[axis];
+        let direction = ray.direction[axis];
+        if direction.abs() < f32::EPSILON {
+            if origin < bounds.min[axis] || origin > bounds.max[axis] {
+                return None;
+            }
+            continue;
+        }
+        let inverse = 1.0 / direction;
+        let (t0, t1) = (
+            (bounds.min[axis] - origin) * inverse,
+            (bounds.max[axis] - origin) * inverse,
+        );
+        near = near.max(t0.min(t1));
+        far = far.min(t0.max(t1));
+        if near > far {
+            return None;
+        }
+    }
+    Some(near)
+}
+
+/// Squared distance from a point to the nearest point of the box, zero inside it
+pub fn bounds_distance_squared(bounds: &BoundingBox, point: &Vec3) -> f32 {
+    if bounds_empty(bounds) {
+        return f32::MAX;
+    }
+    let closest = nalgebra_glm::clamp_vec(point, &bounds.min, &bounds.max);
+    nalgebra_glm::distance2(&closest, point)
+}
+
+/// The planes bounding a view-projection's clip volume, pointing inward
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Frustum {
+    /// Left, right, bottom, top, near and far, as (normal, distance)
+    pub planes: [Vec4; 6],
+}
+
+/// Extracts the frustum of a view-projection matrix with a 0 to 1 depth range
+pub fn frustum_from_matrix(view_projection: &Mat4) -> Frustum {
+    let row = |index: usize| view_projection.row(index).transpose();
+    let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+    Frustum {
+        planes: [w + x, w - x, w + y, w - y, z, w - z],
+    }
+}
+
+/// Whether any part of the box may be inside the frustum. Boxes near corners
+/// can pass without overlapping, which is fine for culling.
+pub fn bounds_in_frustum(frustum: &Frustum, bounds: &BoundingBox) -> bool {
+    if bounds_empty(bounds) {
+        return false;
+    }
+    frustum.planes.iter().all(|plane| {
+        // The corner furthest along the plane normal
+        let corner = Vec3::new(
+            if plane.x >= 0.0 {
+                bounds.max.x
+            } else {
+                bounds.min.x
+            },
+            if plane.y >= 0.0 {
+                bounds.max.y
+            } else {
+                bounds.min.y
+            },
+            if plane.z >= 0.0 {
+                bounds.max.z
+            } else {
+                bounds.min.z
+            },
+        );
+        plane.xyz().dot(&corner) + plane.w >= 0.0
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box() -> BoundingBox {
+        BoundingBox {
+            min: Vec3::new(-1.0, -1.0, -1.0),
+            max: Vec3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    fn close(a: &BoundingBox, b: &BoundingBox) -> bool {
+        (a.min - b.min).norm() < 1e-5 && (a.max - b.max).norm() < 1e-5
+    }
+
+    fn ray(origin: Vec3, direction: Vec3) -> Ray {
+        Ray {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    #[test]
+    fn points_and_meshes_give_enclosing_boxes() {
+        let bounds = bounds_from_points([Vec3::new(1.0, -2.0, 3.0), Vec3::new(-1.0, 2.0, 0.0)]);
+        assert_eq!(bounds.min, Vec3::new(-1.0, -2.0, 0.0));
+        assert_eq!(bounds.max, Vec3::new(1.0, 2.0, 3.0));
+        assert!(bounds_empty(&bounds_from_points([])));
+        assert!(bounds_empty(&BoundingBox::default()));
+        assert_eq!(bounds_center(&bounds), Vec3::new(0.0, 0.0, 1.5));
+        assert_eq!(bounds_half_extents(&bounds), Vec3::new(1.0, 2.0, 1.5));
+        let corners = bounds_corners(&bounds);
+        assert_eq!(corners[0], bounds.min);
+        assert_eq!(corners[7], bounds.max);
+        assert_eq!(corners[2], Vec3::new(-1.0, 2.0, 0.0));
+    }
+
+    #[test]
+    fn merging_encloses_both_and_ignores_empty_boxes() {
+        let a = unit_box();
+        let b = BoundingBox {
+            min: Vec3::new(0.0, 2.0, -3.0),
+            max: Vec3::new(4.0, 3.0, 0.0),
+        };
+        let merged = merge_bounds(&a, &b);
+        assert_eq!(merged.min, Vec3::new(-1.0, -1.0, -3.0));
+        assert_eq!(merged.max, Vec3::new(4.0, 3.0, 1.0));
+        assert!(bounds_contain(&merged, &a) && bounds_contain(&merged, &b));
+        assert_eq!(merge_bounds(&a, &BoundingBox::EMPTY), a);
+        assert_eq!(merge_point(&BoundingBox::EMPTY, &Vec3::x()).min, Vec3::x());
+        assert!(!bounds_contain(&merged, &BoundingBox::EMPTY));
+    }
+
+    #[test]
+    fn intersections_cover_the_overlap() {
+        let a = unit_box();
+        let b = BoundingBox {
+            min: Vec3::new(0.5, 0.5, 0.5),
+            max: Vec3::new(2.0, 2.0, 2.0),
+        };
+        let overlap = bounds_intersection(&a, &b).expect("The boxes overlap!");
+        assert_eq!(overlap.min, b.min);
+        assert_eq!(overlap.max, a.max);
+
+        // Touching faces count, separated boxes and empty boxes do not
+        let touching = BoundingBox {
+            min: Vec3::new(1.0, -1.0, -1.0),
+            max: Vec3::new(2.0, 1.0, 1.0),
+        };
+        assert!(bounds_intersect(&a, &touching));
+        let apart = BoundingBox {
+            min: Vec3::new(1.5, 0.0, 0.0),
+            max: Vec3::new(2.0, 1.0, 1.0),
+        };
+        assert!(bounds_intersection(&a, &apart).is_none());
+        assert!(!bounds_intersect(&a, &BoundingBox::EMPTY));
+    }
+
+    #[test]
+    fn transformed_boxes_enclose_the_transformed_corners() {
+        let bounds = BoundingBox {
+            min: Vec3::new(0.0, -1.0, -2.0),
+            max: Vec3::new(1.0, 1.0, 2.0),
+        };
+        let transforms = [
+            Mat4::identity(),
+            nalgebra_glm::translation(&Vec3::new(5.0, -3.0, 1.0)),
+            nalgebra_glm::rotation(0.7, &Vec3::new(1.0, 2.0, 3.0).normalize()),
+            nalgebra_glm::scaling(&Vec3::new(2.0, -1.0, 0.5)),
+            nalgebra_glm::translation(&Vec3::new(1.0, 2.0, 3.0))
+                * nalgebra_glm::rotation(-1.2, &Vec3::y())
+                * nalgebra_glm::scaling(&Vec3::new(3.0, 1.0, 2.0)),
+        ];
+        for transform in transforms {
+            let expected = bounds_from_points(
+                bounds_corners(&bounds).map(|corner| (transform * corner.push(1.0)).xyz()),
+            );
+            assert!(close(&transform_bounds(&bounds, &transform), &expected));
+        }
+        assert!(bounds_empty(&transform_bounds(
+            &BoundingBox::EMPTY,
+            &nalgebra_glm::translation(&Vec3::x())
+        )));
+    }
+
+    #[test]
+    fn rays_report_the_entry_distance() {
+        let bounds = unit_box();
+        let hit = ray(Vec3::new(-5.0, 0.0, 0.0), Vec3::x());
+        assert_eq!(ray_bounds_intersection(&hit, &bounds), Some(4.0));
+
+        let diagonal = ray(Vec3::new(-3.0, -3.0, 0.0), Vec3::new(1.0, 1.0, 0.0));
+        let distance = ray_bounds_intersection(&diagonal, &bounds).expect("The ray hits!");
+        assert!((distance - 2.0 * 2.0_f32.sqrt()).abs() < 1e-5);
+
+        let inside = ray(Vec3::zeros(), Vec3::new(0.3, -0.2, 1.0));
+        assert_eq!(ray_bounds_intersection(&inside, &bounds), Some(0.0));
+
+        let behind = ray(Vec3::new(5.0, 0.0, 0.0), Vec3::x());
+        assert_eq!(ray_bounds_intersection(&behind, &bounds), None);
+        let beside = ray(Vec3::new(-5.0, 2.0, 0.0), Vec3::x());
+        assert_eq!(ray_bounds_intersection(&beside, &bounds), None);
+        let missing = ray(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 0.0));
+        assert_eq!(ray_bounds_intersection(&missing, &bounds), None);
+        assert_eq!(ray_bounds_intersection(&hit, &BoundingBox::EMPTY), None);
+    }
+
+    #[test]
+    fn distances_are_zero_inside() {
+        let bounds = unit_box();
+        assert_eq!(
+            bounds_distance_squared(&bounds, &Vec3::new(0.5, 0.0, 0.0)),
+            0.0
+        );
+        assert_eq!(
+            bounds_distance_squared(&bounds, &Vec3::new(3.0, 0.0, 0.0)),
+            4.0
+        );
+        assert_eq!(
+            bounds_distance_squared(&bounds, &Vec3::new(2.0, 2.0, 1.0)),
+            2.0
+        );
+    }
+
+    #[test]
+    fn frustum_culls_boxes_outside_the_view() {
+        let projection = nalgebra_glm::perspective_zo(1.0, 90_f32.to_radians(), 0.1, 100.0);
+        let view = nalgebra_glm::look_at(&Vec3::zeros(), &-Vec3::z(), &Vec3::y());
+        let frustum = frustum_from_matrix(&(projection * view));
+        let at = |center: Vec3| BoundingBox {
+            min: center - Vec3::repeat(0.5),
+            max: center + Vec3::repeat(0.5),
+        };
+        assert!(bounds_in_frustum(&frustum, &at(Vec3::new(0.0, 0.0, -5.0))));
+        assert!(bounds_in_frustum(&frustum, &at(Vec3::new(5.2, 0.0, -5.0))));
+        assert!(!bounds_in_frustum(&frustum, &at(Vec3::new(0.0, 0.0, 5.0))));
+        assert!(!bounds_in_frustum(&frustum, &at(Vec3::new(7.0, 0.0, -5.0))));
+        assert!(!bounds_in_frustum(
+            &frustum,
+            &at(Vec3::new(0.0, 0.0, -200.0))
+        ));
+        assert!(!bounds_in_frustum(&frustum, &BoundingBox::EMPTY));
+    }
+}
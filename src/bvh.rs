@@ -0,0 +1,737 @@
+use crate::{
+    bounds::{
+        bounds_contain, bounds_distance_squared, bounds_empty, bounds_in_frustum, bounds_intersect,
+        merge_bounds, ray_bounds_intersection, BoundingBox, Frustum,
+    },
+    world::{get_component, query_entities, EntityId, Ray, World, WorldBounds, WORLD_BOUNDS},
+};
+use nalgebra_glm::Vec3;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+/// How far leaf bounds are grown past the entity's bounds by default,
+/// so small movements refit nothing
+pub const DEFAULT_BVH_MARGIN: f32 = 0.1;
+
+/// A dynamic bounding volume hierarchy over entity world bounds.
+/// Leaves store bounds grown by a margin, and an entity is only
+/// reinserted once its bounds leave its leaf.
+#[derive(Serialize, Deserialize)]
+pub struct Bvh {
+    pub margin: f32,
+    nodes: Vec<BvhNode>,
+    free_nodes: Vec<usize>,
+    root: Option<usize>,
+    leaves: HashMap<EntityId, usize>,
+}
+
+impl Default for Bvh {
+    fn default() -> Self {
+        Self {
+            margin: DEFAULT_BVH_MARGIN,
+            nodes: Vec::new(),
+            free_nodes: Vec::new(),
+            root: None,
+            leaves: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+struct BvhNode {
+    /// Encloses the children, or the entity's bounds plus the margin for leaves
+    bounds: BoundingBox,
+    parent: Option<usize>,
+    children: Option<[usize; 2]>,
+    /// The entity and its exact bounds, for leaves
+    leaf: Option<(EntityId, BoundingBox)>,
+    height: usize,
+}
+
+/// An entity hit by a ray, at a distance along it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BvhHit {
+    pub entity: EntityId,
+    pub distance: f32,
+}
+
+/// Inserts, moves or removes leaves so the tree matches every entity's `WorldBounds`
+pub fn update_bvh_system(world: &mut World) {
+    let entities = query_entities(world, WORLD_BOUNDS);
+    let mut bvh = std::mem::take(&mut world.resources.bvh);
+    let mut present = HashSet::with_capacity(entities.len());
+    for entity in entities {
+        let Some(bounds) = get_component::<WorldBounds>(world, entity, WORLD_BOUNDS) else {
+            continue;
+        };
+        if bounds_empty(bounds) {
+            continue;
+        }
+        present.insert(entity);
+        bvh_update(&mut bvh, entity, *bounds);
+    }
+    let stale = bvh
+        .leaves
+        .keys()
+        .filter(|entity| !present.contains(entity))
+        .copied()
+        .collect::<Vec<_>>();
+    for entity in stale {
+        bvh_remove(&mut bvh, entity);
+    }
+    world.resources.bvh = bvh;
+}
+
+/// Adds or moves an entity, reinserting it only when its bounds left its leaf.
+/// Returns true when the tree changed.
+pub fn bvh_update(bvh: &mut Bvh, entity: EntityId, bounds: BoundingBox) -> bool {
+    if let Some(leaf) = bvh.leaves.get(&entity).copied() {
+        let node = &mut bvh.nodes[leaf];
+        if bounds_contain(&node.bounds, &bounds) {
+            node.leaf = Some((entity, bounds));
+            return false;
+        }
+        bvh_remove(bvh, entity);
+    }
+    bvh_insert(bvh, entity, bounds);
+    true
+}
+
+pub fn bvh_insert(bvh: &mut Bvh, entity: EntityId, bounds: BoundingBox) {
+    if bvh.leaves.contains_key(&entity) {
+        bvh_remove(bvh, entity);
+    }
+    let margin = Vec3::repeat(bvh.margin);
+    let leaf = allocate_node(
+        bvh,
+        BvhNode {
+            bounds: BoundingBox {
+                min: bounds.min - margin,
+                max: bounds.max + margin,
+            },
+            leaf: Some((entity, bounds)),
+            ..Default::default()
+        },
+    );
+    insert_leaf(bvh, leaf);
+    bvh.leaves.insert(entity, leaf);
+}
+
+/// Returns true when the entity was in the tree
+pub fn bvh_remove(bvh: &mut Bvh, entity: EntityId) -> bool {
+    let Some(leaf) = bvh.leaves.remove(&entity) else {
+        return false;
+    };
+    remove_leaf(bvh, leaf);
+    bvh.nodes[leaf] = BvhNode::default();
+    bvh.free_nodes.push(leaf);
+    true
+}
+
+pub fn bvh_contains(bvh: &Bvh, entity: EntityId) -> bool {
+    bvh.leaves.contains_key(&entity)
+}
+
+pub fn bvh_len(bvh: &Bvh) -> usize {
+    bvh.leaves.len()
+}
+
+/// The longest path from the root to a leaf, zero for an empty tree
+pub fn bvh_height(bvh: &Bvh) -> usize {
+    bvh.root.map_or(0, |root| bvh.nodes[root].height + 1)
+}
+
+/// Every entity whose bounds the ray passes through within `max_distance`, nearest first
+pub fn bvh_ray_cast(bvh: &Bvh, ray: &Ray, max_distance: f32) -> Vec<BvhHit> {
+    let mut hits = Vec::new();
+    visit(
+        bvh,
+        |bounds| ray_bounds_intersection(ray, bounds).is_some_and(|t| t <= max_distance),
+        |entity, bounds| {
+            if let Some(distance) =
+                ray_bounds_intersection(ray, &bounds).filter(|t| *t <= max_distance)
+            {
+                hits.push(BvhHit { entity, distance });
+            }
+        },
+    );
+    hits.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+    hits
+}
+
+/// The nearest entity whose bounds the ray passes through within `max_distance`
+pub fn bvh_ray_cast_nearest(bvh: &Bvh, ray: &Ray, max_distance: f32) -> Option<BvhHit> {
+    let root = bvh.root?;
+    let mut nearest: Option<BvhHit> = None;
+    let mut heap = BinaryHeap::new();
+    if let Some(distance) = ray_bounds_intersection(ray, &bvh.nodes[root].bounds) {
+        heap.push(Reverse(Candidate {
+            distance,
+            node: root,
+        }));
+    }
+    while let Some(Reverse(Candidate { distance, node })) = heap.pop() {
+        let limit = nearest.map_or(max_distance, |hit| hit.distance);
+        if distance > limit {
+            break;
+        }
+        let node = &bvh.nodes[node];
+        if let Some((entity, bounds)) = node.leaf {
+            if let Some(distance) = ray_bounds_intersection(ray, &bounds).filter(|t| *t <= limit) {
+                nearest = Some(BvhHit { entity, distance });
+            }
+            continue;
+        }
+        for child in node.children.into_iter().flatten() {
+            if let Some(distance) = ray_bounds_intersection(ray, &bvh.nodes[child].bounds) {
+                heap.push(Reverse(Candidate {
+                    distance,
+                    node: child,
+                }));
+            }
+        }
+    }
+    nearest
+}
+
+/// Entities whose bounds overlap the box
+pub fn bvh_query_bounds(bvh: &Bvh, bounds: &BoundingBox) -> Vec<EntityId> {
+    let mut entities = Vec::new();
+    visit(
+        bvh,
+        |node_bounds| bounds_intersect(node_bounds, bounds),
+        |entity, entity_bounds| {
+            if bounds_intersect(&entity_bounds, bounds) {
+                entities.push(entity);
+            }
+        },
+    );
+    entities
+}
+
+/// Entities whose bounds come within `radius` of `center`
+pub fn bvh_query_sphere(bvh: &Bvh, center: &Vec3, radius: f32) -> Vec<EntityId> {
+    let radius_squared = radius * radius;
+    let mut entities = Vec::new();
+    visit(
+        bvh,
+        |bounds| bounds_distance_squared(bounds, center) <= radius_squared,
+        |entity, bounds| {
+            if bounds_distance_squared(&bounds, center) <= radius_squared {
+                entities.push(entity);
+            }
+        },
+    );
+    entities
+}
+
+/// Entities whose bounds may be visible in the frustum
+pub fn bvh_query_frustum(bvh: &Bvh, frustum: &Frustum) -> Vec<EntityId> {
+    let mut entities = Vec::new();
+    visit(
+        bvh,
+        |bounds| bounds_in_frustum(frustum, bounds),
+        |entity, bounds| {
+            if bounds_in_frustum(frustum, &bounds) {
+                entities.push(entity);
+            }
+        },
+    );
+    entities
+}
+
+/// The `count` entities with bounds nearest to the point, nearest first,
+/// with their distances. Points inside bounds are at distance zero.
+pub fn bvh_nearest(bvh: &Bvh, point: &Vec3, count: usize) -> Vec<(EntityId, f32)> {
+    let mut nearest = Vec::with_capacity(count);
+    let Some(root) = bvh.root.filter(|_| count > 0) else {
+        return nearest;
+    };
+    // Leaves are queued twice: first by their grown bounds, then by their exact bounds
+    let mut heap = BinaryHeap::new();
+    heap.push(Reverse((
+        Candidate {
+            distance: bounds_distance_squared(&bvh.nodes[root].bounds, point),
+            node: root,
+        },
+        false,
+    )));
+    while let Some(Reverse((Candidate { distance, node }, exact))) = heap.pop() {
+        let bvh_node = &bvh.nodes[node];
+        match (bvh_node.leaf, exact) {
+            (Some((entity, _)), true) => {
+                nearest.push((entity, distance.sqrt()));
+                if nearest.len() == count {
+                    break;
+                }
+            }
+            (Some((_, bounds)), false) => heap.push(Reverse((
+                Candidate {
+                    distance: bounds_distance_squared(&bounds, point),
+                    node,
+                },
+                true,
+            ))),
+            (None, _) => {
+                for child in bvh_node.children.into_iter().flatten() {
+                    heap.push(Reverse((
+                        Candidate {
+                            distance: bounds_distance_squared(&bvh.nodes[child].bounds, point),
+                            node: child,
+                        },
+                        false,
+                    )));
+                }
+            }
+        }
+    }
+    nearest
+}
+
+/// A node ordered by a distance, for best-first searches
+#[derive(Copy, Clone, PartialEq)]
+struct Candidate {
+    distance: f32,
+    node: usize,
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then(self.node.cmp(&other.node))
+    }
+}
+
+/// Walks the nodes whose bounds pass the test, calling `leaf` with each leaf's exact bounds
+fn visit(
+    bvh: &Bvh,
+    mut test: impl FnMut(&BoundingBox) -> bool,
+    mut leaf: impl FnMut(EntityId, BoundingBox),
+) {
+    let mut stack = bvh.root.into_iter().collect::<Vec<_>>();
+    while let Some(index) = stack.pop() {
+        let node = &bvh.nodes[index];
+        if !test(&node.bounds) {
+            continue;
+        }
+        if let Some((entity, bounds)) = node.leaf {
+            leaf(entity, bounds);
+        }
+        stack.extend(node.children.into_iter().flatten());
+    }
+}
+
+fn allocate_node(bvh: &mut Bvh, node: BvhNode) -> usize {
+    match bvh.free_nodes.pop() {
+        Some(index) => {
+            bvh.nodes[index] = node;
+            index
+        }
+        None => {
+            bvh.nodes.push(node);
+            bvh.nodes.len() - 1
+        }
+    }
+}
+
+fn surface_area(bounds: &BoundingBox) -> f32 {
+    let size = bounds.max - bounds.min;
+    2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+}
+
+/// Places a leaf beside the sibling that grows the tree's surface area least
+fn insert_leaf(bvh: &mut Bvh, leaf: usize) {
+    let Some(root) = bvh.root else {
+        bvh.root = Some(leaf);
+        bvh.nodes[leaf].parent = None;
+        return;
+    };
+
+    let leaf_bounds = bvh.nodes[leaf].bounds;
+    let mut index = root;
+    while let Some([left, right]) = bvh.nodes[index].children {
+        let area = surface_area(&bvh.nodes[index].bounds);
+        let combined_area = surface_area(&merge_bounds(&bvh.nodes[index].bounds, &leaf_bounds));
+        // Pairing with this node makes a new parent, and descending grows this node anyway
+        let cost = 2.0 * combined_area;
+        let inheritance_cost = 2.0 * (combined_area - area);
+        let child_cost = |child: usize| {
+            let child_bounds = &bvh.nodes[child].bounds;
+            let merged = surface_area(&merge_bounds(child_bounds, &leaf_bounds));
+            let growth = if bvh.nodes[child].leaf.is_some() {
+                merged
+            } else {
+                merged - surface_area(child_bounds)
+            };
+            growth + inheritance_cost
+        };
+        let (left_cost, right_cost) = (child_cost(left), child_cost(right));
+        if cost < left_cost && cost < right_cost {
+            break;
+        }
+        index = if left_cost < right_cost { left } else { right };
+    }
+
+    let sibling = index;
+    let old_parent = bvh.nodes[sibling].parent;
+    let new_parent = allocate_node(
+        bvh,
+        BvhNode {
+            bounds: merge_bounds(&leaf_bounds, &bvh.nodes[sibling].bounds),
+            parent: old_parent,
+            children: Some([sibling, leaf]),
+            leaf: None,
+            height: bvh.nodes[sibling].height + 1,
+        },
+    );
+    match old_parent {
+        Some(old_parent) => replace_child(bvh, old_parent, sibling, new_parent),
+        None => bvh.root = Some(new_parent),
+    }
+    bvh.nodes[sibling].parent = Some(new_parent);
+    bvh.nodes[leaf].parent = Some(new_parent);
+    refit_ancestors(bvh, Some(new_parent));
+}
+
+fn remove_leaf(bvh: &mut Bvh, leaf: usize) {
+    let Some(parent) = bvh.nodes[leaf].parent else {
+        bvh.root = None;
+        return;
+    };
+    let grandparent = bvh.nodes[parent].parent;
+    let sibling = match bvh.nodes[parent].children {
+        Some([left, right]) if left == leaf => right,
+        Some([left, _]) => left,
+        None => unreachable!("Leaf parents always have children!"),
+    };
+    bvh.nodes[sibling].parent = grandparent;
+    match grandparent {
+        Some(grandparent) => replace_child(bvh, grandparent, parent, sibling),
+        None => bvh.root = Some(sibling),
+    }
+    bvh.nodes[parent] = BvhNode::default();
+    bvh.free_nodes.push(parent);
+    bvh.nodes[leaf].parent = None;
+    refit_ancestors(bvh, grandparent);
+}
+
+fn replace_child(bvh: &mut Bvh, parent: usize, old_child: usize, new_child: usize) {
+    if let Some(children) = bvh.nodes[parent].children.as_mut() {
+        for child in children.iter_mut().filter(|child| **child == old_child) {
+            *child = new_child;
+        }
+    }
+}
+
+/// Rebalances and recomputes bounds and heights from a node up to the root
+fn refit_ancestors(bvh: &mut Bvh, start: Option<usize>) {
+    let mut current = start;
+    while let Some(index) = current {
+        let index = balance(bvh, index);
+        let [left, right] = bvh.nodes[index]
+            .children
+            .expect("Ancestors always have children!");
+        let node_bounds = merge_bounds(&bvh.nodes[left].bounds, &bvh.nodes[right].bounds);
+        let height = 1 + bvh.nodes[left].height.max(bvh.nodes[right].height);
+        let node = &mut bvh.nodes[index];
+        node.bounds = node_bounds;
+        node.height = height;
+        current = bvh.nodes[index].parent;
+    }
+}
+
+/// Rotates the taller child up when the node's subtrees differ in height by more than one,
+/// returning the node now at this position
+fn balance(bvh: &mut Bvh, a: usize) -> usize {
+    let Some([b, c]) = bvh.nodes[a].children else {
+        return a;
+    };
+    if bvh.nodes[a].height < 2 {
+        return a;
+    }
+    let (b_height, c_height) = (bvh.nodes[b].height, bvh.nodes[c].height);
+    if c_height > b_height + 1 {
+        rotate_up(bvh, a, c, b)
+    } else if b_height > c_height + 1 {
+        rotate_up(bvh, a, b, c)
+    } else {
+        a
+    }
+}
+
+/// Promotes `tall`, a child of `a`, into `a`'s place. `a` keeps `short`
+/// and takes whichever of `tall`'s children is shorter.
+fn rotate_up(bvh: &mut Bvh, a: usize, tall: usize, short: usize) -> usize {
+    let Some([f, g]) = bvh.nodes[tall].children else {
+        return a;
+    };
+    let a_parent = bvh.nodes[a].parent;
+    bvh.nodes[tall].parent = a_parent;
+    bvh.nodes[a].parent = Some(tall);
+    match a_parent {
+        Some(parent) => replace_child(bvh, parent, a, tall),
+        None => bvh.root = Some(tall),
+    }
+
+    let (keep, give) = if bvh.nodes[f].height > bvh.nodes[g].height {
+        (f, g)
+    } else {
+        (g, f)
+    };
+    bvh.nodes[tall].children = Some([a, keep]);
+    bvh.nodes[a].children = Some([short, give]);
+    bvh.nodes[give].parent = Some(a);
+
+    bvh.nodes[a].bounds = merge_bounds(&bvh.nodes[short].bounds, &bvh.nodes[give].bounds);
+    bvh.nodes[a].height = 1 + bvh.nodes[short].height.max(bvh.nodes[give].height);
+    bvh.nodes[tall].bounds = merge_bounds(&bvh.nodes[a].bounds, &bvh.nodes[keep].bounds);
+    bvh.nodes[tall].height = 1 + bvh.nodes[a].height.max(bvh.nodes[keep].height);
+    tall
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bounds::frustum_from_matrix;
+
+    /// Uniform values in 0..1, the same sequence on every run
+    fn random_source(mut seed: u32) -> impl FnMut() -> f32 {
+        move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as f32 / u32::MAX as f32
+        }
+    }
+
+    fn entity(id: u32) -> EntityId {
+        EntityId { id, generation: 0 }
+    }
+
+    /// A box of random size somewhere in a 100 unit cube
+    fn random_bounds(random: &mut impl FnMut() -> f32) -> BoundingBox {
+        let center = Vec3::new(random(), random(), random()) * 100.0 - Vec3::repeat(50.0);
+        let half_extent = Vec3::new(random(), random(), random()) * 2.0 + Vec3::repeat(0.1);
+        BoundingBox {
+            min: center - half_extent,
+            max: center + half_extent,
+        }
+    }
+
+    /// Checks that parents enclose their children, links agree both ways,
+    /// heights are correct and every leaf is reachable exactly once
+    fn assert_valid(bvh: &Bvh, expected: &HashMap<EntityId, BoundingBox>) {
+        assert_eq!(bvh_len(bvh), expected.len());
+        let Some(root) = bvh.root else {
+            assert!(expected.is_empty());
+            return;
+        };
+        assert_eq!(bvh.nodes[root].parent, None);
+        let mut reachable = 0;
+        let mut stack = vec![root];
+        while let Some(index) = stack.pop() {
+            reachable += 1;
+            let node = &bvh.nodes[index];
+            match (node.children, node.leaf) {
+                (None, Some((entity, bounds))) => {
+                    assert_eq!(bvh.leaves[&entity], index);
+                    assert_eq!(expected[&entity], bounds);
+                    assert!(bounds_contain(&node.bounds, &bounds));
+                    assert_eq!(node.height, 0);
+                }
+                (Some(children), None) => {
+                    for child in children {
+                        assert_eq!(bvh.nodes[child].parent, Some(index));
+                        assert!(bounds_contain(&node.bounds, &bvh.nodes[child].bounds));
+                    }
+                    let [left, right] = children.map(|child| bvh.nodes[child].height);
+                    assert_eq!(node.height, 1 + left.max(right));
+                    stack.extend(children);
+                }
+                _ => panic!("Node {index} must be either a leaf or a branch!"),
+            }
+        }
+        assert_eq!(reachable + bvh.free_nodes.len(), bvh.nodes.len());
+        assert_eq!(reachable, 2 * expected.len() - 1);
+    }
+
+    fn assert_same_entities(actual: Vec<EntityId>, expected: Vec<EntityId>) {
+        assert_eq!(actual.len(), expected.len());
+        assert_eq!(
+            actual.into_iter().collect::<HashSet<_>>(),
+            expected.into_iter().collect::<HashSet<_>>()
+        );
+    }
+
+    /// Compares every query against a linear scan of the expected bounds
+    fn assert_queries_match(
+        bvh: &Bvh,
+        expected: &HashMap<EntityId, BoundingBox>,
+        random: &mut impl FnMut() -> f32,
+    ) {
+        let scan = |keep: &dyn Fn(&BoundingBox) -> bool| {
+            expected
+                .iter()
+                .filter(|(_, bounds)| keep(bounds))
+                .map(|(entity, _)| *entity)
+                .collect::<Vec<_>>()
+        };
+
+        let region = random_bounds(random);
+        let region = BoundingBox {
+            min: region.min - Vec3::repeat(10.0),
+            max: region.max + Vec3::repeat(10.0),
+        };
+        assert_same_entities(
+            bvh_query_bounds(bvh, &region),
+            scan(&|bounds| bounds_intersect(bounds, &region)),
+        );
+
+        let center = Vec3::new(random(), random(), random()) * 100.0 - Vec3::repeat(50.0);
+        let radius = random() * 20.0;
+        assert_same_entities(
+            bvh_query_sphere(bvh, &center, radius),
+            scan(&|bounds| bounds_distance_squared(bounds, &center) <= radius * radius),
+        );
+
+        let projection = nalgebra_glm::perspective_zo(1.5, 1.0, 0.1, 60.0);
+        let view = nalgebra_glm::look_at(&center, &Vec3::zeros(), &Vec3::y());
+        let frustum = frustum_from_matrix(&(projection * view));
+        assert_same_entities(
+            bvh_query_frustum(bvh, &frustum),
+            scan(&|bounds| bounds_in_frustum(&frustum, bounds)),
+        );
+
+        let ray = Ray {
+            origin: center,
+            direction: (Vec3::new(random(), random(), random()) - Vec3::repeat(0.5)).normalize(),
+        };
+        let max_distance = 80.0;
+        let mut linear_hits = expected
+            .iter()
+            .filter_map(|(entity, bounds)| {
+                let distance = ray_bounds_intersection(&ray, bounds)?;
+                (distance <= max_distance).then_some((*entity, distance))
+            })
+            .collect::<Vec<_>>();
+        linear_hits.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let hits = bvh_ray_cast(bvh, &ray, max_distance);
+        assert_same_entities(
+            hits.iter().map(|hit| hit.entity).collect(),
+            linear_hits.iter().map(|(entity, _)| *entity).collect(),
+        );
+        assert!(hits
+            .windows(2)
+            .all(|pair| pair[0].distance <= pair[1].distance));
+        assert_eq!(
+            bvh_ray_cast_nearest(bvh, &ray, max_distance).map(|hit| hit.distance),
+            linear_hits.first().map(|(_, distance)| *distance)
+        );
+
+        let count = 8;
+        let mut linear_distances = expected
+            .values()
+            .map(|bounds| bounds_distance_squared(bounds, &center).sqrt())
+            .collect::<Vec<_>>();
+        linear_distances.sort_by(f32::total_cmp);
+        linear_distances.truncate(count);
+        let nearest = bvh_nearest(bvh, &center, count);
+        // Ties may come back in either order, so the distances are compared
+        let distances = nearest
+            .iter()
+            .map(|(_, distance)| *distance)
+            .collect::<Vec<_>>();
+        assert_eq!(distances, linear_distances);
+        for (entity, distance) in nearest {
+            assert_eq!(
+                bounds_distance_squared(&expected[&entity], &center).sqrt(),
+                distance
+            );
+        }
+    }
+
+    #[test]
+    fn random_edits_keep_the_tree_valid_and_queries_exact() {
+        let mut random = random_source(0x2545_f491);
+        let mut bvh = Bvh::default();
+        let mut expected = HashMap::new();
+
+        for id in 0..300 {
+            let bounds = random_bounds(&mut random);
+            bvh_insert(&mut bvh, entity(id), bounds);
+            expected.insert(entity(id), bounds);
+            assert_valid(&bvh, &expected);
+        }
+        assert_queries_match(&bvh, &expected, &mut random);
+
+        for step in 0..300 {
+            let moved = entity((random() * 299.0) as u32);
+            let bounds = expected[&moved];
+            // Alternate small moves that stay in the leaf with jumps that reinsert it
+            let bounds = if step % 2 == 0 {
+                let offset = Vec3::repeat(bvh.margin * 0.5);
+                BoundingBox {
+                    min: bounds.min + offset,
+                    max: bounds.max + offset,
+                }
+            } else {
+                random_bounds(&mut random)
+            };
+            bvh_update(&mut bvh, moved, bounds);
+            expected.insert(moved, bounds);
+            assert_valid(&bvh, &expected);
+        }
+        assert_queries_match(&bvh, &expected, &mut random);
+
+        for id in (0..300).step_by(3) {
+            assert!(bvh_remove(&mut bvh, entity(id)));
+            expected.remove(&entity(id));
+            assert_valid(&bvh, &expected);
+        }
+        assert!(!bvh_remove(&mut bvh, entity(0)));
+        for _ in 0..20 {
+            assert_queries_match(&bvh, &expected, &mut random);
+        }
+
+        for id in 0..300 {
+            bvh_remove(&mut bvh, entity(id));
+        }
+        expected.clear();
+        assert_valid(&bvh, &expected);
+        assert_eq!(bvh_height(&bvh), 0);
+        assert!(bvh_nearest(&bvh, &Vec3::zeros(), 4).is_empty());
+    }
+
+    #[test]
+    fn rebalancing_keeps_sorted_inserts_shallow() {
+        let mut bvh = Bvh::default();
+        let mut expected = HashMap::new();
+        for id in 0..1024 {
+            let min = Vec3::new(id as f32 * 2.0, 0.0, 0.0);
+            let bounds = BoundingBox {
+                min,
+                max: min + Vec3::repeat(1.0),
+            };
+            bvh_insert(&mut bvh, entity(id), bounds);
+            expected.insert(entity(id), bounds);
+        }
+        assert_valid(&bvh, &expected);
+        assert!(
+            bvh_height(&bvh) <= 2 * 10 + 1,
+            "Height {}",
+            bvh_height(&bvh)
+        );
+    }
+}
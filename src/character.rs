@@ -0,0 +1,534 @@
+use crate::{
+    input::{
+        action_pressed, action_value, Action, AxisBinding, Button, ButtonBinding, GamepadButton,
+        GamepadStick, Input, InputMap, Modifiers,
+    },
+    physics::move_character,
+    world::{
+        get_component, get_component_mut, parent_global_transform, query_entities, LocalTransform,
+        Player, World, CHARACTER_CONTROLLER, LOCAL_TRANSFORM, PLAYER,
+    },
+};
+use nalgebra_glm::{Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use winit::keyboard::KeyCode;
+
+/// Moves a `Player` entity as an upright capsule centered on its origin. The
+/// controller walks relative to the entity's facing and is updated each fixed step.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CharacterController {
+    pub radius: f32,
+    /// Half the height of the capsule's cylinder, excluding the rounded caps
+    pub half_height: f32,
+    /// Walking speed in meters per second
+    pub speed: f32,
+    /// Upward speed given by jumping
+    pub jump_speed: f32,
+    /// Multiplies `Physics::gravity`
+    pub gravity_scale: f32,
+    /// Steepest climbable slope, in degrees
+    pub max_slope: f32,
+    /// Tallest ledge stepped up onto without jumping
+    pub step_height: f32,
+    /// How far down the character sticks to the ground when walking off a drop
+    pub snap_distance: f32,
+    /// Seconds after walking off a ledge during which jumping still works
+    pub coyote_time: f32,
+    pub velocity: Vec3,
+    pub grounded: bool,
+    /// Seconds since the character last stood on the ground
+    pub airborne_time: f32,
+    jump_held: bool,
+}
+
+impl Default for CharacterController {
+    fn default() -> Self {
+        Self {
+            radius: 0.4,
+            half_height: 0.5,
+            speed: 5.0,
+            jump_speed: 5.0,
+            gravity_scale: 1.0,
+            max_slope: 45.0,
+            step_height: 0.3,
+            snap_distance: 0.2,
+            coyote_time: 0.1,
+            velocity: Vec3::zeros(),
+            grounded: false,
+            airborne_time: 0.0,
+            jump_held: false,
+        }
+    }
+}
+
+/// What a player asks their character to do this step
+#[derive(Default, Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CharacterInput {
+    /// Strafe on x and forward on y, with a length of at most one
+    pub movement: Vec2,
+    pub jump: bool,
+}
+
+/// Action for walking, strafing on x and forward on y
+pub const MOVE_ACTION: &str = "move";
+pub const JUMP_ACTION: &str = "jump";
+
+/// Default bindings for each player index, for a state to install in `Input::maps`.
+/// Two players can share a keyboard, and each also gets the gamepad matching their index.
+pub fn character_input_map(player: u8) -> InputMap {
+    let (keys, jump) = match player {
+        0 => (
+            [KeyCode::KeyA, KeyCode::KeyD, KeyCode::KeyS, KeyCode::KeyW],
+            KeyCode::Space,
+        ),
+        1 => (
+            [
+                KeyCode::ArrowLeft,
+                KeyCode::ArrowRight,
+                KeyCode::ArrowDown,
+                KeyCode::ArrowUp,
+            ],
+            KeyCode::ControlRight,
+        ),
+        _ => return InputMap::default(),
+    };
+    let [left, right, down, up] = keys.map(|key| Some(Button::Key(key)));
+    let move_action = Action {
+        buttons: Vec::new(),
+        axes: vec![
+            AxisBinding::Buttons {
+                left,
+                right,
+                down,
+                up,
+            },
+            AxisBinding::Stick {
+                stick: GamepadStick::Left,
+                dead_zone: 0.2,
+            },
+        ],
+    };
+    let jump_action = Action {
+        buttons: [Button::Key(jump), Button::Gamepad(GamepadButton::South)]
+            .map(|button| ButtonBinding {
+                buttons: vec![button],
+                modifiers: Modifiers::empty(),
+            })
+            .to_vec(),
+        axes: Vec::new(),
+    };
+    InputMap {
+        actions: HashMap::from([
+            (MOVE_ACTION.to_string(), move_action),
+            (JUMP_ACTION.to_string(), jump_action),
+        ]),
+    }
+}
+
+pub fn character_input(input: &Input, player: u8) -> CharacterInput {
+    let movement = action_value(input, player, MOVE_ACTION);
+    CharacterInput {
+        movement: if movement.norm() > 1.0 {
+            movement.normalize()
+        } else {
+            movement
+        },
+        jump: action_pressed(input, player, JUMP_ACTION),
+    }
+}
+
+/// Moves every player with a `CharacterController` by one fixed step of its input
+pub fn update_character_controllers_system(world: &mut World) {
+    let delta_time = world.resources.fixed_time.timestep;
+    for entity in query_entities(world, PLAYER | CHARACTER_CONTROLLER | LOCAL_TRANSFORM) {
+        let Player(player) = get_component::<Player>(world, entity, PLAYER)
+            .cloned()
+            .expect("Queried entity has no player!");
+        let input = character_input(&world.resources.input, player);
+        let mut controller =
+            get_component::<CharacterController>(world, entity, CHARACTER_CONTROLLER)
+                .cloned()
+                .expect("Queried entity has no character controller!");
+        let transform = *get_component::<LocalTransform>(world, entity, LOCAL_TRANSFORM)
+            .expect("Queried entity has no local transform!");
+        let parent = parent_global_transform(world, entity);
+        let position = (parent * transform.translation.push(1.0)).xyz();
+
+        let facing = parent
+            * nalgebra_glm::quat_rotate_vec3(
+                &transform.rotation.normalize(),
+                &Vec3::new(0.0, 0.0, -1.0),
+            )
+            .push(0.0);
+        let desired = step_character(
+            &mut controller,
+            &input,
+            &facing.xyz(),
+            &world.resources.physics.gravity,
+            delta_time,
+        );
+        let movement = move_character(
+            &world.resources.physics,
+            entity,
+            &controller,
+            position,
+            desired,
+            delta_time,
+        );
+        land_character(
+            &mut controller,
+            &desired,
+            &movement.translation,
+            movement.grounded,
+            delta_time,
+        );
+
+        let local_movement = nalgebra_glm::inverse(&parent) * movement.translation.push(0.0);
+        if let Some(transform) = get_component_mut::<LocalTransform>(world, entity, LOCAL_TRANSFORM)
+        {
+            transform.translation += local_movement.xyz();
+        }
+        if let Some(component) =
+            get_component_mut::<CharacterController>(world, entity, CHARACTER_CONTROLLER)
+        {
+            *component = controller;
+        }
+    }
+}
+
+/// Applies input, jumping and gravity to the velocity and returns the
+/// world-space movement wanted this step
+fn step_character(
+    controller: &mut CharacterController,
+    input: &CharacterInput,
+    facing: &Vec3,
+    gravity: &Vec3,
+    delta_time: f32,
+) -> Vec3 {
+    let forward = Vec3::new(facing.x, 0.0, facing.z)
+        .try_normalize(f32::EPSILON)
+        .unwrap_or_else(|| Vec3::new(0.0, 0.0, -1.0));
+    let right = forward.cross(&Vec3::y());
+    let walk = (right * input.movement.x + forward * input.movement.y) * controller.speed;
+
+    let jump_pressed = input.jump && !controller.jump_held;
+    controller.jump_held = input.jump;
+    if jump_pressed && controller.airborne_time <= controller.coyote_time {
+        controller.velocity.y = controller.jump_speed;
+        // Spends the coyote time so the jump can't repeat in midair
+        controller.airborne_time = f32::MAX;
+        controller.grounded = false;
+    }
+    controller.velocity.y += gravity.y * controller.gravity_scale * delta_time;
+    controller.velocity.x = walk.x;
+    controller.velocity.z = walk.z;
+    controller.velocity * delta_time
+}
+
+/// Stops falling on landing and rising on hitting a ceiling
+fn land_character(
+    controller: &mut CharacterController,
+    desired: &Vec3,
+    movement: &Vec3,
+    grounded: bool,
+    delta_time: f32,
+) {
+    const CEILING_EPSILON: f32 = 1e-4;
+    // A jump may still touch the ground on its first step
+    controller.grounded = grounded && controller.velocity.y <= 0.0;
+    if controller.grounded {
+        controller.airborne_time = 0.0;
+        controller.velocity.y = 0.0;
+    } else {
+        controller.airborne_time += delta_time;
+    }
+    if desired.y > 0.0 && movement.y < desired.y - CEILING_EPSILON {
+        controller.velocity.y = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        input::{update_input, update_input_system, Gamepads},
+        physics::{step_physics_system, Collider, ColliderShape},
+        world::{run_fixed_systems, spawn_entities, EntityId, Keyboard, Mouse, COLLIDER},
+    };
+    use winit::event::ElementState;
+
+    const DELTA_TIME: f32 = 1.0 / 60.0;
+    const GRAVITY: Vec3 = Vec3::new(0.0, -9.81, 0.0);
+    const FACING: Vec3 = Vec3::new(0.0, 0.0, -1.0);
+
+    fn standing() -> CharacterController {
+        CharacterController {
+            grounded: true,
+            ..Default::default()
+        }
+    }
+
+    fn jump(pressed: bool) -> CharacterInput {
+        CharacterInput {
+            jump: pressed,
+            ..Default::default()
+        }
+    }
+
+    /// Steps with the physics reporting whether the character touches the ground,
+    /// moving it as far as it wanted
+    fn step(controller: &mut CharacterController, input: CharacterInput, grounded: bool) -> Vec3 {
+        let desired = step_character(controller, &input, &FACING, &GRAVITY, DELTA_TIME);
+        land_character(controller, &desired, &desired, grounded, DELTA_TIME);
+        desired
+    }
+
+    #[test]
+    fn jumping_works_shortly_after_walking_off_a_ledge() {
+        let mut controller = standing();
+        for _ in 0..3 {
+            step(&mut controller, jump(false), false);
+        }
+        assert!(!controller.grounded);
+        assert!(controller.airborne_time < controller.coyote_time);
+        step(&mut controller, jump(true), false);
+        assert!(controller.velocity.y > 0.0);
+
+        let mut controller = standing();
+        for _ in 0..10 {
+            step(&mut controller, jump(false), false);
+        }
+        assert!(controller.airborne_time > controller.coyote_time);
+        step(&mut controller, jump(true), false);
+        assert!(controller.velocity.y < 0.0);
+    }
+
+    #[test]
+    fn holding_jump_does_not_jump_again_after_landing() {
+        let mut controller = standing();
+        step(&mut controller, jump(true), true);
+        assert!(controller.velocity.y > 0.0);
+        while controller.velocity.y > 0.0 {
+            step(&mut controller, jump(true), false);
+        }
+        step(&mut controller, jump(true), true);
+        assert!(controller.grounded);
+
+        step(&mut controller, jump(true), true);
+        assert_eq!(controller.velocity.y, 0.0);
+        step(&mut controller, jump(false), true);
+        step(&mut controller, jump(true), true);
+        assert!(controller.velocity.y > 0.0);
+    }
+
+    #[test]
+    fn jumping_again_in_midair_does_nothing() {
+        let mut controller = standing();
+        step(&mut controller, jump(true), true);
+        step(&mut controller, jump(false), false);
+        let rising = controller.velocity.y;
+        step(&mut controller, jump(true), false);
+        assert!(controller.velocity.y < rising);
+        assert!(!controller.grounded);
+    }
+
+    #[test]
+    fn ceilings_and_landings_stop_vertical_motion() {
+        let mut controller = standing();
+        let desired = step_character(&mut controller, &jump(true), &FACING, &GRAVITY, DELTA_TIME);
+        assert!(desired.y > 0.0);
+        // Blocked halfway by a ceiling
+        land_character(
+            &mut controller,
+            &desired,
+            &(desired * 0.5),
+            false,
+            DELTA_TIME,
+        );
+        assert_eq!(controller.velocity.y, 0.0);
+
+        let mut controller = CharacterController {
+            velocity: Vec3::new(0.0, -4.0, 0.0),
+            airborne_time: 1.0,
+            ..Default::default()
+        };
+        let desired = step_character(&mut controller, &jump(false), &FACING, &GRAVITY, DELTA_TIME);
+        land_character(&mut controller, &desired, &Vec3::zeros(), true, DELTA_TIME);
+        assert!(controller.grounded);
+        assert_eq!(controller.velocity.y, 0.0);
+        assert_eq!(controller.airborne_time, 0.0);
+    }
+
+    fn pressed(keys: &[KeyCode]) -> Keyboard {
+        Keyboard {
+            keystates: keys
+                .iter()
+                .map(|key| (*key, ElementState::Pressed))
+                .collect(),
+        }
+    }
+
+    fn players_input(keys: &[KeyCode]) -> Input {
+        let mut input = Input::default();
+        input.maps.insert(0, character_input_map(0));
+        input.maps.insert(1, character_input_map(1));
+        update_input(
+            &mut input,
+            &pressed(keys),
+            &Mouse::default(),
+            &Gamepads::default(),
+        );
+        input
+    }
+
+    #[test]
+    fn diagonal_input_is_normalized() {
+        let input = players_input(&[KeyCode::KeyW, KeyCode::KeyD]);
+        let movement = character_input(&input, 0).movement;
+        assert!((movement.norm() - 1.0).abs() < 1e-5);
+        assert!((movement.x - movement.y).abs() < 1e-5);
+        assert!(movement.x > 0.0);
+
+        let input = players_input(&[KeyCode::KeyW]);
+        assert_eq!(character_input(&input, 0).movement, Vec2::new(0.0, 1.0));
+    }
+
+    #[test]
+    fn each_player_reads_their_own_bindings() {
+        let input = players_input(&[KeyCode::ArrowUp, KeyCode::ControlRight]);
+        assert_eq!(character_input(&input, 0), CharacterInput::default());
+        let second = character_input(&input, 1);
+        assert_eq!(second.movement, Vec2::new(0.0, 1.0));
+        assert!(second.jump);
+    }
+
+    fn spawn_box(world: &mut World, center: Vec3, tilt: f32, half_extents: Vec3) {
+        let entity = spawn_entities(world, COLLIDER | LOCAL_TRANSFORM, 1)[0];
+        let transform = get_component_mut::<LocalTransform>(world, entity, LOCAL_TRANSFORM)
+            .expect("No local transform!");
+        transform.translation = center;
+        transform.rotation = nalgebra_glm::quat_angle_axis(tilt.to_radians(), &Vec3::x());
+        get_component_mut::<Collider>(world, entity, COLLIDER)
+            .expect("No collider!")
+            .shape = ColliderShape::Box { half_extents };
+    }
+
+    /// A floor with its top at zero and a player standing on it at each x
+    fn floor_with_players(xs: &[f32]) -> (World, Vec<EntityId>) {
+        let mut world = World::default();
+        spawn_box(
+            &mut world,
+            Vec3::new(0.0, -0.5, 0.0),
+            0.0,
+            Vec3::new(20.0, 0.5, 20.0),
+        );
+        let players = xs
+            .iter()
+            .enumerate()
+            .map(|(player, x)| {
+                let entity = spawn_entities(
+                    &mut world,
+                    PLAYER | CHARACTER_CONTROLLER | LOCAL_TRANSFORM,
+                    1,
+                )[0];
+                *get_component_mut::<Player>(&mut world, entity, PLAYER).expect("No player!") =
+                    Player(player as u8);
+                let standing_height = {
+                    let controller = CharacterController::default();
+                    controller.half_height + controller.radius
+                };
+                get_component_mut::<LocalTransform>(&mut world, entity, LOCAL_TRANSFORM)
+                    .expect("No local transform!")
+                    .translation = Vec3::new(*x, standing_height + 0.05, 0.0);
+                entity
+            })
+            .collect();
+        world.resources.input.maps.insert(0, character_input_map(0));
+        world.resources.input.maps.insert(1, character_input_map(1));
+        (world, players)
+    }
+
+    /// Holds W, walking the first player towards negative z for two seconds
+    fn walk_forward(world: &mut World) {
+        world
+            .resources
+            .keyboard
+            .keystates
+            .insert(KeyCode::KeyW, ElementState::Pressed);
+        // Builds the colliders before the first character moves
+        step_physics_system(world);
+        for _ in 0..120 {
+            update_input_system(world);
+            run_fixed_systems(world);
+        }
+    }
+
+    fn position(world: &World, entity: EntityId) -> Vec3 {
+        get_component::<LocalTransform>(world, entity, LOCAL_TRANSFORM)
+            .expect("No local transform!")
+            .translation
+    }
+
+    /// Walks the first player into a step of the given height, returning where it ended up
+    fn walk_into_step(height: f32) -> Vec3 {
+        let (mut world, players) = floor_with_players(&[0.0]);
+        spawn_box(
+            &mut world,
+            Vec3::new(0.0, height / 2.0, -9.0),
+            0.0,
+            Vec3::new(3.0, height / 2.0, 6.0),
+        );
+        walk_forward(&mut world);
+        position(&world, players[0])
+    }
+
+    #[test]
+    fn characters_climb_steps_up_to_their_step_height() {
+        let standing_height = 0.9;
+        let low = walk_into_step(0.2);
+        assert!(low.z < -4.0, "Stopped at {low:?}");
+        assert!(
+            (low.y - (0.2 + standing_height)).abs() < 0.1,
+            "Stood at {low:?}"
+        );
+
+        let high = walk_into_step(0.6);
+        assert!(high.z > -3.0 + 0.3, "Passed the step at {high:?}");
+        assert!((high.y - standing_height).abs() < 0.1, "Stood at {high:?}");
+    }
+
+    /// Walks the first player onto a ramp tilted by the given degrees, returning where it ended up
+    fn walk_up_ramp(tilt: f32) -> Vec3 {
+        let (mut world, players) = floor_with_players(&[0.0]);
+        spawn_box(
+            &mut world,
+            Vec3::new(0.0, 0.0, -8.0),
+            tilt,
+            Vec3::new(3.0, 0.5, 6.0),
+        );
+        walk_forward(&mut world);
+        position(&world, players[0])
+    }
+
+    #[test]
+    fn characters_walk_up_gentle_slopes_but_not_steep_ones() {
+        let gentle = walk_up_ramp(20.0);
+        assert!(gentle.z < -8.0, "Stopped at {gentle:?}");
+        assert!(gentle.y > 1.5, "Stood at {gentle:?}");
+
+        let steep = walk_up_ramp(60.0);
+        assert!(steep.z > -7.5, "Climbed to {steep:?}");
+        assert!(steep.y < 1.5, "Climbed to {steep:?}");
+    }
+
+    #[test]
+    fn second_players_ignore_the_first_players_keys() {
+        let (mut world, players) = floor_with_players(&[0.0, 5.0]);
+        walk_forward(&mut world);
+        assert!(position(&world, players[0]).z < -5.0);
+        let second = position(&world, players[1]);
+        assert!(second.z.abs() < 1e-3, "Moved to {second:?}");
+        assert!((second.y - 0.9).abs() < 0.1);
+    }
+}
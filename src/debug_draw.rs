@@ -0,0 +1,880 @@
+use crate::{
+    render_graph::{Pass, PassContext, ResourceId, DEPTH, DEPTH_FORMAT, SURFACE},
+    shader::{builtin_shader_library, compose_shader, shader_key},
+    world::{query_active_camera_matrices, Camera, GlobalTransform, Projection},
+};
+use nalgebra_glm::{Mat4, Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+
+/// Primitives pushed by systems each frame, drawn as lines after the scene
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct DebugDraw {
+    pub primitives: Vec<DebugPrimitive>,
+    /// Primitives before this index were pushed before the last update
+    #[serde(skip)]
+    retained: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DebugPrimitive {
+    pub shape: DebugShape,
+    pub style: DebugStyle,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DebugShape {
+    Line {
+        start: Vec3,
+        end: Vec3,
+    },
+    Arrow {
+        start: Vec3,
+        end: Vec3,
+    },
+    Aabb {
+        min: Vec3,
+        max: Vec3,
+    },
+    /// The cube from -1 to 1 on each axis, transformed
+    OrientedBox {
+        transform: Mat4,
+    },
+    Sphere {
+        center: Vec3,
+        radius: f32,
+    },
+    Circle {
+        center: Vec3,
+        normal: Vec3,
+        radius: f32,
+    },
+    /// Near corners then far corners, ordered like the corners of a box
+    Frustum {
+        corners: [Vec3; 8],
+    },
+    /// Red, green and blue arrows along the X, Y and Z axes of a transform
+    Axes {
+        transform: Mat4,
+        length: f32,
+    },
+    /// Lines on the ground plane that follow the camera, so the grid never ends
+    Grid {
+        spacing: f32,
+        half_line_count: u32,
+    },
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct DebugStyle {
+    /// Linear color, ignored by axes except for its alpha
+    pub color: Vec4,
+    /// Primitives without depth testing are drawn over the scene
+    pub depth_test: bool,
+    /// Seconds to keep drawing after the frame it was pushed, zero draws it once
+    pub duration: f32,
+}
+
+impl Default for DebugStyle {
+    fn default() -> Self {
+        Self {
+            color: nalgebra_glm::vec4(1.0, 1.0, 1.0, 1.0),
+            depth_test: true,
+            duration: 0.0,
+        }
+    }
+}
+
+/// A line list vertex
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DebugVertex {
+    pub position: Vec3,
+    pub color: Vec4,
+}
+
+/// Line list vertices for every primitive, split by depth testing
+#[derive(Default, Debug, Clone)]
+pub struct DebugLines {
+    pub depth_tested: Vec<DebugVertex>,
+    pub overlay: Vec<DebugVertex>,
+}
+
+const SPHERE_SEGMENTS: usize = 32;
+const ARROW_HEAD_LENGTH: f32 = 0.2;
+const ARROW_HEAD_WIDTH: f32 = 0.08;
+/// Where frustums of cameras without a far plane are cut off
+const DEFAULT_FRUSTUM_FAR: f32 = 100.0;
+
+pub fn draw_line(debug: &mut DebugDraw, start: Vec3, end: Vec3, style: DebugStyle) {
+    push_primitive(debug, DebugShape::Line { start, end }, style);
+}
+
+pub fn draw_arrow(debug: &mut DebugDraw, start: Vec3, end: Vec3, style: DebugStyle) {
+    push_primitive(debug, DebugShape::Arrow { start, end }, style);
+}
+
+pub fn draw_aabb(debug: &mut DebugDraw, min: Vec3, max: Vec3, style: DebugStyle) {
+    push_primitive(debug, DebugShape::Aabb { min, max }, style);
+}
+
+/// Draws a box with the given half extents, centered on and oriented by a transform
+pub fn draw_oriented_box(
+    debug: &mut DebugDraw,
+    transform: &Mat4,
+    half_extents: Vec3,
+    style: DebugStyle,
+) {
+    let transform = transform * nalgebra_glm::scaling(&half_extents);
+    push_primitive(debug, DebugShape::OrientedBox { transform }, style);
+}
+
+pub fn draw_sphere(debug: &mut DebugDraw, center: Vec3, radius: f32, style: DebugStyle) {
+    push_primitive(debug, DebugShape::Sphere { center, radius }, style);
+}
+
+pub fn draw_circle(
+    debug: &mut DebugDraw,
+    center: Vec3,
+    normal: Vec3,
+    radius: f32,
+    style: DebugStyle,
+) {
+    let shape = DebugShape::Circle {
+        center,
+        normal,
+        radius,
+    };
+    push_primitive(debug, shape, style);
+}
+
+/// Draws the volume a camera sees, placed by its global transform
+pub fn draw_camera_frustum(
+    debug: &mut DebugDraw,
+    camera: &Camera,
+    global_transform: &GlobalTransform,
+    aspect_ratio: f32,
+    style: DebugStyle,
+) {
+    let corners = camera_frustum_corners(camera, global_transform, aspect_ratio);
+    push_primitive(debug, DebugShape::Frustum { corners }, style);
+}
+
+pub fn draw_axes(
+    debug: &mut DebugDraw,
+    global_transform: &GlobalTransform,
+    length: f32,
+    style: DebugStyle,
+) {
+    let shape = DebugShape::Axes {
+        transform: *global_transform,
+        length,
+    };
+    push_primitive(debug, shape, style);
+}
+
+/// Draws a grid on the ground plane around the camera
+pub fn draw_grid(debug: &mut DebugDraw, spacing: f32, half_line_count: u32, style: DebugStyle) {
+    let shape = DebugShape::Grid {
+        spacing,
+        half_line_count,
+    };
+    push_primitive(debug, shape, style);
+}
+
+fn push_primitive(debug: &mut DebugDraw, shape: DebugShape, style: DebugStyle) {
+    debug.primitives.push(DebugPrimitive { shape, style });
+}
+
+/// Ages primitives pushed before the last update, removing those whose duration ran out.
+/// Call once per frame after systems have pushed their primitives.
+pub fn update_debug_draw(debug: &mut DebugDraw, delta_time: f32) {
+    let retained = debug.retained;
+    let mut index = 0;
+    debug.primitives.retain_mut(|primitive| {
+        index += 1;
+        if index > retained {
+            return true;
+        }
+        primitive.style.duration -= delta_time;
+        primitive.style.duration > 0.0
+    });
+    debug.retained = debug.primitives.len();
+}
+
+/// The near corners then far corners of a camera's view volume in world space
+pub fn camera_frustum_corners(
+    camera: &Camera,
+    global_transform: &GlobalTransform,
+    aspect_ratio: f32,
+) -> [Vec3; 8] {
+    // Half width, half height and distance of the near and far planes
+    let planes = match &camera.projection {
+        Projection::Perspective(perspective) => {
+            let aspect_ratio = perspective.aspect_ratio.unwrap_or(aspect_ratio);
+            let slope = (perspective.y_fov_rad * 0.5).tan();
+            let far = perspective.z_far.unwrap_or(DEFAULT_FRUSTUM_FAR);
+            [perspective.z_near, far]
+                .map(|distance| (slope * distance * aspect_ratio, slope * distance, distance))
+        }
+        Projection::Orthographic(orthographic) => [orthographic.z_near, orthographic.z_far]
+            .map(|distance| (orthographic.x_mag, orthographic.y_mag, distance)),
+    };
+    std::array::from_fn(|index| {
+        let (half_width, half_height, distance) = planes[index >> 2];
+        let x = if index & 1 == 0 {
+            -half_width
+        } else {
+            half_width
+        };
+        let y = if index & 2 == 0 {
+            -half_height
+        } else {
+            half_height
+        };
+        transform_point(global_transform, &nalgebra_glm::vec3(x, y, -distance))
+    })
+}
+
+/// Generates line list vertices for every primitive.
+/// The grid is centered on the camera position.
+pub fn debug_draw_vertices(debug: &DebugDraw, camera_position: &Vec3) -> DebugLines {
+    let mut lines = DebugLines::default();
+    for primitive in debug.primitives.iter() {
+        let vertices = if primitive.style.depth_test {
+            &mut lines.depth_tested
+        } else {
+            &mut lines.overlay
+        };
+        primitive_vertices(primitive, camera_position, vertices);
+    }
+    lines
+}
+
+/// Appends the line list vertices of one primitive
+pub fn primitive_vertices(
+    primitive: &DebugPrimitive,
+    camera_position: &Vec3,
+    vertices: &mut Vec<DebugVertex>,
+) {
+    let color = primitive.style.color;
+    match &primitive.shape {
+        DebugShape::Line { start, end } => push_line(vertices, start, end, &color),
+        DebugShape::Arrow { start, end } => push_arrow(vertices, start, end, &color),
+        DebugShape::Aabb { min, max } => {
+            let corners = std::array::from_fn(|index| {
+                nalgebra_glm::vec3(
+                    if index & 1 == 0 { min.x } else { max.x },
+                    if index & 2 == 0 { min.y } else { max.y },
+                    if index & 4 == 0 { min.z } else { max.z },
+                )
+            });
+            push_box(vertices, &corners, &color);
+        }
+        DebugShape::OrientedBox { transform } => {
+            let corners = std::array::from_fn(|index| {
+                let corner = nalgebra_glm::vec3(
+                    if index & 1 == 0 { -1.0 } else { 1.0 },
+                    if index & 2 == 0 { -1.0 } else { 1.0 },
+                    if index & 4 == 0 { -1.0 } else { 1.0 },
+                );
+                transform_point(transform, &corner)
+            });
+            push_box(vertices, &corners, &color);
+        }
+        DebugShape::Sphere { center, radius } => {
+            let point = |angle: f32, axes: (usize, usize)| {
+                let mut offset = Vec3::zeros();
+                offset[axes.0] = angle.cos() * radius;
+                offset[axes.1] = angle.sin() * radius;
+                center + offset
+            };
+            for axes in [(0, 1), (1, 2), (2, 0)] {
+                for segment in 0..SPHERE_SEGMENTS {
+                    let angle = |segment: usize| {
+                        segment as f32 / SPHERE_SEGMENTS as f32 * std::f32::consts::TAU
+                    };
+                    let start = point(angle(segment), axes);
+                    let end = point(angle(segment + 1), axes);
+                    push_line(vertices, &start, &end, &color);
+                }
+            }
+        }
+        DebugShape::Circle {
+            center,
+            normal,
+            radius,
+        } => {
+            let Some((side, up)) = perpendicular_basis(normal) else {
+                return;
+            };
+            let point = |segment: usize| {
+                let angle = segment as f32 / SPHERE_SEGMENTS as f32 * std::f32::consts::TAU;
+                center + (side * angle.cos() + up * angle.sin()) * *radius
+            };
+            for segment in 0..SPHERE_SEGMENTS {
+                push_line(vertices, &point(segment), &point(segment + 1), &color);
+            }
+        }
+        DebugShape::Frustum { corners } => push_box(vertices, corners, &color),
+        DebugShape::Axes { transform, length } => {
+            let origin = transform_point(transform, &Vec3::zeros());
+            for axis in 0..3 {
+                let mut direction = Vec3::zeros();
+                direction[axis] = *length;
+                let mut axis_color = nalgebra_glm::vec4(0.0, 0.0, 0.0, color.w);
+                axis_color[axis] = 1.0;
+                let end = transform_point(transform, &direction);
+                push_arrow(vertices, &origin, &end, &axis_color);
+            }
+        }
+        DebugShape::Grid {
+            spacing,
+            half_line_count,
+        } => {
+            if *spacing <= 0.0 {
+                return;
+            }
+            let center_x = (camera_position.x / spacing).round() * spacing;
+            let center_z = (camera_position.z / spacing).round() * spacing;
+            let extent = *half_line_count as f32 * spacing;
+            let count = *half_line_count as i32;
+            for line in -count..=count {
+                let offset = line as f32 * spacing;
+                push_line(
+                    vertices,
+                    &nalgebra_glm::vec3(center_x + offset, 0.0, center_z - extent),
+                    &nalgebra_glm::vec3(center_x + offset, 0.0, center_z + extent),
+                    &color,
+                );
+                push_line(
+                    vertices,
+                    &nalgebra_glm::vec3(center_x - extent, 0.0, center_z + offset),
+                    &nalgebra_glm::vec3(center_x + extent, 0.0, center_z + offset),
+                    &color,
+                );
+            }
+        }
+    }
+}
+
+fn transform_point(transform: &Mat4, point: &Vec3) -> Vec3 {
+    (transform * point.push(1.0)).xyz()
+}
+
+fn push_line(vertices: &mut Vec<DebugVertex>, start: &Vec3, end: &Vec3, color: &Vec4) {
+    vertices.push(DebugVertex {
+        position: *start,
+        color: *color,
+    });
+    vertices.push(DebugVertex {
+        position: *end,
+        color: *color,
+    });
+}
+
+/// Corners are indexed by bits, the first bit picks x, the second y and the third z
+fn push_box(vertices: &mut Vec<DebugVertex>, corners: &[Vec3; 8], color: &Vec4) {
+    for index in 0..8 {
+        for bit in [1, 2, 4] {
+            if index & bit == 0 {
+                push_line(vertices, &corners[index], &corners[index | bit], color);
+            }
+        }
+    }
+}
+
+/// Two unit vectors perpendicular to a direction and to each other
+fn perpendicular_basis(direction: &Vec3) -> Option<(Vec3, Vec3)> {
+    let direction = direction.try_normalize(f32::EPSILON)?;
+    let reference = if direction.y.abs() < 0.99 {
+        Vec3::y()
+    } else {
+        Vec3::x()
+    };
+    let side = direction.cross(&reference).normalize();
+    Some((side, side.cross(&direction)))
+}
+
+/// A line with four head lines, scaled by its length
+fn push_arrow(vertices: &mut Vec<DebugVertex>, start: &Vec3, end: &Vec3, color: &Vec4) {
+    push_line(vertices, start, end, color);
+    let length = nalgebra_glm::distance(start, end);
+    if length <= f32::EPSILON {
+        return;
+    }
+    let direction = (end - start) / length;
+    let Some((side, up)) = perpendicular_basis(&direction) else {
+        return;
+    };
+    let base = end - direction * length * ARROW_HEAD_LENGTH;
+    for offset in [side, -side, up, -up] {
+        push_line(
+            vertices,
+            end,
+            &(base + offset * length * ARROW_HEAD_WIDTH),
+            color,
+        );
+    }
+}
+
+/// Draws the `DebugDraw` resource over the scene, reading the scene depth for depth testing
+#[derive(Default)]
+pub struct DebugDrawPass {
+    resources: Option<DebugDrawResources>,
+}
+
+struct DebugDrawResources {
+    depth_tested_pipeline: wgpu::RenderPipeline,
+    overlay_pipeline: wgpu::RenderPipeline,
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    vertex_buffer: wgpu::Buffer,
+    vertex_capacity: usize,
+}
+
+impl Pass for DebugDrawPass {
+    fn name(&self) -> &str {
+        "Debug Draw"
+    }
+
+    fn reads(&self) -> Vec<ResourceId> {
+        vec![DEPTH]
+    }
+
+    fn writes(&self) -> Vec<ResourceId> {
+        vec![SURFACE]
+    }
+
+    fn execute(&mut self, context: &mut PassContext) {
+        let world = context.world;
+        if world.resources.debug_draw.primitives.is_empty() {
+            return;
+        }
+        let Some((_, camera_matrices)) = query_active_camera_matrices(world, &world.resources)
+        else {
+            return;
+        };
+        let resources = self.resources.get_or_insert_with(|| {
+            create_debug_draw_resources(context.device, context.surface_format)
+        });
+
+        let lines = debug_draw_vertices(
+            &world.resources.debug_draw,
+            &camera_matrices.camera_position,
+        );
+        let vertex_count = lines.depth_tested.len() + lines.overlay.len();
+        if vertex_count == 0 {
+            return;
+        }
+        if vertex_count > resources.vertex_capacity {
+            resources.vertex_capacity = vertex_count.next_power_of_two();
+            resources.vertex_buffer =
+                create_vertex_buffer(context.device, resources.vertex_capacity);
+        }
+        let view_projection = camera_matrices.projection * camera_matrices.view;
+        context.queue.write_buffer(
+            &resources.camera_buffer,
+            0,
+            bytemuck::cast_slice(&[view_projection]),
+        );
+        let vertices = [lines.depth_tested.as_slice(), lines.overlay.as_slice()].concat();
+        context
+            .queue
+            .write_buffer(&resources.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+
+        let (surface_view, depth_view) = (context.view(SURFACE), context.view(DEPTH));
+        let timestamp_writes = context.timestamp_writes();
+        let mut render_pass = context
+            .encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Debug Draw Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: surface_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                // The scene depth is only read
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: depth_view,
+                    depth_ops: None,
+                    stencil_ops: None,
+                }),
+                timestamp_writes,
+                occlusion_query_set: None,
+            });
+        render_pass.set_bind_group(0, &resources.camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, resources.vertex_buffer.slice(..));
+        let depth_tested_count = lines.depth_tested.len() as u32;
+        if depth_tested_count > 0 {
+            render_pass.set_pipeline(&resources.depth_tested_pipeline);
+            render_pass.draw(0..depth_tested_count, 0..1);
+            context.stats.draw_calls += 1;
+        }
+        if !lines.overlay.is_empty() {
+            render_pass.set_pipeline(&resources.overlay_pipeline);
+            render_pass.draw(depth_tested_count..vertex_count as u32, 0..1);
+            context.stats.draw_calls += 1;
+        }
+    }
+}
+
+fn create_debug_draw_resources(
+    device: &wgpu::Device,
+    surface_format: wgpu::TextureFormat,
+) -> DebugDrawResources {
+    use wgpu::util::DeviceExt;
+
+    let composed = compose_shader(&builtin_shader_library(), &shader_key("debug.wgsl", &[]))
+        .expect("Failed to compose the debug draw shader!");
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Debug Draw Shader"),
+        source: wgpu::ShaderSource::Wgsl(composed.source.into()),
+    });
+
+    let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Debug Draw Camera Buffer"),
+        contents: bytemuck::cast_slice(&[Mat4::identity()]),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    });
+    let camera_bind_group_layout =
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Debug Draw Camera Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+    let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Debug Draw Camera Bind Group"),
+        layout: &camera_bind_group_layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: camera_buffer.as_entire_binding(),
+        }],
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Debug Draw Pipeline Layout"),
+        bind_group_layouts: &[&camera_bind_group_layout],
+        push_constant_ranges: &[],
+    });
+
+    let create_pipeline = |depth_compare| {
+        let constants = std::collections::HashMap::from([(
+            "OUTPUT_SRGB".to_string(),
+            if surface_format.is_srgb() { 0.0 } else { 1.0 },
+        )]);
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Debug Draw Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants: &constants,
+                    ..Default::default()
+                },
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<DebugVertex>() as u64,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x4],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants: &constants,
+                    ..Default::default()
+                },
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: surface_format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        })
+    };
+
+    DebugDrawResources {
+        depth_tested_pipeline: create_pipeline(wgpu::CompareFunction::LessEqual),
+        overlay_pipeline: create_pipeline(wgpu::CompareFunction::Always),
+        camera_buffer,
+        camera_bind_group,
+        vertex_buffer: create_vertex_buffer(device, 1),
+        vertex_capacity: 1,
+    }
+}
+
+fn create_vertex_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Debug Draw Vertex Buffer"),
+        size: (capacity * std::mem::size_of::<DebugVertex>()) as u64,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{OrthographicCamera, PerspectiveCamera};
+
+    fn vertices(shape: DebugShape) -> Vec<DebugVertex> {
+        let primitive = DebugPrimitive {
+            shape,
+            style: DebugStyle::default(),
+        };
+        let mut vertices = Vec::new();
+        primitive_vertices(&primitive, &Vec3::zeros(), &mut vertices);
+        vertices
+    }
+
+    fn approx(a: &Vec3, b: &Vec3) -> bool {
+        nalgebra_glm::distance(a, b) < 1e-4
+    }
+
+    #[test]
+    fn primitives_generate_line_lists() {
+        let unit = nalgebra_glm::vec3(1.0, 1.0, 1.0);
+        let cases = [
+            (
+                DebugShape::Line {
+                    start: Vec3::zeros(),
+                    end: unit,
+                },
+                2,
+            ),
+            (
+                DebugShape::Arrow {
+                    start: Vec3::zeros(),
+                    end: unit,
+                },
+                10,
+            ),
+            (
+                DebugShape::Aabb {
+                    min: -unit,
+                    max: unit,
+                },
+                24,
+            ),
+            (
+                DebugShape::OrientedBox {
+                    transform: Mat4::identity(),
+                },
+                24,
+            ),
+            (
+                DebugShape::Sphere {
+                    center: Vec3::zeros(),
+                    radius: 1.0,
+                },
+                3 * SPHERE_SEGMENTS * 2,
+            ),
+            (
+                DebugShape::Circle {
+                    center: Vec3::zeros(),
+                    normal: Vec3::y(),
+                    radius: 1.0,
+                },
+                SPHERE_SEGMENTS * 2,
+            ),
+            (
+                DebugShape::Axes {
+                    transform: Mat4::identity(),
+                    length: 1.0,
+                },
+                30,
+            ),
+            (
+                DebugShape::Grid {
+                    spacing: 1.0,
+                    half_line_count: 2,
+                },
+                20,
+            ),
+        ];
+        for (shape, count) in cases {
+            assert_eq!(vertices(shape.clone()).len(), count, "{shape:?}");
+        }
+    }
+
+    #[test]
+    fn degenerate_primitives_draw_nothing_extra() {
+        let zero_arrow = DebugShape::Arrow {
+            start: Vec3::zeros(),
+            end: Vec3::zeros(),
+        };
+        assert_eq!(vertices(zero_arrow).len(), 2);
+        let flat_circle = DebugShape::Circle {
+            center: Vec3::zeros(),
+            normal: Vec3::zeros(),
+            radius: 1.0,
+        };
+        assert!(vertices(flat_circle).is_empty());
+        let empty_grid = DebugShape::Grid {
+            spacing: 0.0,
+            half_line_count: 4,
+        };
+        assert!(vertices(empty_grid).is_empty());
+    }
+
+    #[test]
+    fn boxes_connect_their_corners_along_edges() {
+        let lines = vertices(DebugShape::Aabb {
+            min: Vec3::zeros(),
+            max: nalgebra_glm::vec3(1.0, 2.0, 3.0),
+        });
+        for line in lines.chunks_exact(2) {
+            let difference = line[1].position - line[0].position;
+            let changed_axes = (0..3).filter(|axis| difference[*axis] != 0.0).count();
+            assert_eq!(changed_axes, 1, "{line:?}");
+        }
+    }
+
+    #[test]
+    fn spheres_and_circles_lie_at_their_radius() {
+        let center = nalgebra_glm::vec3(1.0, 2.0, 3.0);
+        for vertex in vertices(DebugShape::Sphere {
+            center,
+            radius: 2.0,
+        }) {
+            assert!((nalgebra_glm::distance(&vertex.position, &center) - 2.0).abs() < 1e-4);
+        }
+        let normal = nalgebra_glm::vec3(1.0, 1.0, 0.0).normalize();
+        for vertex in vertices(DebugShape::Circle {
+            center,
+            normal,
+            radius: 0.5,
+        }) {
+            let offset = vertex.position - center;
+            assert!((offset.norm() - 0.5).abs() < 1e-4);
+            assert!(offset.dot(&normal).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn axes_are_colored_by_axis() {
+        let lines = vertices(DebugShape::Axes {
+            transform: nalgebra_glm::translation(&nalgebra_glm::vec3(0.0, 1.0, 0.0)),
+            length: 2.0,
+        });
+        for (axis, arrow) in lines.chunks_exact(10).enumerate() {
+            let mut expected = nalgebra_glm::vec3(0.0, 1.0, 0.0);
+            expected[axis] += 2.0;
+            assert!(approx(&arrow[1].position, &expected));
+            assert_eq!(arrow[0].color[axis], 1.0);
+            assert_eq!(arrow[0].color.xyz().sum(), 1.0);
+        }
+    }
+
+    #[test]
+    fn grid_snaps_to_the_camera() {
+        let primitive = DebugPrimitive {
+            shape: DebugShape::Grid {
+                spacing: 2.0,
+                half_line_count: 1,
+            },
+            style: DebugStyle::default(),
+        };
+        let mut vertices = Vec::new();
+        primitive_vertices(
+            &primitive,
+            &nalgebra_glm::vec3(9.1, 5.0, -3.2),
+            &mut vertices,
+        );
+        let xs = vertices
+            .iter()
+            .map(|vertex| vertex.position.x)
+            .fold(f32::NAN, f32::max);
+        assert_eq!(xs, 12.0);
+        assert!(vertices.iter().all(|vertex| vertex.position.y == 0.0));
+    }
+
+    #[test]
+    fn depth_testing_splits_primitives() {
+        let mut debug = DebugDraw::default();
+        draw_line(&mut debug, Vec3::zeros(), Vec3::x(), DebugStyle::default());
+        let overlay = DebugStyle {
+            depth_test: false,
+            ..Default::default()
+        };
+        draw_aabb(&mut debug, Vec3::zeros(), Vec3::x(), overlay);
+        let lines = debug_draw_vertices(&debug, &Vec3::zeros());
+        assert_eq!(lines.depth_tested.len(), 2);
+        assert_eq!(lines.overlay.len(), 24);
+    }
+
+    #[test]
+    fn primitives_last_for_their_duration() {
+        let mut debug = DebugDraw::default();
+        draw_line(&mut debug, Vec3::zeros(), Vec3::x(), DebugStyle::default());
+        let lasting = DebugStyle {
+            duration: 0.25,
+            ..Default::default()
+        };
+        draw_line(&mut debug, Vec3::zeros(), Vec3::y(), lasting);
+        // Everything pushed this frame is drawn once
+        update_debug_draw(&mut debug, 0.1);
+        assert_eq!(debug.primitives.len(), 2);
+        update_debug_draw(&mut debug, 0.1);
+        assert_eq!(debug.primitives.len(), 1);
+        update_debug_draw(&mut debug, 0.1);
+        assert_eq!(debug.primitives.len(), 1);
+        update_debug_draw(&mut debug, 0.1);
+        assert!(debug.primitives.is_empty());
+    }
+
+    #[test]
+    fn frustum_corners_follow_the_projection() {
+        let perspective = Camera {
+            projection: Projection::Perspective(PerspectiveCamera {
+                aspect_ratio: None,
+                y_fov_rad: std::f32::consts::FRAC_PI_2,
+                z_far: Some(10.0),
+                z_near: 1.0,
+            }),
+            ..Default::default()
+        };
+        let corners = camera_frustum_corners(&perspective, &Mat4::identity(), 2.0);
+        assert!(approx(&corners[0], &nalgebra_glm::vec3(-2.0, -1.0, -1.0)));
+        assert!(approx(&corners[7], &nalgebra_glm::vec3(20.0, 10.0, -10.0)));
+
+        let orthographic = Camera {
+            projection: Projection::Orthographic(OrthographicCamera {
+                x_mag: 3.0,
+                y_mag: 2.0,
+                z_far: 5.0,
+                z_near: 0.5,
+            }),
+            ..Default::default()
+        };
+        let moved = nalgebra_glm::translation(&nalgebra_glm::vec3(0.0, 0.0, 1.0));
+        let corners = camera_frustum_corners(&orthographic, &moved, 1.0);
+        assert!(approx(&corners[1], &nalgebra_glm::vec3(3.0, -2.0, 0.5)));
+        assert!(approx(&corners[6], &nalgebra_glm::vec3(-3.0, 2.0, -4.0)));
+    }
+}
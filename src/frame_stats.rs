@@ -0,0 +1,220 @@
+use crate::world::World;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// How many frames the percentiles cover by default
+pub const DEFAULT_FRAME_STATS_WINDOW: usize = 240;
+
+/// How far the smoothed FPS moves toward each new frame's rate
+const FPS_SMOOTHING: f32 = 0.05;
+
+/// Draw counts render passes report for a frame
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RenderStats {
+    pub draw_calls: u32,
+    pub triangles: u64,
+    /// Objects skipped because they were outside the camera's view
+    pub culled_objects: u32,
+}
+
+impl std::ops::AddAssign for RenderStats {
+    fn add_assign(&mut self, other: Self) {
+        self.draw_calls += other.draw_calls;
+        self.triangles += other.triangles;
+        self.culled_objects += other.culled_objects;
+    }
+}
+
+/// Frame timing and rendering statistics, updated by `App` each frame.
+/// Frame times are in seconds, over a sliding window of recent frames.
+#[derive(Serialize, Deserialize)]
+pub struct FrameStats {
+    /// Exponentially smoothed frames per second
+    pub fps: f32,
+    pub frame_time: f32,
+    pub min_frame_time: f32,
+    pub max_frame_time: f32,
+    pub p95_frame_time: f32,
+    pub p99_frame_time: f32,
+    pub entity_count: usize,
+    /// Counts from the most recently rendered frame
+    pub render: RenderStats,
+    /// Seconds between logging the statistics, `None` to never log
+    pub log_interval: Option<f32>,
+    window: usize,
+    frame_times: VecDeque<f32>,
+    since_log: f32,
+}
+
+impl Default for FrameStats {
+    fn default() -> Self {
+        Self {
+            fps: 0.0,
+            frame_time: 0.0,
+            min_frame_time: 0.0,
+            max_frame_time: 0.0,
+            p95_frame_time: 0.0,
+            p99_frame_time: 0.0,
+            entity_count: 0,
+            render: RenderStats::default(),
+            log_interval: None,
+            window: DEFAULT_FRAME_STATS_WINDOW,
+            frame_times: VecDeque::new(),
+            since_log: 0.0,
+        }
+    }
+}
+
+/// Records a frame's duration and recomputes the statistics
+pub fn update_frame_stats(stats: &mut FrameStats, delta_time: f32, entity_count: usize) {
+    stats.entity_count = entity_count;
+    if delta_time <= 0.0 {
+        return;
+    }
+    stats.frame_time = delta_time;
+    let fps = 1.0 / delta_time;
+    stats.fps = if stats.frame_times.is_empty() {
+        fps
+    } else {
+        stats.fps + (fps - stats.fps) * FPS_SMOOTHING
+    };
+
+    stats.frame_times.push_back(delta_time);
+    while stats.frame_times.len() > stats.window {
+        stats.frame_times.pop_front();
+    }
+    let mut sorted = stats.frame_times.iter().copied().collect::<Vec<_>>();
+    sorted.sort_by(f32::total_cmp);
+    stats.min_frame_time = sorted[0];
+    stats.max_frame_time = sorted[sorted.len() - 1];
+    stats.p95_frame_time = percentile(&sorted, 95.0);
+    stats.p99_frame_time = percentile(&sorted, 99.0);
+
+    if let Some(interval) = stats.log_interval {
+        stats.since_log += delta_time;
+        if stats.since_log >= interval {
+            stats.since_log = 0.0;
+            log::info!("{}", frame_stats_summary(stats));
+        }
+    }
+}
+
+pub fn record_render_stats(stats: &mut FrameStats, render: RenderStats) {
+    stats.render = render;
+}
+
+/// Sets how many frames the statistics cover, dropping the oldest beyond it
+pub fn set_frame_stats_window(stats: &mut FrameStats, frames: usize) {
+    stats.window = frames.max(1);
+    while stats.frame_times.len() > stats.window {
+        stats.frame_times.pop_front();
+    }
+}
+
+/// The nearest-rank percentile of ascending values, zero when empty
+pub fn percentile(sorted: &[f32], percent: f32) -> f32 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (percent.clamp(0.0, 100.0) / 100.0 * sorted.len() as f32).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Counts entities across every table
+pub fn entity_count(world: &World) -> usize {
+    world
+        .tables
+        .iter()
+        .map(|table| table.entity_indices.len())
+        .sum()
+}
+
+pub fn frame_stats_summary(stats: &FrameStats) -> String {
+    const MILLISECONDS: f32 = 1000.0;
+    format!(
+        "{:.0} FPS, frame {:.2} ms (min {:.2}, max {:.2}, p95 {:.2}, p99 {:.2}), \
+         {} entities, {} draw calls, {} triangles, {} culled",
+        stats.fps,
+        stats.frame_time * MILLISECONDS,
+        stats.min_frame_time * MILLISECONDS,
+        stats.max_frame_time * MILLISECONDS,
+        stats.p95_frame_time * MILLISECONDS,
+        stats.p99_frame_time * MILLISECONDS,
+        stats.entity_count,
+        stats.render.draw_calls,
+        stats.render.triangles,
+        stats.render.culled_objects,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_use_the_nearest_rank() {
+        let sorted = (1..=100).map(|value| value as f32).collect::<Vec<_>>();
+        assert_eq!(percentile(&sorted, 95.0), 95.0);
+        assert_eq!(percentile(&sorted, 99.0), 99.0);
+        assert_eq!(percentile(&sorted, 100.0), 100.0);
+        assert_eq!(percentile(&sorted, 0.0), 1.0);
+        assert_eq!(percentile(&sorted, 150.0), 100.0);
+        assert_eq!(percentile(&[4.0], 50.0), 4.0);
+        assert_eq!(percentile(&[1.0, 2.0, 3.0], 50.0), 2.0);
+        assert_eq!(percentile(&[], 95.0), 0.0);
+    }
+
+    #[test]
+    fn frame_times_are_summarized() {
+        let mut stats = FrameStats::default();
+        for frame in 0..100 {
+            let delta_time = if frame == 50 { 0.1 } else { 0.01 };
+            update_frame_stats(&mut stats, delta_time, 3);
+        }
+        assert_eq!(stats.entity_count, 3);
+        assert_eq!(stats.frame_time, 0.01);
+        assert_eq!(stats.min_frame_time, 0.01);
+        assert_eq!(stats.max_frame_time, 0.1);
+        assert_eq!(stats.p95_frame_time, 0.01);
+        assert_eq!(stats.p99_frame_time, 0.01);
+        // The smoothed rate dips for the slow frame and recovers toward 100
+        assert!(stats.fps > 90.0 && stats.fps < 100.0);
+    }
+
+    #[test]
+    fn the_first_frame_sets_the_rate_directly() {
+        let mut stats = FrameStats::default();
+        update_frame_stats(&mut stats, 0.02, 0);
+        assert_eq!(stats.fps, 50.0);
+        assert_eq!(stats.p99_frame_time, 0.02);
+    }
+
+    #[test]
+    fn paused_frames_only_update_the_entity_count() {
+        let mut stats = FrameStats::default();
+        update_frame_stats(&mut stats, 0.02, 1);
+        update_frame_stats(&mut stats, 0.0, 5);
+        assert_eq!(stats.entity_count, 5);
+        assert_eq!(stats.frame_time, 0.02);
+        assert_eq!(stats.frame_times.len(), 1);
+    }
+
+    #[test]
+    fn the_window_drops_old_frames() {
+        let mut stats = FrameStats::default();
+        set_frame_stats_window(&mut stats, 4);
+        update_frame_stats(&mut stats, 0.5, 0);
+        for _ in 0..4 {
+            update_frame_stats(&mut stats, 0.01, 0);
+        }
+        assert_eq!(stats.max_frame_time, 0.01);
+
+        for delta_time in [0.01, 0.02, 0.03] {
+            update_frame_stats(&mut stats, delta_time, 0);
+        }
+        set_frame_stats_window(&mut stats, 2);
+        update_frame_stats(&mut stats, 0.04, 0);
+        assert_eq!(stats.min_frame_time, 0.03);
+        assert_eq!(stats.frame_times.len(), 2);
+    }
+}
@@ -0,0 +1,758 @@
+use crate::{
+    bvh::bvh_ray_cast_nearest,
+    debug_draw::{draw_aabb, draw_arrow, draw_circle, draw_line, DebugDraw, DebugStyle},
+    history::{
+        begin_transaction, end_transaction, record_component_edit, snapshot_component,
+        ComponentValue, EditHistory,
+    },
+    world::{
+        get_component, get_component_mut, parent_global_transform, query_active_camera_matrices,
+        screen_to_world_ray, CameraMatrices, EntityId, GlobalTransform, LocalTransform, Ray, World,
+        GLOBAL_TRANSFORM, LOCAL_TRANSFORM,
+    },
+};
+use nalgebra_glm::{Mat3, Mat4, Vec3};
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GizmoMode {
+    #[default]
+    Translate,
+    Rotate,
+    Scale,
+}
+
+/// The axes translation and rotation follow. Scaling always uses the local axes.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GizmoSpace {
+    #[default]
+    World,
+    Local,
+}
+
+/// Increments edits are rounded to, `None` disables snapping
+#[derive(Default, Debug, Copy, Clone, PartialEq)]
+pub struct GizmoSnap {
+    pub translation: Option<f32>,
+    pub rotation_degrees: Option<f32>,
+    pub scale: Option<f32>,
+}
+
+/// Manipulates the `LocalTransform` of the selected entity in the viewport
+#[derive(Default)]
+pub struct Gizmo {
+    pub mode: GizmoMode,
+    pub space: GizmoSpace,
+    pub snap: GizmoSnap,
+    hovered_axis: Option<usize>,
+    drag: Option<GizmoDrag>,
+}
+
+/// Everything about the entity captured when a drag starts
+struct GizmoDrag {
+    entity: EntityId,
+    axis: usize,
+    axis_direction: Vec3,
+    center: Vec3,
+    /// Where the drag started along the axis, or on the rotation plane
+    start_point: Vec3,
+    start_local: LocalTransform,
+    parent_global: Mat4,
+    before: ComponentValue,
+}
+
+/// The gizmo covers this fraction of the viewport height, whatever its distance
+const GIZMO_SCREEN_FRACTION: f32 = 0.15;
+/// How close the cursor must pass to a handle, relative to the gizmo size
+const GIZMO_HIT_FRACTION: f32 = 0.08;
+const MIN_SCALE: f32 = 0.001;
+
+/// World-space layout of the gizmo for an entity
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GizmoFrame {
+    pub center: Vec3,
+    pub axes: [Vec3; 3],
+    pub size: f32,
+}
+
+pub fn gizmo_frame(
+    gizmo: &Gizmo,
+    global_transform: &GlobalTransform,
+    camera_matrices: &CameraMatrices,
+) -> GizmoFrame {
+    let center = global_transform.column(3).xyz();
+    let local_axes = gizmo.space == GizmoSpace::Local || gizmo.mode == GizmoMode::Scale;
+    let axes = std::array::from_fn(|axis| {
+        let world_axis = Vec3::ith(axis, 1.0);
+        if local_axes {
+            (global_transform.fixed_view::<3, 3>(0, 0) * world_axis)
+                .try_normalize(f32::EPSILON)
+                .unwrap_or(world_axis)
+        } else {
+            world_axis
+        }
+    });
+    // Perspective projections have a zero in their last diagonal entry
+    let projection = &camera_matrices.projection;
+    let visible_height = if projection[(3, 3)] == 0.0 {
+        2.0 * nalgebra_glm::distance(&camera_matrices.camera_position, &center) / projection[(1, 1)]
+    } else {
+        2.0 / projection[(1, 1)]
+    };
+    GizmoFrame {
+        center,
+        axes,
+        size: visible_height.abs() * GIZMO_SCREEN_FRACTION,
+    }
+}
+
+/// Parameters along the ray and along the line where they pass closest, if not parallel
+fn closest_ray_line_parameters(ray: &Ray, origin: &Vec3, direction: &Vec3) -> Option<(f32, f32)> {
+    let offset = ray.origin - origin;
+    let cosine = ray.direction.dot(direction);
+    let denominator = 1.0 - cosine * cosine;
+    if denominator < 1e-6 {
+        return None;
+    }
+    let along_ray = ray.direction.dot(&offset);
+    let along_line = direction.dot(&offset);
+    Some((
+        (cosine * along_line - along_ray) / denominator,
+        (along_line - cosine * along_ray) / denominator,
+    ))
+}
+
+fn ray_plane_intersection(ray: &Ray, point: &Vec3, normal: &Vec3) -> Option<Vec3> {
+    let facing = ray.direction.dot(normal);
+    if facing.abs() < 1e-6 {
+        return None;
+    }
+    let distance = (point - ray.origin).dot(normal) / facing;
+    (distance >= 0.0).then(|| ray.origin + ray.direction * distance)
+}
+
+/// The handle the ray passes over, preferring the closest to the ray
+pub fn gizmo_hit_test(mode: GizmoMode, frame: &GizmoFrame, ray: &Ray) -> Option<usize> {
+    let threshold = frame.size * GIZMO_HIT_FRACTION;
+    let mut closest: Option<(usize, f32)> = None;
+    for (axis, direction) in frame.axes.iter().enumerate() {
+        let distance = match mode {
+            GizmoMode::Translate | GizmoMode::Scale => {
+                let Some((along_ray, along_axis)) =
+                    closest_ray_line_parameters(ray, &frame.center, direction)
+                else {
+                    continue;
+                };
+                if along_ray < 0.0 || !(0.0..=frame.size).contains(&along_axis) {
+                    continue;
+                }
+                let on_ray = ray.origin + ray.direction * along_ray;
+                let on_axis = frame.center + direction * along_axis;
+                nalgebra_glm::distance(&on_ray, &on_axis)
+            }
+            GizmoMode::Rotate => {
+                let Some(hit) = ray_plane_intersection(ray, &frame.center, direction) else {
+                    continue;
+                };
+                (nalgebra_glm::distance(&hit, &frame.center) - frame.size).abs()
+            }
+        };
+        if distance < threshold && closest.is_none_or(|(_, closest)| distance < closest) {
+            closest = Some((axis, distance));
+        }
+    }
+    closest.map(|(axis, _)| axis)
+}
+
+fn snap(value: f32, increment: Option<f32>) -> f32 {
+    match increment {
+        Some(increment) if increment > 0.0 => (value / increment).round() * increment,
+        _ => value,
+    }
+}
+
+/// Where the ray meets the dragged handle, along the axis or on the rotation plane
+fn drag_point(mode: GizmoMode, ray: &Ray, center: &Vec3, axis_direction: &Vec3) -> Option<Vec3> {
+    match mode {
+        GizmoMode::Translate | GizmoMode::Scale => {
+            let (_, along_axis) = closest_ray_line_parameters(ray, center, axis_direction)?;
+            Some(center + axis_direction * along_axis)
+        }
+        GizmoMode::Rotate => ray_plane_intersection(ray, center, axis_direction),
+    }
+}
+
+/// The local transform produced by dragging a handle from `start_point` to `point`.
+/// The parent's global transform converts world-space motion into the parent's space,
+/// so rotated and scaled parents are handled.
+#[allow(clippy::too_many_arguments)]
+pub fn dragged_transform(
+    gizmo: &Gizmo,
+    axis: usize,
+    axis_direction: &Vec3,
+    center: &Vec3,
+    start_point: &Vec3,
+    point: &Vec3,
+    start_local: &LocalTransform,
+    parent_global: &Mat4,
+) -> LocalTransform {
+    let mut transform = *start_local;
+    match gizmo.mode {
+        GizmoMode::Translate => {
+            let distance = snap(
+                (point - start_point).dot(axis_direction),
+                gizmo.snap.translation,
+            );
+            let world_position = center + axis_direction * distance;
+            let Some(parent_inverse) = parent_global.try_inverse() else {
+                return transform;
+            };
+            transform.translation = (parent_inverse * world_position.push(1.0)).xyz();
+        }
+        GizmoMode::Rotate => {
+            let (Some(start), Some(current)) = (
+                (start_point - center).try_normalize(f32::EPSILON),
+                (point - center).try_normalize(f32::EPSILON),
+            ) else {
+                return transform;
+            };
+            let angle = axis_direction
+                .dot(&start.cross(&current))
+                .atan2(start.dot(&current));
+            let angle = snap(angle.to_degrees(), gizmo.snap.rotation_degrees).to_radians();
+            let parent_linear: Mat3 = parent_global.fixed_view::<3, 3>(0, 0).into();
+            // Mirroring parents turn rotations the other way, in either space
+            let angle = if parent_linear.determinant() < 0.0 {
+                -angle
+            } else {
+                angle
+            };
+            transform.rotation = match gizmo.space {
+                GizmoSpace::Local => {
+                    start_local.rotation
+                        * nalgebra_glm::quat_angle_axis(angle, &Vec3::ith(axis, 1.0))
+                }
+                GizmoSpace::World => {
+                    let Some(parent_axis) = parent_linear
+                        .try_inverse()
+                        .and_then(|inverse| (inverse * axis_direction).try_normalize(f32::EPSILON))
+                    else {
+                        return transform;
+                    };
+                    nalgebra_glm::quat_angle_axis(angle, &parent_axis) * start_local.rotation
+                }
+            };
+        }
+        GizmoMode::Scale => {
+            let start_distance = (start_point - center).dot(axis_direction);
+            if start_distance.abs() < f32::EPSILON {
+                return transform;
+            }
+            let factor = (point - center).dot(axis_direction) / start_distance;
+            let scale = snap(start_local.scale[axis] * factor, gizmo.snap.scale);
+            transform.scale[axis] = scale.max(MIN_SCALE);
+        }
+    }
+    transform
+}
+
+/// Draws the gizmo at the selected entity and applies drags to its `LocalTransform`.
+/// Each drag is one undo step. Returns true while dragging.
+pub fn gizmo_ui(
+    gizmo: &mut Gizmo,
+    history: &mut EditHistory,
+    selected: Option<EntityId>,
+    context: &egui::Context,
+    world: &mut World,
+) -> bool {
+    gizmo_settings_ui(gizmo, context);
+
+    let target = selected.and_then(|entity| {
+        let global_transform = *get_component::<GlobalTransform>(world, entity, GLOBAL_TRANSFORM)?;
+        let local_transform = *get_component::<LocalTransform>(world, entity, LOCAL_TRANSFORM)?;
+        Some((entity, global_transform, local_transform))
+    });
+    let camera_matrices = query_active_camera_matrices(world, &world.resources);
+    let (Some((entity, global_transform, local_transform)), Some((_, camera_matrices))) =
+        (target, camera_matrices)
+    else {
+        finish_drag(gizmo, history, world);
+        gizmo.hovered_axis = None;
+        return false;
+    };
+    if gizmo
+        .drag
+        .as_ref()
+        .is_some_and(|drag| drag.entity != entity)
+    {
+        finish_drag(gizmo, history, world);
+    }
+
+    let (pressed, down) = context.input(|input| {
+        (
+            input.pointer.primary_pressed(),
+            input.pointer.primary_down(),
+        )
+    });
+    let ray = pointer_ray(context, world, &camera_matrices);
+    let frame = gizmo_frame(gizmo, &global_transform, &camera_matrices);
+
+    if let Some(drag) = gizmo.drag.as_ref() {
+        if !down {
+            finish_drag(gizmo, history, world);
+        } else if let Some(point) = ray
+            .as_ref()
+            .and_then(|ray| drag_point(gizmo.mode, ray, &drag.center, &drag.axis_direction))
+        {
+            let transform = dragged_transform(
+                gizmo,
+                drag.axis,
+                &drag.axis_direction,
+                &drag.center,
+                &drag.start_point,
+                &point,
+                &drag.start_local,
+                &drag.parent_global,
+            );
+            if let Some(local_transform) =
+                get_component_mut::<LocalTransform>(world, entity, LOCAL_TRANSFORM)
+            {
+                *local_transform = transform;
+            }
+        }
+    } else {
+        let over_ui = context.is_pointer_over_area();
+        gizmo.hovered_axis = ray
+            .as_ref()
+            .filter(|_| !over_ui)
+            .and_then(|ray| gizmo_hit_test(gizmo.mode, &frame, ray));
+        let start = gizmo.hovered_axis.filter(|_| pressed).and_then(|axis| {
+            let axis_direction = frame.axes[axis];
+            let ray = ray.as_ref()?;
+            let start_point = drag_point(gizmo.mode, ray, &frame.center, &axis_direction)?;
+            Some((axis, axis_direction, start_point))
+        });
+        if let (Some((axis, axis_direction, start_point)), Some(before)) = (
+            start,
+            snapshot_component(history, world, entity, LOCAL_TRANSFORM),
+        ) {
+            let label = match gizmo.mode {
+                GizmoMode::Translate => "Translate",
+                GizmoMode::Rotate => "Rotate",
+                GizmoMode::Scale => "Scale",
+            };
+            begin_transaction(history, label);
+            gizmo.drag = Some(GizmoDrag {
+                entity,
+                axis,
+                axis_direction,
+                center: frame.center,
+                start_point,
+                start_local: local_transform,
+                parent_global: parent_global_transform(world, entity),
+                before,
+            });
+        }
+    }
+
+    let active_axis = gizmo
+        .drag
+        .as_ref()
+        .map(|drag| drag.axis)
+        .or(gizmo.hovered_axis);
+    draw_gizmo(
+        gizmo.mode,
+        &frame,
+        active_axis,
+        &mut world.resources.debug_draw,
+    );
+    gizmo.drag.is_some()
+}
+
+/// Selects the entity whose `WorldBounds` the pointer is over when the viewport is pressed
+/// away from the gizmo, or clears the selection over empty space. Call after `gizmo_ui`,
+/// which decides whether the press grabbed a handle.
+pub fn pick_ui(
+    gizmo: &Gizmo,
+    selected: &mut Option<EntityId>,
+    context: &egui::Context,
+    world: &World,
+) {
+    let pressed = context.input(|input| input.pointer.primary_pressed());
+    if !pressed
+        || gizmo.hovered_axis.is_some()
+        || gizmo.drag.is_some()
+        || context.is_pointer_over_area()
+    {
+        return;
+    }
+    let Some((_, camera_matrices)) = query_active_camera_matrices(world, &world.resources) else {
+        return;
+    };
+    let Some(ray) = pointer_ray(context, world, &camera_matrices) else {
+        return;
+    };
+    *selected = bvh_ray_cast_nearest(&world.resources.bvh, &ray, f32::MAX).map(|hit| hit.entity);
+}
+
+/// The world-space ray under the pointer, if it is over the window
+fn pointer_ray(
+    context: &egui::Context,
+    world: &World,
+    camera_matrices: &CameraMatrices,
+) -> Option<Ray> {
+    let (pointer_position, pixels_per_point) =
+        context.input(|input| (input.pointer.hover_pos(), input.pixels_per_point));
+    let position = pointer_position?;
+    let viewport_size = nalgebra_glm::vec2(
+        world.resources.viewport_width as f32,
+        world.resources.viewport_height as f32,
+    );
+    let position = nalgebra_glm::vec2(position.x, position.y) * pixels_per_point;
+    screen_to_world_ray(camera_matrices, viewport_size, position)
+}
+
+fn finish_drag(gizmo: &mut Gizmo, history: &mut EditHistory, world: &World) {
+    if let Some(drag) = gizmo.drag.take() {
+        record_component_edit(history, world, drag.entity, LOCAL_TRANSFORM, drag.before);
+        end_transaction(history);
+    }
+}
+
+fn gizmo_settings_ui(gizmo: &mut Gizmo, context: &egui::Context) {
+    // Alt+W, Alt+E and Alt+R switch modes, since the bare keys move the first player
+    if !context.wants_keyboard_input() {
+        context.input_mut(|input| {
+            let mut shortcut = |key| {
+                input.consume_shortcut(&egui::KeyboardShortcut::new(egui::Modifiers::ALT, key))
+            };
+            if shortcut(egui::Key::W) {
+                gizmo.mode = GizmoMode::Translate;
+            }
+            if shortcut(egui::Key::E) {
+                gizmo.mode = GizmoMode::Rotate;
+            }
+            if shortcut(egui::Key::R) {
+                gizmo.mode = GizmoMode::Scale;
+            }
+        });
+    }
+
+    egui::Window::new("Gizmo").show(context, |ui| {
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut gizmo.mode, GizmoMode::Translate, "Translate");
+            ui.selectable_value(&mut gizmo.mode, GizmoMode::Rotate, "Rotate");
+            ui.selectable_value(&mut gizmo.mode, GizmoMode::Scale, "Scale");
+        });
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut gizmo.space, GizmoSpace::World, "World");
+            ui.selectable_value(&mut gizmo.space, GizmoSpace::Local, "Local");
+        });
+        let snap_ui = |ui: &mut egui::Ui, label: &str, value: &mut Option<f32>, default: f32| {
+            ui.horizontal(|ui| {
+                let mut enabled = value.is_some();
+                if ui.checkbox(&mut enabled, label).changed() {
+                    *value = enabled.then_some(default);
+                }
+                if let Some(increment) = value.as_mut() {
+                    ui.add(
+                        egui::DragValue::new(increment)
+                            .speed(0.01)
+                            .range(0.001..=f32::MAX),
+                    );
+                }
+            });
+        };
+        snap_ui(ui, "Snap translation", &mut gizmo.snap.translation, 0.5);
+        snap_ui(ui, "Snap rotation", &mut gizmo.snap.rotation_degrees, 15.0);
+        snap_ui(ui, "Snap scale", &mut gizmo.snap.scale, 0.1);
+    });
+}
+
+fn draw_gizmo(
+    mode: GizmoMode,
+    frame: &GizmoFrame,
+    active_axis: Option<usize>,
+    debug_draw: &mut DebugDraw,
+) {
+    for (axis, direction) in frame.axes.iter().enumerate() {
+        let mut color = nalgebra_glm::vec4(0.0, 0.0, 0.0, 1.0);
+        color[axis] = 1.0;
+        if active_axis == Some(axis) {
+            color = nalgebra_glm::vec4(1.0, 1.0, 0.0, 1.0);
+        }
+        let style = DebugStyle {
+            color,
+            depth_test: false,
+            ..Default::default()
+        };
+        let end = frame.center + direction * frame.size;
+        match mode {
+            GizmoMode::Translate => draw_arrow(debug_draw, frame.center, end, style),
+            GizmoMode::Rotate => {
+                draw_circle(debug_draw, frame.center, *direction, frame.size, style)
+            }
+            GizmoMode::Scale => {
+                draw_line(debug_draw, frame.center, end, style);
+                let half_extent = Vec3::repeat(frame.size * 0.05);
+                draw_aabb(debug_draw, end - half_extent, end + half_extent, style);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::transform_matrix;
+
+    fn assert_close(actual: Vec3, expected: Vec3) {
+        assert!(
+            nalgebra_glm::distance(&actual, &expected) < 1e-3,
+            "{actual:?} != {expected:?}"
+        );
+    }
+
+    /// A rotated parent scaled differently along each axis, optionally mirrored
+    fn parent(mirrored: bool) -> Mat4 {
+        let scale = nalgebra_glm::vec3(if mirrored { -2.0 } else { 2.0 }, 0.5, 1.5);
+        nalgebra_glm::translation(&nalgebra_glm::vec3(1.0, 2.0, 3.0))
+            * nalgebra_glm::rotation(0.7, &nalgebra_glm::vec3(1.0, 1.0, 0.0).normalize())
+            * nalgebra_glm::scaling(&scale)
+    }
+
+    fn child() -> LocalTransform {
+        LocalTransform {
+            translation: nalgebra_glm::vec3(0.5, -1.0, 2.0),
+            rotation: nalgebra_glm::quat_angle_axis(0.3, &Vec3::y()),
+            scale: nalgebra_glm::vec3(1.0, 1.5, 0.8),
+        }
+    }
+
+    fn gizmo(mode: GizmoMode, space: GizmoSpace) -> Gizmo {
+        Gizmo {
+            mode,
+            space,
+            ..Default::default()
+        }
+    }
+
+    /// The gizmo axis for the child, as `gizmo_frame` lays it out
+    fn axis_direction(gizmo: &Gizmo, global: &Mat4, axis: usize) -> Vec3 {
+        let camera_matrices = CameraMatrices {
+            camera_position: Vec3::zeros(),
+            projection: Mat4::identity(),
+            view: Mat4::identity(),
+        };
+        gizmo_frame(gizmo, global, &camera_matrices).axes[axis]
+    }
+
+    fn transform_point(matrix: &Mat4, point: Vec3) -> Vec3 {
+        (matrix * point.push(1.0)).xyz()
+    }
+
+    #[test]
+    fn translating_moves_the_world_position_along_the_axis() {
+        for space in [GizmoSpace::World, GizmoSpace::Local] {
+            let gizmo = gizmo(GizmoMode::Translate, space);
+            let parent = parent(false);
+            let global = parent * transform_matrix(&child());
+            let center = global.column(3).xyz();
+            let axis = axis_direction(&gizmo, &global, 1);
+            let start_point = center + axis * 0.2;
+            // Motion away from the axis is ignored
+            let point = center + axis * 1.2 + axis.cross(&Vec3::x()) * 5.0;
+            let transform = dragged_transform(
+                &gizmo,
+                1,
+                &axis,
+                &center,
+                &start_point,
+                &point,
+                &child(),
+                &parent,
+            );
+            let moved = parent * transform_matrix(&transform);
+            assert_close(moved.column(3).xyz(), center + axis);
+            assert_eq!(transform.rotation, child().rotation);
+            assert_eq!(transform.scale, child().scale);
+        }
+    }
+
+    /// Drags a quarter turn around the axis and checks the world-space motion:
+    /// points on the axis stay put and points off it turn the way the cursor did
+    fn assert_quarter_turn(space: GizmoSpace, parent: Mat4) {
+        let gizmo = gizmo(GizmoMode::Rotate, space);
+        let global = parent * transform_matrix(&child());
+        let center = global.column(3).xyz();
+        for axis_index in 0..3 {
+            let axis = axis_direction(&gizmo, &global, axis_index);
+            let start_offset = axis.cross(&Vec3::new(0.3, 0.5, 0.8)).normalize();
+            let point = center + axis.cross(&start_offset);
+            let transform = dragged_transform(
+                &gizmo,
+                axis_index,
+                &axis,
+                &center,
+                &(center + start_offset),
+                &point,
+                &child(),
+                &parent,
+            );
+            let motion = parent * transform_matrix(&transform) * global.try_inverse().unwrap();
+            assert_close(transform_point(&motion, center), center);
+            assert_close(transform_point(&motion, center + axis), center + axis);
+            let moved = transform_point(&motion, center + start_offset) - center;
+            assert!(
+                start_offset.cross(&moved).dot(&axis) > 0.0,
+                "{space:?} axis {axis_index} turned the wrong way"
+            );
+        }
+    }
+
+    #[test]
+    fn rotating_turns_around_the_world_axis_under_scaled_parents() {
+        assert_quarter_turn(GizmoSpace::World, parent(false));
+        assert_quarter_turn(GizmoSpace::Local, parent(false));
+    }
+
+    #[test]
+    fn rotating_under_mirrored_parents_follows_the_cursor() {
+        assert_quarter_turn(GizmoSpace::World, parent(true));
+        assert_quarter_turn(GizmoSpace::Local, parent(true));
+    }
+
+    #[test]
+    fn rotating_under_uniformly_scaled_parents_is_a_rigid_turn() {
+        let parent =
+            nalgebra_glm::rotation(0.7, &Vec3::z()) * nalgebra_glm::scaling(&Vec3::repeat(2.0));
+        let gizmo = gizmo(GizmoMode::Rotate, GizmoSpace::World);
+        let global = parent * transform_matrix(&child());
+        let center = global.column(3).xyz();
+        let transform = dragged_transform(
+            &gizmo,
+            2,
+            &Vec3::z(),
+            &center,
+            &(center + Vec3::x()),
+            &(center + Vec3::y()),
+            &child(),
+            &parent,
+        );
+        let turn = nalgebra_glm::rotation(std::f32::consts::FRAC_PI_2, &Vec3::z());
+        let turned: Mat3 = (turn * global).fixed_view::<3, 3>(0, 0).into();
+        let rotated: Mat3 = (parent * transform_matrix(&transform))
+            .fixed_view::<3, 3>(0, 0)
+            .into();
+        for column in 0..3 {
+            assert_close(rotated.column(column).into(), turned.column(column).into());
+        }
+    }
+
+    #[test]
+    fn scaling_stretches_only_the_dragged_local_axis() {
+        let gizmo = gizmo(GizmoMode::Scale, GizmoSpace::World);
+        let parent = parent(false);
+        let global = parent * transform_matrix(&child());
+        let center = global.column(3).xyz();
+        let axis = axis_direction(&gizmo, &global, 0);
+        let transform = dragged_transform(
+            &gizmo,
+            0,
+            &axis,
+            &center,
+            &(center + axis),
+            &(center + axis * 2.0),
+            &child(),
+            &parent,
+        );
+        let scaled = parent * transform_matrix(&transform);
+        assert_close(scaled.column(0).xyz(), global.column(0).xyz() * 2.0);
+        assert_close(scaled.column(1).xyz(), global.column(1).xyz());
+        assert_close(scaled.column(2).xyz(), global.column(2).xyz());
+        assert_close(scaled.column(3).xyz(), center);
+    }
+
+    fn frame() -> GizmoFrame {
+        GizmoFrame {
+            center: Vec3::zeros(),
+            axes: [Vec3::x(), Vec3::y(), Vec3::z()],
+            size: 1.0,
+        }
+    }
+
+    /// A ray looking down the z axis from above the given point
+    fn ray_at(x: f32, y: f32) -> Ray {
+        Ray {
+            origin: nalgebra_glm::vec3(x, y, 5.0),
+            direction: -Vec3::z(),
+        }
+    }
+
+    #[test]
+    fn arrows_are_hit_along_their_length() {
+        let frame = frame();
+        assert_eq!(
+            gizmo_hit_test(GizmoMode::Translate, &frame, &ray_at(0.5, 0.0)),
+            Some(0)
+        );
+        assert_eq!(
+            gizmo_hit_test(GizmoMode::Translate, &frame, &ray_at(0.0, 0.7)),
+            Some(1)
+        );
+        assert_eq!(
+            gizmo_hit_test(GizmoMode::Translate, &frame, &ray_at(0.5, 0.05)),
+            Some(0)
+        );
+        assert_eq!(
+            gizmo_hit_test(GizmoMode::Translate, &frame, &ray_at(1.5, 0.0)),
+            None
+        );
+        assert_eq!(
+            gizmo_hit_test(GizmoMode::Translate, &frame, &ray_at(-0.5, 0.0)),
+            None
+        );
+        assert_eq!(
+            gizmo_hit_test(GizmoMode::Translate, &frame, &ray_at(0.5, 0.5)),
+            None
+        );
+    }
+
+    #[test]
+    fn rings_are_hit_at_their_radius() {
+        let frame = frame();
+        assert_eq!(
+            gizmo_hit_test(GizmoMode::Rotate, &frame, &ray_at(0.6, 0.8)),
+            Some(2)
+        );
+        assert_eq!(
+            gizmo_hit_test(GizmoMode::Rotate, &frame, &ray_at(0.0, 0.0)),
+            None
+        );
+        assert_eq!(
+            gizmo_hit_test(GizmoMode::Rotate, &frame, &ray_at(1.5, 0.0)),
+            None
+        );
+        let side = Ray {
+            origin: nalgebra_glm::vec3(5.0, 0.0, 1.0),
+            direction: -Vec3::x(),
+        };
+        assert_eq!(gizmo_hit_test(GizmoMode::Rotate, &frame, &side), Some(0));
+    }
+
+    #[test]
+    fn scale_handles_are_hit_at_the_axis_ends() {
+        let frame = frame();
+        assert_eq!(
+            gizmo_hit_test(GizmoMode::Scale, &frame, &ray_at(1.0, 0.0)),
+            Some(0)
+        );
+        assert_eq!(
+            gizmo_hit_test(GizmoMode::Scale, &frame, &ray_at(0.0, 1.0)),
+            Some(1)
+        );
+        assert_eq!(
+            gizmo_hit_test(GizmoMode::Scale, &frame, &ray_at(1.2, 0.0)),
+            None
+        );
+    }
+}
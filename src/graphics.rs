@@ -1,10 +1,47 @@
+use crate::{
+    asset::{asset_events, get_asset, get_asset_by_id, AssetEvent, AssetId, Handle},
+    bounds::{bounds_corners, bounds_empty, frustum_from_matrix, mesh_bounds, BoundingBox},
+    bvh::{bvh_contains, bvh_query_frustum},
+    debug_draw::DebugDrawPass,
+    frame_stats::{record_render_stats, RenderStats},
+    material::{
+        create_material_cache, material_bind_group, material_key_has_texture,
+        prepare_material_bind_group, prune_material_cache, MaterialCache, MaterialKey, TextureSlot,
+    },
+    profiler::{
+        begin_scope, collect_gpu_timings, create_gpu_profiler, current_frame_index, end_scope,
+        gpu_timestamp_writes, map_gpu_timestamps, resolve_gpu_timestamps, GpuProfiler, Profiler,
+    },
+    render_graph::{
+        add_pass, add_transient_texture, compile_render_graph, execute_render_graph, needs_compile,
+        render_graph_failed, resize_render_graph, Pass, PassContext, RenderGraph, ResourceId,
+        TextureSize, TransientTexture, DEPTH, DEPTH_FORMAT, SURFACE,
+    },
+    shader::{
+        builtin_shader_library, cached_pipeline, cached_pipeline_keys, create_shader_cache,
+        get_pipeline, replace_shader_source, shader_key, shader_source, PipelineKey, ShaderCache,
+        ShaderError, NORMAL_MAP, SKINNING,
+    },
+    skinning::{joint_palette, MAX_JOINTS},
+    ui::{create_ui_renderer, free_ui_textures, render_ui, UiFrame},
+    world::{
+        query_active_camera_matrices, Material, Mesh, RenderMesh, Shader, SkinWeights, Vertex,
+        World, GLOBAL_TRANSFORM, MATERIAL, RENDER_MESH, SKIN,
+    },
+};
+use freecs::has_components;
+use std::collections::{HashMap, HashSet};
+
 pub struct Graphics<'window> {
     pub surface: wgpu::Surface<'window>,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub surface_config: wgpu::SurfaceConfiguration,
     pub surface_format: wgpu::TextureFormat,
-    pub depth_texture_view: wgpu::TextureView,
+    pub render_graph: RenderGraph,
+    pub ui_renderer: egui_wgpu::Renderer,
+    /// Present when the device supports timestamp queries
+    pub gpu_profiler: Option<GpuProfiler>,
 }
 
 /// Creates resources needed for rendering
@@ -34,7 +71,10 @@ pub async fn create_renderer_resources<'window>(
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: Some("WGPU Device"),
-                    required_features: wgpu::Features::default(),
+                    // Timestamp queries let the profiler time passes on the GPU
+                    required_features: adapter.features()
+                        & (wgpu::Features::TIMESTAMP_QUERY
+                            | wgpu::Features::TEXTURE_COMPRESSION_BC),
                     required_limits: wgpu::Limits {
                         max_texture_dimension_2d: 4096, // Allow higher resolutions on native
                         ..wgpu::Limits::downlevel_defaults()
@@ -70,7 +110,21 @@ pub async fn create_renderer_resources<'window>(
 
     surface.configure(&device, &surface_config);
 
-    let depth_texture_view = create_depth_texture(&device, width, height);
+    let mut render_graph = RenderGraph::default();
+    add_transient_texture(
+        &mut render_graph,
+        DEPTH,
+        TransientTexture {
+            size: TextureSize::Surface,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        },
+    );
+    add_pass(&mut render_graph, ScenePass::default());
+    add_pass(&mut render_graph, DebugDrawPass::default());
+
+    let ui_renderer = create_ui_renderer(&device, surface_format);
+    let gpu_profiler = create_gpu_profiler(&device, &queue);
 
     Graphics {
         surface,
@@ -78,7 +132,9 @@ pub async fn create_renderer_resources<'window>(
         queue,
         surface_config,
         surface_format,
-        depth_texture_view,
+        render_graph,
+        ui_renderer,
+        gpu_profiler,
     }
 }
 
@@ -90,11 +146,41 @@ pub fn resize_renderer(graphics: &mut Graphics, width: u32, height: u32) {
     graphics
         .surface
         .configure(&graphics.device, &graphics.surface_config);
-    graphics.depth_texture_view = create_depth_texture(&graphics.device, width, height);
+    resize_render_graph(&mut graphics.render_graph, &graphics.device, width, height);
+}
+
+/// Renders a frame with the UI drawn over it, call once per frame after updates
+pub fn render_frame(graphics: &mut Graphics, world: &mut World, ui_frame: &UiFrame) {
+    // The profiler leaves the world while passes read it, and returns once they are recorded
+    let mut profiler = std::mem::take(&mut world.resources.profiler);
+    let stats = render_frame_profiled(graphics, world, &mut profiler, ui_frame);
+    world.resources.profiler = profiler;
+    record_render_stats(&mut world.resources.frame_stats, stats);
 }
 
-/// Renders a frame, call once per frame after updates
-pub fn render_frame(graphics: &mut Graphics) {
+fn render_frame_profiled(
+    graphics: &mut Graphics,
+    world: &World,
+    profiler: &mut Profiler,
+    ui_frame: &UiFrame,
+) -> RenderStats {
+    if let Some(gpu_profiler) = graphics.gpu_profiler.as_mut() {
+        collect_gpu_timings(gpu_profiler, &graphics.device, profiler);
+    }
+
+    let (width, height) = (
+        graphics.surface_config.width,
+        graphics.surface_config.height,
+    );
+    if needs_compile(&graphics.render_graph) {
+        // Custom passes can be invalid, the UI is still drawn so the app stays usable
+        if let Err(error) =
+            compile_render_graph(&mut graphics.render_graph, &graphics.device, width, height)
+        {
+            log::error!("Failed to compile render graph, skipping its passes: {error}");
+        }
+    }
+
     let mut encoder = graphics
         .device
         .create_command_encoder(&wgpu::CommandEncoderDescriptor {
@@ -119,71 +205,1093 @@ pub fn render_frame(graphics: &mut Graphics) {
             array_layer_count: None,
         });
 
-    encoder.insert_debug_marker("Render scene");
-
-    // This scope around the crate::render_pass prevents the
-    // crate::render_pass from holding a borrow to the encoder,
-    // which would prevent calling `.finish()` in
-    // preparation for queue submission.
-    {
-        let _render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("Render Pass"),
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &surface_texture_view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color {
-                        r: 0.19,
-                        g: 0.24,
-                        b: 0.42,
-                        a: 1.0,
+    if render_graph_failed(&graphics.render_graph) {
+        clear_surface(&mut encoder, &surface_texture_view);
+    }
+    let mut stats = execute_render_graph(
+        &mut graphics.render_graph,
+        &graphics.device,
+        &graphics.queue,
+        &mut encoder,
+        world,
+        profiler,
+        graphics.gpu_profiler.as_mut(),
+        &surface_texture_view,
+        graphics.surface_format,
+        (width, height),
+    );
+
+    begin_scope(profiler, "UI");
+    let ui_command_buffers = render_ui(
+        &mut graphics.ui_renderer,
+        &graphics.device,
+        &graphics.queue,
+        &mut encoder,
+        &surface_texture_view,
+        (width, height),
+        ui_frame,
+        graphics
+            .gpu_profiler
+            .as_mut()
+            .and_then(|gpu_profiler| gpu_timestamp_writes(gpu_profiler, "UI")),
+    );
+    end_scope(profiler);
+
+    if let (Some(gpu_profiler), Some(frame_index)) = (
+        graphics.gpu_profiler.as_mut(),
+        current_frame_index(profiler),
+    ) {
+        resolve_gpu_timestamps(gpu_profiler, &mut encoder, frame_index);
+    }
+
+    begin_scope(profiler, "Submit");
+    graphics.queue.submit(
+        ui_command_buffers
+            .into_iter()
+            .chain(std::iter::once(encoder.finish())),
+    );
+    end_scope(profiler);
+    if let Some(gpu_profiler) = graphics.gpu_profiler.as_mut() {
+        map_gpu_timestamps(gpu_profiler);
+    }
+    free_ui_textures(&mut graphics.ui_renderer, ui_frame);
+    begin_scope(profiler, "Present");
+    surface_texture.present();
+    end_scope(profiler);
+
+    for primitive in ui_frame.paint_jobs.iter() {
+        if let egui::epaint::Primitive::Mesh(mesh) = &primitive.primitive {
+            stats.draw_calls += 1;
+            stats.triangles += (mesh.indices.len() / 3) as u64;
+        }
+    }
+    stats
+}
+
+/// Clears the surface for the UI when the render graph draws nothing
+fn clear_surface(encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Clear Surface"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    });
+}
+
+/// Clears the surface and depth texture, then draws every entity with a mesh
+#[derive(Default)]
+pub struct ScenePass {
+    resources: Option<SceneResources>,
+    /// Shader assets replacing the built-in sources of the same names
+    shaders: Vec<(String, Handle<Shader>)>,
+}
+
+/// A scene pass drawing with shader assets in place of the built-in sources they are named after,
+/// such as `pbr.wgsl` and the files it includes. Pipelines using a file rebuild whenever it reloads.
+pub fn scene_pass_with_shaders(shaders: Vec<(String, Handle<Shader>)>) -> ScenePass {
+    ScenePass {
+        resources: None,
+        shaders,
+    }
+}
+
+struct SceneResources {
+    shaders: ShaderCache,
+    /// Permutations that failed to build, skipped until the shader changes
+    failed_pipelines: HashSet<PipelineKey>,
+    pipeline_layout: wgpu::PipelineLayout,
+    /// Adds the joint palette bind group, for the skinning permutations
+    skinned_pipeline_layout: wgpu::PipelineLayout,
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    material_cache: MaterialCache,
+    meshes: HashMap<AssetId, GpuMesh>,
+    instance_buffer: wgpu::Buffer,
+    instance_capacity: usize,
+    skin_bind_group_layout: wgpu::BindGroupLayout,
+    /// One `MAX_JOINTS` palette per skinned draw, bound with a dynamic offset
+    palette_buffer: wgpu::Buffer,
+    palette_bind_group: wgpu::BindGroup,
+    palette_capacity: usize,
+}
+
+/// Bytes in one joint palette, a multiple of the uniform offset alignment
+const PALETTE_SIZE: u64 = (MAX_JOINTS * std::mem::size_of::<nalgebra_glm::Mat4>()) as u64;
+
+struct GpuMesh {
+    vertex_buffer: wgpu::Buffer,
+    /// Present when the mesh has skin weights for every vertex
+    skin_buffer: Option<wgpu::Buffer>,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+    /// Local-space box around the vertices, for culling
+    bounds: BoundingBox,
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CameraUniform {
+    view_projection: nalgebra_glm::Mat4,
+    position: nalgebra_glm::Vec4,
+    light_direction: nalgebra_glm::Vec4,
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct InstanceData {
+    model: nalgebra_glm::Mat4,
+    normal_matrix: [nalgebra_glm::Vec4; 3],
+}
+
+/// Meshes sharing a mesh and material are drawn with one instanced draw call
+struct DrawBatch {
+    pipeline: PipelineKey,
+    mesh: AssetId,
+    material_key: MaterialKey,
+    first_instance: usize,
+    instance_count: usize,
+    /// Index of the joint palette for skinned draws, which are never batched
+    palette: Option<usize>,
+}
+
+impl Pass for ScenePass {
+    fn name(&self) -> &str {
+        "Scene"
+    }
+
+    fn writes(&self) -> Vec<ResourceId> {
+        vec![SURFACE, DEPTH]
+    }
+
+    fn execute(&mut self, context: &mut PassContext) {
+        let world = context.world;
+        let shaders = &world.resources.assets.shaders;
+        let created = self.resources.is_none();
+        let resources = self.resources.get_or_insert_with(|| {
+            create_scene_resources(context.device, context.queue, context.surface_format)
+        });
+        for (name, shader) in self.shaders.iter() {
+            let changed = asset_events(shaders).iter().any(|event| {
+                matches!(event, AssetEvent::Loaded(id) | AssetEvent::Modified(id) if *id == shader.id)
+            });
+            if let Some(source) = get_asset(shaders, shader).filter(|_| changed || created) {
+                if let Err(error) =
+                    reload_scene_shader(resources, context.device, name, &source.source)
+                {
+                    log::error!("Failed to reload {name}, keeping the previous version: {error}");
+                }
+            }
+        }
+
+        let camera_matrices = query_active_camera_matrices(world, &world.resources);
+        if let Some((_, camera_matrices)) = camera_matrices.as_ref() {
+            let camera = CameraUniform {
+                view_projection: camera_matrices.projection * camera_matrices.view,
+                position: nalgebra_glm::vec3_to_vec4(&camera_matrices.camera_position),
+                light_direction: nalgebra_glm::vec4(-0.4, -1.0, -0.3, 0.0),
+            };
+            context.queue.write_buffer(
+                &resources.camera_buffer,
+                0,
+                bytemuck::cast_slice(&[camera]),
+            );
+        }
+
+        let (batches, instances, palettes) = match camera_matrices.as_ref() {
+            Some((_, camera_matrices)) => {
+                let (batches, instances, palettes, culled) = prepare_draw_batches(
+                    resources,
+                    context.device,
+                    context.queue,
+                    world,
+                    context.surface_format,
+                    &(camera_matrices.projection * camera_matrices.view),
+                );
+                context.stats.culled_objects += culled;
+                (batches, instances, palettes)
+            }
+            None => (Vec::new(), Vec::new(), Vec::new()),
+        };
+        for batch in batches.iter() {
+            if resources.failed_pipelines.contains(&batch.pipeline) {
+                continue;
+            }
+            if let Err(error) = prepare_scene_pipeline(resources, context.device, &batch.pipeline) {
+                log::error!("Failed to build a PBR pipeline permutation: {error}");
+                resources.failed_pipelines.insert(batch.pipeline.clone());
+            }
+        }
+
+        if instances.len() > resources.instance_capacity {
+            resources.instance_capacity = instances.len().next_power_of_two();
+            resources.instance_buffer =
+                create_instance_buffer(context.device, resources.instance_capacity);
+        }
+        if !instances.is_empty() {
+            context.queue.write_buffer(
+                &resources.instance_buffer,
+                0,
+                bytemuck::cast_slice(&instances),
+            );
+        }
+
+        if palettes.len() > resources.palette_capacity {
+            resources.palette_capacity = palettes.len().next_power_of_two();
+            (resources.palette_buffer, resources.palette_bind_group) = create_palette_buffer(
+                context.device,
+                &resources.skin_bind_group_layout,
+                resources.palette_capacity,
+            );
+        }
+        for (index, palette) in palettes.iter().enumerate() {
+            context.queue.write_buffer(
+                &resources.palette_buffer,
+                index as u64 * PALETTE_SIZE,
+                bytemuck::cast_slice(palette),
+            );
+        }
+
+        let (surface_view, depth_view) = (context.view(SURFACE), context.view(DEPTH));
+        let timestamp_writes = context.timestamp_writes();
+
+        // This scope around the render pass prevents it from
+        // holding a borrow to the encoder after recording
+        {
+            let mut render_pass = context
+                .encoder
+                .begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Render Pass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: surface_view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color {
+                                r: 0.19,
+                                g: 0.24,
+                                b: 0.42,
+                                a: 1.0,
+                            }),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: depth_view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: wgpu::StoreOp::Store,
+                        }),
+                        stencil_ops: None,
                     }),
-                    store: wgpu::StoreOp::Store,
+                    timestamp_writes,
+                    occlusion_query_set: None,
+                });
+
+            render_pass.set_bind_group(0, &resources.camera_bind_group, &[]);
+            let instance_size = std::mem::size_of::<InstanceData>() as u64;
+            for batch in batches.iter() {
+                let (Some(pipeline), Some(mesh), Some(material_bind_group)) = (
+                    get_pipeline(&resources.shaders, &batch.pipeline),
+                    resources.meshes.get(&batch.mesh),
+                    material_bind_group(&resources.material_cache, &batch.material_key),
+                ) else {
+                    continue;
+                };
+                render_pass.set_pipeline(pipeline);
+                let instances_start = batch.first_instance as u64 * instance_size;
+                let instances_end = instances_start + batch.instance_count as u64 * instance_size;
+                render_pass.set_bind_group(1, material_bind_group, &[]);
+                render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                render_pass.set_vertex_buffer(
+                    1,
+                    resources
+                        .instance_buffer
+                        .slice(instances_start..instances_end),
+                );
+                if let (Some(palette), Some(skin_buffer)) = (batch.palette, &mesh.skin_buffer) {
+                    render_pass.set_bind_group(
+                        2,
+                        &resources.palette_bind_group,
+                        &[(palette as u64 * PALETTE_SIZE) as u32],
+                    );
+                    render_pass.set_vertex_buffer(2, skin_buffer.slice(..));
+                }
+                render_pass
+                    .set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                render_pass.draw_indexed(0..mesh.index_count, 0, 0..batch.instance_count as u32);
+                context.stats.draw_calls += 1;
+                context.stats.triangles +=
+                    (mesh.index_count / 3) as u64 * batch.instance_count as u64;
+            }
+        }
+    }
+}
+
+/// Groups renderable entities by mesh and material, uploading any GPU resources they need.
+/// Entities outside the view are skipped and counted, found with one frustum query of the
+/// BVH for entities in it and by testing the mesh bounds of the rest. Skinned entities get
+/// a draw and joint palette each, and are never culled since their joints can move them anywhere.
+fn prepare_draw_batches(
+    resources: &mut SceneResources,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    world: &World,
+    surface_format: wgpu::TextureFormat,
+    view_projection: &nalgebra_glm::Mat4,
+) -> (
+    Vec<DrawBatch>,
+    Vec<InstanceData>,
+    Vec<Vec<nalgebra_glm::Mat4>>,
+    u32,
+) {
+    let default_material = Material::default();
+    let assets = &world.resources.assets;
+    resources
+        .meshes
+        .retain(|mesh, _| get_asset_by_id(&assets.meshes, *mesh).is_some());
+    for event in asset_events(&assets.meshes) {
+        if let AssetEvent::Modified(mesh) = event {
+            resources.meshes.remove(mesh);
+        }
+    }
+    prune_material_cache(&mut resources.material_cache, &assets.images);
+
+    let mut batch_instances: Vec<(AssetId, MaterialKey, Option<usize>, Vec<InstanceData>)> =
+        Vec::new();
+    let mut batch_lookup: HashMap<(AssetId, MaterialKey), usize> = HashMap::new();
+    let mut palettes = Vec::new();
+    let mut culled = 0;
+    let bvh = &world.resources.bvh;
+    let visible = bvh_query_frustum(bvh, &frustum_from_matrix(view_projection))
+        .into_iter()
+        .collect::<HashSet<_>>();
+
+    for table in world.tables.iter() {
+        if !has_components!(table, RENDER_MESH | GLOBAL_TRANSFORM) {
+            continue;
+        }
+        for (index, (RenderMesh(mesh), model)) in table
+            .render_mesh
+            .iter()
+            .zip(table.global_transform.iter())
+            .enumerate()
+        {
+            let Some(mesh_data) = get_asset(&assets.meshes, mesh) else {
+                continue;
+            };
+            let gpu_mesh = resources
+                .meshes
+                .entry(mesh.id)
+                .or_insert_with(|| create_gpu_mesh(device, mesh_data));
+            let skinned = has_components!(table, SKIN) && gpu_mesh.skin_buffer.is_some();
+            let entity = table.entity_indices[index];
+            let outside = if bvh_contains(bvh, entity) {
+                !visible.contains(&entity)
+            } else {
+                outside_view(&(view_projection * model), &gpu_mesh.bounds)
+            };
+            if !skinned && outside {
+                culled += 1;
+                continue;
+            }
+
+            let material = if has_components!(table, MATERIAL) {
+                &table.material[index]
+            } else {
+                &default_material
+            };
+            let material_key = prepare_material_bind_group(
+                &mut resources.material_cache,
+                device,
+                queue,
+                &assets.images,
+                material,
+            );
+
+            let normal_matrix = nalgebra_glm::mat4_to_mat3(model)
+                .try_inverse()
+                .unwrap_or_else(nalgebra_glm::Mat3::identity)
+                .transpose();
+            let instance = InstanceData {
+                model: *model,
+                normal_matrix: [0, 1, 2].map(|column| {
+                    nalgebra_glm::vec3_to_vec4(&normal_matrix.column(column).into_owned())
+                }),
+            };
+
+            if skinned {
+                let mut palette = joint_palette(world, &table.skin[index], model);
+                palette.resize(MAX_JOINTS, nalgebra_glm::Mat4::identity());
+                palettes.push(palette);
+                batch_instances.push((
+                    mesh.id,
+                    material_key,
+                    Some(palettes.len() - 1),
+                    vec![instance],
+                ));
+                continue;
+            }
+            let batch = *batch_lookup
+                .entry((mesh.id, material_key.clone()))
+                .or_insert_with(|| {
+                    batch_instances.push((mesh.id, material_key, None, Vec::new()));
+                    batch_instances.len() - 1
+                });
+            batch_instances[batch].3.push(instance);
+        }
+    }
+
+    let mut batches = Vec::with_capacity(batch_instances.len());
+    let mut instances = Vec::new();
+    for (mesh, material_key, palette, batch) in batch_instances {
+        batches.push(DrawBatch {
+            pipeline: scene_pipeline_key(&material_key, palette.is_some(), surface_format),
+            mesh,
+            material_key,
+            first_instance: instances.len(),
+            instance_count: batch.len(),
+            palette,
+        });
+        instances.extend(batch);
+    }
+    (batches, instances, palettes, culled)
+}
+
+fn create_scene_resources(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    surface_format: wgpu::TextureFormat,
+) -> SceneResources {
+    let material_cache = create_material_cache(device, queue);
+
+    let camera_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Camera Uniform Buffer"),
+        size: std::mem::size_of::<CameraUniform>() as u64,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let camera_bind_group_layout =
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Camera Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
                 },
+                count: None,
+            }],
+        });
+    let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Camera Bind Group"),
+        layout: &camera_bind_group_layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: camera_buffer.as_entire_binding(),
+        }],
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("PBR Pipeline Layout"),
+        bind_group_layouts: &[&camera_bind_group_layout, &material_cache.bind_group_layout],
+        push_constant_ranges: &[],
+    });
+
+    let skin_bind_group_layout =
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Skin Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(PALETTE_SIZE),
+                },
+                count: None,
+            }],
+        });
+    let skinned_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Skinned PBR Pipeline Layout"),
+        bind_group_layouts: &[
+            &camera_bind_group_layout,
+            &material_cache.bind_group_layout,
+            &skin_bind_group_layout,
+        ],
+        push_constant_ranges: &[],
+    });
+    let (palette_buffer, palette_bind_group) =
+        create_palette_buffer(device, &skin_bind_group_layout, 1);
+    let mut resources = SceneResources {
+        shaders: create_shader_cache(builtin_shader_library()),
+        failed_pipelines: HashSet::new(),
+        pipeline_layout,
+        skinned_pipeline_layout,
+        camera_buffer,
+        camera_bind_group,
+        material_cache,
+        meshes: HashMap::new(),
+        instance_buffer: create_instance_buffer(device, 1),
+        instance_capacity: 1,
+        skin_bind_group_layout,
+        palette_buffer,
+        palette_bind_group,
+        palette_capacity: 1,
+    };
+    prepare_scene_pipeline(
+        &mut resources,
+        device,
+        &scene_pipeline_key(&MaterialKey::default(), false, surface_format),
+    )
+    .expect("Failed to create the PBR pipeline!");
+    resources
+}
+
+const PBR_SHADER: &str = "pbr.wgsl";
+
+/// Materials with a normal texture use the normal mapped permutation,
+/// and skinned meshes the skinning one
+fn scene_pipeline_key(
+    material_key: &MaterialKey,
+    skinned: bool,
+    surface_format: wgpu::TextureFormat,
+) -> PipelineKey {
+    let mut defines = Vec::new();
+    if material_key_has_texture(material_key, TextureSlot::Normal) {
+        defines.push(NORMAL_MAP);
+    }
+    if skinned {
+        defines.push(SKINNING);
+    }
+    PipelineKey {
+        shader: shader_key(PBR_SHADER, &defines),
+        color_format: surface_format,
+        sample_count: 1,
+    }
+}
+
+fn prepare_scene_pipeline(
+    resources: &mut SceneResources,
+    device: &wgpu::Device,
+    key: &PipelineKey,
+) -> Result<(), ShaderError> {
+    let SceneResources {
+        shaders,
+        pipeline_layout,
+        skinned_pipeline_layout,
+        ..
+    } = resources;
+    let pipeline_layout = if scene_pipeline_skinned(key) {
+        skinned_pipeline_layout
+    } else {
+        pipeline_layout
+    };
+    cached_pipeline(shaders, device, key, |module| {
+        create_scene_pipeline(device, pipeline_layout, key, module).map_err(|error| ShaderError {
+            file: key.shader.name.clone(),
+            line: None,
+            message: error.to_string(),
+        })
+    })
+    .map(|_| ())
+}
+
+fn scene_pipeline_skinned(key: &PipelineKey) -> bool {
+    key.shader.defines.contains(SKINNING)
+}
+
+/// Swaps in a new source for one of the scene shader files, restoring the previous one
+/// if any pipeline built so far fails to build with it
+fn reload_scene_shader(
+    resources: &mut SceneResources,
+    device: &wgpu::Device,
+    name: &str,
+    source: &str,
+) -> Result<(), ShaderError> {
+    let previous = shader_source(&resources.shaders.library, name)
+        .unwrap_or_default()
+        .to_string();
+    let keys = cached_pipeline_keys(&resources.shaders);
+    replace_shader_source(&mut resources.shaders, name, source)?;
+    let result = keys
+        .iter()
+        .try_for_each(|key| prepare_scene_pipeline(resources, device, key));
+    if result.is_err() {
+        replace_shader_source(&mut resources.shaders, name, &previous)?;
+        for key in keys.iter() {
+            prepare_scene_pipeline(resources, device, key)?;
+        }
+    } else {
+        resources.failed_pipelines.clear();
+    }
+    result
+}
+
+/// Validation errors are captured rather than raised, so a bad shader can be reported and skipped
+fn create_scene_pipeline(
+    device: &wgpu::Device,
+    pipeline_layout: &wgpu::PipelineLayout,
+    key: &PipelineKey,
+    shader: &wgpu::ShaderModule,
+) -> Result<wgpu::RenderPipeline, wgpu::Error> {
+    let surface_format = key.color_format;
+    let skin_layout = wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<SkinWeights>() as u64,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &wgpu::vertex_attr_array![11 => Uint32x4, 12 => Float32x4],
+    };
+    let buffers = [
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Vertex>() as u64,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &wgpu::vertex_attr_array![
+                0 => Float32x3,
+                1 => Float32x3,
+                2 => Float32x2,
+                3 => Float32x4,
+            ],
+        },
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<InstanceData>() as u64,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &wgpu::vertex_attr_array![
+                4 => Float32x4,
+                5 => Float32x4,
+                6 => Float32x4,
+                7 => Float32x4,
+                8 => Float32x4,
+                9 => Float32x4,
+                10 => Float32x4,
+            ],
+        },
+        skin_layout,
+    ];
+    let buffer_count = if scene_pipeline_skinned(key) { 3 } else { 2 };
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let constants = HashMap::from([(
+        "OUTPUT_SRGB".to_string(),
+        if surface_format.is_srgb() { 0.0 } else { 1.0 },
+    )]);
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("PBR Pipeline"),
+        layout: Some(pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_main"),
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants: &constants,
+                ..Default::default()
+            },
+            buffers: &buffers[..buffer_count],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some("fs_main"),
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants: &constants,
+                ..Default::default()
+            },
+            targets: &[Some(wgpu::ColorTargetState {
+                format: surface_format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
             })],
-            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: &graphics.depth_texture_view,
-                depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(1.0),
-                    store: wgpu::StoreOp::Store,
-                }),
-                stencil_ops: None,
+        }),
+        primitive: wgpu::PrimitiveState {
+            cull_mode: Some(wgpu::Face::Back),
+            ..Default::default()
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: key.sample_count,
+            ..Default::default()
+        },
+        multiview: None,
+        cache: None,
+    });
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(error) => Err(error),
+        None => Ok(pipeline),
+    }
+}
+
+fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Instance Buffer"),
+        size: (capacity * std::mem::size_of::<InstanceData>()) as u64,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Holds `capacity` joint palettes, bound one at a time
+fn create_palette_buffer(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    capacity: usize,
+) -> (wgpu::Buffer, wgpu::BindGroup) {
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Joint Palette Buffer"),
+        size: capacity as u64 * PALETTE_SIZE,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Joint Palette Bind Group"),
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                buffer: &buffer,
+                offset: 0,
+                size: wgpu::BufferSize::new(PALETTE_SIZE),
             }),
-            timestamp_writes: None,
-            occlusion_query_set: None,
-        });
+        }],
+    });
+    (buffer, bind_group)
+}
+
+fn create_gpu_mesh(device: &wgpu::Device, mesh: &Mesh) -> GpuMesh {
+    use wgpu::util::DeviceExt;
+    let skinned = !mesh.skin_weights.is_empty() && mesh.skin_weights.len() == mesh.vertices.len();
+    GpuMesh {
+        vertex_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: bytemuck::cast_slice(&mesh.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        }),
+        skin_buffer: skinned.then(|| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Skin Buffer"),
+                contents: bytemuck::cast_slice(&mesh.skin_weights),
+                usage: wgpu::BufferUsages::VERTEX,
+            })
+        }),
+        index_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Index Buffer"),
+            contents: bytemuck::cast_slice(&mesh.indices),
+            usage: wgpu::BufferUsages::INDEX,
+        }),
+        index_count: mesh.indices.len() as u32,
+        bounds: mesh_bounds(mesh),
     }
+}
 
-    graphics.queue.submit(std::iter::once(encoder.finish()));
-    surface_texture.present();
+/// Whether a box is entirely outside one of the clip volume's planes
+fn outside_view(clip_from_local: &nalgebra_glm::Mat4, bounds: &BoundingBox) -> bool {
+    if bounds_empty(bounds) {
+        return false;
+    }
+    let corners = bounds_corners(bounds).map(|corner| clip_from_local * corner.push(1.0));
+    let outside = |inside: fn(&nalgebra_glm::Vec4) -> bool| !corners.iter().any(inside);
+    outside(|clip| clip.x >= -clip.w)
+        || outside(|clip| clip.x <= clip.w)
+        || outside(|clip| clip.y >= -clip.w)
+        || outside(|clip| clip.y <= clip.w)
+        || outside(|clip| clip.z >= 0.0)
+        || outside(|clip| clip.z <= clip.w)
 }
 
-/// Creates a depth texture and view
-fn create_depth_texture(device: &wgpu::Device, width: u32, height: u32) -> wgpu::TextureView {
-    let texture = device.create_texture(
-        &(wgpu::TextureDescriptor {
-            label: Some("Depth Texture"),
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{
+        asset::add_asset,
+        profiler::Profiler,
+        skinning::{joint_palette, skin_position, Skin},
+        world::{
+            cube_mesh, get_component, get_component_mut, spawn_entities, Camera, EntityId,
+            GlobalTransform, LocalTransform, OrthographicCamera, Projection, ACTIVE_CAMERA, CAMERA,
+            LOCAL_TRANSFORM,
+        },
+    };
+
+    const SIZE: u32 = 64;
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+    /// The software adapter, so the test runs without a GPU. `None` when there is none.
+    pub(crate) fn fallback_device() -> Option<(wgpu::Device, wgpu::Queue)> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            compatible_surface: None,
+            force_fallback_adapter: true,
+        }))?;
+        let descriptor = wgpu::DeviceDescriptor {
+            label: Some("Test Device"),
+            required_features: wgpu::Features::empty(),
+            required_limits: wgpu::Limits::downlevel_defaults(),
+            memory_hints: wgpu::MemoryHints::default(),
+        };
+        pollster::block_on(adapter.request_device(&descriptor, None)).ok()
+    }
+
+    fn set<T: 'static>(world: &mut World, entity: EntityId, mask: u32, value: T) {
+        *get_component_mut::<T>(world, entity, mask).expect("Missing component!") = value;
+    }
+
+    /// An orthographic camera at z = 5 looking down -z, seeing x and y from -2 to 2
+    fn spawn_camera(world: &mut World) {
+        let camera = spawn_entities(
+            world,
+            ACTIVE_CAMERA | CAMERA | LOCAL_TRANSFORM | GLOBAL_TRANSFORM,
+            1,
+        )[0];
+        let translation = nalgebra_glm::vec3(0.0, 0.0, 5.0);
+        let projection = Projection::Orthographic(OrthographicCamera {
+            x_mag: 2.0,
+            y_mag: 2.0,
+            z_far: 100.0,
+            z_near: 0.01,
+        });
+        set(
+            world,
+            camera,
+            CAMERA,
+            Camera {
+                projection,
+                ..Default::default()
+            },
+        );
+        set(
+            world,
+            camera,
+            LOCAL_TRANSFORM,
+            LocalTransform {
+                translation,
+                ..Default::default()
+            },
+        );
+        set(
+            world,
+            camera,
+            GLOBAL_TRANSFORM,
+            nalgebra_glm::translation(&translation),
+        );
+    }
+
+    /// Renders the world's scene pass into an offscreen texture and reads it back as RGBA
+    fn render_offscreen(device: &wgpu::Device, queue: &wgpu::Queue, world: &World) -> Vec<u8> {
+        let mut graph = RenderGraph::default();
+        add_transient_texture(
+            &mut graph,
+            DEPTH,
+            TransientTexture {
+                size: TextureSize::Surface,
+                format: DEPTH_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            },
+        );
+        add_pass(&mut graph, ScenePass::default());
+        compile_render_graph(&mut graph, device, SIZE, SIZE).expect("The graph is valid!");
+
+        let target = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Target"),
             size: wgpu::Extent3d {
-                width,
-                height,
+                width: SIZE,
+                height: SIZE,
                 depth_or_array_layers: 1,
             },
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Depth32Float,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
             view_formats: &[],
-        }),
-    );
-    texture.create_view(&wgpu::TextureViewDescriptor {
-        label: None,
-        format: Some(wgpu::TextureFormat::Depth32Float),
-        dimension: Some(wgpu::TextureViewDimension::D2),
-        aspect: wgpu::TextureAspect::All,
-        base_mip_level: 0,
-        base_array_layer: 0,
-        array_layer_count: None,
-        mip_level_count: None,
-    })
+        });
+        let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+        // 64 pixels of 4 bytes is already a multiple of the row alignment
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Offscreen Readback"),
+            size: (SIZE * SIZE * 4) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        execute_render_graph(
+            &mut graph,
+            device,
+            queue,
+            &mut encoder,
+            world,
+            &mut Profiler::default(),
+            None,
+            &view,
+            FORMAT,
+            (SIZE, SIZE),
+        );
+        encoder.copy_texture_to_buffer(
+            target.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &readback,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(SIZE * 4),
+                    rows_per_image: Some(SIZE),
+                },
+            },
+            wgpu::Extent3d {
+                width: SIZE,
+                height: SIZE,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit([encoder.finish()]);
+        let slice = readback.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| {
+            result.expect("Failed to map the readback buffer!")
+        });
+        device.poll(wgpu::Maintain::Wait);
+        let pixels = slice.get_mapped_range().to_vec();
+        readback.unmap();
+        pixels
+    }
+
+    fn pixel(pixels: &[u8], x: u32, y: u32) -> [u8; 4] {
+        let offset = ((y * SIZE + x) * 4) as usize;
+        pixels[offset..offset + 4]
+            .try_into()
+            .expect("Pixels have four channels!")
+    }
+
+    /// The pixel a world-space point lands on
+    fn screen_pixel(world: &World, point: &nalgebra_glm::Vec3) -> (u32, u32) {
+        let (_, matrices) =
+            query_active_camera_matrices(world, &world.resources).expect("The world has a camera!");
+        let clip = matrices.projection * matrices.view * point.push(1.0);
+        let ndc = clip.xy() / clip.w;
+        (
+            ((ndc.x + 1.0) * 0.5 * SIZE as f32) as u32,
+            ((1.0 - ndc.y) * 0.5 * SIZE as f32) as u32,
+        )
+    }
+
+    #[test]
+    fn skinned_meshes_render_where_the_cpu_palette_puts_them() {
+        let Some((device, queue)) = fallback_device() else {
+            eprintln!("Skipping, no fallback adapter is available");
+            return;
+        };
+        let mut world = World::default();
+        world.resources.viewport_width = SIZE;
+        world.resources.viewport_height = SIZE;
+        spawn_camera(&mut world);
+
+        // A unit cube with every vertex bound to one joint, moved one unit right
+        let mut mesh = cube_mesh();
+        mesh.skin_weights = vec![
+            SkinWeights {
+                joints: [0; 4],
+                weights: [1.0, 0.0, 0.0, 0.0],
+            };
+            mesh.vertices.len()
+        ];
+        let mesh = add_asset(&mut world.resources.assets.meshes, mesh);
+        let joint = spawn_entities(&mut world, GLOBAL_TRANSFORM, 1)[0];
+        set(
+            &mut world,
+            joint,
+            GLOBAL_TRANSFORM,
+            nalgebra_glm::translation(&nalgebra_glm::Vec3::x()),
+        );
+        let skinned = spawn_entities(&mut world, RENDER_MESH | SKIN | GLOBAL_TRANSFORM, 1)[0];
+        set(&mut world, skinned, RENDER_MESH, RenderMesh(mesh));
+        set(
+            &mut world,
+            skinned,
+            GLOBAL_TRANSFORM,
+            GlobalTransform::identity(),
+        );
+        set(
+            &mut world,
+            skinned,
+            SKIN,
+            Skin {
+                joints: vec![joint],
+                inverse_bind_matrices: Vec::new(),
+            },
+        );
+
+        let skin = get_component::<Skin>(&world, skinned, SKIN).expect("No skin!");
+        let global_transform = *get_component::<GlobalTransform>(&world, skinned, GLOBAL_TRANSFORM)
+            .expect("No global transform!");
+        let palette = joint_palette(&world, skin, &global_transform);
+        let center = skin_position(
+            &palette,
+            &nalgebra_glm::Vec3::zeros(),
+            &SkinWeights {
+                joints: [0; 4],
+                weights: [1.0, 0.0, 0.0, 0.0],
+            },
+        );
+        let center = (global_transform * center.push(1.0)).xyz();
+
+        let pixels = render_offscreen(&device, &queue, &world);
+        let background = pixel(&pixels, 1, 1);
+        let (x, y) = screen_pixel(&world, &center);
+        assert_eq!((x, y), (48, 32));
+        assert_ne!(pixel(&pixels, x, y), background);
+        // The bind pose position is left empty
+        let (x, y) = screen_pixel(&world, &nalgebra_glm::Vec3::zeros());
+        assert_eq!(pixel(&pixels, x, y), background);
+    }
+
+    /// Reads a texture, and writes another when given
+    struct TexturePass(&'static str, ResourceId, Option<ResourceId>);
+
+    impl Pass for TexturePass {
+        fn name(&self) -> &str {
+            self.0
+        }
+        fn reads(&self) -> Vec<ResourceId> {
+            vec![self.1]
+        }
+        fn writes(&self) -> Vec<ResourceId> {
+            self.2.into_iter().collect()
+        }
+        fn execute(&mut self, _context: &mut PassContext) {}
+    }
+
+    #[test]
+    fn invalid_graphs_fail_once_until_they_change() {
+        let Some((device, _)) = fallback_device() else {
+            eprintln!("Skipping, no fallback adapter is available");
+            return;
+        };
+        let texture = TransientTexture {
+            size: TextureSize::Surface,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        };
+        let mut graph = RenderGraph::default();
+        add_transient_texture(&mut graph, "blurred", texture);
+        add_transient_texture(&mut graph, "hdr", texture);
+        add_pass(&mut graph, TexturePass("blur", "hdr", Some("blurred")));
+        assert!(compile_render_graph(&mut graph, &device, SIZE, SIZE).is_err());
+        assert!(render_graph_failed(&graph));
+        assert!(!needs_compile(&graph));
+        assert!(crate::render_graph::pass_order(&graph).is_empty());
+
+        add_pass(&mut graph, TexturePass("scene", SURFACE, Some("hdr")));
+        assert!(needs_compile(&graph));
+        compile_render_graph(&mut graph, &device, SIZE, SIZE).expect("The graph is valid!");
+        assert!(!render_graph_failed(&graph));
+        assert_eq!(crate::render_graph::pass_order(&graph), ["scene", "blur"]);
+    }
 }
@@ -0,0 +1,841 @@
+use crate::world::{
+    add_components, component_mask, despawn_entities, get_component, get_component_mut,
+    query_entities, remove_components, repair_entity_locations, spawn_entities, EntityId, Parent,
+    Skin, World, PARENT,
+};
+use serde::Serialize;
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet},
+    marker::PhantomData,
+    rc::Rc,
+};
+
+/// A snapshot of a component value. Values are compared by their serialized form,
+/// while the value itself is kept so asset handles it holds stay alive.
+#[derive(Clone)]
+pub struct ComponentValue {
+    value: Rc<dyn Any>,
+    serialized: String,
+}
+
+impl std::fmt::Debug for ComponentValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.serialized)
+    }
+}
+
+impl PartialEq for ComponentValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.serialized == other.serialized
+    }
+}
+
+pub fn component_value<T: Clone + Serialize + 'static>(value: &T) -> ComponentValue {
+    ComponentValue {
+        value: Rc::new(value.clone()),
+        serialized: ron::to_string(value).expect("Failed to serialize a component!"),
+    }
+}
+
+/// The value in RON, useful for debugging and display
+pub fn component_value_ron(value: &ComponentValue) -> &str {
+    &value.serialized
+}
+
+/// Components holding entity ids, so undo can point them at entities it recreates
+pub trait MapEntities {
+    fn map_entities(&mut self, map: &mut dyn FnMut(EntityId) -> EntityId);
+}
+
+impl MapEntities for Parent {
+    fn map_entities(&mut self, map: &mut dyn FnMut(EntityId) -> EntityId) {
+        self.0 = map(self.0);
+    }
+}
+
+impl MapEntities for Skin {
+    fn map_entities(&mut self, map: &mut dyn FnMut(EntityId) -> EntityId) {
+        for joint in self.joints.iter_mut() {
+            *joint = map(*joint);
+        }
+    }
+}
+
+/// Reads and writes one component type, so the history can snapshot any component by mask
+#[derive(Clone, Copy)]
+pub struct ComponentAccess {
+    pub mask: u32,
+    snapshot: fn(&World, EntityId, u32) -> Option<ComponentValue>,
+    restore: fn(&mut World, EntityId, u32, &ComponentValue) -> bool,
+    entities: Option<EntityAccess>,
+}
+
+/// Entity ids inside a component type, for types implementing `MapEntities`
+#[derive(Clone, Copy)]
+struct EntityAccess {
+    remap: fn(&mut World, EntityId, u32, &HashMap<EntityId, EntityId>),
+    referenced: fn(&ComponentValue) -> Vec<EntityId>,
+}
+
+pub fn component_access<T: Clone + Serialize + 'static>(mask: u32) -> ComponentAccess {
+    ComponentAccess {
+        mask,
+        snapshot: snapshot_component_as::<T>,
+        restore: restore_component_as::<T>,
+        entities: None,
+    }
+}
+
+/// Like `component_access`, also keeping the entity ids the component holds current
+pub fn entity_component_access<T: Clone + Serialize + MapEntities + 'static>(
+    mask: u32,
+) -> ComponentAccess {
+    ComponentAccess {
+        entities: Some(EntityAccess {
+            remap: remap_component_entities::<T>,
+            referenced: referenced_entities::<T>,
+        }),
+        ..component_access::<T>(mask)
+    }
+}
+
+/// One accessor per component in the world, shared with the inspector through the
+/// same component list. Types implementing `MapEntities` get their ids remapped.
+pub fn world_component_accesses() -> Vec<ComponentAccess> {
+    use crate::world::*;
+    macro_rules! component_accesses {
+        ($($name:ident: $type:ty => $mask:ident),* $(,)?) => {
+            vec![$((&&AccessProbe::<$type>(PhantomData)).access($mask)),*]
+        };
+    }
+    for_each_component!(component_accesses)
+}
+
+/// Picks `entity_component_access` for types implementing `MapEntities`, as
+/// `InspectProbe` does for the inspector
+struct AccessProbe<T>(PhantomData<T>);
+
+trait EntityAccessProbe {
+    fn access(&self, mask: u32) -> ComponentAccess;
+}
+
+impl<T: Clone + Serialize + MapEntities + 'static> EntityAccessProbe for &AccessProbe<T> {
+    fn access(&self, mask: u32) -> ComponentAccess {
+        entity_component_access::<T>(mask)
+    }
+}
+
+trait PlainAccessProbe {
+    fn access(&self, mask: u32) -> ComponentAccess;
+}
+
+impl<T: Clone + Serialize + 'static> PlainAccessProbe for AccessProbe<T> {
+    fn access(&self, mask: u32) -> ComponentAccess {
+        component_access::<T>(mask)
+    }
+}
+
+fn remap_component_entities<T: MapEntities + 'static>(
+    world: &mut World,
+    entity: EntityId,
+    mask: u32,
+    entity_map: &HashMap<EntityId, EntityId>,
+) {
+    if let Some(component) = get_component_mut::<T>(world, entity, mask) {
+        component.map_entities(&mut |entity| resolve_entity(entity_map, entity));
+    }
+}
+
+fn referenced_entities<T: Clone + MapEntities + 'static>(value: &ComponentValue) -> Vec<EntityId> {
+    let mut entities = Vec::new();
+    if let Some(value) = value.value.downcast_ref::<T>() {
+        value.clone().map_entities(&mut |entity| {
+            entities.push(entity);
+            entity
+        });
+    }
+    entities
+}
+
+fn snapshot_component_as<T: Clone + Serialize + 'static>(
+    world: &World,
+    entity: EntityId,
+    mask: u32,
+) -> Option<ComponentValue> {
+    get_component::<T>(world, entity, mask).map(component_value)
+}
+
+fn restore_component_as<T: Clone + Serialize + 'static>(
+    world: &mut World,
+    entity: EntityId,
+    mask: u32,
+    value: &ComponentValue,
+) -> bool {
+    let (Some(component), Some(value)) = (
+        get_component_mut::<T>(world, entity, mask),
+        value.value.downcast_ref::<T>(),
+    ) else {
+        return false;
+    };
+    *component = value.clone();
+    true
+}
+
+/// Every component of an entity
+#[derive(Clone, Debug)]
+pub struct EntitySnapshot {
+    pub mask: u32,
+    pub components: Vec<(u32, ComponentValue)>,
+}
+
+/// A reversible change to the world
+#[derive(Clone, Debug)]
+pub enum EditOperation {
+    SetComponent {
+        entity: EntityId,
+        mask: u32,
+        before: ComponentValue,
+        after: ComponentValue,
+    },
+    Spawn {
+        entity: EntityId,
+        snapshot: EntitySnapshot,
+    },
+    Despawn {
+        entity: EntityId,
+        snapshot: EntitySnapshot,
+    },
+    Reparent {
+        entity: EntityId,
+        before: Option<EntityId>,
+        after: Option<EntityId>,
+    },
+}
+
+/// Operations undone and redone together as one step
+#[derive(Clone, Debug)]
+pub struct Transaction {
+    pub label: String,
+    pub operations: Vec<EditOperation>,
+}
+
+pub const DEFAULT_HISTORY_DEPTH: usize = 100;
+
+/// Undo and redo stacks of world edits.
+/// Edits made outside a transaction are undone one at a time.
+pub struct EditHistory {
+    pub components: Vec<ComponentAccess>,
+    max_depth: usize,
+    undo_stack: Vec<Transaction>,
+    redo_stack: Vec<Transaction>,
+    open: Option<Transaction>,
+    open_depth: usize,
+    /// Entities recreated by undo or redo get new ids, this maps each old id to its successor
+    entity_map: HashMap<EntityId, EntityId>,
+}
+
+impl Default for EditHistory {
+    fn default() -> Self {
+        Self {
+            components: world_component_accesses(),
+            max_depth: DEFAULT_HISTORY_DEPTH,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            open: None,
+            open_depth: 0,
+            entity_map: HashMap::new(),
+        }
+    }
+}
+
+/// Makes a component type part of entity snapshots, replacing any access for the same mask
+pub fn register_component_access(history: &mut EditHistory, access: ComponentAccess) {
+    history
+        .components
+        .retain(|component| component.mask != access.mask);
+    history.components.push(access);
+}
+
+/// Limits how many transactions can be undone, dropping the oldest
+pub fn set_history_depth(history: &mut EditHistory, depth: usize) {
+    history.max_depth = depth;
+    let excess = history.undo_stack.len().saturating_sub(depth);
+    if excess > 0 {
+        history.undo_stack.drain(..excess);
+        prune_entity_map(history);
+    }
+}
+
+pub fn can_undo(history: &EditHistory) -> bool {
+    !history.undo_stack.is_empty() || history.open.is_some()
+}
+
+pub fn can_redo(history: &EditHistory) -> bool {
+    !history.redo_stack.is_empty()
+}
+
+/// Labels of the transactions that can be undone, most recent last
+pub fn undo_labels(history: &EditHistory) -> Vec<&str> {
+    history
+        .undo_stack
+        .iter()
+        .map(|transaction| transaction.label.as_str())
+        .collect()
+}
+
+/// Groups the following edits into one undo step until the matching `end_transaction`.
+/// Nested transactions join the outermost one.
+pub fn begin_transaction(history: &mut EditHistory, label: &str) {
+    history.open_depth += 1;
+    history.open.get_or_insert_with(|| Transaction {
+        label: label.to_string(),
+        operations: Vec::new(),
+    });
+}
+
+pub fn end_transaction(history: &mut EditHistory) {
+    history.open_depth = history.open_depth.saturating_sub(1);
+    if history.open_depth > 0 {
+        return;
+    }
+    if let Some(transaction) = history.open.take() {
+        commit_transaction(history, transaction);
+    }
+}
+
+pub fn in_transaction(history: &EditHistory) -> bool {
+    history.open.is_some()
+}
+
+fn commit_transaction(history: &mut EditHistory, transaction: Transaction) {
+    if transaction.operations.is_empty() {
+        return;
+    }
+    let redo_dropped = !history.redo_stack.is_empty();
+    history.redo_stack.clear();
+    history.undo_stack.push(transaction);
+    let excess = history.undo_stack.len().saturating_sub(history.max_depth);
+    history.undo_stack.drain(..excess);
+    if redo_dropped || excess > 0 {
+        prune_entity_map(history);
+    }
+}
+
+/// Drops recreated ids that no transaction left in the history refers to
+fn prune_entity_map(history: &mut EditHistory) {
+    let mut referenced = HashSet::new();
+    let transactions = history
+        .undo_stack
+        .iter()
+        .chain(history.redo_stack.iter())
+        .chain(history.open.iter());
+    for operation in transactions.flat_map(|transaction| transaction.operations.iter()) {
+        operation_entities(&history.components, operation, &mut referenced);
+    }
+    let mut entity_map = HashMap::new();
+    for mut entity in referenced {
+        while let Some(next) = history.entity_map.get(&entity) {
+            entity_map.insert(entity, *next);
+            entity = *next;
+        }
+    }
+    history.entity_map = entity_map;
+}
+
+fn operation_entities(
+    components: &[ComponentAccess],
+    operation: &EditOperation,
+    entities: &mut HashSet<EntityId>,
+) {
+    let mut add_value = |mask: u32, value: &ComponentValue| {
+        let referenced = components
+            .iter()
+            .find(|access| access.mask == mask)
+            .and_then(|access| access.entities)
+            .map(|access| (access.referenced)(value))
+            .unwrap_or_default();
+        entities.extend(referenced);
+    };
+    match operation {
+        EditOperation::SetComponent {
+            entity,
+            mask,
+            before,
+            after,
+        } => {
+            add_value(*mask, before);
+            add_value(*mask, after);
+            entities.insert(*entity);
+        }
+        EditOperation::Spawn { entity, snapshot } | EditOperation::Despawn { entity, snapshot } => {
+            for (mask, value) in snapshot.components.iter() {
+                add_value(*mask, value);
+            }
+            entities.insert(*entity);
+        }
+        EditOperation::Reparent {
+            entity,
+            before,
+            after,
+        } => {
+            entities.extend([Some(*entity), *before, *after].into_iter().flatten());
+        }
+    }
+}
+
+fn record_operation(history: &mut EditHistory, label: &str, operation: EditOperation) {
+    let Some(transaction) = history.open.as_mut() else {
+        let transaction = Transaction {
+            label: label.to_string(),
+            operations: vec![operation],
+        };
+        commit_transaction(history, transaction);
+        return;
+    };
+
+    // Repeated edits of a component, like each frame of a drag, become one operation
+    if let (
+        EditOperation::SetComponent {
+            entity,
+            mask,
+            after,
+            ..
+        },
+        Some(EditOperation::SetComponent {
+            entity: last_entity,
+            mask: last_mask,
+            after: last_after,
+            ..
+        }),
+    ) = (&operation, transaction.operations.last_mut())
+    {
+        if entity == last_entity && mask == last_mask {
+            *last_after = after.clone();
+            return;
+        }
+    }
+    transaction.operations.push(operation);
+}
+
+/// The id an entity has now, following any recreation by undo or redo
+pub fn current_entity(history: &EditHistory, entity: EntityId) -> EntityId {
+    resolve_entity(&history.entity_map, entity)
+}
+
+fn resolve_entity(entity_map: &HashMap<EntityId, EntityId>, mut entity: EntityId) -> EntityId {
+    // Each recreation maps to a newer id, so the chain always ends
+    while let Some(next) = entity_map.get(&entity) {
+        entity = *next;
+    }
+    entity
+}
+
+/// Captures a component through the registered accessors
+pub fn snapshot_component(
+    history: &EditHistory,
+    world: &World,
+    entity: EntityId,
+    mask: u32,
+) -> Option<ComponentValue> {
+    history
+        .components
+        .iter()
+        .find(|access| access.mask == mask)
+        .and_then(|access| (access.snapshot)(world, entity, mask))
+}
+
+pub fn snapshot_entity(history: &EditHistory, world: &World, entity: EntityId) -> EntitySnapshot {
+    let mask = component_mask(world, entity).unwrap_or_default();
+    EntitySnapshot {
+        mask,
+        components: history
+            .components
+            .iter()
+            .filter(|access| mask & access.mask != 0)
+            .filter_map(|access| {
+                Some((access.mask, (access.snapshot)(world, entity, access.mask)?))
+            })
+            .collect(),
+    }
+}
+
+/// Records an edit that was already applied, given the component before it.
+/// Returns false when the component is unchanged.
+pub fn record_component_edit(
+    history: &mut EditHistory,
+    world: &World,
+    entity: EntityId,
+    mask: u32,
+    before: ComponentValue,
+) -> bool {
+    let Some(after) = snapshot_component(history, world, entity, mask) else {
+        return false;
+    };
+    if after == before {
+        return false;
+    }
+    let operation = EditOperation::SetComponent {
+        entity,
+        mask,
+        before,
+        after,
+    };
+    record_operation(history, "Edit component", operation);
+    true
+}
+
+/// Sets a component and records the change.
+/// Returns false when the entity does not have the component.
+pub fn set_component<T: Clone + Serialize + 'static>(
+    history: &mut EditHistory,
+    world: &mut World,
+    entity: EntityId,
+    mask: u32,
+    value: T,
+) -> bool {
+    let Some(component) = get_component_mut::<T>(world, entity, mask) else {
+        return false;
+    };
+    let before = component_value(component);
+    let after = component_value(&value);
+    *component = value;
+    if before != after {
+        let operation = EditOperation::SetComponent {
+            entity,
+            mask,
+            before,
+            after,
+        };
+        record_operation(history, "Edit component", operation);
+    }
+    true
+}
+
+/// Spawns an entity with default components and records it
+pub fn spawn_entity(history: &mut EditHistory, world: &mut World, mask: u32) -> EntityId {
+    let entity = spawn_entities(world, mask, 1)[0];
+    let snapshot = snapshot_entity(history, world, entity);
+    record_operation(
+        history,
+        "Spawn entity",
+        EditOperation::Spawn { entity, snapshot },
+    );
+    entity
+}
+
+/// Despawns an entity and its descendants, recording them so undo restores the whole tree
+pub fn despawn_entity(history: &mut EditHistory, world: &mut World, entity: EntityId) {
+    if component_mask(world, entity).is_none() {
+        return;
+    }
+    let entities = entity_and_descendants(world, entity);
+    begin_transaction(history, "Despawn entity");
+    for entity in entities.iter() {
+        let snapshot = snapshot_entity(history, world, *entity);
+        let operation = EditOperation::Despawn {
+            entity: *entity,
+            snapshot,
+        };
+        record_operation(history, "Despawn entity", operation);
+    }
+    end_transaction(history);
+    despawn_entities(world, &entities);
+    repair_entity_locations(world);
+}
+
+/// Parents first, so restoring in reverse brings back children before their parents
+fn entity_and_descendants(world: &World, entity: EntityId) -> Vec<EntityId> {
+    let parented = query_entities(world, PARENT)
+        .into_iter()
+        .filter_map(|child| {
+            get_component::<Parent>(world, child, PARENT).map(|Parent(parent)| (*parent, child))
+        })
+        .collect::<Vec<_>>();
+    let mut entities = vec![entity];
+    let mut index = 0;
+    while index < entities.len() {
+        let parent = entities[index];
+        for (_, child) in parented
+            .iter()
+            .filter(|(candidate, _)| *candidate == parent)
+        {
+            if !entities.contains(child) {
+                entities.push(*child);
+            }
+        }
+        index += 1;
+    }
+    entities
+}
+
+/// Moves an entity under a new parent, or to the root, keeping its local transform.
+/// Returns false when the parent is the entity itself or one of its descendants.
+pub fn reparent_entity(
+    history: &mut EditHistory,
+    world: &mut World,
+    entity: EntityId,
+    parent: Option<EntityId>,
+) -> bool {
+    if component_mask(world, entity).is_none() {
+        return false;
+    }
+    if let Some(parent) = parent {
+        if entity_and_descendants(world, entity).contains(&parent) {
+            return false;
+        }
+    }
+    let before = get_component::<Parent>(world, entity, PARENT).map(|Parent(parent)| *parent);
+    if before == parent {
+        return true;
+    }
+    set_parent(world, entity, parent);
+    let operation = EditOperation::Reparent {
+        entity,
+        before,
+        after: parent,
+    };
+    record_operation(history, "Reparent entity", operation);
+    true
+}
+
+fn set_parent(world: &mut World, entity: EntityId, parent: Option<EntityId>) {
+    match parent {
+        Some(parent) => {
+            add_components(world, entity, PARENT);
+            repair_entity_locations(world);
+            if let Some(component) = get_component_mut::<Parent>(world, entity, PARENT) {
+                *component = Parent(parent);
+            }
+        }
+        None => {
+            remove_components(world, entity, PARENT);
+            repair_entity_locations(world);
+        }
+    }
+}
+
+/// Reverts the most recent transaction, closing any open one first
+pub fn undo(history: &mut EditHistory, world: &mut World) -> bool {
+    if history.open.is_some() {
+        history.open_depth = 1;
+        end_transaction(history);
+    }
+    let Some(transaction) = history.undo_stack.pop() else {
+        return false;
+    };
+    for operation in transaction.operations.iter().rev() {
+        apply_operation(history, world, operation, false);
+    }
+    remap_entities(history, world);
+    history.redo_stack.push(transaction);
+    true
+}
+
+/// Reapplies the most recently undone transaction
+pub fn redo(history: &mut EditHistory, world: &mut World) -> bool {
+    let Some(transaction) = history.redo_stack.pop() else {
+        return false;
+    };
+    for operation in transaction.operations.iter() {
+        apply_operation(history, world, operation, true);
+    }
+    remap_entities(history, world);
+    history.undo_stack.push(transaction);
+    true
+}
+
+fn apply_operation(
+    history: &mut EditHistory,
+    world: &mut World,
+    operation: &EditOperation,
+    forward: bool,
+) {
+    match operation {
+        EditOperation::SetComponent {
+            entity,
+            mask,
+            before,
+            after,
+        } => {
+            let entity = current_entity(history, *entity);
+            let value = if forward { after } else { before };
+            if let Some(access) = history
+                .components
+                .iter()
+                .find(|access| access.mask == *mask)
+            {
+                (access.restore)(world, entity, *mask, value);
+            }
+        }
+        EditOperation::Spawn { entity, snapshot } | EditOperation::Despawn { entity, snapshot } => {
+            let spawn = matches!(operation, EditOperation::Spawn { .. }) == forward;
+            if spawn {
+                let restored = restore_entity(history, world, snapshot);
+                let current = current_entity(history, *entity);
+                history.entity_map.insert(current, restored);
+            } else {
+                despawn_entities(world, &[current_entity(history, *entity)]);
+                repair_entity_locations(world);
+            }
+        }
+        EditOperation::Reparent {
+            entity,
+            before,
+            after,
+        } => {
+            let entity = current_entity(history, *entity);
+            let parent = if forward { after } else { before };
+            let parent = parent.map(|parent| current_entity(history, parent));
+            set_parent(world, entity, parent);
+        }
+    }
+}
+
+fn restore_entity(history: &EditHistory, world: &mut World, snapshot: &EntitySnapshot) -> EntityId {
+    let entity = spawn_entities(world, snapshot.mask, 1)[0];
+    for (mask, value) in snapshot.components.iter() {
+        if let Some(access) = history
+            .components
+            .iter()
+            .find(|access| access.mask == *mask)
+        {
+            (access.restore)(world, entity, *mask, value);
+        }
+    }
+    entity
+}
+
+/// Points every component holding entity ids at the current ids of recreated entities,
+/// including values restored from snapshots taken before the recreation
+fn remap_entities(history: &EditHistory, world: &mut World) {
+    for access in history.components.iter() {
+        let Some(entities) = access.entities else {
+            continue;
+        };
+        for entity in query_entities(world, access.mask) {
+            (entities.remap)(world, entity, access.mask, &history.entity_map);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{Name, LOCAL_TRANSFORM, NAME, SKIN};
+
+    fn name(world: &World, entity: EntityId) -> Option<&str> {
+        get_component::<Name>(world, entity, NAME).map(|Name(name)| name.as_str())
+    }
+
+    fn skin_joints(world: &World, entity: EntityId) -> Vec<EntityId> {
+        get_component::<Skin>(world, entity, SKIN)
+            .map(|skin| skin.joints.clone())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn every_world_component_is_snapshotted() {
+        let history = EditHistory::default();
+        let masks = history
+            .components
+            .iter()
+            .fold(0, |masks, access| masks | access.mask);
+        assert_eq!(
+            history.components.len() as u32,
+            crate::world::Component::AUDIO_LISTENER as u32 + 1
+        );
+        assert_eq!(masks.count_ones() as usize, history.components.len());
+        let remapped = history
+            .components
+            .iter()
+            .filter(|access| access.entities.is_some())
+            .map(|access| access.mask)
+            .fold(0, |masks, mask| masks | mask);
+        assert_eq!(remapped, PARENT | SKIN);
+    }
+
+    #[test]
+    fn undo_and_redo_round_trip_component_edits() {
+        let mut world = World::default();
+        let mut history = EditHistory::default();
+        let entity = spawn_entity(&mut history, &mut world, NAME | LOCAL_TRANSFORM);
+        assert!(set_component(
+            &mut history,
+            &mut world,
+            entity,
+            NAME,
+            Name("Edited".to_string())
+        ));
+        assert_eq!(undo_labels(&history), ["Spawn entity", "Edit component"]);
+
+        assert!(undo(&mut history, &mut world));
+        assert_eq!(name(&world, entity), Some(""));
+        assert!(redo(&mut history, &mut world));
+        assert_eq!(name(&world, entity), Some("Edited"));
+
+        assert!(undo(&mut history, &mut world));
+        assert!(undo(&mut history, &mut world));
+        assert!(component_mask(&world, entity).is_none());
+        assert!(!can_undo(&history));
+        assert!(redo(&mut history, &mut world));
+        assert!(redo(&mut history, &mut world));
+        let entity = current_entity(&history, entity);
+        assert_eq!(name(&world, entity), Some("Edited"));
+    }
+
+    #[test]
+    fn undoing_a_despawn_remaps_parents_and_skin_joints() {
+        let mut world = World::default();
+        let mut history = EditHistory::default();
+        let root = spawn_entities(&mut world, NAME | SKIN, 1)[0];
+        let joint = spawn_entities(&mut world, NAME | PARENT, 1)[0];
+        let skinned = spawn_entities(&mut world, SKIN, 1)[0];
+        *get_component_mut::<Parent>(&mut world, joint, PARENT).unwrap() = Parent(root);
+        for entity in [root, skinned] {
+            get_component_mut::<Skin>(&mut world, entity, SKIN)
+                .unwrap()
+                .joints = vec![joint];
+        }
+
+        despawn_entity(&mut history, &mut world, root);
+        assert!(component_mask(&world, joint).is_none());
+        assert!(undo(&mut history, &mut world));
+
+        let (root, joint) = (
+            current_entity(&history, root),
+            current_entity(&history, joint),
+        );
+        assert_eq!(
+            get_component::<Parent>(&world, joint, PARENT),
+            Some(&Parent(root))
+        );
+        assert_eq!(skin_joints(&world, root), [joint]);
+        assert_eq!(skin_joints(&world, skinned), [joint]);
+
+        // A second round trip recreates the entities again
+        assert!(redo(&mut history, &mut world));
+        assert!(undo(&mut history, &mut world));
+        let joint = current_entity(&history, joint);
+        assert_eq!(skin_joints(&world, skinned), [joint]);
+    }
+
+    #[test]
+    fn recreated_ids_are_forgotten_with_their_transactions() {
+        let mut world = World::default();
+        let mut history = EditHistory::default();
+        let entity = spawn_entities(&mut world, NAME, 1)[0];
+        let other = spawn_entities(&mut world, NAME, 1)[0];
+        despawn_entity(&mut history, &mut world, entity);
+        assert!(undo(&mut history, &mut world));
+        assert_eq!(history.entity_map.len(), 1);
+
+        // The despawn is still redoable, so its mapping is kept
+        set_history_depth(&mut history, 10);
+        assert_eq!(history.entity_map.len(), 1);
+
+        // A new edit drops the redo stack and with it the only reference
+        set_component(&mut history, &mut world, other, NAME, Name("Other".into()));
+        assert!(history.entity_map.is_empty());
+    }
+}
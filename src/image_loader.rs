@@ -0,0 +1,398 @@
+use crate::{
+    mipmap::{mip_level_count, mip_level_size},
+    texture::image_data_size,
+    world::{Image, ImageFormat},
+};
+use std::path::Path;
+
+const KTX2_MAGIC: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+const DDS_MAGIC: [u8; 4] = *b"DDS ";
+
+#[derive(Debug)]
+pub enum ImageLoadError {
+    Io(std::io::Error),
+    Decode(image::ImageError),
+    Ktx2(ktx2::ParseError),
+    Dds(ddsfile::Error),
+    /// The container stores a pixel format that has no `ImageFormat` equivalent
+    UnsupportedFormat(String),
+    /// The container parsed, but its contents are inconsistent
+    InvalidData(String),
+}
+
+impl std::fmt::Display for ImageLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "Failed to read image: {error}"),
+            Self::Decode(error) => write!(f, "Failed to decode image: {error}"),
+            Self::Ktx2(error) => write!(f, "Failed to parse KTX2 image: {error}"),
+            Self::Dds(error) => write!(f, "Failed to parse DDS image: {error}"),
+            Self::UnsupportedFormat(format) => write!(f, "Unsupported image format: {format}"),
+            Self::InvalidData(reason) => write!(f, "Invalid image data: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ImageLoadError {}
+
+impl From<std::io::Error> for ImageLoadError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<image::ImageError> for ImageLoadError {
+    fn from(error: image::ImageError) -> Self {
+        Self::Decode(error)
+    }
+}
+
+impl From<ktx2::ParseError> for ImageLoadError {
+    fn from(error: ktx2::ParseError) -> Self {
+        Self::Ktx2(error)
+    }
+}
+
+impl From<ddsfile::Error> for ImageLoadError {
+    fn from(error: ddsfile::Error) -> Self {
+        Self::Dds(error)
+    }
+}
+
+/// Reads and decodes an image file
+pub fn load_image(path: impl AsRef<Path>) -> Result<Image, ImageLoadError> {
+    decode_image_bytes(&std::fs::read(path)?)
+}
+
+/// Decodes PNG, JPEG, HDR, EXR, KTX2 or DDS data, detected from its contents rather than an extension
+pub fn decode_image_bytes(bytes: &[u8]) -> Result<Image, ImageLoadError> {
+    if bytes.starts_with(&KTX2_MAGIC) {
+        decode_ktx2(bytes)
+    } else if bytes.starts_with(&DDS_MAGIC) {
+        decode_dds(bytes)
+    } else {
+        decode_common(bytes)
+    }
+}
+
+/// 8-bit images decode to `R8G8B8A8`, 16-bit to `R16G16B16A16` and floating point to `R32G32B32A32F`
+fn decode_common(bytes: &[u8]) -> Result<Image, ImageLoadError> {
+    let decoded = image::ImageReader::new(std::io::Cursor::new(bytes))
+        .with_guessed_format()?
+        .decode()?;
+    let (width, height) = (decoded.width(), decoded.height());
+    let (pixels, format) = match decoded {
+        image::DynamicImage::ImageLuma16(_)
+        | image::DynamicImage::ImageLumaA16(_)
+        | image::DynamicImage::ImageRgb16(_)
+        | image::DynamicImage::ImageRgba16(_) => (
+            decoded
+                .to_rgba16()
+                .into_raw()
+                .iter()
+                .flat_map(|channel| channel.to_le_bytes())
+                .collect(),
+            ImageFormat::R16G16B16A16,
+        ),
+        image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_) => (
+            bytemuck::cast_slice(&decoded.to_rgba32f().into_raw()).to_vec(),
+            ImageFormat::R32G32B32A32F,
+        ),
+        _ => (decoded.to_rgba8().into_raw(), ImageFormat::R8G8B8A8),
+    };
+    Ok(Image {
+        pixels,
+        format,
+        width,
+        height,
+        mip_levels: Vec::new(),
+    })
+}
+
+fn decode_ktx2(bytes: &[u8]) -> Result<Image, ImageLoadError> {
+    let reader = ktx2::Reader::new(bytes)?;
+    let header = reader.header();
+    if header.supercompression_scheme.is_some() {
+        return Err(ImageLoadError::UnsupportedFormat(format!(
+            "KTX2 supercompression {:?}",
+            header.supercompression_scheme
+        )));
+    }
+    if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count > 1 {
+        return Err(ImageLoadError::UnsupportedFormat(
+            "KTX2 volume, array or cubemap texture".to_string(),
+        ));
+    }
+    let format = header
+        .format
+        .and_then(map_ktx2_format)
+        .ok_or_else(|| ImageLoadError::UnsupportedFormat(format!("KTX2 {:?}", header.format)))?;
+    let levels = reader
+        .levels()
+        .map(|level| level.data.to_vec())
+        .collect::<Vec<_>>();
+    build_image(
+        format,
+        header.pixel_width,
+        header.pixel_height.max(1),
+        levels,
+    )
+}
+
+fn map_ktx2_format(format: ktx2::Format) -> Option<ImageFormat> {
+    Some(match format {
+        ktx2::Format::R8_UNORM | ktx2::Format::R8_SRGB => ImageFormat::R8,
+        ktx2::Format::R8G8_UNORM => ImageFormat::R8G8,
+        ktx2::Format::R8G8B8_UNORM | ktx2::Format::R8G8B8_SRGB => ImageFormat::R8G8B8,
+        ktx2::Format::R8G8B8A8_UNORM | ktx2::Format::R8G8B8A8_SRGB => ImageFormat::R8G8B8A8,
+        ktx2::Format::B8G8R8_UNORM | ktx2::Format::B8G8R8_SRGB => ImageFormat::B8G8R8,
+        ktx2::Format::B8G8R8A8_UNORM | ktx2::Format::B8G8R8A8_SRGB => ImageFormat::B8G8R8A8,
+        ktx2::Format::R16_UNORM => ImageFormat::R16,
+        ktx2::Format::R16G16_UNORM => ImageFormat::R16G16,
+        ktx2::Format::R16G16B16_UNORM => ImageFormat::R16G16B16,
+        ktx2::Format::R16G16B16A16_UNORM => ImageFormat::R16G16B16A16,
+        ktx2::Format::R16_SFLOAT => ImageFormat::R16F,
+        ktx2::Format::R16G16_SFLOAT => ImageFormat::R16G16F,
+        ktx2::Format::R16G16B16_SFLOAT => ImageFormat::R16G16B16F,
+        ktx2::Format::R16G16B16A16_SFLOAT => ImageFormat::R16G16B16A16F,
+        ktx2::Format::R32_SFLOAT => ImageFormat::R32F,
+        ktx2::Format::R32G32_SFLOAT => ImageFormat::R32G32F,
+        ktx2::Format::R32G32B32_SFLOAT => ImageFormat::R32G32B32F,
+        ktx2::Format::R32G32B32A32_SFLOAT => ImageFormat::R32G32B32A32F,
+        ktx2::Format::BC1_RGB_UNORM_BLOCK
+        | ktx2::Format::BC1_RGB_SRGB_BLOCK
+        | ktx2::Format::BC1_RGBA_UNORM_BLOCK
+        | ktx2::Format::BC1_RGBA_SRGB_BLOCK => ImageFormat::BC1,
+        ktx2::Format::BC2_UNORM_BLOCK | ktx2::Format::BC2_SRGB_BLOCK => ImageFormat::BC2,
+        ktx2::Format::BC3_UNORM_BLOCK | ktx2::Format::BC3_SRGB_BLOCK => ImageFormat::BC3,
+        ktx2::Format::BC4_UNORM_BLOCK => ImageFormat::BC4,
+        ktx2::Format::BC5_UNORM_BLOCK => ImageFormat::BC5,
+        ktx2::Format::BC6H_UFLOAT_BLOCK => ImageFormat::BC6H,
+        ktx2::Format::BC7_UNORM_BLOCK | ktx2::Format::BC7_SRGB_BLOCK => ImageFormat::BC7,
+        _ => return None,
+    })
+}
+
+fn decode_dds(bytes: &[u8]) -> Result<Image, ImageLoadError> {
+    let dds = ddsfile::Dds::read(bytes)?;
+    if dds.get_depth() > 1 || dds.get_num_array_layers() > 1 {
+        return Err(ImageLoadError::UnsupportedFormat(
+            "DDS volume, array or cubemap texture".to_string(),
+        ));
+    }
+    let format = match (dds.get_dxgi_format(), dds.get_d3d_format()) {
+        (Some(format), _) => map_dxgi_format(format)
+            .ok_or_else(|| ImageLoadError::UnsupportedFormat(format!("DDS {format:?}")))?,
+        (None, Some(format)) => map_d3d_format(format)
+            .ok_or_else(|| ImageLoadError::UnsupportedFormat(format!("DDS {format:?}")))?,
+        (None, None) => {
+            return Err(ImageLoadError::UnsupportedFormat(
+                "DDS pixel format".to_string(),
+            ))
+        }
+    };
+
+    let (width, height) = (dds.get_width(), dds.get_height());
+    let level_count = dds.get_num_mipmap_levels().max(1);
+    if level_count > mip_level_count(width, height) {
+        return Err(ImageLoadError::InvalidData(format!(
+            "{level_count} DDS mip levels do not fit a {width}x{height} image"
+        )));
+    }
+    // With a single layer the mip chain starts at the beginning of the data
+    let mut data = dds.data.as_slice();
+    let mut levels = Vec::new();
+    for level in 0..level_count {
+        let (level_width, level_height) = mip_level_size(width, height, level);
+        let size = image_data_size(format, level_width, level_height);
+        if data.len() < size {
+            return Err(ImageLoadError::InvalidData(format!(
+                "DDS mip level {level} is cut short"
+            )));
+        }
+        let (level_data, rest) = data.split_at(size);
+        levels.push(level_data.to_vec());
+        data = rest;
+    }
+    build_image(format, width, height, levels)
+}
+
+fn map_dxgi_format(format: ddsfile::DxgiFormat) -> Option<ImageFormat> {
+    use ddsfile::DxgiFormat;
+    Some(match format {
+        DxgiFormat::R8_UNorm => ImageFormat::R8,
+        DxgiFormat::R8G8_UNorm => ImageFormat::R8G8,
+        DxgiFormat::R8G8B8A8_UNorm | DxgiFormat::R8G8B8A8_UNorm_sRGB => ImageFormat::R8G8B8A8,
+        DxgiFormat::B8G8R8A8_UNorm | DxgiFormat::B8G8R8A8_UNorm_sRGB => ImageFormat::B8G8R8A8,
+        DxgiFormat::R16_UNorm => ImageFormat::R16,
+        DxgiFormat::R16G16_UNorm => ImageFormat::R16G16,
+        DxgiFormat::R16G16B16A16_UNorm => ImageFormat::R16G16B16A16,
+        DxgiFormat::R16_Float => ImageFormat::R16F,
+        DxgiFormat::R16G16_Float => ImageFormat::R16G16F,
+        DxgiFormat::R16G16B16A16_Float => ImageFormat::R16G16B16A16F,
+        DxgiFormat::R32_Float => ImageFormat::R32F,
+        DxgiFormat::R32G32_Float => ImageFormat::R32G32F,
+        DxgiFormat::R32G32B32_Float => ImageFormat::R32G32B32F,
+        DxgiFormat::R32G32B32A32_Float => ImageFormat::R32G32B32A32F,
+        DxgiFormat::BC1_UNorm | DxgiFormat::BC1_UNorm_sRGB => ImageFormat::BC1,
+        DxgiFormat::BC2_UNorm | DxgiFormat::BC2_UNorm_sRGB => ImageFormat::BC2,
+        DxgiFormat::BC3_UNorm | DxgiFormat::BC3_UNorm_sRGB => ImageFormat::BC3,
+        DxgiFormat::BC4_UNorm => ImageFormat::BC4,
+        DxgiFormat::BC5_UNorm => ImageFormat::BC5,
+        DxgiFormat::BC6H_UF16 => ImageFormat::BC6H,
+        DxgiFormat::BC7_UNorm | DxgiFormat::BC7_UNorm_sRGB => ImageFormat::BC7,
+        _ => return None,
+    })
+}
+
+/// Legacy formats are named from the most significant bit, so `A8R8G8B8` is stored as BGRA
+fn map_d3d_format(format: ddsfile::D3DFormat) -> Option<ImageFormat> {
+    use ddsfile::D3DFormat;
+    Some(match format {
+        D3DFormat::L8 | D3DFormat::A8 => ImageFormat::R8,
+        D3DFormat::A8B8G8R8 => ImageFormat::R8G8B8A8,
+        D3DFormat::A8R8G8B8 => ImageFormat::B8G8R8A8,
+        D3DFormat::R8G8B8 => ImageFormat::B8G8R8,
+        D3DFormat::L16 => ImageFormat::R16,
+        D3DFormat::G16R16 => ImageFormat::R16G16,
+        D3DFormat::A16B16G16R16 => ImageFormat::R16G16B16A16,
+        D3DFormat::R16F => ImageFormat::R16F,
+        D3DFormat::G16R16F => ImageFormat::R16G16F,
+        D3DFormat::A16B16G16R16F => ImageFormat::R16G16B16A16F,
+        D3DFormat::R32F => ImageFormat::R32F,
+        D3DFormat::G32R32F => ImageFormat::R32G32F,
+        D3DFormat::A32B32G32R32F => ImageFormat::R32G32B32A32F,
+        D3DFormat::DXT1 => ImageFormat::BC1,
+        D3DFormat::DXT3 => ImageFormat::BC2,
+        D3DFormat::DXT5 => ImageFormat::BC3,
+        _ => return None,
+    })
+}
+
+/// Splits container levels into the base image and its mip chain, checking every level's size
+fn build_image(
+    format: ImageFormat,
+    width: u32,
+    height: u32,
+    levels: Vec<Vec<u8>>,
+) -> Result<Image, ImageLoadError> {
+    if width == 0 || height == 0 {
+        return Err(ImageLoadError::InvalidData(format!(
+            "Image size {width}x{height} is empty"
+        )));
+    }
+    if levels.len() > mip_level_count(width, height) as usize {
+        return Err(ImageLoadError::InvalidData(format!(
+            "{} mip levels do not fit a {width}x{height} image",
+            levels.len()
+        )));
+    }
+    for (level, data) in levels.iter().enumerate() {
+        let (level_width, level_height) = mip_level_size(width, height, level as u32);
+        let expected = image_data_size(format, level_width, level_height);
+        if data.len() != expected {
+            return Err(ImageLoadError::InvalidData(format!(
+                "Mip level {level} has {} bytes, expected {expected}",
+                data.len()
+            )));
+        }
+    }
+
+    let mut levels = levels.into_iter();
+    let pixels = levels
+        .next()
+        .ok_or_else(|| ImageLoadError::InvalidData("Image has no levels".to_string()))?;
+    Ok(Image {
+        pixels,
+        format,
+        width,
+        height,
+        mip_levels: levels.collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dds_bytes(width: u32, height: u32, mipmap_levels: u32) -> Vec<u8> {
+        let mut dds = ddsfile::Dds::new_dxgi(ddsfile::NewDxgiParams {
+            height,
+            width,
+            depth: None,
+            format: ddsfile::DxgiFormat::R8G8B8A8_UNorm,
+            mipmap_levels: Some(mipmap_levels),
+            array_layers: None,
+            caps2: None,
+            is_cubemap: false,
+            resource_dimension: ddsfile::D3D10ResourceDimension::Texture2D,
+            alpha_mode: ddsfile::AlphaMode::Unknown,
+        })
+        .unwrap();
+        for (index, byte) in dds.data.iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+        let mut bytes = Vec::new();
+        dds.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    /// The mip count is the eighth little-endian word after the magic
+    const DDS_MIP_COUNT_OFFSET: usize = 28;
+
+    #[test]
+    fn dds_mip_chains_are_split_into_levels() {
+        let image = decode_image_bytes(&dds_bytes(4, 2, 3)).unwrap();
+        assert_eq!(image.format, ImageFormat::R8G8B8A8);
+        assert_eq!((image.width, image.height), (4, 2));
+        assert_eq!(image.pixels.len(), 4 * 2 * 4);
+        let sizes = image.mip_levels.iter().map(Vec::len).collect::<Vec<_>>();
+        assert_eq!(sizes, vec![2 * 4, 4]);
+        assert_eq!(image.mip_levels[0][0], 32);
+    }
+
+    #[test]
+    fn dds_files_claiming_too_many_mips_are_rejected() {
+        for claimed in [4, 32, 40, u32::MAX] {
+            let mut bytes = dds_bytes(4, 2, 3);
+            bytes[DDS_MIP_COUNT_OFFSET..DDS_MIP_COUNT_OFFSET + 4]
+                .copy_from_slice(&claimed.to_le_bytes());
+            // Enough trailing data for dozens of 1x1 levels, so only the count is wrong
+            bytes.extend(std::iter::repeat_n(0, 64 * 4));
+            assert!(
+                matches!(
+                    decode_image_bytes(&bytes),
+                    Err(ImageLoadError::InvalidData(_))
+                ),
+                "{claimed} mip levels"
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_dds_levels_are_rejected() {
+        let mut bytes = dds_bytes(4, 4, 3);
+        bytes.truncate(bytes.len() - 1);
+        assert!(matches!(
+            decode_image_bytes(&bytes),
+            Err(ImageLoadError::InvalidData(_))
+        ));
+    }
+
+    #[test]
+    fn png_images_decode_to_rgba8() {
+        let mut bytes = Vec::new();
+        image::RgbImage::from_raw(2, 1, vec![255, 0, 0, 0, 255, 0])
+            .unwrap()
+            .write_to(
+                &mut std::io::Cursor::new(&mut bytes),
+                image::ImageFormat::Png,
+            )
+            .unwrap();
+        let image = decode_image_bytes(&bytes).unwrap();
+        assert_eq!(image.format, ImageFormat::R8G8B8A8);
+        assert_eq!(image.pixels, vec![255, 0, 0, 255, 0, 255, 0, 255]);
+    }
+}
@@ -0,0 +1,837 @@
+use crate::world::{is_key_pressed, Keyboard, Mouse, MouseButtons, World};
+use nalgebra_glm::Vec2;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use winit::{
+    event::{ElementState, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+};
+
+/// Something that is either held or not
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Button {
+    Key(KeyCode),
+    /// Only the left, middle and right buttons are tracked by `Mouse`
+    Mouse(MouseButton),
+    /// A button on the player's gamepad
+    Gamepad(GamepadButton),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    LeftStick,
+    RightStick,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GamepadStick {
+    Left,
+    Right,
+}
+
+bitflags::bitflags! {
+    /// Modifier keys, either side
+    #[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Modifiers: u8 {
+        const SHIFT = 0b0000_0001;
+        const CONTROL = 0b0000_0010;
+        const ALT = 0b0000_0100;
+        const SUPER = 0b0000_1000;
+    }
+}
+
+/// Buttons held together, such as a chord or a key with modifiers. Modifiers
+/// must be held when given, and are otherwise ignored. When held bindings of one
+/// player share a button, only those needing the most modifiers see it, so
+/// Ctrl+S does not also press an action bound to S.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ButtonBinding {
+    pub buttons: Vec<Button>,
+    pub modifiers: Modifiers,
+}
+
+/// A source of a two dimensional value
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AxisBinding {
+    /// Buttons pushing the value toward -x, +x, -y and +y, limited to a length of one
+    Buttons {
+        left: Option<Button>,
+        right: Option<Button>,
+        down: Option<Button>,
+        up: Option<Button>,
+    },
+    /// Cursor movement this frame in pixels, scaled per component
+    MouseMotion { scale: Vec2 },
+    /// Wheel movement this frame in lines, scaled per component
+    Wheel { scale: Vec2 },
+    /// A stick of the player's gamepad with a radial dead zone
+    Stick { stick: GamepadStick, dead_zone: f32 },
+    /// One axis of the player's gamepad, placed into the value by `scale`
+    GamepadAxis {
+        axis: GamepadAxis,
+        dead_zone: f32,
+        scale: Vec2,
+    },
+}
+
+/// Everything that triggers a named action
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Action {
+    pub buttons: Vec<ButtonBinding>,
+    pub axes: Vec<AxisBinding>,
+}
+
+/// One player's actions by name
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputMap {
+    pub actions: HashMap<String, Action>,
+}
+
+/// An action's state this frame. Actions are pressed while any button binding
+/// is held or any axis is away from zero. Held buttons give a value of (1, 0).
+#[derive(Default, Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ActionState {
+    pub pressed: bool,
+    pub just_pressed: bool,
+    pub just_released: bool,
+    pub value: Vec2,
+}
+
+/// State of a connected gamepad, with sticks and triggers from -1 to 1 and 0 to 1
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Gamepad {
+    pub buttons: Vec<GamepadButton>,
+    pub axes: HashMap<GamepadAxis, f32>,
+}
+
+/// Gamepads in connection order, the nth belonging to the nth player
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Gamepads {
+    pub pads: Vec<Gamepad>,
+}
+
+/// Action bindings for each `Player` index, and the resulting action states
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct Input {
+    pub maps: HashMap<u8, InputMap>,
+    #[serde(skip)]
+    states: HashMap<u8, HashMap<String, ActionState>>,
+}
+
+pub fn keyboard_modifiers(keyboard: &Keyboard) -> Modifiers {
+    let held = |left, right| is_key_pressed(keyboard, left) || is_key_pressed(keyboard, right);
+    let mut modifiers = Modifiers::empty();
+    modifiers.set(
+        Modifiers::SHIFT,
+        held(KeyCode::ShiftLeft, KeyCode::ShiftRight),
+    );
+    modifiers.set(
+        Modifiers::CONTROL,
+        held(KeyCode::ControlLeft, KeyCode::ControlRight),
+    );
+    modifiers.set(Modifiers::ALT, held(KeyCode::AltLeft, KeyCode::AltRight));
+    modifiers.set(
+        Modifiers::SUPER,
+        held(KeyCode::SuperLeft, KeyCode::SuperRight),
+    );
+    modifiers
+}
+
+pub fn is_button_pressed(
+    keyboard: &Keyboard,
+    mouse: &Mouse,
+    gamepad: Option<&Gamepad>,
+    button: Button,
+) -> bool {
+    match button {
+        Button::Key(key) => is_key_pressed(keyboard, key),
+        Button::Mouse(button) => {
+            mouse_button_flag(button).is_some_and(|flag| mouse.buttons.contains(flag))
+        }
+        Button::Gamepad(button) => gamepad.is_some_and(|gamepad| gamepad.buttons.contains(&button)),
+    }
+}
+
+fn mouse_button_flag(button: MouseButton) -> Option<MouseButtons> {
+    match button {
+        MouseButton::Left => Some(MouseButtons::LEFT_CLICKED),
+        MouseButton::Middle => Some(MouseButtons::MIDDLE_CLICKED),
+        MouseButton::Right => Some(MouseButtons::RIGHT_CLICKED),
+        _ => None,
+    }
+}
+
+/// Scales a value so it grows from zero at the edge of the dead zone to one at full tilt
+pub fn apply_dead_zone(value: Vec2, dead_zone: f32) -> Vec2 {
+    let length = value.norm();
+    if length <= dead_zone || length <= f32::EPSILON {
+        return Vec2::zeros();
+    }
+    let scaled = ((length - dead_zone) / (1.0 - dead_zone).max(f32::EPSILON)).min(1.0);
+    value * (scaled / length)
+}
+
+/// Buttons of the map's held bindings that need modifiers, with those modifiers
+pub fn claimed_buttons(
+    keyboard: &Keyboard,
+    mouse: &Mouse,
+    gamepad: Option<&Gamepad>,
+    map: &InputMap,
+) -> Vec<(Button, Modifiers)> {
+    let modifiers = keyboard_modifiers(keyboard);
+    map.actions
+        .values()
+        .flat_map(|action| action.buttons.iter())
+        .filter(|binding| {
+            !binding.modifiers.is_empty()
+                && modifiers.contains(binding.modifiers)
+                && binding
+                    .buttons
+                    .iter()
+                    .all(|button| is_button_pressed(keyboard, mouse, gamepad, *button))
+        })
+        .flat_map(|binding| {
+            binding
+                .buttons
+                .iter()
+                .map(|button| (*button, binding.modifiers))
+        })
+        .collect()
+}
+
+/// Whether a button is held and not claimed by a binding needing more modifiers
+fn is_button_available(
+    keyboard: &Keyboard,
+    mouse: &Mouse,
+    gamepad: Option<&Gamepad>,
+    claimed: &[(Button, Modifiers)],
+    button: Button,
+    modifiers: Modifiers,
+) -> bool {
+    is_button_pressed(keyboard, mouse, gamepad, button)
+        && !claimed.iter().any(|(claimed, claimed_modifiers)| {
+            *claimed == button
+                && *claimed_modifiers != modifiers
+                && claimed_modifiers.contains(modifiers)
+        })
+}
+
+fn axis_value(
+    keyboard: &Keyboard,
+    mouse: &Mouse,
+    gamepad: Option<&Gamepad>,
+    claimed: &[(Button, Modifiers)],
+    binding: &AxisBinding,
+) -> Vec2 {
+    let gamepad_axis = |axis| {
+        gamepad
+            .and_then(|gamepad| gamepad.axes.get(&axis))
+            .copied()
+            .unwrap_or(0.0)
+    };
+    match binding {
+        AxisBinding::Buttons {
+            left,
+            right,
+            down,
+            up,
+        } => {
+            let held = |button: &Option<Button>| {
+                button.is_some_and(|button| {
+                    is_button_available(
+                        keyboard,
+                        mouse,
+                        gamepad,
+                        claimed,
+                        button,
+                        Modifiers::empty(),
+                    )
+                }) as i32 as f32
+            };
+            let value = Vec2::new(held(right) - held(left), held(up) - held(down));
+            if value.norm() > 1.0 {
+                value.normalize()
+            } else {
+                value
+            }
+        }
+        AxisBinding::MouseMotion { scale } => mouse.position_delta.component_mul(scale),
+        AxisBinding::Wheel { scale } => mouse.wheel_delta.component_mul(scale),
+        AxisBinding::Stick { stick, dead_zone } => {
+            let (x, y) = match stick {
+                GamepadStick::Left => (GamepadAxis::LeftStickX, GamepadAxis::LeftStickY),
+                GamepadStick::Right => (GamepadAxis::RightStickX, GamepadAxis::RightStickY),
+            };
+            apply_dead_zone(Vec2::new(gamepad_axis(x), gamepad_axis(y)), *dead_zone)
+        }
+        AxisBinding::GamepadAxis {
+            axis,
+            dead_zone,
+            scale,
+        } => scale * apply_dead_zone(Vec2::new(gamepad_axis(*axis), 0.0), *dead_zone).x,
+    }
+}
+
+/// An action's pressed state and value from the current input, without edges.
+/// `claimed` comes from `claimed_buttons` for the map holding the action.
+pub fn evaluate_action(
+    keyboard: &Keyboard,
+    mouse: &Mouse,
+    gamepad: Option<&Gamepad>,
+    claimed: &[(Button, Modifiers)],
+    action: &Action,
+) -> (bool, Vec2) {
+    let modifiers = keyboard_modifiers(keyboard);
+    let held = action.buttons.iter().any(|binding| {
+        !binding.buttons.is_empty()
+            && modifiers.contains(binding.modifiers)
+            && binding.buttons.iter().all(|button| {
+                is_button_available(
+                    keyboard,
+                    mouse,
+                    gamepad,
+                    claimed,
+                    *button,
+                    binding.modifiers,
+                )
+            })
+    });
+    let mut value = action
+        .axes
+        .iter()
+        .map(|binding| axis_value(keyboard, mouse, gamepad, claimed, binding))
+        .fold(Vec2::zeros(), |sum, value| sum + value);
+    if held && value == Vec2::zeros() {
+        value = Vec2::new(1.0, 0.0);
+    }
+    (held || value != Vec2::zeros(), value)
+}
+
+/// Updates every player's action states from the current input, detecting presses and releases
+pub fn update_input(input: &mut Input, keyboard: &Keyboard, mouse: &Mouse, gamepads: &Gamepads) {
+    let Input { maps, states } = input;
+    states.retain(|player, _| maps.contains_key(player));
+    for (player, map) in maps.iter() {
+        let gamepad = gamepads.pads.get(*player as usize);
+        let claimed = claimed_buttons(keyboard, mouse, gamepad, map);
+        let states = states.entry(*player).or_default();
+        states.retain(|name, _| map.actions.contains_key(name));
+        for (name, action) in map.actions.iter() {
+            let (pressed, value) = evaluate_action(keyboard, mouse, gamepad, &claimed, action);
+            let state = states.entry(name.clone()).or_default();
+            *state = ActionState {
+                pressed,
+                just_pressed: pressed && !state.pressed,
+                just_released: !pressed && state.pressed,
+                value,
+            };
+        }
+    }
+}
+
+pub fn update_input_system(world: &mut World) {
+    let resources = &mut world.resources;
+    let center = Vec2::new(
+        resources.viewport_width as f32,
+        resources.viewport_height as f32,
+    ) / 2.0;
+    resources.mouse.offset_from_center = resources.mouse.position - center;
+    update_input(
+        &mut resources.input,
+        &resources.keyboard,
+        &resources.mouse,
+        &resources.gamepads,
+    );
+}
+
+pub fn action_state(input: &Input, player: u8, action: &str) -> ActionState {
+    input
+        .states
+        .get(&player)
+        .and_then(|states| states.get(action))
+        .copied()
+        .unwrap_or_default()
+}
+
+pub fn action_pressed(input: &Input, player: u8, action: &str) -> bool {
+    action_state(input, player, action).pressed
+}
+
+pub fn action_just_pressed(input: &Input, player: u8, action: &str) -> bool {
+    action_state(input, player, action).just_pressed
+}
+
+pub fn action_just_released(input: &Input, player: u8, action: &str) -> bool {
+    action_state(input, player, action).just_released
+}
+
+pub fn action_value(input: &Input, player: u8, action: &str) -> Vec2 {
+    action_state(input, player, action).value
+}
+
+/// Replaces an action's bindings for a player, creating their map if needed
+pub fn bind_action(input: &mut Input, player: u8, name: &str, action: Action) {
+    input
+        .maps
+        .entry(player)
+        .or_default()
+        .actions
+        .insert(name.to_string(), action);
+}
+
+/// A window event that changes input resources, in a form that can be recorded
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum InputEvent {
+    Key {
+        key: KeyCode,
+        state: ElementState,
+    },
+    CursorMoved {
+        position: Vec2,
+    },
+    MouseButton {
+        button: MouseButton,
+        state: ElementState,
+    },
+    /// Scrolling in lines
+    MouseWheel {
+        delta: Vec2,
+    },
+    Resized {
+        width: u32,
+        height: u32,
+    },
+}
+
+/// The input change a window event makes. Presses and scrolling the UI consumed
+/// are dropped, but releases and cursor movement are kept so buttons do not stick.
+pub fn input_event(event: &WindowEvent, consumed: bool) -> Option<InputEvent> {
+    match event {
+        WindowEvent::KeyboardInput {
+            event:
+                KeyEvent {
+                    physical_key: PhysicalKey::Code(key),
+                    state,
+                    ..
+                },
+            ..
+        } if !consumed || *state == ElementState::Released => Some(InputEvent::Key {
+            key: *key,
+            state: *state,
+        }),
+        WindowEvent::CursorMoved { position, .. } => Some(InputEvent::CursorMoved {
+            position: Vec2::new(position.x as f32, position.y as f32),
+        }),
+        WindowEvent::MouseInput { state, button, .. }
+            if !consumed || *state == ElementState::Released =>
+        {
+            Some(InputEvent::MouseButton {
+                button: *button,
+                state: *state,
+            })
+        }
+        WindowEvent::MouseWheel { delta, .. } if !consumed => Some(InputEvent::MouseWheel {
+            // Touchpads report pixels, roughly a line per 20 of them
+            delta: match delta {
+                MouseScrollDelta::LineDelta(x, y) => Vec2::new(*x, *y),
+                MouseScrollDelta::PixelDelta(position) => {
+                    Vec2::new(position.x as f32, position.y as f32) / 20.0
+                }
+            },
+        }),
+        WindowEvent::Resized(size) => Some(InputEvent::Resized {
+            width: size.width.max(1),
+            height: size.height.max(1),
+        }),
+        _ => None,
+    }
+}
+
+/// Records an input event into the `Keyboard`, `Mouse` and viewport size resources
+pub fn apply_input_event(world: &mut World, event: &InputEvent) {
+    let resources = &mut world.resources;
+    match *event {
+        InputEvent::Key { key, state } => {
+            resources.keyboard.keystates.insert(key, state);
+        }
+        InputEvent::CursorMoved { position } => {
+            let mouse = &mut resources.mouse;
+            // The first position recorded has nothing to move from
+            if mouse.buttons.contains(MouseButtons::MOVED) || mouse.position != Vec2::zeros() {
+                mouse.position_delta += position - mouse.position;
+            }
+            mouse.position = position;
+            mouse.buttons.insert(MouseButtons::MOVED);
+        }
+        InputEvent::MouseButton { button, state } => {
+            if let Some(flag) = mouse_button_flag(button) {
+                resources
+                    .mouse
+                    .buttons
+                    .set(flag, state == ElementState::Pressed);
+            }
+        }
+        InputEvent::MouseWheel { delta } => {
+            resources.mouse.wheel_delta += delta;
+            resources.mouse.buttons.insert(MouseButtons::SCROLLED);
+        }
+        InputEvent::Resized { width, height } => {
+            resources.viewport_width = width;
+            resources.viewport_height = height;
+        }
+    }
+}
+
+/// Clears the per-frame mouse motion and scrolling, call once per frame after updates
+pub fn end_mouse_frame(mouse: &mut Mouse) {
+    mouse.position_delta = Vec2::zeros();
+    mouse.wheel_delta = Vec2::zeros();
+    mouse
+        .buttons
+        .remove(MouseButtons::MOVED | MouseButtons::SCROLLED);
+}
+
+/// Reads connected gamepads while alive
+#[cfg(feature = "gamepad")]
+pub struct GamepadInput {
+    gilrs: gilrs::Gilrs,
+}
+
+#[cfg(feature = "gamepad")]
+pub fn start_gamepad_input() -> Result<GamepadInput, String> {
+    gilrs::Gilrs::new()
+        .map(|gilrs| GamepadInput { gilrs })
+        .map_err(|error| error.to_string())
+}
+
+/// Copies the state of connected gamepads into `Gamepads`, in the order they connected
+#[cfg(feature = "gamepad")]
+pub fn poll_gamepads(input: &mut GamepadInput, gamepads: &mut Gamepads) {
+    use gilrs::{Axis, Button as GilrsButton};
+
+    while input.gilrs.next_event().is_some() {}
+    let mut connected = input
+        .gilrs
+        .gamepads()
+        .filter(|(_, gamepad)| gamepad.is_connected())
+        .collect::<Vec<_>>();
+    connected.sort_by_key(|(id, _)| usize::from(*id));
+
+    let buttons = [
+        (GilrsButton::South, GamepadButton::South),
+        (GilrsButton::East, GamepadButton::East),
+        (GilrsButton::North, GamepadButton::North),
+        (GilrsButton::West, GamepadButton::West),
+        (GilrsButton::LeftTrigger, GamepadButton::LeftBumper),
+        (GilrsButton::RightTrigger, GamepadButton::RightBumper),
+        (GilrsButton::LeftTrigger2, GamepadButton::LeftTrigger),
+        (GilrsButton::RightTrigger2, GamepadButton::RightTrigger),
+        (GilrsButton::Select, GamepadButton::Select),
+        (GilrsButton::Start, GamepadButton::Start),
+        (GilrsButton::LeftThumb, GamepadButton::LeftStick),
+        (GilrsButton::RightThumb, GamepadButton::RightStick),
+        (GilrsButton::DPadUp, GamepadButton::DPadUp),
+        (GilrsButton::DPadDown, GamepadButton::DPadDown),
+        (GilrsButton::DPadLeft, GamepadButton::DPadLeft),
+        (GilrsButton::DPadRight, GamepadButton::DPadRight),
+    ];
+    let axes = [
+        (Axis::LeftStickX, GamepadAxis::LeftStickX),
+        (Axis::LeftStickY, GamepadAxis::LeftStickY),
+        (Axis::RightStickX, GamepadAxis::RightStickX),
+        (Axis::RightStickY, GamepadAxis::RightStickY),
+    ];
+    gamepads.pads = connected
+        .into_iter()
+        .map(|(_, gamepad)| {
+            let mut pad = Gamepad {
+                buttons: buttons
+                    .iter()
+                    .filter(|(button, _)| gamepad.is_pressed(*button))
+                    .map(|(_, button)| *button)
+                    .collect(),
+                axes: axes
+                    .iter()
+                    .map(|(axis, ours)| (*ours, gamepad.value(*axis)))
+                    .collect(),
+            };
+            // Analog triggers report through their button's value
+            for (button, axis) in [
+                (GilrsButton::LeftTrigger2, GamepadAxis::LeftTrigger),
+                (GilrsButton::RightTrigger2, GamepadAxis::RightTrigger),
+            ] {
+                let value = gamepad.button_data(button).map_or(0.0, |data| data.value());
+                pad.axes.insert(axis, value);
+            }
+            pad
+        })
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyboard(keys: &[KeyCode]) -> Keyboard {
+        Keyboard {
+            keystates: keys
+                .iter()
+                .map(|key| (*key, ElementState::Pressed))
+                .collect(),
+        }
+    }
+
+    fn key(key: KeyCode) -> Button {
+        Button::Key(key)
+    }
+
+    fn binding(buttons: &[Button], modifiers: Modifiers) -> ButtonBinding {
+        ButtonBinding {
+            buttons: buttons.to_vec(),
+            modifiers,
+        }
+    }
+
+    fn button_action(bindings: Vec<ButtonBinding>) -> Action {
+        Action {
+            buttons: bindings,
+            axes: Vec::new(),
+        }
+    }
+
+    fn wasd() -> AxisBinding {
+        AxisBinding::Buttons {
+            left: Some(key(KeyCode::KeyA)),
+            right: Some(key(KeyCode::KeyD)),
+            down: Some(key(KeyCode::KeyS)),
+            up: Some(key(KeyCode::KeyW)),
+        }
+    }
+
+    /// A map with plain S for going back, Ctrl+S for saving and Ctrl+Shift+S for saving as
+    fn editor_map() -> InputMap {
+        InputMap {
+            actions: HashMap::from([
+                (
+                    "move".to_string(),
+                    Action {
+                        buttons: Vec::new(),
+                        axes: vec![wasd()],
+                    },
+                ),
+                (
+                    "back".to_string(),
+                    button_action(vec![binding(&[key(KeyCode::KeyS)], Modifiers::empty())]),
+                ),
+                (
+                    "save".to_string(),
+                    button_action(vec![binding(&[key(KeyCode::KeyS)], Modifiers::CONTROL)]),
+                ),
+                (
+                    "save_as".to_string(),
+                    button_action(vec![binding(
+                        &[key(KeyCode::KeyS)],
+                        Modifiers::CONTROL | Modifiers::SHIFT,
+                    )]),
+                ),
+            ]),
+        }
+    }
+
+    /// Which of the editor map's actions are pressed with the keys held
+    fn pressed_actions(keys: &[KeyCode]) -> Vec<String> {
+        let mut input = Input::default();
+        input.maps.insert(0, editor_map());
+        update_input(
+            &mut input,
+            &keyboard(keys),
+            &Mouse::default(),
+            &Gamepads::default(),
+        );
+        let mut pressed: Vec<String> = input.maps[&0]
+            .actions
+            .keys()
+            .filter(|name| action_pressed(&input, 0, name))
+            .cloned()
+            .collect();
+        pressed.sort();
+        pressed
+    }
+
+    fn evaluate(keyboard: &Keyboard, mouse: &Mouse, action: &Action) -> (bool, Vec2) {
+        evaluate_action(keyboard, mouse, None, &[], action)
+    }
+
+    #[test]
+    fn modifiers_are_read_from_either_side() {
+        assert_eq!(
+            keyboard_modifiers(&keyboard(&[KeyCode::ShiftRight, KeyCode::ControlLeft])),
+            Modifiers::SHIFT | Modifiers::CONTROL
+        );
+        assert_eq!(keyboard_modifiers(&keyboard(&[])), Modifiers::empty());
+    }
+
+    #[test]
+    fn chords_need_every_button() {
+        let action = button_action(vec![binding(
+            &[key(KeyCode::KeyQ), Button::Mouse(MouseButton::Left)],
+            Modifiers::empty(),
+        )]);
+        let mouse = Mouse {
+            buttons: MouseButtons::LEFT_CLICKED,
+            ..Default::default()
+        };
+        assert_eq!(
+            evaluate(&keyboard(&[KeyCode::KeyQ]), &mouse, &action),
+            (true, Vec2::new(1.0, 0.0))
+        );
+        assert!(!evaluate(&keyboard(&[KeyCode::KeyQ]), &Mouse::default(), &action).0);
+        assert!(!evaluate(&keyboard(&[]), &mouse, &action).0);
+    }
+
+    #[test]
+    fn modifier_bindings_need_their_modifiers() {
+        let action = button_action(vec![binding(&[key(KeyCode::KeyS)], Modifiers::CONTROL)]);
+        let mouse = Mouse::default();
+        assert!(!evaluate(&keyboard(&[KeyCode::KeyS]), &mouse, &action).0);
+        assert!(
+            evaluate(
+                &keyboard(&[KeyCode::KeyS, KeyCode::ControlRight]),
+                &mouse,
+                &action
+            )
+            .0
+        );
+    }
+
+    #[test]
+    fn the_binding_with_the_most_modifiers_wins() {
+        assert_eq!(pressed_actions(&[KeyCode::KeyS]), vec!["back", "move"]);
+        assert_eq!(
+            pressed_actions(&[KeyCode::KeyS, KeyCode::ControlLeft]),
+            vec!["save"]
+        );
+        assert_eq!(
+            pressed_actions(&[KeyCode::KeyS, KeyCode::ControlLeft, KeyCode::ShiftLeft]),
+            vec!["save_as"]
+        );
+    }
+
+    #[test]
+    fn unclaimed_buttons_ignore_extra_modifiers() {
+        assert_eq!(
+            pressed_actions(&[KeyCode::KeyW, KeyCode::ShiftLeft]),
+            vec!["move"]
+        );
+        assert_eq!(
+            pressed_actions(&[KeyCode::KeyW, KeyCode::KeyS, KeyCode::ControlLeft]),
+            vec!["move", "save"]
+        );
+    }
+
+    #[test]
+    fn button_axes_are_limited_to_a_length_of_one() {
+        let action = Action {
+            buttons: Vec::new(),
+            axes: vec![wasd()],
+        };
+        let (pressed, value) = evaluate(
+            &keyboard(&[KeyCode::KeyW, KeyCode::KeyD]),
+            &Mouse::default(),
+            &action,
+        );
+        assert!(pressed);
+        assert!((value - Vec2::new(1.0, 1.0).normalize()).norm() < 1e-6);
+        let (pressed, value) = evaluate(
+            &keyboard(&[KeyCode::KeyA, KeyCode::KeyD]),
+            &Mouse::default(),
+            &action,
+        );
+        assert!(!pressed);
+        assert_eq!(value, Vec2::zeros());
+    }
+
+    #[test]
+    fn mouse_axes_scale_the_frame_movement() {
+        let action = Action {
+            buttons: Vec::new(),
+            axes: vec![
+                AxisBinding::MouseMotion {
+                    scale: Vec2::new(0.5, -0.5),
+                },
+                AxisBinding::Wheel {
+                    scale: Vec2::new(0.0, 2.0),
+                },
+            ],
+        };
+        let mouse = Mouse {
+            position_delta: Vec2::new(4.0, 2.0),
+            wheel_delta: Vec2::new(0.0, 1.0),
+            ..Default::default()
+        };
+        assert_eq!(
+            evaluate(&keyboard(&[]), &mouse, &action),
+            (true, Vec2::new(2.0, 1.0))
+        );
+        assert!(!evaluate(&keyboard(&[]), &Mouse::default(), &action).0);
+    }
+
+    #[test]
+    fn dead_zones_rescale_the_remaining_range() {
+        assert_eq!(apply_dead_zone(Vec2::new(0.1, 0.0), 0.2), Vec2::zeros());
+        assert!((apply_dead_zone(Vec2::new(0.6, 0.0), 0.2).x - 0.5).abs() < 1e-6);
+        assert!((apply_dead_zone(Vec2::new(0.0, 1.0), 0.2).y - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn presses_and_releases_are_detected_across_updates() {
+        let mut input = Input::default();
+        bind_action(
+            &mut input,
+            1,
+            "fire",
+            button_action(vec![binding(
+                &[Button::Mouse(MouseButton::Left)],
+                Modifiers::empty(),
+            )]),
+        );
+        let pressed = Mouse {
+            buttons: MouseButtons::LEFT_CLICKED,
+            ..Default::default()
+        };
+        let keyboard = keyboard(&[]);
+        let gamepads = Gamepads::default();
+        update_input(&mut input, &keyboard, &pressed, &gamepads);
+        assert!(action_just_pressed(&input, 1, "fire"));
+        update_input(&mut input, &keyboard, &pressed, &gamepads);
+        assert!(action_pressed(&input, 1, "fire"));
+        assert!(!action_just_pressed(&input, 1, "fire"));
+        update_input(&mut input, &keyboard, &Mouse::default(), &gamepads);
+        assert!(action_just_released(&input, 1, "fire"));
+        assert!(!action_pressed(&input, 0, "fire"));
+    }
+}
@@ -0,0 +1,549 @@
+use crate::{
+    asset::get_asset,
+    bounds::{bounds_center, bounds_empty, bounds_half_extents, mesh_bounds},
+    debug_draw::{draw_axes, draw_oriented_box, DebugStyle},
+    history::{
+        begin_transaction, can_redo, can_undo, component_value_ron, current_entity,
+        end_transaction, record_component_edit, redo, snapshot_component, undo, EditHistory,
+    },
+    world::{
+        component_mask, get_component, get_component_mut, query_entities, Camera, Color, EntityId,
+        GlobalTransform, LocalTransform, Name, OrthographicCamera, Parent, PerspectiveCamera,
+        Player, Projection, RenderMesh, World, GLOBAL_TRANSFORM, NAME, PARENT, RENDER_MESH,
+    },
+};
+use std::{
+    collections::{HashMap, HashSet},
+    marker::PhantomData,
+};
+
+/// A value that can draw widgets editing itself
+pub trait Inspect {
+    /// Returns true when the value was changed
+    fn inspect(&mut self, ui: &mut egui::Ui) -> bool;
+}
+
+type InspectFn = fn(&mut World, EntityId, u32, &mut egui::Ui) -> bool;
+
+/// Edits one component type on whichever entity is selected,
+/// components without an `Inspect` implementation are shown read only
+#[derive(Clone, Copy)]
+pub struct ComponentInspector {
+    pub name: &'static str,
+    pub mask: u32,
+    inspect: Option<InspectFn>,
+}
+
+/// Selection and registered components of the outliner and inspector panels
+pub struct Inspector {
+    pub selected: Option<EntityId>,
+    pub components: Vec<ComponentInspector>,
+    /// Set while edits are grouped into one undo step, until the pointer is released
+    editing: bool,
+}
+
+impl Default for Inspector {
+    fn default() -> Self {
+        Self {
+            selected: None,
+            editing: false,
+            components: world_component_inspectors(),
+        }
+    }
+}
+
+/// One inspector per component in the world, in declaration order
+pub fn world_component_inspectors() -> Vec<ComponentInspector> {
+    use crate::world::*;
+    macro_rules! component_inspectors {
+        ($($name:ident: $type:ty => $mask:ident),* $(,)?) => {
+            vec![$(ComponentInspector {
+                name: stringify!($type),
+                mask: $mask,
+                inspect: (&&InspectProbe::<$type>(PhantomData)).editor(),
+            }),*]
+        };
+    }
+    for_each_component!(component_inspectors)
+}
+
+/// Picks `inspect_component` for types implementing `Inspect` and `None` otherwise.
+/// Method lookup tries `&&InspectProbe<T>` before auto-dereferencing to `&InspectProbe<T>`,
+/// so the `Inspect` bound decides which trait the call resolves to.
+struct InspectProbe<T>(PhantomData<T>);
+
+trait InspectEditor {
+    fn editor(&self) -> Option<InspectFn>;
+}
+
+impl<T: Inspect + 'static> InspectEditor for &InspectProbe<T> {
+    fn editor(&self) -> Option<InspectFn> {
+        Some(inspect_component::<T>)
+    }
+}
+
+trait ReadOnlyEditor {
+    fn editor(&self) -> Option<InspectFn> {
+        None
+    }
+}
+
+impl<T> ReadOnlyEditor for InspectProbe<T> {}
+
+/// Describes how to inspect a component, any `Inspect` type stored under `mask` works
+pub fn component_inspector<T: Inspect + 'static>(
+    name: &'static str,
+    mask: u32,
+) -> ComponentInspector {
+    ComponentInspector {
+        name,
+        mask,
+        inspect: Some(inspect_component::<T>),
+    }
+}
+
+/// Makes a component show up in the inspector, replacing any inspector for the same mask
+pub fn register_component_inspector<T: Inspect + 'static>(
+    inspector: &mut Inspector,
+    name: &'static str,
+    mask: u32,
+) {
+    inspector
+        .components
+        .retain(|component| component.mask != mask);
+    inspector
+        .components
+        .push(component_inspector::<T>(name, mask));
+}
+
+fn inspect_component<T: Inspect + 'static>(
+    world: &mut World,
+    entity: EntityId,
+    mask: u32,
+    ui: &mut egui::Ui,
+) -> bool {
+    get_component_mut::<T>(world, entity, mask).is_some_and(|component| component.inspect(ui))
+}
+
+/// Draws the outliner and inspector panels, edits apply to the world immediately
+/// and are recorded in the history. Returns true when a component was changed.
+pub fn inspector_ui(
+    inspector: &mut Inspector,
+    history: &mut EditHistory,
+    context: &egui::Context,
+    world: &mut World,
+) -> bool {
+    let undo_shortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Z);
+    let redo_shortcut = egui::KeyboardShortcut::new(
+        egui::Modifiers::COMMAND | egui::Modifiers::SHIFT,
+        egui::Key::Z,
+    );
+    // The redo shortcut is checked first, since it contains the undo shortcut
+    let (redo_pressed, undo_pressed) = context.input_mut(|input| {
+        let redo_pressed = input.consume_shortcut(&redo_shortcut);
+        (redo_pressed, input.consume_shortcut(&undo_shortcut))
+    });
+    let mut history_changed = false;
+    if undo_pressed {
+        history_changed |= undo(history, world);
+    }
+    if redo_pressed {
+        history_changed |= redo(history, world);
+    }
+    if history_changed {
+        inspector.editing = false;
+        inspector.selected = inspector
+            .selected
+            .map(|entity| current_entity(history, entity));
+    }
+
+    egui::SidePanel::left("Outliner").show(context, |ui| {
+        ui.heading("Outliner");
+        egui::ScrollArea::vertical().show(ui, |ui| outliner_ui(inspector, ui, world));
+    });
+
+    if inspector
+        .selected
+        .is_some_and(|entity| component_mask(world, entity).is_none())
+    {
+        inspector.selected = None;
+    }
+
+    let mut changed = false;
+    egui::SidePanel::right("Inspector").show(context, |ui| {
+        ui.horizontal(|ui| {
+            ui.heading("Inspector");
+            if ui
+                .add_enabled(can_undo(history), egui::Button::new("Undo"))
+                .clicked()
+            {
+                undo(history, world);
+                inspector.editing = false;
+            }
+            if ui
+                .add_enabled(can_redo(history), egui::Button::new("Redo"))
+                .clicked()
+            {
+                redo(history, world);
+            }
+        });
+        if let Some(entity) = inspector.selected {
+            inspector.selected = Some(current_entity(history, entity))
+                .filter(|entity| component_mask(world, *entity).is_some());
+        }
+        let Some(entity) = inspector.selected else {
+            ui.label("No entity selected");
+            return;
+        };
+        ui.label(entity_label(world, entity));
+        let mask = component_mask(world, entity).unwrap_or_default();
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for component in inspector.components.iter() {
+                if mask & component.mask == 0 {
+                    continue;
+                }
+                let before = snapshot_component(history, world, entity, component.mask);
+                let Some(inspect) = component.inspect else {
+                    if let Some(value) = before.as_ref() {
+                        egui::CollapsingHeader::new(component.name)
+                            .default_open(false)
+                            .show(ui, |ui| ui.monospace(component_value_ron(value)));
+                    }
+                    continue;
+                };
+                let edited = egui::CollapsingHeader::new(component.name)
+                    .default_open(true)
+                    .show(ui, |ui| inspect(world, entity, component.mask, ui))
+                    .body_returned
+                    .unwrap_or_default();
+                let Some(before) = before.filter(|_| edited) else {
+                    continue;
+                };
+                if !inspector.editing {
+                    begin_transaction(history, &format!("Edit {}", component.name));
+                    inspector.editing = true;
+                }
+                record_component_edit(history, world, entity, component.mask, before);
+                changed = true;
+            }
+        });
+    });
+
+    if inspector.editing && !context.input(|input| input.pointer.any_down()) {
+        end_transaction(history);
+        inspector.editing = false;
+    }
+
+    if let Some(entity) = inspector.selected {
+        highlight_entity(world, entity);
+    }
+    changed
+}
+
+/// Children of each entity by `Parent`, with roots under `None`
+pub fn entity_hierarchy(world: &World) -> HashMap<Option<EntityId>, Vec<EntityId>> {
+    let entities = query_entities(world, 0);
+    let alive = entities.iter().copied().collect::<HashSet<_>>();
+    let mut hierarchy: HashMap<Option<EntityId>, Vec<EntityId>> = HashMap::new();
+    for entity in entities {
+        let parent = get_component::<Parent>(world, entity, PARENT)
+            .map(|Parent(parent)| *parent)
+            .filter(|parent| alive.contains(parent) && *parent != entity);
+        hierarchy.entry(parent).or_default().push(entity);
+    }
+    for children in hierarchy.values_mut() {
+        children.sort_by_key(|entity| entity.id);
+    }
+    hierarchy
+}
+
+fn outliner_ui(inspector: &mut Inspector, ui: &mut egui::Ui, world: &World) {
+    let hierarchy = entity_hierarchy(world);
+    let mut visited = HashSet::new();
+    for root in hierarchy.get(&None).into_iter().flatten() {
+        outliner_node_ui(inspector, ui, world, &hierarchy, *root, &mut visited);
+    }
+}
+
+fn outliner_node_ui(
+    inspector: &mut Inspector,
+    ui: &mut egui::Ui,
+    world: &World,
+    hierarchy: &HashMap<Option<EntityId>, Vec<EntityId>>,
+    entity: EntityId,
+    visited: &mut HashSet<EntityId>,
+) {
+    // Entities in a parent cycle are never reached from a root, this guards the rest
+    if !visited.insert(entity) {
+        return;
+    }
+    let selected = inspector.selected == Some(entity);
+    let label = entity_label(world, entity);
+    let Some(children) = hierarchy.get(&Some(entity)) else {
+        if ui.selectable_label(selected, label).clicked() {
+            inspector.selected = Some(entity);
+        }
+        return;
+    };
+    let id = ui.make_persistent_id(("outliner", entity.id, entity.generation));
+    egui::collapsing_header::CollapsingState::load_with_default_open(ui.ctx(), id, true)
+        .show_header(ui, |ui| {
+            if ui.selectable_label(selected, label).clicked() {
+                inspector.selected = Some(entity);
+            }
+        })
+        .body(|ui| {
+            for child in children.iter() {
+                outliner_node_ui(inspector, ui, world, hierarchy, *child, visited);
+            }
+        });
+}
+
+fn entity_label(world: &World, entity: EntityId) -> String {
+    match get_component::<Name>(world, entity, NAME) {
+        Some(Name(name)) if !name.is_empty() => name.clone(),
+        _ => format!("Entity {}", entity.id),
+    }
+}
+
+/// Outlines the entity's mesh, or draws its axes when it has none
+fn highlight_entity(world: &mut World, entity: EntityId) {
+    let Some(global_transform) =
+        get_component::<GlobalTransform>(world, entity, GLOBAL_TRANSFORM).copied()
+    else {
+        return;
+    };
+    let style = DebugStyle {
+        color: nalgebra_glm::vec4(1.0, 0.6, 0.0, 1.0),
+        depth_test: false,
+        ..Default::default()
+    };
+    let bounds = get_component::<RenderMesh>(world, entity, RENDER_MESH)
+        .and_then(|RenderMesh(mesh)| get_asset(&world.resources.assets.meshes, mesh))
+        .map(mesh_bounds)
+        .filter(|bounds| !bounds_empty(bounds));
+    let debug_draw = &mut world.resources.debug_draw;
+    match bounds {
+        Some(bounds) => {
+            let transform = global_transform * nalgebra_glm::translation(&bounds_center(&bounds));
+            draw_oriented_box(debug_draw, &transform, bounds_half_extents(&bounds), style);
+        }
+        None => draw_axes(debug_draw, &global_transform, 1.0, style),
+    }
+}
+
+fn drag_value(ui: &mut egui::Ui, label: &str, value: &mut f32, speed: f32) -> bool {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        ui.add(egui::DragValue::new(value).speed(speed)).changed()
+    })
+    .inner
+}
+
+fn inspect_vec3(
+    ui: &mut egui::Ui,
+    label: &str,
+    value: &mut nalgebra_glm::Vec3,
+    speed: f32,
+) -> bool {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        let mut changed = false;
+        for (axis, component) in ["x", "y", "z"].iter().zip(value.iter_mut()) {
+            changed |= ui
+                .add(
+                    egui::DragValue::new(component)
+                        .speed(speed)
+                        .prefix(format!("{axis}: ")),
+                )
+                .changed();
+        }
+        changed
+    })
+    .inner
+}
+
+/// Roll, pitch and yaw in radians, applied about X, then Y, then Z
+pub fn quat_to_euler(rotation: &nalgebra_glm::Quat) -> nalgebra_glm::Vec3 {
+    let matrix = nalgebra_glm::quat_to_mat3(&rotation.normalize());
+    let pitch = (-matrix[(2, 0)]).clamp(-1.0, 1.0).asin();
+    if pitch.cos() > 1e-4 {
+        nalgebra_glm::vec3(
+            matrix[(2, 1)].atan2(matrix[(2, 2)]),
+            pitch,
+            matrix[(1, 0)].atan2(matrix[(0, 0)]),
+        )
+    } else {
+        // Gimbal lock, roll and yaw share an axis so all of it goes to yaw
+        nalgebra_glm::vec3(0.0, pitch, (-matrix[(0, 1)]).atan2(matrix[(1, 1)]))
+    }
+}
+
+/// The inverse of `quat_to_euler`
+pub fn euler_to_quat(euler: &nalgebra_glm::Vec3) -> nalgebra_glm::Quat {
+    nalgebra_glm::quat_angle_axis(euler.z, &nalgebra_glm::Vec3::z())
+        * nalgebra_glm::quat_angle_axis(euler.y, &nalgebra_glm::Vec3::y())
+        * nalgebra_glm::quat_angle_axis(euler.x, &nalgebra_glm::Vec3::x())
+}
+
+impl Inspect for LocalTransform {
+    fn inspect(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = inspect_vec3(ui, "Translation", &mut self.translation, 0.05);
+        let mut degrees = quat_to_euler(&self.rotation).map(f32::to_degrees);
+        if inspect_vec3(ui, "Rotation", &mut degrees, 0.5) {
+            self.rotation = euler_to_quat(&degrees.map(f32::to_radians));
+            changed = true;
+        }
+        changed | inspect_vec3(ui, "Scale", &mut self.scale, 0.05)
+    }
+}
+
+impl Inspect for Name {
+    fn inspect(&mut self, ui: &mut egui::Ui) -> bool {
+        ui.text_edit_singleline(&mut self.0).changed()
+    }
+}
+
+impl Inspect for Color {
+    fn inspect(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut rgba = [self.0.x, self.0.y, self.0.z, self.0.w];
+        let changed = ui.color_edit_button_rgba_unmultiplied(&mut rgba).changed();
+        if changed {
+            self.0 = nalgebra_glm::Vec4::from(rgba);
+        }
+        changed
+    }
+}
+
+impl Inspect for Player {
+    fn inspect(&mut self, ui: &mut egui::Ui) -> bool {
+        ui.horizontal(|ui| {
+            ui.label("Index");
+            ui.add(egui::DragValue::new(&mut self.0)).changed()
+        })
+        .inner
+    }
+}
+
+impl Inspect for Camera {
+    fn inspect(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+        let perspective = matches!(self.projection, Projection::Perspective(_));
+        egui::ComboBox::from_label("Projection")
+            .selected_text(if perspective {
+                "Perspective"
+            } else {
+                "Orthographic"
+            })
+            .show_ui(ui, |ui| {
+                if ui.selectable_label(perspective, "Perspective").clicked() && !perspective {
+                    self.projection = Projection::Perspective(PerspectiveCamera::default());
+                    changed = true;
+                }
+                if ui.selectable_label(!perspective, "Orthographic").clicked() && perspective {
+                    self.projection = Projection::Orthographic(OrthographicCamera {
+                        x_mag: 10.0,
+                        y_mag: 10.0,
+                        z_far: 1000.0,
+                        z_near: 0.01,
+                    });
+                    changed = true;
+                }
+            });
+
+        match &mut self.projection {
+            Projection::Perspective(camera) => {
+                let mut y_fov_degrees = camera.y_fov_rad.to_degrees();
+                if drag_value(ui, "Vertical FOV", &mut y_fov_degrees, 0.5) {
+                    camera.y_fov_rad = y_fov_degrees.clamp(1.0, 179.0).to_radians();
+                    changed = true;
+                }
+                if drag_value(ui, "Near", &mut camera.z_near, 0.01) {
+                    camera.z_near = camera.z_near.max(0.0001);
+                    changed = true;
+                }
+                let mut infinite = camera.z_far.is_none();
+                if ui.checkbox(&mut infinite, "Infinite far plane").changed() {
+                    camera.z_far = (!infinite).then_some(1000.0);
+                    changed = true;
+                }
+                if let Some(z_far) = camera.z_far.as_mut() {
+                    changed |= drag_value(ui, "Far", z_far, 1.0);
+                }
+            }
+            Projection::Orthographic(camera) => {
+                changed |= drag_value(ui, "Half width", &mut camera.x_mag, 0.1);
+                changed |= drag_value(ui, "Half height", &mut camera.y_mag, 0.1);
+                changed |= drag_value(ui, "Near", &mut camera.z_near, 0.01);
+                changed |= drag_value(ui, "Far", &mut camera.z_far, 1.0);
+            }
+        }
+        changed |= drag_value(ui, "Sensitivity X", &mut self.sensitivity.x, 0.01);
+        changed | drag_value(ui, "Sensitivity Y", &mut self.sensitivity.y, 0.01)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{CAMERA, COLOR, GLOBAL_TRANSFORM, LOCAL_TRANSFORM, PLAYER, SKIN};
+
+    fn editable(inspector: &Inspector, mask: u32) -> Option<bool> {
+        inspector
+            .components
+            .iter()
+            .find(|component| component.mask == mask)
+            .map(|component| component.inspect.is_some())
+    }
+
+    #[test]
+    fn every_world_component_is_registered_once() {
+        let inspector = Inspector::default();
+        let masks = inspector.components.iter().fold(0, |masks, component| {
+            assert_eq!(masks & component.mask, 0, "{} twice", component.name);
+            masks | component.mask
+        });
+        assert_eq!(
+            masks.count_ones(),
+            crate::world::Component::AUDIO_LISTENER as u32 + 1
+        );
+    }
+
+    #[test]
+    fn components_with_inspect_are_editable() {
+        let inspector = Inspector::default();
+        for mask in [LOCAL_TRANSFORM, NAME, COLOR, CAMERA, PLAYER] {
+            assert_eq!(editable(&inspector, mask), Some(true));
+        }
+        for mask in [GLOBAL_TRANSFORM, SKIN] {
+            assert_eq!(editable(&inspector, mask), Some(false));
+        }
+    }
+
+    #[test]
+    fn registering_replaces_the_existing_inspector() {
+        let mut inspector = Inspector::default();
+        let count = inspector.components.len();
+        register_component_inspector::<LocalTransform>(
+            &mut inspector,
+            "Transform",
+            LOCAL_TRANSFORM,
+        );
+        assert_eq!(inspector.components.len(), count);
+        let component = inspector
+            .components
+            .last()
+            .expect("No inspector was registered!");
+        assert_eq!(
+            (component.name, component.mask),
+            ("Transform", LOCAL_TRANSFORM)
+        );
+    }
+
+    #[test]
+    fn euler_round_trip() {
+        let euler = nalgebra_glm::vec3(0.3, -0.7, 1.2);
+        let round_trip = quat_to_euler(&euler_to_quat(&euler));
+        assert!((round_trip - euler).norm() < 1e-4);
+    }
+}
@@ -0,0 +1,27 @@
+pub mod animation;
+pub mod app;
+pub mod asset;
+pub mod audio;
+pub mod bounds;
+pub mod bvh;
+pub mod character;
+pub mod debug_draw;
+pub mod frame_stats;
+pub mod gizmo;
+pub mod graphics;
+pub mod history;
+pub mod image_loader;
+pub mod input;
+pub mod inspector;
+pub mod material;
+pub mod mipmap;
+pub mod physics;
+pub mod profiler;
+pub mod render_graph;
+pub mod replay;
+pub mod shader;
+pub mod skinning;
+pub mod texture;
+pub mod tween;
+pub mod ui;
+pub mod world;
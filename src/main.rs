@@ -1,9 +1,18 @@
-use app::{App, State};
-use world::*;
-
-mod app;
-mod graphics;
-mod world;
+use spree::{
+    app::{App, State},
+    asset::{add_asset, asset_root_path, enable_hot_reload, load_asset},
+    character::character_input_map,
+    debug_draw::{draw_grid, DebugStyle},
+    gizmo::{gizmo_ui, pick_ui, Gizmo},
+    graphics::scene_pass_with_shaders,
+    history::EditHistory,
+    inspector::{inspector_ui, Inspector},
+    profiler::profiler_ui,
+    render_graph::{replace_pass, RenderGraph},
+    shader::{builtin_shader_library, shader_includes},
+    world::*,
+};
+use std::path::PathBuf;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
@@ -13,15 +22,124 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Assets load from `SPREE_ASSET_ROOT`, by default the source directory holding the shaders
+fn asset_root() -> PathBuf {
+    std::env::var_os("SPREE_ASSET_ROOT")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/src")))
+}
+
 #[derive(Default)]
-pub struct AppState {}
+pub struct AppState {
+    inspector: Inspector,
+    gizmo: Gizmo,
+    history: EditHistory,
+}
 
 impl State for AppState {
-    fn initialize(&mut self, world: &mut world::World) {
-        spawn_entities(world, ACTIVE_CAMERA | CAMERA | LOCAL_TRANSFORM | PLAYER, 1)[0];
+    fn initialize(&mut self, world: &mut World) {
+        world.resources.assets.root = asset_root();
+        for player in 0..2 {
+            world
+                .resources
+                .input
+                .maps
+                .insert(player, character_input_map(player));
+        }
+        let camera = spawn_entities(
+            world,
+            ACTIVE_CAMERA | CAMERA | LOCAL_TRANSFORM | GLOBAL_TRANSFORM | PLAYER | NAME,
+            1,
+        )[0];
+        if let Some(name) = get_component_mut::<Name>(world, camera, NAME) {
+            *name = Name("Camera".to_string());
+        }
+        if let Some(transform) = get_component_mut::<LocalTransform>(world, camera, LOCAL_TRANSFORM)
+        {
+            transform.translation = nalgebra_glm::vec3(0.0, 1.0, 3.0);
+        }
+
+        let cube_mesh = add_asset(&mut world.resources.assets.meshes, cube_mesh());
+        let cube = spawn_entities(
+            world,
+            RENDER_MESH
+                | MATERIAL
+                | LOCAL_TRANSFORM
+                | GLOBAL_TRANSFORM
+                | NAME
+                | BOUNDING_BOX
+                | WORLD_BOUNDS,
+            1,
+        )[0];
+        if let Some(name) = get_component_mut::<Name>(world, cube, NAME) {
+            *name = Name("Cube".to_string());
+        }
+        if let Some(render_mesh) = get_component_mut::<RenderMesh>(world, cube, RENDER_MESH) {
+            *render_mesh = RenderMesh(cube_mesh);
+        }
+        if let Some(material) = get_component_mut::<Material>(world, cube, MATERIAL) {
+            material.base_color_factor = nalgebra_glm::vec4(0.8, 0.3, 0.2, 1.0);
+            material.metallic_factor = 0.0;
+            material.roughness_factor = 0.5;
+        }
     }
 
-    fn receive_event(&mut self, _world: &mut world::World, _event: &winit::event::WindowEvent) {}
+    fn receive_event(&mut self, _world: &mut World, _event: &winit::event::WindowEvent) {}
 
-    fn update(&mut self, _world: &mut world::World) {}
+    fn update(&mut self, world: &mut World) {
+        let style = DebugStyle {
+            color: nalgebra_glm::vec4(0.5, 0.5, 0.5, 0.5),
+            ..Default::default()
+        };
+        draw_grid(&mut world.resources.debug_draw, 1.0, 20, style);
+    }
+
+    fn ui(&mut self, context: &egui::Context, world: &mut World) {
+        egui::Window::new("Spree").show(context, |ui| {
+            let stats = &world.resources.frame_stats;
+            ui.label(format!(
+                "{:.0} FPS, {:.2} ms (p99 {:.2} ms)",
+                stats.fps,
+                stats.frame_time * 1000.0,
+                stats.p99_frame_time * 1000.0
+            ));
+            ui.label(format!(
+                "{} entities, {} draw calls, {} triangles, {} culled",
+                stats.entity_count,
+                stats.render.draw_calls,
+                stats.render.triangles,
+                stats.render.culled_objects
+            ));
+        });
+        profiler_ui(&mut world.resources.profiler, context);
+        inspector_ui(&mut self.inspector, &mut self.history, context, world);
+        gizmo_ui(
+            &mut self.gizmo,
+            &mut self.history,
+            self.inspector.selected,
+            context,
+            world,
+        );
+        pick_ui(&self.gizmo, &mut self.inspector.selected, context, world);
+    }
+
+    /// Debug builds reload the PBR shader and the files it includes from the asset root when saved
+    fn configure_render_graph(&mut self, world: &mut World, render_graph: &mut RenderGraph) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+        if let Err(error) = enable_hot_reload(&mut world.resources.assets) {
+            log::warn!("Hot reloading is unavailable: {error}");
+            return;
+        }
+        let assets = &mut world.resources.assets;
+        let shaders = shader_includes(&builtin_shader_library(), "pbr.wgsl")
+            .into_iter()
+            .map(|name| {
+                let path = asset_root_path(assets, format!("shaders/{name}"));
+                (name, load_asset(&mut assets.shaders, path))
+            })
+            .collect();
+        replace_pass(render_graph, "Scene", scene_pass_with_shaders(shaders));
+    }
 }
@@ -0,0 +1,497 @@
+use crate::{
+    asset::{asset_events, get_asset_by_id, AssetEvent, AssetId, Assets},
+    mipmap::{create_mipmap_generator, MipmapGenerator},
+    texture::upload_image,
+    world::{Image, ImageFormat, MagFilter, Material, MinFilter, Sampler, Texture, WrappingMode},
+};
+use std::collections::{HashMap, HashSet};
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialUniform {
+    pub base_color_factor: nalgebra_glm::Vec4,
+    pub emissive_factor: nalgebra_glm::Vec4,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+}
+
+/// The textures a material samples, in binding order
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TextureSlot {
+    BaseColor,
+    MetallicRoughness,
+    Normal,
+    Occlusion,
+    Emissive,
+}
+
+pub const TEXTURE_SLOTS: [TextureSlot; 5] = [
+    TextureSlot::BaseColor,
+    TextureSlot::MetallicRoughness,
+    TextureSlot::Normal,
+    TextureSlot::Occlusion,
+    TextureSlot::Emissive,
+];
+
+/// Color textures are stored in sRGB, data textures are linear
+pub fn is_srgb_slot(slot: TextureSlot) -> bool {
+    matches!(slot, TextureSlot::BaseColor | TextureSlot::Emissive)
+}
+
+/// Identifies materials that can share a bind group.
+/// Textures that are not uploaded yet are left out, so the key changes once they are.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MaterialKey {
+    factors: [u32; 12],
+    textures: [Option<(AssetId, Sampler)>; 5],
+}
+
+pub struct MaterialCache {
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    textures: HashMap<(AssetId, bool), wgpu::TextureView>,
+    /// Images that failed to upload, skipped until they reload
+    failed_textures: HashSet<(AssetId, bool)>,
+    samplers: HashMap<Sampler, wgpu::Sampler>,
+    bind_groups: HashMap<MaterialKey, wgpu::BindGroup>,
+    /// Keys prepared since the last prune, the rest are evicted then
+    used_keys: HashSet<MaterialKey>,
+    white_texture: wgpu::TextureView,
+    flat_normal_texture: wgpu::TextureView,
+    mipmap_generator: MipmapGenerator,
+}
+
+pub fn material_uniform(material: &Material) -> MaterialUniform {
+    let emissive = material.emissive_factor;
+    MaterialUniform {
+        base_color_factor: material.base_color_factor,
+        emissive_factor: nalgebra_glm::vec4(emissive.x, emissive.y, emissive.z, 1.0),
+        metallic_factor: material.metallic_factor,
+        roughness_factor: material.roughness_factor,
+        normal_scale: material.normal_scale,
+        occlusion_strength: material.occlusion_strength,
+    }
+}
+
+pub fn material_texture(material: &Material, slot: TextureSlot) -> Option<&Texture> {
+    match slot {
+        TextureSlot::BaseColor => material.base_color_texture.as_ref(),
+        TextureSlot::MetallicRoughness => material.metallic_roughness_texture.as_ref(),
+        TextureSlot::Normal => material.normal_texture.as_ref(),
+        TextureSlot::Occlusion => material.occlusion_texture.as_ref(),
+        TextureSlot::Emissive => material.emissive_texture.as_ref(),
+    }
+}
+
+/// Builds the key of a material from the textures the cache has uploaded so far
+pub fn material_key(cache: &MaterialCache, material: &Material) -> MaterialKey {
+    MaterialKey {
+        factors: bytemuck::cast(material_uniform(material)),
+        textures: TEXTURE_SLOTS.map(|slot| {
+            material_texture(material, slot)
+                .filter(|texture| {
+                    cache
+                        .textures
+                        .contains_key(&(texture.image.id, is_srgb_slot(slot)))
+                })
+                .map(|texture| (texture.image.id, texture.sampler))
+        }),
+    }
+}
+
+/// Whether a material key's bind group samples a texture in the slot, rather than a fallback
+pub fn material_key_has_texture(key: &MaterialKey, slot: TextureSlot) -> bool {
+    key.textures[slot as usize].is_some()
+}
+
+/// Creates the material bind group layout and the fallback textures used for unset slots
+pub fn create_material_cache(device: &wgpu::Device, queue: &wgpu::Queue) -> MaterialCache {
+    let mut entries = vec![wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }];
+    for index in 0..TEXTURE_SLOTS.len() as u32 {
+        entries.push(wgpu::BindGroupLayoutEntry {
+            binding: 1 + index * 2,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        });
+        entries.push(wgpu::BindGroupLayoutEntry {
+            binding: 2 + index * 2,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        });
+    }
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Material Bind Group Layout"),
+        entries: &entries,
+    });
+
+    MaterialCache {
+        bind_group_layout,
+        textures: HashMap::new(),
+        failed_textures: HashSet::new(),
+        samplers: HashMap::new(),
+        bind_groups: HashMap::new(),
+        used_keys: HashSet::new(),
+        white_texture: create_solid_texture(device, queue, [255, 255, 255, 255]),
+        flat_normal_texture: create_solid_texture(device, queue, [128, 128, 255, 255]),
+        mipmap_generator: create_mipmap_generator(device),
+    }
+}
+
+/// Creates the bind group for a material unless an identical material already has one
+pub fn prepare_material_bind_group(
+    cache: &mut MaterialCache,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    images: &Assets<Image>,
+    material: &Material,
+) -> MaterialKey {
+    for slot in TEXTURE_SLOTS {
+        let Some(texture) = material_texture(material, slot) else {
+            continue;
+        };
+        let texture_key = (texture.image.id, is_srgb_slot(slot));
+        if cache.textures.contains_key(&texture_key) || cache.failed_textures.contains(&texture_key)
+        {
+            continue;
+        }
+        // Images that are still loading are uploaded once they finish
+        let Some(image) = get_asset_by_id(images, texture.image.id) else {
+            continue;
+        };
+        match create_gpu_texture(
+            device,
+            queue,
+            image,
+            is_srgb_slot(slot),
+            Some(&mut cache.mipmap_generator),
+        ) {
+            Some(view) => {
+                cache.textures.insert(texture_key, view);
+            }
+            None => {
+                cache.failed_textures.insert(texture_key);
+            }
+        }
+    }
+
+    let key = material_key(cache, material);
+    cache.used_keys.insert(key.clone());
+    if cache.bind_groups.contains_key(&key) {
+        return key;
+    }
+    for sampler in key
+        .textures
+        .iter()
+        .map(|texture| texture.map(|(_, sampler)| sampler).unwrap_or_default())
+    {
+        cache
+            .samplers
+            .entry(sampler)
+            .or_insert_with(|| create_sampler(device, &sampler));
+    }
+
+    let uniform_buffer = wgpu::util::DeviceExt::create_buffer_init(
+        device,
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Material Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[material_uniform(material)]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        },
+    );
+
+    let views_and_samplers = TEXTURE_SLOTS.map(|slot| {
+        let texture = key.textures[slot as usize];
+        let view = texture
+            .and_then(|(image, _)| cache.textures.get(&(image, is_srgb_slot(slot))))
+            .unwrap_or(match slot {
+                TextureSlot::Normal => &cache.flat_normal_texture,
+                _ => &cache.white_texture,
+            });
+        let sampler = &cache.samplers[&texture.map(|(_, sampler)| sampler).unwrap_or_default()];
+        (view, sampler)
+    });
+
+    let mut entries = vec![wgpu::BindGroupEntry {
+        binding: 0,
+        resource: uniform_buffer.as_entire_binding(),
+    }];
+    for (index, (view, sampler)) in views_and_samplers.iter().enumerate() {
+        entries.push(wgpu::BindGroupEntry {
+            binding: 1 + index as u32 * 2,
+            resource: wgpu::BindingResource::TextureView(view),
+        });
+        entries.push(wgpu::BindGroupEntry {
+            binding: 2 + index as u32 * 2,
+            resource: wgpu::BindingResource::Sampler(sampler),
+        });
+    }
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Material Bind Group"),
+        layout: &cache.bind_group_layout,
+        entries: &entries,
+    });
+    cache.bind_groups.insert(key.clone(), bind_group);
+    key
+}
+
+/// Looks up a bind group created by `prepare_material_bind_group`
+pub fn material_bind_group<'a>(
+    cache: &'a MaterialCache,
+    key: &MaterialKey,
+) -> Option<&'a wgpu::BindGroup> {
+    cache.bind_groups.get(key)
+}
+
+/// Drops every cached GPU resource, call when images change
+pub fn clear_material_cache(cache: &mut MaterialCache) {
+    cache.textures.clear();
+    cache.failed_textures.clear();
+    cache.samplers.clear();
+    cache.bind_groups.clear();
+    cache.used_keys.clear();
+}
+
+/// Drops the textures and upload failures of unloaded and reloaded images, the bind
+/// groups that use them, and the bind groups no material used since the last prune,
+/// such as those of animated or edited factors. Call once per frame before preparing materials.
+pub fn prune_material_cache(cache: &mut MaterialCache, images: &Assets<Image>) {
+    let used_keys = std::mem::take(&mut cache.used_keys);
+    cache.bind_groups.retain(|key, _| used_keys.contains(key));
+
+    cache
+        .textures
+        .retain(|(image, _), _| get_asset_by_id(images, *image).is_some());
+    cache
+        .failed_textures
+        .retain(|(image, _)| get_asset_by_id(images, *image).is_some());
+    for event in asset_events(images) {
+        if let AssetEvent::Modified(image) = event {
+            cache.textures.retain(|(id, _), _| id != image);
+            cache.failed_textures.retain(|(id, _)| id != image);
+        }
+    }
+    let textures = &cache.textures;
+    cache.bind_groups.retain(|key, _| {
+        TEXTURE_SLOTS.iter().all(|slot| {
+            key.textures[*slot as usize]
+                .is_none_or(|(image, _)| textures.contains_key(&(image, is_srgb_slot(*slot))))
+        })
+    });
+}
+
+/// Returns the minification and mipmap filters
+pub fn map_min_filter(filter: MinFilter) -> (wgpu::FilterMode, wgpu::FilterMode) {
+    match filter {
+        MinFilter::Nearest | MinFilter::NearestMipmapNearest => {
+            (wgpu::FilterMode::Nearest, wgpu::FilterMode::Nearest)
+        }
+        MinFilter::Linear | MinFilter::LinearMipmapNearest => {
+            (wgpu::FilterMode::Linear, wgpu::FilterMode::Nearest)
+        }
+        MinFilter::NearestMipmapLinear => (wgpu::FilterMode::Nearest, wgpu::FilterMode::Linear),
+        MinFilter::LinearMipmapLinear => (wgpu::FilterMode::Linear, wgpu::FilterMode::Linear),
+    }
+}
+
+pub fn map_mag_filter(filter: MagFilter) -> wgpu::FilterMode {
+    match filter {
+        MagFilter::Nearest => wgpu::FilterMode::Nearest,
+        MagFilter::Linear => wgpu::FilterMode::Linear,
+    }
+}
+
+pub fn map_wrapping_mode(mode: WrappingMode) -> wgpu::AddressMode {
+    match mode {
+        WrappingMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+        WrappingMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
+        WrappingMode::Repeat => wgpu::AddressMode::Repeat,
+    }
+}
+
+pub fn create_sampler(device: &wgpu::Device, sampler: &Sampler) -> wgpu::Sampler {
+    let (min_filter, mipmap_filter) = map_min_filter(sampler.min_filter);
+    // Filters without a mipmap mode only ever sample the base level
+    let uses_mipmaps = !matches!(sampler.min_filter, MinFilter::Nearest | MinFilter::Linear);
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("Material Sampler"),
+        address_mode_u: map_wrapping_mode(sampler.wrap_s),
+        address_mode_v: map_wrapping_mode(sampler.wrap_t),
+        address_mode_w: wgpu::AddressMode::Repeat,
+        mag_filter: map_mag_filter(sampler.mag_filter),
+        min_filter,
+        mipmap_filter,
+        lod_max_clamp: if uses_mipmaps { 32.0 } else { 0.0 },
+        ..Default::default()
+    })
+}
+
+/// Uploads an image, logging and returning `None` when it is invalid
+pub fn create_gpu_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    image: &Image,
+    srgb: bool,
+    mipmaps: Option<&mut MipmapGenerator>,
+) -> Option<wgpu::TextureView> {
+    match upload_image(device, queue, image, srgb, mipmaps) {
+        Ok(texture) => Some(texture.create_view(&wgpu::TextureViewDescriptor::default())),
+        Err(error) => {
+            log::warn!("Failed to upload material texture: {error}");
+            None
+        }
+    }
+}
+
+fn create_solid_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    color: [u8; 4],
+) -> wgpu::TextureView {
+    let image = Image {
+        pixels: color.to_vec(),
+        format: ImageFormat::R8G8B8A8,
+        width: 1,
+        height: 1,
+        mip_levels: Vec::new(),
+    };
+    create_gpu_texture(device, queue, &image, false, None).expect("Failed to create solid texture!")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        asset::{add_asset, update_assets},
+        graphics::tests::fallback_device,
+    };
+
+    #[test]
+    fn min_filters_map_to_a_filter_and_a_mipmap_filter() {
+        use wgpu::FilterMode::{Linear, Nearest};
+        for (filter, expected) in [
+            (MinFilter::Nearest, (Nearest, Nearest)),
+            (MinFilter::Linear, (Linear, Nearest)),
+            (MinFilter::NearestMipmapNearest, (Nearest, Nearest)),
+            (MinFilter::LinearMipmapNearest, (Linear, Nearest)),
+            (MinFilter::NearestMipmapLinear, (Nearest, Linear)),
+            (MinFilter::LinearMipmapLinear, (Linear, Linear)),
+        ] {
+            assert_eq!(map_min_filter(filter), expected, "{filter:?}");
+        }
+    }
+
+    #[test]
+    fn mag_filters_and_wrapping_modes_map_to_wgpu() {
+        assert_eq!(
+            map_mag_filter(MagFilter::Nearest),
+            wgpu::FilterMode::Nearest
+        );
+        assert_eq!(map_mag_filter(MagFilter::Linear), wgpu::FilterMode::Linear);
+        assert_eq!(
+            map_wrapping_mode(WrappingMode::ClampToEdge),
+            wgpu::AddressMode::ClampToEdge
+        );
+        assert_eq!(
+            map_wrapping_mode(WrappingMode::MirroredRepeat),
+            wgpu::AddressMode::MirrorRepeat
+        );
+        assert_eq!(
+            map_wrapping_mode(WrappingMode::Repeat),
+            wgpu::AddressMode::Repeat
+        );
+    }
+
+    #[test]
+    fn materials_round_trip_through_ron() {
+        let material = Material {
+            base_color_factor: nalgebra_glm::vec4(0.8, 0.3, 0.2, 0.5),
+            base_color_texture: Some(Texture {
+                image: crate::asset::Handle::detached(AssetId(7)),
+                sampler: Sampler {
+                    min_filter: MinFilter::NearestMipmapLinear,
+                    mag_filter: MagFilter::Nearest,
+                    wrap_s: WrappingMode::ClampToEdge,
+                    wrap_t: WrappingMode::MirroredRepeat,
+                },
+            }),
+            metallic_factor: 0.25,
+            roughness_factor: 0.75,
+            normal_scale: 0.5,
+            occlusion_strength: 0.4,
+            emissive_factor: nalgebra_glm::vec3(1.0, 2.0, 3.0),
+            ..Default::default()
+        };
+        let serialized = ron::to_string(&material).expect("Failed to serialize the material!");
+        let loaded: Material = ron::from_str(&serialized).expect("Failed to parse the material!");
+        assert_eq!(
+            bytemuck::bytes_of(&material_uniform(&loaded)),
+            bytemuck::bytes_of(&material_uniform(&material))
+        );
+        assert_eq!(loaded.base_color_texture, material.base_color_texture);
+        assert!(loaded.normal_texture.is_none());
+        assert_eq!(
+            ron::to_string(&loaded).expect("Failed to serialize the material!"),
+            serialized
+        );
+    }
+
+    #[test]
+    fn failed_uploads_are_not_retried_until_the_image_changes() {
+        let Some((device, queue)) = fallback_device() else {
+            eprintln!("Skipping, no fallback adapter is available");
+            return;
+        };
+        let mut cache = create_material_cache(&device, &queue);
+        let mut images = Assets::default();
+        // Wider than any texture the device allows
+        let image = add_asset(
+            &mut images,
+            Image {
+                pixels: Vec::new(),
+                format: ImageFormat::R8G8B8A8,
+                width: device.limits().max_texture_dimension_2d + 1,
+                height: 1,
+                mip_levels: Vec::new(),
+            },
+        );
+        let material = Material {
+            base_color_texture: Some(Texture {
+                image: image.clone(),
+                sampler: Sampler::default(),
+            }),
+            ..Default::default()
+        };
+        let key = prepare_material_bind_group(&mut cache, &device, &queue, &images, &material);
+        assert!(!material_key_has_texture(&key, TextureSlot::BaseColor));
+        assert!(cache.failed_textures.contains(&(image.id, true)));
+
+        // Marked as failed, the image is skipped without another upload
+        prune_material_cache(&mut cache, &images);
+        prepare_material_bind_group(&mut cache, &device, &queue, &images, &material);
+        assert_eq!(cache.failed_textures.len(), 1);
+
+        // Unloading the image forgets the failure
+        let id = image.id;
+        drop((image, material));
+        update_assets(&mut images);
+        prune_material_cache(&mut cache, &images);
+        assert!(!cache.failed_textures.contains(&(id, true)));
+    }
+}
@@ -0,0 +1,564 @@
+use crate::{
+    texture::{
+        channel_size, image_format_layout, read_channel, write_channel, ChannelType, TextureError,
+    },
+    world::{Image, ImageFormat},
+};
+use std::collections::HashMap;
+
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MipFilter {
+    /// Averages the source pixels each destination pixel covers
+    #[default]
+    Box,
+    /// Kaiser-windowed sinc, sharper than a box filter at the cost of slight ringing
+    Kaiser,
+}
+
+/// Radius of the Kaiser filter in destination pixels
+const KAISER_RADIUS: f32 = 3.0;
+const KAISER_BETA: f32 = 4.0;
+
+/// Returns the number of levels in a full mip chain down to 1x1
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+pub fn mip_level_size(width: u32, height: u32, level: u32) -> (u32, u32) {
+    ((width >> level).max(1), (height >> level).max(1))
+}
+
+/// Generates every mip level below the base image, in the base image's format.
+/// Filtering happens in linear space, so sRGB color channels are decoded first and alpha is left linear.
+pub fn generate_mipmaps(
+    image: &Image,
+    srgb: bool,
+    filter: MipFilter,
+) -> Result<Vec<Image>, TextureError> {
+    let mut pixels = decode_image(image, srgb)?;
+    let layout =
+        image_format_layout(image.format).ok_or(TextureError::UnsupportedFormat(image.format))?;
+    let channels = layout.0;
+    let (mut width, mut height) = (image.width, image.height);
+
+    let mut levels = Vec::new();
+    for level in 1..mip_level_count(image.width, image.height) {
+        let (next_width, next_height) = mip_level_size(image.width, image.height, level);
+        pixels = resample_axis(&pixels, channels, (width, height), next_width, true, filter);
+        pixels = resample_axis(
+            &pixels,
+            channels,
+            (next_width, height),
+            next_height,
+            false,
+            filter,
+        );
+        (width, height) = (next_width, next_height);
+        levels.push(encode_image(
+            &pixels,
+            image.format,
+            layout,
+            (width, height),
+            srgb,
+        ));
+    }
+    Ok(levels)
+}
+
+/// Decodes pixels to linear floating point channels
+fn decode_image(image: &Image, srgb: bool) -> Result<Vec<f32>, TextureError> {
+    if image.width == 0 || image.height == 0 {
+        return Err(TextureError::EmptyImage);
+    }
+    let (channels, channel_type) =
+        image_format_layout(image.format).ok_or(TextureError::UnsupportedFormat(image.format))?;
+    let channel_size = channel_size(channel_type);
+    let expected = image.width as usize * image.height as usize * channels * channel_size;
+    if image.pixels.len() != expected {
+        return Err(TextureError::SizeMismatch {
+            expected,
+            actual: image.pixels.len(),
+        });
+    }
+    Ok(image
+        .pixels
+        .chunks_exact(channel_size)
+        .enumerate()
+        .map(|(index, bytes)| {
+            let value = read_channel(bytes, channel_type);
+            if is_color_channel(srgb, (channels, channel_type), index % channels) {
+                srgb_to_linear(value)
+            } else {
+                value
+            }
+        })
+        .collect())
+}
+
+fn encode_image(
+    pixels: &[f32],
+    format: ImageFormat,
+    (channels, channel_type): (usize, ChannelType),
+    (width, height): (u32, u32),
+    srgb: bool,
+) -> Image {
+    let mut bytes = Vec::with_capacity(pixels.len() * channel_size(channel_type));
+    for (index, value) in pixels.iter().enumerate() {
+        let value = if is_color_channel(srgb, (channels, channel_type), index % channels) {
+            linear_to_srgb(*value)
+        } else {
+            *value
+        };
+        write_channel(&mut bytes, channel_type, value);
+    }
+    Image {
+        pixels: bytes,
+        format,
+        width,
+        height,
+        mip_levels: Vec::new(),
+    }
+}
+
+/// Only the color channels of 8-bit three and four channel images are sRGB encoded,
+/// wider formats are uploaded as linear textures even in sRGB slots
+fn is_color_channel(
+    srgb: bool,
+    (channels, channel_type): (usize, ChannelType),
+    channel: usize,
+) -> bool {
+    srgb && channel_type == ChannelType::Unorm8 && channels >= 3 && channel < 3
+}
+
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Resamples one axis of an image to a new length with a separable filter
+fn resample_axis(
+    pixels: &[f32],
+    channels: usize,
+    (width, height): (u32, u32),
+    new_length: u32,
+    horizontal: bool,
+    filter: MipFilter,
+) -> Vec<f32> {
+    let (width, height) = (width as usize, height as usize);
+    let (length, new_length) = if horizontal {
+        (width, new_length as usize)
+    } else {
+        (height, new_length as usize)
+    };
+    let weights = (0..new_length)
+        .map(|index| filter_weights(filter, index, length, new_length))
+        .collect::<Vec<_>>();
+
+    let (new_width, new_height) = if horizontal {
+        (new_length, height)
+    } else {
+        (width, new_length)
+    };
+    let mut resampled = vec![0.0; new_width * new_height * channels];
+    for y in 0..new_height {
+        for x in 0..new_width {
+            let (index, fixed) = if horizontal { (x, y) } else { (y, x) };
+            let target = (y * new_width + x) * channels;
+            for (source, weight) in weights[index].iter() {
+                let (source_x, source_y) = if horizontal {
+                    (*source, fixed)
+                } else {
+                    (fixed, *source)
+                };
+                let source = (source_y * width + source_x) * channels;
+                for channel in 0..channels {
+                    resampled[target + channel] += pixels[source + channel] * weight;
+                }
+            }
+        }
+    }
+    resampled
+}
+
+/// Returns the normalized source weights for one destination pixel
+fn filter_weights(
+    filter: MipFilter,
+    index: usize,
+    length: usize,
+    new_length: usize,
+) -> Vec<(usize, f32)> {
+    let scale = length as f32 / new_length as f32;
+    let mut weights = match filter {
+        MipFilter::Box => {
+            let (start, end) = (index as f32 * scale, (index + 1) as f32 * scale);
+            (start.floor() as usize..(end.ceil() as usize).min(length))
+                .map(|source| {
+                    let coverage = end.min(source as f32 + 1.0) - start.max(source as f32);
+                    (source, coverage.max(0.0))
+                })
+                .collect::<Vec<_>>()
+        }
+        MipFilter::Kaiser => {
+            let center = (index as f32 + 0.5) * scale;
+            let radius = KAISER_RADIUS * scale;
+            let first = (center - radius).floor() as isize;
+            let last = (center + radius).ceil() as isize;
+            let mut weights: Vec<(usize, f32)> = Vec::new();
+            for source in first..=last {
+                let distance = (source as f32 + 0.5 - center) / scale;
+                let weight = sinc(distance) * kaiser(distance / KAISER_RADIUS);
+                let source = source.clamp(0, length as isize - 1) as usize;
+                match weights.iter_mut().find(|(existing, _)| *existing == source) {
+                    Some((_, existing)) => *existing += weight,
+                    None => weights.push((source, weight)),
+                }
+            }
+            weights
+        }
+    };
+    let total = weights.iter().map(|(_, weight)| weight).sum::<f32>();
+    if total.abs() > f32::EPSILON {
+        weights.iter_mut().for_each(|(_, weight)| *weight /= total);
+    }
+    weights
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-6 {
+        1.0
+    } else {
+        let x = x * std::f32::consts::PI;
+        x.sin() / x
+    }
+}
+
+fn kaiser(t: f32) -> f32 {
+    if t.abs() > 1.0 {
+        return 0.0;
+    }
+    bessel_i0(KAISER_BETA * (1.0 - t * t).sqrt()) / bessel_i0(KAISER_BETA)
+}
+
+/// Zeroth order modified Bessel function of the first kind
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half_squared = x * x / 4.0;
+    for k in 1..32 {
+        term *= half_squared / (k * k) as f32;
+        sum += term;
+        if term < sum * 1e-8 {
+            break;
+        }
+    }
+    sum
+}
+
+/// Downsamples mip levels on the GPU by rendering each level from the one above it
+pub struct MipmapGenerator {
+    shader: wgpu::ShaderModule,
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    pipelines: HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>,
+}
+
+pub fn create_mipmap_generator(device: &wgpu::Device) -> MipmapGenerator {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Mipmap Blit Shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("shaders/blit.wgsl").into()),
+    });
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("Mipmap Sampler"),
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Mipmap Bind Group Layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ],
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Mipmap Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    MipmapGenerator {
+        shader,
+        sampler,
+        bind_group_layout,
+        pipeline_layout,
+        pipelines: HashMap::new(),
+    }
+}
+
+/// The GPU path needs to both filter and render to the format
+pub fn supports_gpu_mipmaps(device: &wgpu::Device, format: wgpu::TextureFormat) -> bool {
+    let format_features = format.guaranteed_format_features(device.features());
+    format_features
+        .allowed_usages
+        .contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+        && format_features
+            .flags
+            .contains(wgpu::TextureFormatFeatureFlags::FILTERABLE)
+}
+
+/// Records passes filling every mip level below the base level.
+/// The texture needs `TEXTURE_BINDING` and `RENDER_ATTACHMENT` usages.
+pub fn generate_mipmaps_gpu(
+    generator: &mut MipmapGenerator,
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    texture: &wgpu::Texture,
+) {
+    let format = texture.format();
+    let MipmapGenerator {
+        shader,
+        sampler,
+        bind_group_layout,
+        pipeline_layout,
+        pipelines,
+    } = generator;
+    let pipeline = pipelines.entry(format).or_insert_with(|| {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Mipmap Blit Pipeline"),
+            layout: Some(pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: Some("vs_main"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: Some("fs_main"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                targets: &[Some(format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        })
+    });
+
+    let views = (0..texture.mip_level_count())
+        .map(|level| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("Mipmap Level View"),
+                base_mip_level: level,
+                mip_level_count: Some(1),
+                ..Default::default()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for pair in views.windows(2) {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Mipmap Bind Group"),
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&pair[0]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        });
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Mipmap Blit Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &pair[1],
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(format: ImageFormat, width: u32, height: u32, values: &[f32]) -> Image {
+        let (_, channel_type) = image_format_layout(format).unwrap();
+        let mut pixels = Vec::new();
+        for value in values {
+            write_channel(&mut pixels, channel_type, *value);
+        }
+        Image {
+            pixels,
+            format,
+            width,
+            height,
+            mip_levels: Vec::new(),
+        }
+    }
+
+    fn values(image: &Image) -> Vec<f32> {
+        let (_, channel_type) = image_format_layout(image.format).unwrap();
+        image
+            .pixels
+            .chunks_exact(channel_size(channel_type))
+            .map(|bytes| read_channel(bytes, channel_type))
+            .collect()
+    }
+
+    #[test]
+    fn mip_counts_and_sizes_follow_the_larger_side() {
+        assert_eq!(mip_level_count(1, 1), 1);
+        assert_eq!(mip_level_count(2, 1), 2);
+        assert_eq!(mip_level_count(256, 256), 9);
+        assert_eq!(mip_level_count(300, 17), 9);
+        assert_eq!(mip_level_size(300, 17, 1), (150, 8));
+        assert_eq!(mip_level_size(300, 17, 5), (9, 1));
+        assert_eq!(mip_level_size(300, 17, 8), (1, 1));
+    }
+
+    #[test]
+    fn every_level_is_generated_in_the_base_format() {
+        let base = image(ImageFormat::R8G8B8A8, 8, 4, &[0.5; 8 * 4 * 4]);
+        let levels = generate_mipmaps(&base, false, MipFilter::Box).unwrap();
+        let sizes = levels
+            .iter()
+            .map(|level| (level.width, level.height))
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, vec![(4, 2), (2, 1), (1, 1)]);
+        for level in levels.iter() {
+            assert_eq!(level.format, ImageFormat::R8G8B8A8);
+            assert_eq!(
+                level.pixels.len(),
+                (level.width * level.height * 4) as usize
+            );
+        }
+    }
+
+    #[test]
+    fn box_filter_averages_linear_values() {
+        let base = image(ImageFormat::R32F, 4, 1, &[0.0, 1.0, 2.0, 3.0]);
+        let levels = generate_mipmaps(&base, false, MipFilter::Box).unwrap();
+        assert_eq!(values(&levels[0]), vec![0.5, 2.5]);
+        assert_eq!(values(&levels[1]), vec![1.5]);
+    }
+
+    #[test]
+    fn srgb_colors_are_averaged_in_linear_space() {
+        // Black and white average to 50% linear light, not 50% of the encoded value
+        let base = image(
+            ImageFormat::R8G8B8A8,
+            2,
+            1,
+            &[0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0],
+        );
+        let level = &generate_mipmaps(&base, true, MipFilter::Box).unwrap()[0];
+        let expected_color = (linear_to_srgb(0.5) * 255.0).round() as u8;
+        assert_eq!(
+            level.pixels,
+            vec![expected_color; 3]
+                .into_iter()
+                .chain([128])
+                .collect::<Vec<_>>()
+        );
+
+        let linear = &generate_mipmaps(&base, false, MipFilter::Box).unwrap()[0];
+        assert_eq!(linear.pixels, vec![128; 4]);
+    }
+
+    #[test]
+    fn wide_formats_in_srgb_slots_stay_linear() {
+        for format in [
+            ImageFormat::R16G16B16A16,
+            ImageFormat::R16G16B16A16F,
+            ImageFormat::R32G32B32A32F,
+        ] {
+            let base = image(format, 2, 1, &[0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0]);
+            let level = &generate_mipmaps(&base, true, MipFilter::Box).unwrap()[0];
+            for value in values(level) {
+                assert!((value - 0.5).abs() < 1e-3, "{format:?} gave {value}");
+            }
+        }
+    }
+
+    #[test]
+    fn kaiser_filter_preserves_flat_images() {
+        let base = image(ImageFormat::R32G32B32A32F, 16, 8, &[0.25; 16 * 8 * 4]);
+        for level in generate_mipmaps(&base, false, MipFilter::Kaiser).unwrap() {
+            for value in values(&level) {
+                assert!((value - 0.25).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn transfer_functions_invert_each_other() {
+        for step in 0..=20 {
+            let value = step as f32 / 20.0;
+            assert!((linear_to_srgb(srgb_to_linear(value)) - value).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn invalid_images_are_rejected() {
+        let mut base = image(ImageFormat::R8, 2, 2, &[0.0; 4]);
+        base.pixels.pop();
+        assert_eq!(
+            generate_mipmaps(&base, false, MipFilter::Box).err(),
+            Some(TextureError::SizeMismatch {
+                expected: 4,
+                actual: 3
+            })
+        );
+        let compressed = Image {
+            pixels: vec![0; 8],
+            format: ImageFormat::BC1,
+            width: 4,
+            height: 4,
+            mip_levels: Vec::new(),
+        };
+        assert_eq!(
+            generate_mipmaps(&compressed, false, MipFilter::Box).err(),
+            Some(TextureError::UnsupportedFormat(ImageFormat::BC1))
+        );
+    }
+}
@@ -0,0 +1,762 @@
+use crate::world::{
+    get_component, get_component_mut, parent_global_transform, query_entities, transform_matrix,
+    EntityId, LocalTransform, Mesh, Ray, World, COLLIDER, LOCAL_TRANSFORM, RIGID_BODY,
+};
+use nalgebra_glm::{Mat4, Quat, Vec3};
+use rapier3d::{control, prelude as rapier};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Mutex};
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RigidBodyKind {
+    /// Moved by forces, gravity and contacts
+    #[default]
+    Dynamic,
+    /// Moved only by editing its `LocalTransform`, pushing dynamic bodies aside
+    Kinematic,
+    /// Never moves
+    Static,
+}
+
+/// Makes an entity take part in the simulation. Dynamic bodies write their
+/// pose back to `LocalTransform` and their velocities back here each step.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RigidBody {
+    pub kind: RigidBodyKind,
+    /// In kilograms
+    pub mass: f32,
+    pub linear_velocity: Vec3,
+    /// In radians per second about each axis
+    pub angular_velocity: Vec3,
+    pub linear_damping: f32,
+    pub angular_damping: f32,
+    pub gravity_scale: f32,
+}
+
+impl Default for RigidBody {
+    fn default() -> Self {
+        Self {
+            kind: RigidBodyKind::Dynamic,
+            mass: 1.0,
+            linear_velocity: Vec3::zeros(),
+            angular_velocity: Vec3::zeros(),
+            linear_damping: 0.0,
+            angular_damping: 0.0,
+            gravity_scale: 1.0,
+        }
+    }
+}
+
+/// Collision geometry in the entity's space, unaffected by its scale
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ColliderShape {
+    Sphere {
+        radius: f32,
+    },
+    Box {
+        half_extents: Vec3,
+    },
+    /// Along the Y axis, `half_height` excluding the rounded caps
+    Capsule {
+        half_height: f32,
+        radius: f32,
+    },
+    ConvexHull {
+        points: Vec<Vec3>,
+    },
+    /// For static level geometry, since it has no volume to give it mass
+    TriangleMesh {
+        vertices: Vec<Vec3>,
+        indices: Vec<[u32; 3]>,
+    },
+}
+
+/// A shape that collides with others. Without a `RigidBody` it is static.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Collider {
+    pub shape: ColliderShape,
+    pub friction: f32,
+    pub restitution: f32,
+    /// Reports collisions without producing contact forces
+    pub sensor: bool,
+}
+
+impl Default for Collider {
+    fn default() -> Self {
+        Self {
+            shape: ColliderShape::Sphere { radius: 0.5 },
+            friction: 0.5,
+            restitution: 0.0,
+            sensor: false,
+        }
+    }
+}
+
+pub fn convex_hull_from_mesh(mesh: &Mesh) -> ColliderShape {
+    ColliderShape::ConvexHull {
+        points: mesh.vertices.iter().map(|vertex| vertex.position).collect(),
+    }
+}
+
+pub fn triangle_mesh_from_mesh(mesh: &Mesh) -> ColliderShape {
+    ColliderShape::TriangleMesh {
+        vertices: mesh.vertices.iter().map(|vertex| vertex.position).collect(),
+        indices: mesh
+            .indices
+            .chunks_exact(3)
+            .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+            .collect(),
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CollisionEvent {
+    Started(EntityId, EntityId),
+    Stopped(EntityId, EntityId),
+}
+
+/// The simulation, stepped by `App` in the fixed-timestep update. Its state is
+/// rebuilt from `RigidBody` and `Collider` components and is not serialized.
+#[derive(Serialize, Deserialize)]
+pub struct Physics {
+    pub gravity: Vec3,
+    /// Collisions that started or stopped during this frame's steps
+    #[serde(skip)]
+    pub events: Vec<CollisionEvent>,
+    #[serde(skip)]
+    state: PhysicsState,
+}
+
+impl Default for Physics {
+    fn default() -> Self {
+        Self {
+            gravity: Vec3::new(0.0, -9.81, 0.0),
+            events: Vec::new(),
+            state: PhysicsState::default(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct PhysicsState {
+    pipeline: rapier::PhysicsPipeline,
+    islands: rapier::IslandManager,
+    broad_phase: rapier::DefaultBroadPhase,
+    narrow_phase: rapier::NarrowPhase,
+    bodies: rapier::RigidBodySet,
+    colliders: rapier::ColliderSet,
+    impulse_joints: rapier::ImpulseJointSet,
+    multibody_joints: rapier::MultibodyJointSet,
+    ccd_solver: rapier::CCDSolver,
+    query_pipeline: rapier::QueryPipeline,
+    body_handles: HashMap<EntityId, rapier::RigidBodyHandle>,
+    collider_handles: HashMap<EntityId, (rapier::ColliderHandle, Collider)>,
+    /// Kept until after the next step so events for removed colliders still resolve
+    collider_entities: HashMap<rapier::ColliderHandle, EntityId>,
+}
+
+/// Gathers collision events during a step
+#[derive(Default)]
+struct CollisionCollector(Mutex<Vec<rapier::CollisionEvent>>);
+
+impl rapier::EventHandler for CollisionCollector {
+    fn handle_collision_event(
+        &self,
+        _bodies: &rapier::RigidBodySet,
+        _colliders: &rapier::ColliderSet,
+        event: rapier::CollisionEvent,
+        _contact_pair: Option<&rapier::ContactPair>,
+    ) {
+        self.0
+            .lock()
+            .expect("Collision event lock was poisoned!")
+            .push(event);
+    }
+
+    fn handle_contact_force_event(
+        &self,
+        _dt: f32,
+        _bodies: &rapier::RigidBodySet,
+        _colliders: &rapier::ColliderSet,
+        _contact_pair: &rapier::ContactPair,
+        _total_force_magnitude: f32,
+    ) {
+    }
+}
+
+/// Clears the collision events reported during the previous frame
+pub fn clear_collision_events(physics: &mut Physics) {
+    physics.events.clear();
+}
+
+/// Advances the simulation one fixed step: components are synced into it, it is
+/// stepped, and dynamic bodies are written back.
+pub fn step_physics_system(world: &mut World) {
+    let timestep = world.resources.fixed_time.timestep;
+    let mut physics = std::mem::take(&mut world.resources.physics);
+    sync_bodies(&mut physics.state, world);
+    sync_colliders(&mut physics.state, world);
+    step_physics(&mut physics, timestep);
+    write_back_bodies(&physics.state, world);
+    world.resources.physics = physics;
+}
+
+fn step_physics(physics: &mut Physics, timestep: f32) {
+    let state = &mut physics.state;
+    let integration_parameters = rapier::IntegrationParameters {
+        dt: timestep,
+        ..Default::default()
+    };
+    let collector = CollisionCollector::default();
+    state.pipeline.step(
+        &physics.gravity,
+        &integration_parameters,
+        &mut state.islands,
+        &mut state.broad_phase,
+        &mut state.narrow_phase,
+        &mut state.bodies,
+        &mut state.colliders,
+        &mut state.impulse_joints,
+        &mut state.multibody_joints,
+        &mut state.ccd_solver,
+        Some(&mut state.query_pipeline),
+        &(),
+        &collector,
+    );
+
+    let entity = |handle| state.collider_entities.get(&handle).copied();
+    for event in collector
+        .0
+        .into_inner()
+        .expect("Collision event lock was poisoned!")
+    {
+        let (Some(first), Some(second)) = (entity(event.collider1()), entity(event.collider2()))
+        else {
+            continue;
+        };
+        physics.events.push(match event {
+            rapier::CollisionEvent::Started(..) => CollisionEvent::Started(first, second),
+            rapier::CollisionEvent::Stopped(..) => CollisionEvent::Stopped(first, second),
+        });
+    }
+    let colliders = &state.colliders;
+    state
+        .collider_entities
+        .retain(|handle, _| colliders.contains(*handle));
+}
+
+/// Creates, updates and removes bodies to match the `RigidBody` components.
+/// Entities are visited in query order so handles are assigned deterministically.
+/// Removing a body removes its collider too, `sync_colliders` then rebuilds it as static.
+fn sync_bodies(state: &mut PhysicsState, world: &World) {
+    let entities = query_entities(world, RIGID_BODY);
+    for entity in stale_entities(&state.body_handles, &entities) {
+        if let Some(handle) = state.body_handles.remove(&entity) {
+            state.bodies.remove(
+                handle,
+                &mut state.islands,
+                &mut state.colliders,
+                &mut state.impulse_joints,
+                &mut state.multibody_joints,
+                true,
+            );
+            state.collider_handles.remove(&entity);
+        }
+    }
+
+    for entity in entities {
+        let component = get_component::<RigidBody>(world, entity, RIGID_BODY)
+            .expect("Queried entity has no rigid body!");
+        let pose = entity_pose(world, entity);
+        let Some(handle) = state.body_handles.get(&entity).copied() else {
+            let body = rapier::RigidBodyBuilder::new(body_type(component.kind))
+                .position(pose)
+                .build();
+            let handle = state.bodies.insert(body);
+            state.body_handles.insert(entity, handle);
+            apply_body_settings(&mut state.bodies[handle], component);
+            continue;
+        };
+        let body = &mut state.bodies[handle];
+        if body.body_type() != body_type(component.kind) {
+            body.set_body_type(body_type(component.kind), true);
+        }
+        apply_body_settings(body, component);
+        // A pose that differs from the body's was edited outside the simulation
+        if !poses_match(body.position(), &pose) {
+            match component.kind {
+                RigidBodyKind::Kinematic => body.set_next_kinematic_position(pose),
+                _ => body.set_position(pose, true),
+            }
+        }
+    }
+}
+
+fn apply_body_settings(body: &mut rapier::RigidBody, component: &RigidBody) {
+    if body.is_dynamic() {
+        // Edited velocities are picked up here since they are written back each step
+        if *body.linvel() != component.linear_velocity {
+            body.set_linvel(component.linear_velocity, true);
+        }
+        if *body.angvel() != component.angular_velocity {
+            body.set_angvel(component.angular_velocity, true);
+        }
+    }
+    body.set_linear_damping(component.linear_damping);
+    body.set_angular_damping(component.angular_damping);
+    if body.gravity_scale() != component.gravity_scale {
+        body.set_gravity_scale(component.gravity_scale, true);
+    }
+}
+
+/// Creates, rebuilds and removes colliders to match the `Collider` components.
+/// A collider is attached to its entity's body, and mass comes from the body.
+/// Colliders are rebuilt when the component or the body they belong to changes.
+fn sync_colliders(state: &mut PhysicsState, world: &World) {
+    let entities = query_entities(world, COLLIDER);
+    for entity in stale_entities(&state.collider_handles, &entities) {
+        if let Some((handle, _)) = state.collider_handles.remove(&entity) {
+            // Removed with the body already when the entity was despawned
+            if state.colliders.contains(handle) {
+                state
+                    .colliders
+                    .remove(handle, &mut state.islands, &mut state.bodies, true);
+            }
+        }
+    }
+
+    for entity in entities {
+        let component = get_component::<Collider>(world, entity, COLLIDER)
+            .expect("Queried entity has no collider!");
+        let body = state.body_handles.get(&entity).copied();
+        let mass = get_component::<RigidBody>(world, entity, RIGID_BODY)
+            .map(|rigid_body| rigid_body.mass)
+            .unwrap_or_default();
+
+        let attached = |handle: &rapier::ColliderHandle| {
+            state
+                .colliders
+                .get(*handle)
+                .is_some_and(|collider| collider.parent() == body)
+        };
+        match state.collider_handles.get(&entity) {
+            Some((handle, built)) if built == component && attached(handle) => {
+                let handle = *handle;
+                let collider = &mut state.colliders[handle];
+                match body {
+                    Some(_) => {
+                        if collider.mass() != mass {
+                            collider.set_mass(mass);
+                        }
+                    }
+                    None => {
+                        let pose = entity_pose(world, entity);
+                        if !poses_match(collider.position(), &pose) {
+                            collider.set_position(pose);
+                        }
+                    }
+                }
+                continue;
+            }
+            Some((handle, _)) if state.colliders.contains(*handle) => {
+                state
+                    .colliders
+                    .remove(*handle, &mut state.islands, &mut state.bodies, true);
+            }
+            _ => {}
+        }
+
+        let builder = collider_builder(&component.shape)
+            .friction(component.friction)
+            .restitution(component.restitution)
+            .sensor(component.sensor)
+            .active_events(rapier::ActiveEvents::COLLISION_EVENTS);
+        let handle = match body {
+            Some(body) => state.colliders.insert_with_parent(
+                builder.mass(mass).build(),
+                body,
+                &mut state.bodies,
+            ),
+            None => state
+                .colliders
+                .insert(builder.position(entity_pose(world, entity)).build()),
+        };
+        state
+            .collider_handles
+            .insert(entity, (handle, component.clone()));
+        state.collider_entities.insert(handle, entity);
+    }
+}
+
+/// Mapped entities missing from `entities`, sorted so the simulation's handle
+/// reuse stays deterministic
+fn stale_entities<T>(map: &HashMap<EntityId, T>, entities: &[EntityId]) -> Vec<EntityId> {
+    let entities = entities.iter().collect::<std::collections::HashSet<_>>();
+    let mut stale = map
+        .keys()
+        .filter(|entity| !entities.contains(entity))
+        .copied()
+        .collect::<Vec<_>>();
+    stale.sort_by_key(|entity| (entity.id, entity.generation));
+    stale
+}
+
+fn collider_builder(shape: &ColliderShape) -> rapier::ColliderBuilder {
+    let fallback = || {
+        log::warn!("Degenerate collider shape, using a small sphere instead");
+        rapier::ColliderBuilder::ball(0.01)
+    };
+    match shape {
+        ColliderShape::Sphere { radius } => rapier::ColliderBuilder::ball(*radius),
+        ColliderShape::Box { half_extents } => {
+            rapier::ColliderBuilder::cuboid(half_extents.x, half_extents.y, half_extents.z)
+        }
+        ColliderShape::Capsule {
+            half_height,
+            radius,
+        } => rapier::ColliderBuilder::capsule_y(*half_height, *radius),
+        ColliderShape::ConvexHull { points } => {
+            let points = points
+                .iter()
+                .map(|point| rapier::Point::from(*point))
+                .collect::<Vec<_>>();
+            rapier::ColliderBuilder::convex_hull(&points).unwrap_or_else(fallback)
+        }
+        ColliderShape::TriangleMesh { vertices, indices } => rapier::ColliderBuilder::trimesh(
+            vertices
+                .iter()
+                .map(|vertex| rapier::Point::from(*vertex))
+                .collect(),
+            indices.clone(),
+        )
+        .unwrap_or_else(|_| fallback()),
+    }
+}
+
+fn body_type(kind: RigidBodyKind) -> rapier::RigidBodyType {
+    match kind {
+        RigidBodyKind::Dynamic => rapier::RigidBodyType::Dynamic,
+        RigidBodyKind::Kinematic => rapier::RigidBodyType::KinematicPositionBased,
+        RigidBodyKind::Static => rapier::RigidBodyType::Fixed,
+    }
+}
+
+/// Writes dynamic body poses into `LocalTransform`, relative to the parent, and
+/// their velocities into `RigidBody`
+fn write_back_bodies(state: &PhysicsState, world: &mut World) {
+    for entity in query_entities(world, RIGID_BODY | LOCAL_TRANSFORM) {
+        let Some(body) = state
+            .body_handles
+            .get(&entity)
+            .and_then(|handle| state.bodies.get(*handle))
+        else {
+            continue;
+        };
+        if !body.is_dynamic() {
+            continue;
+        }
+        let parent = parent_global_transform(world, entity);
+        let parent_rotation = matrix_rotation(&parent);
+        let position = body.position();
+        let translation = nalgebra_glm::inverse(&parent)
+            * nalgebra_glm::vec4(
+                position.translation.x,
+                position.translation.y,
+                position.translation.z,
+                1.0,
+            );
+        let rotation =
+            nalgebra_glm::quat_inverse(&parent_rotation) * position.rotation.into_inner();
+        if let Some(transform) = get_component_mut::<LocalTransform>(world, entity, LOCAL_TRANSFORM)
+        {
+            transform.translation = translation.xyz();
+            transform.rotation = rotation;
+        }
+        if let Some(rigid_body) = get_component_mut::<RigidBody>(world, entity, RIGID_BODY) {
+            rigid_body.linear_velocity = *body.linvel();
+            rigid_body.angular_velocity = *body.angvel();
+        }
+    }
+}
+
+/// The entity's world-space translation and rotation, from its parent's global
+/// transform and its own local transform
+fn entity_pose(world: &World, entity: EntityId) -> rapier::Isometry<f32> {
+    let parent = parent_global_transform(world, entity);
+    let local = get_component::<LocalTransform>(world, entity, LOCAL_TRANSFORM)
+        .copied()
+        .unwrap_or_default();
+    let global = parent * transform_matrix(&local);
+    let rotation = matrix_rotation(&parent) * local.rotation.normalize();
+    rapier::Isometry::from_parts(
+        rapier::Translation::from(global.column(3).xyz()),
+        rapier::Rotation::new_normalize(rotation),
+    )
+}
+
+fn matrix_rotation(matrix: &Mat4) -> Quat {
+    let mut rotation = matrix.fixed_view::<3, 3>(0, 0).into_owned();
+    for mut column in rotation.column_iter_mut() {
+        column.normalize_mut();
+    }
+    nalgebra_glm::mat3_to_quat(&rotation)
+}
+
+fn poses_match(a: &rapier::Isometry<f32>, b: &rapier::Isometry<f32>) -> bool {
+    const EPSILON: f32 = 1e-5;
+    (a.translation.vector - b.translation.vector).amax() < EPSILON
+        && a.rotation.angle_to(&b.rotation) < EPSILON
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PhysicsHit {
+    pub entity: EntityId,
+    pub distance: f32,
+    pub point: Vec3,
+    pub normal: Vec3,
+}
+
+/// The nearest collider along the ray within `max_distance`, as of the last step
+pub fn physics_ray_cast(
+    physics: &Physics,
+    ray: &Ray,
+    max_distance: f32,
+    exclude: Option<EntityId>,
+) -> Option<PhysicsHit> {
+    let state = &physics.state;
+    let excluded = exclude.and_then(|entity| state.collider_handles.get(&entity));
+    let mut filter = rapier::QueryFilter::default();
+    if let Some((handle, _)) = excluded {
+        filter = filter.exclude_collider(*handle);
+    }
+    let (handle, intersection) = state.query_pipeline.cast_ray_and_get_normal(
+        &state.bodies,
+        &state.colliders,
+        &rapier::Ray::new(rapier::Point::from(ray.origin), ray.direction),
+        max_distance,
+        true,
+        filter,
+    )?;
+    Some(PhysicsHit {
+        entity: *state.collider_entities.get(&handle)?,
+        distance: intersection.time_of_impact,
+        point: ray.origin + ray.direction * intersection.time_of_impact,
+        normal: intersection.normal,
+    })
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CharacterMovement {
+    /// The world-space movement actually made
+    pub translation: Vec3,
+    pub grounded: bool,
+}
+
+/// Sweeps the controller's upright capsule from `position` by `desired`, sliding
+/// along colliders, climbing steps and slopes within its limits and snapping down
+/// onto the ground. Sensors and the entity's own collider are ignored.
+pub fn move_character(
+    physics: &Physics,
+    entity: EntityId,
+    controller: &crate::character::CharacterController,
+    position: Vec3,
+    desired: Vec3,
+    delta_time: f32,
+) -> CharacterMovement {
+    let state = &physics.state;
+    let mut filter = rapier::QueryFilter::default().exclude_sensors();
+    if let Some(body) = state.body_handles.get(&entity) {
+        filter = filter.exclude_rigid_body(*body);
+    }
+    if let Some((collider, _)) = state.collider_handles.get(&entity) {
+        filter = filter.exclude_collider(*collider);
+    }
+    let character = control::KinematicCharacterController {
+        autostep: (controller.step_height > 0.0).then_some(control::CharacterAutostep {
+            max_height: control::CharacterLength::Absolute(controller.step_height),
+            min_width: control::CharacterLength::Absolute(controller.radius * 0.5),
+            include_dynamic_bodies: false,
+        }),
+        max_slope_climb_angle: controller.max_slope.to_radians(),
+        min_slope_slide_angle: controller.max_slope.to_radians(),
+        snap_to_ground: (controller.snap_distance > 0.0)
+            .then_some(control::CharacterLength::Absolute(controller.snap_distance)),
+        ..Default::default()
+    };
+    let movement = character.move_shape(
+        delta_time,
+        &state.bodies,
+        &state.colliders,
+        &state.query_pipeline,
+        &rapier::Capsule::new_y(controller.half_height, controller.radius),
+        &rapier::Isometry::translation(position.x, position.y, position.z),
+        desired,
+        filter,
+        |_| {},
+    );
+    CharacterMovement {
+        translation: movement.translation,
+        grounded: movement.grounded,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        replay::world_checksum,
+        world::{add_components, remove_components, repair_entity_locations, spawn_entities},
+    };
+
+    fn spawn(world: &mut World, mask: u32, translation: Vec3, shape: ColliderShape) -> EntityId {
+        let entity = spawn_entities(world, mask | COLLIDER | LOCAL_TRANSFORM, 1)[0];
+        get_component_mut::<LocalTransform>(world, entity, LOCAL_TRANSFORM)
+            .expect("No local transform!")
+            .translation = translation;
+        get_component_mut::<Collider>(world, entity, COLLIDER)
+            .expect("No collider!")
+            .shape = shape;
+        entity
+    }
+
+    /// A floor with a stack of boxes and spheres dropped onto it
+    fn scene() -> World {
+        let mut world = World::default();
+        let floor = spawn(
+            &mut world,
+            RIGID_BODY,
+            Vec3::zeros(),
+            ColliderShape::Box {
+                half_extents: Vec3::new(10.0, 0.5, 10.0),
+            },
+        );
+        get_component_mut::<RigidBody>(&mut world, floor, RIGID_BODY)
+            .expect("No rigid body!")
+            .kind = RigidBodyKind::Static;
+        for index in 0..6 {
+            let offset = Vec3::new(index as f32 * 0.13, 1.5 + index as f32 * 1.1, 0.07);
+            let shape = if index % 2 == 0 {
+                ColliderShape::Box {
+                    half_extents: Vec3::repeat(0.5),
+                }
+            } else {
+                ColliderShape::Sphere { radius: 0.5 }
+            };
+            spawn(&mut world, RIGID_BODY, offset, shape);
+        }
+        world
+    }
+
+    fn step(world: &mut World, steps: usize) {
+        for _ in 0..steps {
+            step_physics_system(world);
+        }
+    }
+
+    fn translation(world: &World, entity: EntityId) -> Vec3 {
+        get_component::<LocalTransform>(world, entity, LOCAL_TRANSFORM)
+            .expect("No local transform!")
+            .translation
+    }
+
+    fn collider_parent(world: &World, entity: EntityId) -> Option<rapier::RigidBodyHandle> {
+        let state = &world.resources.physics.state;
+        let (handle, _) = state.collider_handles.get(&entity).expect("No collider!");
+        state.colliders[*handle].parent()
+    }
+
+    #[test]
+    fn stepping_is_deterministic() {
+        let mut first = scene();
+        let mut second = scene();
+        for _ in 0..4 {
+            step(&mut first, 30);
+            step(&mut second, 30);
+            assert_eq!(world_checksum(&first), world_checksum(&second));
+        }
+        // Bodies came to rest on the floor rather than falling through it
+        let resting = query_entities(&first, RIGID_BODY)
+            .into_iter()
+            .map(|entity| translation(&first, entity).y)
+            .fold(f32::MAX, f32::min);
+        assert!(resting > -0.1);
+    }
+
+    #[test]
+    fn dynamic_bodies_fall_and_collide() {
+        let mut world = World::default();
+        let floor = spawn(
+            &mut world,
+            0,
+            Vec3::zeros(),
+            ColliderShape::Box {
+                half_extents: Vec3::new(10.0, 0.5, 10.0),
+            },
+        );
+        let ball = spawn(
+            &mut world,
+            RIGID_BODY,
+            Vec3::new(0.0, 2.0, 0.0),
+            ColliderShape::Sphere { radius: 0.5 },
+        );
+        let mut started = false;
+        for _ in 0..120 {
+            clear_collision_events(&mut world.resources.physics);
+            step_physics_system(&mut world);
+            started |= world.resources.physics.events.iter().any(|event| {
+                matches!(event, CollisionEvent::Started(a, b)
+                    if [*a, *b] == [floor, ball] || [*a, *b] == [ball, floor])
+            });
+        }
+        assert!(started);
+        assert!((translation(&world, ball).y - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn removing_a_body_keeps_the_collider_as_static() {
+        let mut world = World::default();
+        let entity = spawn(
+            &mut world,
+            RIGID_BODY,
+            Vec3::new(0.0, 5.0, 0.0),
+            ColliderShape::Sphere { radius: 0.5 },
+        );
+        step(&mut world, 10);
+        assert!(collider_parent(&world, entity).is_some());
+
+        remove_components(&mut world, entity, RIGID_BODY);
+        repair_entity_locations(&mut world);
+        step(&mut world, 10);
+        assert_eq!(collider_parent(&world, entity), None);
+        let state = &world.resources.physics.state;
+        assert!(state.body_handles.is_empty());
+        assert_eq!(state.colliders.len(), 1);
+    }
+
+    #[test]
+    fn adding_a_body_attaches_the_existing_collider() {
+        let mut world = World::default();
+        let entity = spawn(
+            &mut world,
+            0,
+            Vec3::new(0.0, 5.0, 0.0),
+            ColliderShape::Sphere { radius: 0.5 },
+        );
+        step(&mut world, 10);
+        assert_eq!(collider_parent(&world, entity), None);
+
+        add_components(&mut world, entity, RIGID_BODY);
+        repair_entity_locations(&mut world);
+        get_component_mut::<RigidBody>(&mut world, entity, RIGID_BODY)
+            .expect("No rigid body!")
+            .mass = 2.0;
+        step(&mut world, 30);
+        let state = &world.resources.physics.state;
+        let body = state.body_handles.get(&entity).copied();
+        assert!(body.is_some());
+        assert_eq!(collider_parent(&world, entity), body);
+        assert_eq!(state.colliders.len(), 1);
+        assert!((state.bodies[body.unwrap()].mass() - 2.0).abs() < 1e-4);
+        assert!(translation(&world, entity).y < 4.5);
+    }
+}
@@ -0,0 +1,677 @@
+use crate::world::World;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+/// How many frames the profiler keeps by default
+pub const DEFAULT_PROFILER_HISTORY: usize = 300;
+
+/// A timed span of CPU work, nested inside the scopes open when it began
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileScope {
+    pub name: String,
+    /// Seconds since the profiler started
+    pub start: f64,
+    /// Seconds
+    pub duration: f64,
+    pub depth: usize,
+}
+
+/// GPU time spent in a render pass, measured with timestamp queries
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct GpuPassTiming {
+    pub name: String,
+    /// Seconds since the frame's first timed pass began on the GPU
+    pub start: f64,
+    /// Seconds
+    pub duration: f64,
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct FrameProfile {
+    pub index: u64,
+    /// Seconds since the profiler started
+    pub start: f64,
+    /// Seconds
+    pub duration: f64,
+    /// Scopes in the order they began
+    pub scopes: Vec<ProfileScope>,
+    /// Filled in a few frames later, once the GPU finished the frame
+    pub gpu_passes: Vec<GpuPassTiming>,
+}
+
+/// Records nested CPU scopes each frame and keeps a rolling history of frames
+#[derive(Serialize, Deserialize)]
+pub struct Profiler {
+    pub enabled: bool,
+    /// Where the profiler window saves Chrome traces
+    #[serde(default = "default_trace_path")]
+    pub trace_path: PathBuf,
+    max_frames: usize,
+    frames: VecDeque<FrameProfile>,
+    next_index: u64,
+    #[serde(skip)]
+    current: Option<FrameProfile>,
+    /// Indices of the open scopes in the current frame, innermost last
+    #[serde(skip)]
+    open_scopes: Vec<usize>,
+    #[serde(skip)]
+    epoch: Option<Instant>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            trace_path: default_trace_path(),
+            max_frames: DEFAULT_PROFILER_HISTORY,
+            frames: VecDeque::new(),
+            next_index: 0,
+            current: None,
+            open_scopes: Vec::new(),
+            epoch: None,
+        }
+    }
+}
+
+fn default_trace_path() -> PathBuf {
+    PathBuf::from("trace.json")
+}
+
+fn profiler_time(profiler: &mut Profiler) -> f64 {
+    profiler
+        .epoch
+        .get_or_insert_with(Instant::now)
+        .elapsed()
+        .as_secs_f64()
+}
+
+/// Starts recording a frame, ending the previous one if it is still open
+pub fn begin_frame(profiler: &mut Profiler) {
+    end_frame(profiler);
+    if !profiler.enabled {
+        return;
+    }
+    let start = profiler_time(profiler);
+    profiler.current = Some(FrameProfile {
+        index: profiler.next_index,
+        start,
+        ..Default::default()
+    });
+    profiler.next_index += 1;
+}
+
+/// Closes any open scopes and moves the frame into the history
+pub fn end_frame(profiler: &mut Profiler) {
+    while !profiler.open_scopes.is_empty() {
+        end_scope(profiler);
+    }
+    let now = profiler_time(profiler);
+    let Some(mut frame) = profiler.current.take() else {
+        return;
+    };
+    frame.duration = now - frame.start;
+    profiler.frames.push_back(frame);
+    while profiler.frames.len() > profiler.max_frames {
+        profiler.frames.pop_front();
+    }
+}
+
+/// Opens a scope nested in the scopes already open. Does nothing outside a frame.
+pub fn begin_scope(profiler: &mut Profiler, name: &str) {
+    let start = profiler_time(profiler);
+    let depth = profiler.open_scopes.len();
+    let Some(frame) = profiler.current.as_mut() else {
+        return;
+    };
+    profiler.open_scopes.push(frame.scopes.len());
+    frame.scopes.push(ProfileScope {
+        name: name.to_string(),
+        start,
+        duration: 0.0,
+        depth,
+    });
+}
+
+/// Closes the innermost open scope
+pub fn end_scope(profiler: &mut Profiler) {
+    let now = profiler_time(profiler);
+    let (Some(index), Some(frame)) = (profiler.open_scopes.pop(), profiler.current.as_mut()) else {
+        return;
+    };
+    let scope = &mut frame.scopes[index];
+    scope.duration = now - scope.start;
+}
+
+/// Runs a function on the world inside a profiler scope
+pub fn profile<T>(world: &mut World, name: &str, run: impl FnOnce(&mut World) -> T) -> T {
+    begin_scope(&mut world.resources.profiler, name);
+    let result = run(world);
+    end_scope(&mut world.resources.profiler);
+    result
+}
+
+/// Sets how many frames are kept, dropping the oldest beyond it
+pub fn set_profiler_history(profiler: &mut Profiler, max_frames: usize) {
+    profiler.max_frames = max_frames.max(1);
+    while profiler.frames.len() > profiler.max_frames {
+        profiler.frames.pop_front();
+    }
+}
+
+/// Completed frames, oldest first
+pub fn profiler_frames(profiler: &Profiler) -> impl DoubleEndedIterator<Item = &FrameProfile> {
+    profiler.frames.iter()
+}
+
+/// The index of the frame being recorded, if any
+pub fn current_frame_index(profiler: &Profiler) -> Option<u64> {
+    profiler.current.as_ref().map(|frame| frame.index)
+}
+
+/// Attaches GPU pass timings to a frame still in the history
+pub fn record_gpu_timings(profiler: &mut Profiler, frame_index: u64, timings: Vec<GpuPassTiming>) {
+    let frame = profiler
+        .frames
+        .iter_mut()
+        .chain(profiler.current.as_mut())
+        .find(|frame| frame.index == frame_index);
+    if let Some(frame) = frame {
+        frame.gpu_passes = timings;
+    }
+}
+
+/// Average and longest frame time across the history, in seconds
+pub fn frame_time_summary(profiler: &Profiler) -> (f64, f64) {
+    if profiler.frames.is_empty() {
+        return (0.0, 0.0);
+    }
+    let durations = profiler.frames.iter().map(|frame| frame.duration);
+    let total: f64 = durations.clone().sum();
+    let longest = durations.fold(0.0, f64::max);
+    (total / profiler.frames.len() as f64, longest)
+}
+
+/// Exports the history in the Chrome trace event format, viewable in
+/// chrome://tracing or Perfetto. GPU passes go on their own track,
+/// aligned to the start of their frame.
+pub fn chrome_trace_json(profiler: &Profiler) -> String {
+    const CPU_TRACK: u32 = 1;
+    const GPU_TRACK: u32 = 2;
+    let microseconds = |seconds: f64| seconds * 1_000_000.0;
+
+    let mut events = vec![
+        serde_json::json!({
+            "name": "thread_name", "ph": "M", "pid": 1, "tid": CPU_TRACK,
+            "args": { "name": "CPU" },
+        }),
+        serde_json::json!({
+            "name": "thread_name", "ph": "M", "pid": 1, "tid": GPU_TRACK,
+            "args": { "name": "GPU" },
+        }),
+    ];
+    for frame in profiler.frames.iter() {
+        events.push(serde_json::json!({
+            "name": format!("Frame {}", frame.index),
+            "cat": "frame",
+            "ph": "X",
+            "ts": microseconds(frame.start),
+            "dur": microseconds(frame.duration),
+            "pid": 1,
+            "tid": CPU_TRACK,
+        }));
+        events.extend(frame.scopes.iter().map(|scope| {
+            serde_json::json!({
+                "name": scope.name,
+                "cat": "cpu",
+                "ph": "X",
+                "ts": microseconds(scope.start),
+                "dur": microseconds(scope.duration),
+                "pid": 1,
+                "tid": CPU_TRACK,
+            })
+        }));
+        events.extend(frame.gpu_passes.iter().map(|pass| {
+            serde_json::json!({
+                "name": pass.name,
+                "cat": "gpu",
+                "ph": "X",
+                "ts": microseconds(frame.start + pass.start),
+                "dur": microseconds(pass.duration),
+                "pid": 1,
+                "tid": GPU_TRACK,
+            })
+        }));
+    }
+    serde_json::json!({ "traceEvents": events, "displayTimeUnit": "ms" }).to_string()
+}
+
+pub fn save_chrome_trace(profiler: &Profiler, path: impl AsRef<Path>) -> std::io::Result<()> {
+    std::fs::write(path, chrome_trace_json(profiler))
+}
+
+/// The most passes timed on the GPU per frame
+pub const MAX_GPU_TIMED_PASSES: u32 = 32;
+/// Frames of timestamps that can wait for readback before new frames go untimed
+const GPU_READBACK_FRAMES: usize = 3;
+
+/// Measures render pass durations with timestamp queries.
+/// Only created when the device supports `Features::TIMESTAMP_QUERY`.
+pub struct GpuProfiler {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readbacks: Vec<GpuReadback>,
+    next_readback: usize,
+    /// Passes given queries in the frame being recorded, in query order
+    passes: Vec<String>,
+    /// Nanoseconds per timestamp tick
+    timestamp_period: f64,
+}
+
+struct GpuReadback {
+    buffer: wgpu::Buffer,
+    frame_index: u64,
+    passes: Vec<String>,
+    state: ReadbackState,
+    mapped: Arc<AtomicBool>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum ReadbackState {
+    Free,
+    /// Timestamps were copied in a frame that is not submitted yet
+    Resolved,
+    Mapping,
+}
+
+pub fn create_gpu_profiler(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<GpuProfiler> {
+    if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+        return None;
+    }
+    let query_count = MAX_GPU_TIMED_PASSES * 2;
+    let size = query_count as u64 * std::mem::size_of::<u64>() as u64;
+    let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+        label: Some("Profiler Timestamps"),
+        ty: wgpu::QueryType::Timestamp,
+        count: query_count,
+    });
+    let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Profiler Timestamp Resolve Buffer"),
+        size,
+        usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let readbacks = (0..GPU_READBACK_FRAMES)
+        .map(|_| GpuReadback {
+            buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Profiler Timestamp Readback Buffer"),
+                size,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            frame_index: 0,
+            passes: Vec::new(),
+            state: ReadbackState::Free,
+            mapped: Arc::new(AtomicBool::new(false)),
+        })
+        .collect();
+    Some(GpuProfiler {
+        query_set,
+        resolve_buffer,
+        readbacks,
+        next_readback: 0,
+        passes: Vec::new(),
+        timestamp_period: queue.get_timestamp_period() as f64,
+    })
+}
+
+/// Allocates begin and end timestamps for a render pass, or `None` when the frame is full
+pub fn gpu_timestamp_writes<'a>(
+    gpu_profiler: &'a mut GpuProfiler,
+    name: &str,
+) -> Option<wgpu::RenderPassTimestampWrites<'a>> {
+    let index = gpu_profiler.passes.len() as u32;
+    if index >= MAX_GPU_TIMED_PASSES {
+        return None;
+    }
+    gpu_profiler.passes.push(name.to_string());
+    Some(wgpu::RenderPassTimestampWrites {
+        query_set: &gpu_profiler.query_set,
+        beginning_of_pass_write_index: Some(index * 2),
+        end_of_pass_write_index: Some(index * 2 + 1),
+    })
+}
+
+/// Releases the timestamps most recently allocated, for a pass that never used them
+pub fn release_gpu_timestamp_writes(gpu_profiler: &mut GpuProfiler) {
+    gpu_profiler.passes.pop();
+}
+
+/// Copies the frame's timestamps into a readback buffer.
+/// The frame goes untimed when every readback buffer is still waiting on the GPU.
+pub fn resolve_gpu_timestamps(
+    gpu_profiler: &mut GpuProfiler,
+    encoder: &mut wgpu::CommandEncoder,
+    frame_index: u64,
+) {
+    let passes = std::mem::take(&mut gpu_profiler.passes);
+    let readback = &mut gpu_profiler.readbacks[gpu_profiler.next_readback];
+    if passes.is_empty() || readback.state != ReadbackState::Free {
+        return;
+    }
+    let query_count = passes.len() as u32 * 2;
+    encoder.resolve_query_set(
+        &gpu_profiler.query_set,
+        0..query_count,
+        &gpu_profiler.resolve_buffer,
+        0,
+    );
+    encoder.copy_buffer_to_buffer(
+        &gpu_profiler.resolve_buffer,
+        0,
+        &readback.buffer,
+        0,
+        query_count as u64 * std::mem::size_of::<u64>() as u64,
+    );
+    readback.frame_index = frame_index;
+    readback.passes = passes;
+    readback.state = ReadbackState::Resolved;
+}
+
+/// Maps the readback buffer written this frame, call after submitting the frame
+pub fn map_gpu_timestamps(gpu_profiler: &mut GpuProfiler) {
+    let readback = &mut gpu_profiler.readbacks[gpu_profiler.next_readback];
+    if readback.state != ReadbackState::Resolved {
+        return;
+    }
+    readback.state = ReadbackState::Mapping;
+    gpu_profiler.next_readback = (gpu_profiler.next_readback + 1) % GPU_READBACK_FRAMES;
+    let mapped = readback.mapped.clone();
+    let size = readback.passes.len() as u64 * 2 * std::mem::size_of::<u64>() as u64;
+    readback
+        .buffer
+        .slice(..size)
+        .map_async(wgpu::MapMode::Read, move |result| {
+            if let Err(error) = result {
+                log::warn!("Failed to read GPU timestamps: {error}");
+            }
+            mapped.store(true, Ordering::Release);
+        });
+}
+
+/// Moves finished GPU timings into the profiler without waiting on the GPU
+pub fn collect_gpu_timings(
+    gpu_profiler: &mut GpuProfiler,
+    device: &wgpu::Device,
+    profiler: &mut Profiler,
+) {
+    device.poll(wgpu::Maintain::Poll);
+    let timestamp_period = gpu_profiler.timestamp_period;
+    for readback in gpu_profiler.readbacks.iter_mut() {
+        if readback.state != ReadbackState::Mapping
+            || !readback.mapped.swap(false, Ordering::Acquire)
+        {
+            continue;
+        }
+        let size = readback.passes.len() as u64 * 2 * std::mem::size_of::<u64>() as u64;
+        let timestamps = {
+            let view = readback.buffer.slice(..size).get_mapped_range();
+            bytemuck::cast_slice::<u8, u64>(&view).to_vec()
+        };
+        readback.buffer.unmap();
+        readback.state = ReadbackState::Free;
+
+        let seconds = |ticks: u64| ticks as f64 * timestamp_period / 1_000_000_000.0;
+        let first = timestamps.iter().step_by(2).copied().min().unwrap_or(0);
+        let timings = readback
+            .passes
+            .drain(..)
+            .zip(timestamps.chunks_exact(2))
+            .filter(|(_, pair)| pair[1] >= pair[0])
+            .map(|(name, pair)| GpuPassTiming {
+                name,
+                start: seconds(pair[0] - first),
+                duration: seconds(pair[1] - pair[0]),
+            })
+            .collect();
+        record_gpu_timings(profiler, readback.frame_index, timings);
+    }
+}
+
+/// A window showing frame times, the last frame's scopes and GPU pass times
+pub fn profiler_ui(profiler: &mut Profiler, context: &egui::Context) {
+    const MILLISECONDS: f64 = 1000.0;
+    egui::Window::new("Profiler")
+        .default_open(false)
+        .show(context, |ui| {
+            let (average, longest) = frame_time_summary(profiler);
+            ui.label(format!(
+                "Frame {:.2} ms ({:.0} FPS), longest {:.2} ms",
+                average * MILLISECONDS,
+                if average > 0.0 { 1.0 / average } else { 0.0 },
+                longest * MILLISECONDS,
+            ));
+            frame_time_graph(ui, profiler, longest);
+
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut profiler.enabled, "Recording");
+                let path = profiler.trace_path.display().to_string();
+                if ui
+                    .button("Save Chrome trace")
+                    .on_hover_text(&path)
+                    .clicked()
+                {
+                    match save_chrome_trace(profiler, &profiler.trace_path) {
+                        Ok(()) => log::info!("Saved profiler trace to {path}"),
+                        Err(error) => {
+                            log::error!("Failed to save profiler trace to {path}: {error}")
+                        }
+                    }
+                }
+            });
+
+            egui::CollapsingHeader::new("CPU")
+                .default_open(true)
+                .show(ui, |ui| {
+                    let Some(frame) = profiler.frames.back() else {
+                        return;
+                    };
+                    egui::Grid::new("profiler_cpu").show(ui, |ui| {
+                        for scope in frame.scopes.iter() {
+                            ui.label(format!("{}{}", "  ".repeat(scope.depth), scope.name));
+                            ui.label(format!("{:.3} ms", scope.duration * MILLISECONDS));
+                            ui.end_row();
+                        }
+                    });
+                });
+
+            egui::CollapsingHeader::new("GPU")
+                .default_open(true)
+                .show(ui, |ui| {
+                    // GPU timings arrive a few frames late
+                    let Some(frame) = profiler
+                        .frames
+                        .iter()
+                        .rev()
+                        .find(|frame| !frame.gpu_passes.is_empty())
+                    else {
+                        ui.label("No GPU timings, timestamp queries may be unsupported");
+                        return;
+                    };
+                    egui::Grid::new("profiler_gpu").show(ui, |ui| {
+                        for pass in frame.gpu_passes.iter() {
+                            ui.label(&pass.name);
+                            ui.label(format!("{:.3} ms", pass.duration * MILLISECONDS));
+                            ui.end_row();
+                        }
+                    });
+                });
+        });
+}
+
+/// Bars for each frame in the history, scaled so at least 30 FPS fits
+fn frame_time_graph(ui: &mut egui::Ui, profiler: &Profiler, longest: f64) {
+    let (response, painter) = ui.allocate_painter(egui::vec2(300.0, 60.0), egui::Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+    let scale = longest.max(1.0 / 30.0);
+    let bar_width = rect.width() / profiler.max_frames as f32;
+    for (index, frame) in profiler.frames.iter().enumerate() {
+        let height = (frame.duration / scale) as f32 * rect.height();
+        let left = rect.left() + index as f32 * bar_width;
+        let color = if frame.duration > 1.0 / 30.0 {
+            egui::Color32::RED
+        } else if frame.duration > 1.0 / 60.0 {
+            egui::Color32::YELLOW
+        } else {
+            egui::Color32::GREEN
+        };
+        painter.rect_filled(
+            egui::Rect::from_min_max(
+                egui::pos2(left, rect.bottom() - height),
+                egui::pos2(left + bar_width.max(1.0), rect.bottom()),
+            ),
+            0.0,
+            color,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records frames with a scope each, named after the frame
+    fn record_frames(profiler: &mut Profiler, count: usize) {
+        for frame in 0..count {
+            begin_frame(profiler);
+            begin_scope(profiler, &format!("Scope {frame}"));
+            end_scope(profiler);
+        }
+        end_frame(profiler);
+    }
+
+    #[test]
+    fn nested_scopes_record_their_depth() {
+        let mut profiler = Profiler::default();
+        begin_frame(&mut profiler);
+        begin_scope(&mut profiler, "Update");
+        begin_scope(&mut profiler, "Physics");
+        begin_scope(&mut profiler, "Broad phase");
+        end_scope(&mut profiler);
+        end_scope(&mut profiler);
+        begin_scope(&mut profiler, "Render");
+        end_scope(&mut profiler);
+        end_scope(&mut profiler);
+        // Left open, so ending the frame closes it
+        begin_scope(&mut profiler, "Present");
+        end_frame(&mut profiler);
+
+        let frame = profiler_frames(&profiler).next().unwrap();
+        let depths = frame
+            .scopes
+            .iter()
+            .map(|scope| (scope.name.as_str(), scope.depth))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            depths,
+            [
+                ("Update", 0),
+                ("Physics", 1),
+                ("Broad phase", 2),
+                ("Render", 1),
+                ("Present", 0),
+            ]
+        );
+        let update = &frame.scopes[0];
+        for scope in &frame.scopes[1..4] {
+            assert!(scope.start >= update.start);
+            assert!(scope.start + scope.duration <= update.start + update.duration);
+        }
+        assert!(profiler.open_scopes.is_empty());
+    }
+
+    #[test]
+    fn scopes_outside_a_frame_are_ignored() {
+        let mut profiler = Profiler::default();
+        begin_scope(&mut profiler, "Loose");
+        end_scope(&mut profiler);
+        assert!(profiler.open_scopes.is_empty());
+        assert_eq!(profiler_frames(&profiler).count(), 0);
+    }
+
+    #[test]
+    fn history_keeps_the_newest_frames() {
+        let mut profiler = Profiler::default();
+        set_profiler_history(&mut profiler, 4);
+        record_frames(&mut profiler, 10);
+        let indices = profiler_frames(&profiler)
+            .map(|frame| frame.index)
+            .collect::<Vec<_>>();
+        assert_eq!(indices, [6, 7, 8, 9]);
+
+        set_profiler_history(&mut profiler, 2);
+        let indices = profiler_frames(&profiler)
+            .map(|frame| frame.index)
+            .collect::<Vec<_>>();
+        assert_eq!(indices, [8, 9]);
+
+        set_profiler_history(&mut profiler, 0);
+        assert_eq!(profiler_frames(&profiler).count(), 1);
+    }
+
+    #[test]
+    fn chrome_traces_are_json_with_complete_events() {
+        let mut profiler = Profiler::default();
+        record_frames(&mut profiler, 3);
+        record_gpu_timings(
+            &mut profiler,
+            1,
+            vec![GpuPassTiming {
+                name: "Shadows".to_string(),
+                start: 0.0,
+                duration: 0.001,
+            }],
+        );
+        let trace: serde_json::Value = serde_json::from_str(&chrome_trace_json(&profiler))
+            .expect("Chrome trace is not valid JSON!");
+        let events = trace["traceEvents"].as_array().unwrap();
+
+        let names = |category: &str| {
+            events
+                .iter()
+                .filter(|event| event["cat"] == category)
+                .map(|event| event["name"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names("frame"), ["Frame 0", "Frame 1", "Frame 2"]);
+        assert_eq!(names("cpu"), ["Scope 0", "Scope 1", "Scope 2"]);
+        assert_eq!(names("gpu"), ["Shadows"]);
+        for event in events.iter().filter(|event| event["ph"] != "M") {
+            // Complete events carry their own duration, so there are no begin and end pairs to match
+            assert_eq!(event["ph"], "X");
+            assert!(event["ts"].as_f64().unwrap() >= 0.0);
+            assert!(event["dur"].as_f64().unwrap() >= 0.0);
+        }
+    }
+
+    #[test]
+    fn chrome_traces_save_to_the_given_path() {
+        let mut profiler = Profiler::default();
+        record_frames(&mut profiler, 1);
+        let path = std::env::temp_dir().join(format!("spree-{}-trace.json", std::process::id()));
+        save_chrome_trace(&profiler, &path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            chrome_trace_json(&profiler)
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
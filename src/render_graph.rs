@@ -0,0 +1,645 @@
+use crate::{
+    frame_stats::RenderStats,
+    profiler::{
+        begin_scope, end_scope, gpu_timestamp_writes, release_gpu_timestamp_writes, GpuProfiler,
+        Profiler,
+    },
+    world::World,
+};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+/// Names a texture read or written by render graph passes
+pub type ResourceId = &'static str;
+
+/// The swapchain texture, imported into the graph each frame
+pub const SURFACE: ResourceId = "surface";
+
+/// The scene depth texture
+pub const DEPTH: ResourceId = "depth";
+
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TextureSize {
+    /// Matches the surface and follows it when the window resizes
+    Surface,
+    /// The surface size divided by a factor, useful for bloom or blur chains
+    SurfaceDivided(u32),
+    Fixed {
+        width: u32,
+        height: u32,
+    },
+}
+
+/// Describes a texture the graph allocates, and may alias, on behalf of its passes
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TransientTexture {
+    pub size: TextureSize,
+    pub format: wgpu::TextureFormat,
+    pub usage: wgpu::TextureUsages,
+}
+
+/// A unit of GPU work that declares the textures it reads and writes
+pub trait Pass {
+    fn name(&self) -> &str;
+    fn reads(&self) -> Vec<ResourceId> {
+        Vec::new()
+    }
+    fn writes(&self) -> Vec<ResourceId> {
+        Vec::new()
+    }
+    fn execute(&mut self, context: &mut PassContext);
+}
+
+/// Everything a pass needs to record its commands
+pub struct PassContext<'a> {
+    pub device: &'a wgpu::Device,
+    pub queue: &'a wgpu::Queue,
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    pub world: &'a World,
+    pub surface_format: wgpu::TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    /// Passes add the draws they record
+    pub stats: &'a mut RenderStats,
+    views: &'a HashMap<ResourceId, &'a wgpu::TextureView>,
+    timestamp_writes: Option<wgpu::RenderPassTimestampWrites<'a>>,
+}
+
+impl<'a> PassContext<'a> {
+    /// Returns the view bound to a texture the pass declared
+    pub fn view(&self, id: ResourceId) -> &'a wgpu::TextureView {
+        self.views
+            .get(id)
+            .copied()
+            .unwrap_or_else(|| panic!("Render graph texture '{id}' is not bound!"))
+    }
+
+    /// Timestamp writes that time the pass on the GPU, for its main render pass.
+    /// Given out once, and `None` when timestamp queries are unsupported.
+    pub fn timestamp_writes(&mut self) -> Option<wgpu::RenderPassTimestampWrites<'a>> {
+        self.timestamp_writes.take()
+    }
+}
+
+#[derive(Debug)]
+pub enum RenderGraphError {
+    UnknownTexture { pass: String, texture: ResourceId },
+    MissingWriter { texture: ResourceId },
+    Cycle { passes: Vec<String> },
+}
+
+impl std::fmt::Display for RenderGraphError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownTexture { pass, texture } => {
+                write!(f, "Pass '{pass}' uses undeclared texture '{texture}'")
+            }
+            Self::MissingWriter { texture } => {
+                write!(f, "Texture '{texture}' is read but no pass writes it")
+            }
+            Self::Cycle { passes } => {
+                write!(f, "Passes form a dependency cycle: {}", passes.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for RenderGraphError {}
+
+struct PhysicalTexture {
+    _texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+#[derive(Default)]
+pub struct RenderGraph {
+    passes: Vec<Box<dyn Pass>>,
+    textures: HashMap<ResourceId, TransientTexture>,
+    order: Vec<usize>,
+    physical_textures: Vec<PhysicalTexture>,
+    bindings: HashMap<ResourceId, usize>,
+    compiled: bool,
+    /// Set when the last compile failed, so it is not retried until the graph changes
+    failed: bool,
+}
+
+/// Marks the graph for recompiling before the next frame
+fn invalidate(graph: &mut RenderGraph) {
+    graph.compiled = false;
+    graph.failed = false;
+}
+
+/// Appends a pass, the graph is recompiled before the next frame
+pub fn add_pass(graph: &mut RenderGraph, pass: impl Pass + 'static) {
+    graph.passes.push(Box::new(pass));
+    invalidate(graph);
+}
+
+/// Removes every pass with the given name, returning whether any were found
+pub fn remove_pass(graph: &mut RenderGraph, name: &str) -> bool {
+    let count = graph.passes.len();
+    graph.passes.retain(|pass| pass.name() != name);
+    invalidate(graph);
+    graph.passes.len() != count
+}
+
+/// Swaps the pass with the given name for another, keeping its place in the graph.
+/// Returns whether a pass was found.
+pub fn replace_pass(graph: &mut RenderGraph, name: &str, pass: impl Pass + 'static) -> bool {
+    let Some(index) = graph
+        .passes
+        .iter()
+        .position(|existing| existing.name() == name)
+    else {
+        return false;
+    };
+    graph.passes[index] = Box::new(pass);
+    invalidate(graph);
+    true
+}
+
+/// Declares a texture that the graph allocates for its passes
+pub fn add_transient_texture(graph: &mut RenderGraph, id: ResourceId, texture: TransientTexture) {
+    graph.textures.insert(id, texture);
+    invalidate(graph);
+}
+
+/// Returns the pass names in the order they will execute
+pub fn pass_order(graph: &RenderGraph) -> Vec<&str> {
+    graph
+        .order
+        .iter()
+        .map(|index| graph.passes[*index].name())
+        .collect()
+}
+
+/// Orders passes by their texture dependencies and allocates transient textures.
+/// On failure the graph runs no passes until it changes and compiles.
+pub fn compile_render_graph(
+    graph: &mut RenderGraph,
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+) -> Result<(), RenderGraphError> {
+    let result = compile_passes(graph, device, width, height);
+    if result.is_err() {
+        graph.order.clear();
+        graph.physical_textures.clear();
+        graph.bindings.clear();
+        graph.failed = true;
+    }
+    result
+}
+
+fn compile_passes(
+    graph: &mut RenderGraph,
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+) -> Result<(), RenderGraphError> {
+    let accesses = graph
+        .passes
+        .iter()
+        .map(|pass| (pass.name().to_string(), pass.reads(), pass.writes()))
+        .collect::<Vec<_>>();
+
+    for (name, reads, writes) in accesses.iter() {
+        if let Some(texture) = reads
+            .iter()
+            .chain(writes.iter())
+            .find(|texture| **texture != SURFACE && !graph.textures.contains_key(*texture))
+        {
+            return Err(RenderGraphError::UnknownTexture {
+                pass: name.clone(),
+                texture,
+            });
+        }
+    }
+
+    graph.order = sort_passes(&accesses)?;
+    allocate_textures(graph, device, width, height);
+    graph.compiled = true;
+    Ok(())
+}
+
+/// Reallocates transient textures, call when the surface resizes
+pub fn resize_render_graph(
+    graph: &mut RenderGraph,
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+) {
+    if graph.compiled {
+        allocate_textures(graph, device, width, height);
+    }
+}
+
+/// Returns true when passes or textures changed since the last compile
+pub fn needs_compile(graph: &RenderGraph) -> bool {
+    !graph.compiled && !graph.failed
+}
+
+/// Returns true when the last compile failed and the graph has not changed since
+pub fn render_graph_failed(graph: &RenderGraph) -> bool {
+    graph.failed
+}
+
+/// Records every pass into the encoder in dependency order,
+/// profiling each on the CPU and, when a GPU profiler is given, on the GPU.
+/// Returns the draw counts the passes reported.
+#[allow(clippy::too_many_arguments)]
+pub fn execute_render_graph(
+    graph: &mut RenderGraph,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    encoder: &mut wgpu::CommandEncoder,
+    world: &World,
+    profiler: &mut Profiler,
+    mut gpu_profiler: Option<&mut GpuProfiler>,
+    surface_view: &wgpu::TextureView,
+    surface_format: wgpu::TextureFormat,
+    (width, height): (u32, u32),
+) -> RenderStats {
+    let RenderGraph {
+        passes,
+        order,
+        physical_textures,
+        bindings,
+        ..
+    } = graph;
+
+    let mut views = bindings
+        .iter()
+        .map(|(id, slot)| (*id, &physical_textures[*slot].view))
+        .collect::<HashMap<_, _>>();
+    views.insert(SURFACE, surface_view);
+
+    let mut stats = RenderStats::default();
+
+    for index in order.iter() {
+        let pass = &mut passes[*index];
+        begin_scope(profiler, pass.name());
+        encoder.push_debug_group(pass.name());
+        let timestamp_writes = gpu_profiler
+            .as_deref_mut()
+            .and_then(|gpu_profiler| gpu_timestamp_writes(gpu_profiler, pass.name()));
+        let timed = timestamp_writes.is_some();
+        let mut context = PassContext {
+            device,
+            queue,
+            encoder,
+            world,
+            surface_format,
+            width,
+            height,
+            stats: &mut stats,
+            views: &views,
+            timestamp_writes,
+        };
+        pass.execute(&mut context);
+        let unused_timestamps = timed && context.timestamp_writes.is_some();
+        encoder.pop_debug_group();
+        if let Some(gpu_profiler) = gpu_profiler.as_deref_mut().filter(|_| unused_timestamps) {
+            release_gpu_timestamp_writes(gpu_profiler);
+        }
+        end_scope(profiler);
+    }
+    stats
+}
+
+/// Topologically sorts passes, keeping insertion order where dependencies allow.
+/// Writers of a texture run in insertion order, and readers run after all of its writers.
+fn sort_passes(
+    accesses: &[(String, Vec<ResourceId>, Vec<ResourceId>)],
+) -> Result<Vec<usize>, RenderGraphError> {
+    let mut writers: HashMap<ResourceId, Vec<usize>> = HashMap::new();
+    for (index, (_, _, writes)) in accesses.iter().enumerate() {
+        for texture in writes.iter() {
+            writers.entry(texture).or_default().push(index);
+        }
+    }
+
+    let mut edges = vec![HashSet::new(); accesses.len()];
+    for pass_writers in writers.values() {
+        for pair in pass_writers.windows(2) {
+            edges[pair[0]].insert(pair[1]);
+        }
+    }
+    for (index, (_, reads, writes)) in accesses.iter().enumerate() {
+        for texture in reads.iter().filter(|texture| !writes.contains(texture)) {
+            let Some(pass_writers) = writers.get(texture) else {
+                if *texture == SURFACE {
+                    continue;
+                }
+                return Err(RenderGraphError::MissingWriter { texture });
+            };
+            for writer in pass_writers.iter() {
+                edges[*writer].insert(index);
+            }
+        }
+    }
+
+    let mut in_degree = vec![0; accesses.len()];
+    for targets in edges.iter() {
+        for target in targets.iter() {
+            in_degree[*target] += 1;
+        }
+    }
+
+    let mut ready = in_degree
+        .iter()
+        .enumerate()
+        .filter(|(_, degree)| **degree == 0)
+        .map(|(index, _)| Reverse(index))
+        .collect::<BinaryHeap<_>>();
+    let mut order = Vec::with_capacity(accesses.len());
+    while let Some(Reverse(index)) = ready.pop() {
+        order.push(index);
+        for target in edges[index].iter() {
+            in_degree[*target] -= 1;
+            if in_degree[*target] == 0 {
+                ready.push(Reverse(*target));
+            }
+        }
+    }
+
+    if order.len() != accesses.len() {
+        return Err(RenderGraphError::Cycle {
+            passes: (0..accesses.len())
+                .filter(|index| !order.contains(index))
+                .map(|index| accesses[index].0.clone())
+                .collect(),
+        });
+    }
+
+    Ok(order)
+}
+
+/// Creates physical textures for the transient textures, as planned by `plan_textures`
+fn allocate_textures(graph: &mut RenderGraph, device: &wgpu::Device, width: u32, height: u32) {
+    let ordered_accesses = graph
+        .order
+        .iter()
+        .map(|index| {
+            let pass = &graph.passes[*index];
+            pass.reads().into_iter().chain(pass.writes()).collect()
+        })
+        .collect::<Vec<_>>();
+    let (slots, bindings) = plan_textures(&ordered_accesses, &graph.textures, width, height);
+    graph.physical_textures = slots
+        .into_iter()
+        .map(|(width, height, format, usage)| {
+            create_physical_texture(device, width, height, format, usage)
+        })
+        .collect();
+    graph.bindings = bindings;
+}
+
+/// The size, format and usage of a physical texture
+type TextureSlot = (u32, u32, wgpu::TextureFormat, wgpu::TextureUsages);
+
+/// Assigns the transient textures used by passes, given in execution order, to physical
+/// slots. Textures with the same size, format and usage share a slot when their lifetimes
+/// don't overlap.
+fn plan_textures(
+    ordered_accesses: &[Vec<ResourceId>],
+    textures: &HashMap<ResourceId, TransientTexture>,
+    width: u32,
+    height: u32,
+) -> (Vec<TextureSlot>, HashMap<ResourceId, usize>) {
+    let mut lifetimes: HashMap<ResourceId, (usize, usize)> = HashMap::new();
+    for (position, accesses) in ordered_accesses.iter().enumerate() {
+        for texture in accesses.iter() {
+            if !textures.contains_key(texture) {
+                continue;
+            }
+            let lifetime = lifetimes.entry(texture).or_insert((position, position));
+            lifetime.1 = lifetime.1.max(position);
+        }
+    }
+
+    let mut lifetimes = lifetimes.into_iter().collect::<Vec<_>>();
+    lifetimes.sort_by_key(|(id, (first, _))| (*first, *id));
+
+    let mut slots: Vec<(TextureSlot, usize)> = Vec::new();
+    let mut bindings = HashMap::new();
+    for (id, (first, last)) in lifetimes {
+        let texture = textures[id];
+        let (texture_width, texture_height) = resolve_size(texture.size, width, height);
+        let key = (texture_width, texture_height, texture.format, texture.usage);
+
+        let slot = match slots
+            .iter()
+            .position(|(slot_key, slot_last)| *slot_key == key && *slot_last < first)
+        {
+            Some(slot) => slot,
+            None => {
+                slots.push((key, last));
+                slots.len() - 1
+            }
+        };
+        slots[slot].1 = last;
+        bindings.insert(id, slot);
+    }
+    (slots.into_iter().map(|(key, _)| key).collect(), bindings)
+}
+
+fn resolve_size(size: TextureSize, width: u32, height: u32) -> (u32, u32) {
+    match size {
+        TextureSize::Surface => (width, height),
+        TextureSize::SurfaceDivided(factor) => (
+            (width / factor.max(1)).max(1),
+            (height / factor.max(1)).max(1),
+        ),
+        TextureSize::Fixed { width, height } => (width, height),
+    }
+}
+
+fn create_physical_texture(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+    usage: wgpu::TextureUsages,
+) -> PhysicalTexture {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Render Graph Texture"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    PhysicalTexture {
+        _texture: texture,
+        view,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLOR: TransientTexture = TransientTexture {
+        size: TextureSize::Surface,
+        format: wgpu::TextureFormat::Rgba16Float,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+    };
+
+    fn access(
+        name: &str,
+        reads: &[ResourceId],
+        writes: &[ResourceId],
+    ) -> (String, Vec<ResourceId>, Vec<ResourceId>) {
+        (name.to_string(), reads.to_vec(), writes.to_vec())
+    }
+
+    fn names(
+        accesses: &[(String, Vec<ResourceId>, Vec<ResourceId>)],
+        order: &[usize],
+    ) -> Vec<String> {
+        order
+            .iter()
+            .map(|index| accesses[*index].0.clone())
+            .collect()
+    }
+
+    #[test]
+    fn readers_run_after_their_writers() {
+        let accesses = [
+            access("composite", &["bloom", "hdr"], &[SURFACE]),
+            access("bloom", &["hdr"], &["bloom"]),
+            access("scene", &[], &["hdr", DEPTH]),
+            access("ui", &[], &[SURFACE]),
+        ];
+        let order = sort_passes(&accesses).expect("The graph has no cycle!");
+        assert_eq!(
+            names(&accesses, &order),
+            ["scene", "bloom", "composite", "ui"]
+        );
+    }
+
+    #[test]
+    fn independent_passes_keep_insertion_order() {
+        let accesses = [
+            access("b", &[], &["b"]),
+            access("a", &[], &["a"]),
+            access("c", &[], &["c"]),
+        ];
+        let order = sort_passes(&accesses).expect("The graph has no cycle!");
+        assert_eq!(order, [0, 1, 2]);
+    }
+
+    #[test]
+    fn cycles_are_reported_with_their_passes() {
+        let accesses = [
+            access("first", &["b"], &["a"]),
+            access("second", &["a"], &["b"]),
+            access("free", &[], &["c"]),
+        ];
+        match sort_passes(&accesses) {
+            Err(RenderGraphError::Cycle { mut passes }) => {
+                passes.sort();
+                assert_eq!(passes, ["first", "second"]);
+            }
+            other => panic!("Expected a cycle, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn reading_an_unwritten_texture_is_an_error() {
+        let accesses = [access("blur", &["hdr"], &["blurred"])];
+        assert!(matches!(
+            sort_passes(&accesses),
+            Err(RenderGraphError::MissingWriter { texture: "hdr" })
+        ));
+        // The surface is imported, so reading it needs no writer
+        assert!(sort_passes(&[access("copy", &[SURFACE], &["copy"])]).is_ok());
+    }
+
+    #[test]
+    fn textures_share_a_slot_only_after_the_previous_one_ends() {
+        let textures = HashMap::from([("a", COLOR), ("b", COLOR), ("c", COLOR)]);
+        // a lives in passes 0 and 1, b in 1 and 2, c in 2 and 3
+        let accesses = [vec!["a"], vec!["a", "b"], vec!["b", "c"], vec!["c"]];
+        let (slots, bindings) = plan_textures(&accesses, &textures, 64, 32);
+        assert_eq!(slots.len(), 2);
+        assert_ne!(bindings["a"], bindings["b"]);
+        assert_ne!(bindings["b"], bindings["c"]);
+        assert_eq!(bindings["a"], bindings["c"]);
+    }
+
+    #[test]
+    fn textures_share_a_slot_only_with_the_same_size_and_format() {
+        let half = TransientTexture {
+            size: TextureSize::SurfaceDivided(2),
+            ..COLOR
+        };
+        let depth = TransientTexture {
+            format: DEPTH_FORMAT,
+            ..COLOR
+        };
+        let textures = HashMap::from([("full", COLOR), ("half", half), ("depth", depth)]);
+        let accesses = [vec!["full"], vec!["half"], vec!["depth"]];
+        let (slots, bindings) = plan_textures(&accesses, &textures, 64, 32);
+        assert_eq!(slots.len(), 3);
+        assert_eq!(bindings.len(), 3);
+
+        // The same texture size, reached through a fixed size, may share
+        let fixed = TransientTexture {
+            size: TextureSize::Fixed {
+                width: 64,
+                height: 32,
+            },
+            ..COLOR
+        };
+        let textures = HashMap::from([("full", COLOR), ("fixed", fixed)]);
+        let (slots, _) = plan_textures(&[vec!["full"], vec!["fixed"]], &textures, 64, 32);
+        assert_eq!(slots.len(), 1);
+    }
+
+    #[test]
+    fn undeclared_textures_get_no_slot() {
+        let textures = HashMap::from([("a", COLOR)]);
+        let (slots, bindings) = plan_textures(&[vec!["a", SURFACE]], &textures, 8, 8);
+        assert_eq!(slots.len(), 1);
+        assert!(!bindings.contains_key(SURFACE));
+    }
+
+    #[test]
+    fn resizing_resolves_relative_sizes_again() {
+        let half = TransientTexture {
+            size: TextureSize::SurfaceDivided(2),
+            ..COLOR
+        };
+        let fixed = TransientTexture {
+            size: TextureSize::Fixed {
+                width: 16,
+                height: 16,
+            },
+            ..COLOR
+        };
+        let textures = HashMap::from([("full", COLOR), ("half", half), ("fixed", fixed)]);
+        let accesses = [vec!["full", "half", "fixed"]];
+        let sizes = |width, height| {
+            let (slots, bindings) = plan_textures(&accesses, &textures, width, height);
+            ["full", "half", "fixed"].map(|id| {
+                let (width, height, ..) = slots[bindings[id]];
+                (width, height)
+            })
+        };
+        assert_eq!(sizes(800, 600), [(800, 600), (400, 300), (16, 16)]);
+        assert_eq!(sizes(1, 1), [(1, 1), (1, 1), (16, 16)]);
+    }
+}
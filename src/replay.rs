@@ -0,0 +1,332 @@
+use crate::{
+    app::{update_frame, State},
+    input::{apply_input_event, end_mouse_frame, Gamepads, InputEvent},
+    profiler::{begin_frame, end_frame},
+    world::World,
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// The input one frame received, and a checksum of the component data it produced
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    pub delta_time: f32,
+    /// Events applied before the frame, in the order they arrived
+    pub events: Vec<InputEvent>,
+    /// Present when the gamepads changed since the previous frame
+    #[serde(default)]
+    pub gamepads: Option<Gamepads>,
+    pub checksum: u64,
+}
+
+/// Input and frame times captured from a run. Replaying them from the same
+/// initial world reproduces it, provided gameplay reads input through resources
+/// rather than `State::receive_event` and assets load in the same frames.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputRecording {
+    /// The viewport size when recording started
+    pub viewport: (u32, u32),
+    pub frames: Vec<RecordedFrame>,
+}
+
+/// Where a replay first produced different component data than the recording
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ReplayDivergence {
+    pub frame: usize,
+    pub expected: u64,
+    pub actual: u64,
+}
+
+impl std::fmt::Display for ReplayDivergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Replay diverged at frame {}: expected checksum {:016x}, got {:016x}",
+            self.frame, self.expected, self.actual
+        )
+    }
+}
+
+pub fn save_recording(recording: &InputRecording, path: impl AsRef<Path>) -> Result<(), String> {
+    let serialized = ron::to_string(recording).map_err(|error| error.to_string())?;
+    std::fs::write(path, serialized).map_err(|error| error.to_string())
+}
+
+pub fn load_recording(path: impl AsRef<Path>) -> Result<InputRecording, String> {
+    let bytes = std::fs::read(path).map_err(|error| error.to_string())?;
+    ron::de::from_bytes(&bytes).map_err(|error| format!("Failed to parse recording: {error}"))
+}
+
+/// A hash of every entity's component data, stable across runs and platforms
+pub fn world_checksum(world: &World) -> u64 {
+    let mut hasher = ChecksumWriter(FNV_OFFSET);
+    ron::ser::to_writer(&mut hasher, &world.tables).expect("Failed to serialize components!");
+    hasher.0
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// Hashes serialized bytes with FNV-1a as they are written
+struct ChecksumWriter(u64);
+
+impl std::io::Write for ChecksumWriter {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(FNV_PRIME);
+        }
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Collects a recording as the app runs
+#[derive(Default)]
+pub struct InputRecorder {
+    pub recording: InputRecording,
+    events: Vec<InputEvent>,
+    gamepads: Gamepads,
+}
+
+pub fn create_input_recorder(world: &World) -> InputRecorder {
+    InputRecorder {
+        recording: InputRecording {
+            viewport: (
+                world.resources.viewport_width,
+                world.resources.viewport_height,
+            ),
+            frames: Vec::new(),
+        },
+        ..Default::default()
+    }
+}
+
+/// Keeps an event for the next recorded frame
+pub fn record_input_event(recorder: &mut InputRecorder, event: InputEvent) {
+    recorder.events.push(event);
+}
+
+/// Finishes a frame, call after its systems have run
+pub fn record_frame(recorder: &mut InputRecorder, world: &World) {
+    let gamepads = &world.resources.gamepads;
+    let changed = *gamepads != recorder.gamepads;
+    if changed {
+        recorder.gamepads = gamepads.clone();
+    }
+    recorder.recording.frames.push(RecordedFrame {
+        delta_time: world.resources.delta_time,
+        events: std::mem::take(&mut recorder.events),
+        gamepads: changed.then(|| gamepads.clone()),
+        checksum: world_checksum(world),
+    });
+}
+
+/// Plays a recording back one frame at a time
+#[derive(Default)]
+pub struct InputReplay {
+    pub recording: InputRecording,
+    /// The next frame to play
+    pub frame: usize,
+    pub divergence: Option<ReplayDivergence>,
+}
+
+pub fn create_input_replay(recording: InputRecording) -> InputReplay {
+    InputReplay {
+        recording,
+        ..Default::default()
+    }
+}
+
+/// Applies the viewport size the recording started with
+pub fn begin_replay(replay: &InputReplay, world: &mut World) {
+    let (width, height) = replay.recording.viewport;
+    world.resources.viewport_width = width;
+    world.resources.viewport_height = height;
+}
+
+/// Applies the next frame's input and delta time, or returns false once the recording ends
+pub fn begin_replay_frame(replay: &InputReplay, world: &mut World) -> bool {
+    let Some(frame) = replay.recording.frames.get(replay.frame) else {
+        return false;
+    };
+    for event in frame.events.iter() {
+        apply_input_event(world, event);
+    }
+    if let Some(gamepads) = frame.gamepads.as_ref() {
+        world.resources.gamepads = gamepads.clone();
+    }
+    world.resources.delta_time = frame.delta_time;
+    true
+}
+
+/// Compares the frame's component data with the recording, call after its systems have run.
+/// Returns the divergence if this is the first differing frame.
+pub fn end_replay_frame(replay: &mut InputReplay, world: &World) -> Option<ReplayDivergence> {
+    let expected = replay.recording.frames.get(replay.frame)?.checksum;
+    let frame = replay.frame;
+    replay.frame += 1;
+    if replay.divergence.is_some() {
+        return None;
+    }
+    let actual = world_checksum(world);
+    if actual == expected {
+        return None;
+    }
+    replay.divergence = Some(ReplayDivergence {
+        frame,
+        expected,
+        actual,
+    });
+    replay.divergence
+}
+
+/// Replays a recording without a window, stopping at the first divergence.
+/// The UI is not run, so changes made by `State::ui` are not reproduced.
+pub fn replay_headless(
+    world: &mut World,
+    state: &mut dyn State,
+    recording: InputRecording,
+) -> Result<(), ReplayDivergence> {
+    let mut replay = create_input_replay(recording);
+    begin_replay(&replay, world);
+    while begin_replay_frame(&replay, world) {
+        begin_frame(&mut world.resources.profiler);
+        update_frame(world, state, |_, _| ());
+        end_mouse_frame(&mut world.resources.mouse);
+        end_frame(&mut world.resources.profiler);
+        if let Some(divergence) = end_replay_frame(&mut replay, world) {
+            return Err(divergence);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        character::{character_input_map, MOVE_ACTION},
+        input::action_value,
+        world::{
+            get_component_mut, query_entities, spawn_entities, LocalTransform, GLOBAL_TRANSFORM,
+            LOCAL_TRANSFORM,
+        },
+    };
+    use winit::{event::ElementState, keyboard::KeyCode};
+
+    /// Moves every entity with the move action on fixed steps, and up with the cursor each frame
+    struct Mover;
+
+    impl State for Mover {
+        fn fixed_update(&mut self, world: &mut World) {
+            let value = action_value(&world.resources.input, 0, MOVE_ACTION);
+            let timestep = world.resources.fixed_time.timestep;
+            for entity in query_entities(world, LOCAL_TRANSFORM) {
+                let transform = get_component_mut::<LocalTransform>(world, entity, LOCAL_TRANSFORM)
+                    .expect("No local transform!");
+                transform.translation.x += value.x * timestep;
+                transform.translation.z += value.y * timestep;
+            }
+        }
+
+        fn update(&mut self, world: &mut World) {
+            let delta = world.resources.mouse.position_delta.y;
+            for entity in query_entities(world, LOCAL_TRANSFORM) {
+                get_component_mut::<LocalTransform>(world, entity, LOCAL_TRANSFORM)
+                    .expect("No local transform!")
+                    .translation
+                    .y += delta * 0.01;
+            }
+        }
+    }
+
+    fn initial_world() -> World {
+        let mut world = World::default();
+        world.resources.input.maps.insert(0, character_input_map(0));
+        spawn_entities(&mut world, LOCAL_TRANSFORM | GLOBAL_TRANSFORM, 3);
+        world
+    }
+
+    fn key(key: KeyCode, state: ElementState) -> InputEvent {
+        InputEvent::Key { key, state }
+    }
+
+    /// Runs frames of uneven length through the app's frame path, holding keys and moving the cursor
+    fn record(world: &mut World) -> InputRecording {
+        let mut recorder = create_input_recorder(world);
+        for frame in 0..120 {
+            let mut events = Vec::new();
+            match frame {
+                5 => events.push(key(KeyCode::KeyW, ElementState::Pressed)),
+                40 => events.push(key(KeyCode::KeyD, ElementState::Pressed)),
+                60 => events.push(key(KeyCode::KeyW, ElementState::Released)),
+                _ => {}
+            }
+            if frame % 7 == 0 {
+                let position = nalgebra_glm::vec2(frame as f32, 2.0 * frame as f32);
+                events.push(InputEvent::CursorMoved { position });
+            }
+            for event in events {
+                apply_input_event(world, &event);
+                record_input_event(&mut recorder, event);
+            }
+            world.resources.delta_time = 0.013 + (frame % 3) as f32 * 0.004;
+            begin_frame(&mut world.resources.profiler);
+            update_frame(world, &mut Mover, |_, _| ());
+            record_frame(&mut recorder, world);
+            end_mouse_frame(&mut world.resources.mouse);
+            end_frame(&mut world.resources.profiler);
+        }
+        recorder.recording
+    }
+
+    #[test]
+    fn recordings_replay_to_the_same_checksums() {
+        let mut world = initial_world();
+        let recording = record(&mut world);
+        let initial_checksum = world_checksum(&initial_world());
+        assert_ne!(
+            recording.frames.last().map(|frame| frame.checksum),
+            Some(initial_checksum)
+        );
+
+        let path = std::env::temp_dir().join(format!("spree-replay-{}.ron", std::process::id()));
+        save_recording(&recording, &path).expect("Failed to save the recording!");
+        let loaded = load_recording(&path).expect("Failed to load the recording!");
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(loaded, recording);
+
+        let mut replayed = initial_world();
+        assert_eq!(replay_headless(&mut replayed, &mut Mover, loaded), Ok(()));
+        assert_eq!(world_checksum(&replayed), world_checksum(&world));
+    }
+
+    #[test]
+    fn replays_report_the_first_diverging_frame() {
+        let recording = record(&mut initial_world());
+
+        let mut changed_time = recording.clone();
+        changed_time.frames[50].delta_time += 0.001;
+        let divergence = replay_headless(&mut initial_world(), &mut Mover, changed_time)
+            .expect_err("A longer frame changes the world!");
+        // The extra time waits in the fixed step accumulator until it adds a step
+        assert!(divergence.frame >= 50);
+
+        let mut extra_key = recording.clone();
+        extra_key.frames[77]
+            .events
+            .push(key(KeyCode::KeyA, ElementState::Pressed));
+        let divergence = replay_headless(&mut initial_world(), &mut Mover, extra_key)
+            .expect_err("An extra key press changes the world!");
+        assert!(divergence.frame >= 77);
+
+        let mut other_world = initial_world();
+        spawn_entities(&mut other_world, LOCAL_TRANSFORM, 1);
+        let divergence = replay_headless(&mut other_world, &mut Mover, recording)
+            .expect_err("A different initial world diverges!");
+        assert_eq!(divergence.frame, 0);
+    }
+}
@@ -0,0 +1,536 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// The built-in shaders, by the names they are included with
+pub const BUILTIN_SHADERS: [(&str, &str); 5] = [
+    ("blit.wgsl", include_str!("shaders/blit.wgsl")),
+    ("brdf.wgsl", include_str!("shaders/brdf.wgsl")),
+    ("color.wgsl", include_str!("shaders/color.wgsl")),
+    ("debug.wgsl", include_str!("shaders/debug.wgsl")),
+    ("pbr.wgsl", include_str!("shaders/pbr.wgsl")),
+];
+
+/// Enables tangent-space normal mapping in the PBR shader
+pub const NORMAL_MAP: &str = "NORMAL_MAP";
+
+/// Deforms vertices by a joint palette in the PBR shader
+pub const SKINNING: &str = "SKINNING";
+
+/// Named WGSL sources that can include each other.
+/// Sources are preprocessed before compiling, supporting these line directives:
+/// `#include "name.wgsl"`, `#ifdef NAME`, `#ifndef NAME`, `#else` and `#endif`.
+/// Each file is included at most once per shader.
+#[derive(Default, Clone, Debug)]
+pub struct ShaderLibrary {
+    sources: HashMap<String, String>,
+}
+
+/// Identifies one permutation of a shader
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ShaderKey {
+    pub name: String,
+    pub defines: BTreeSet<String>,
+}
+
+/// A preprocessed shader, with the file and line each output line came from
+#[derive(Clone, Debug, Default)]
+pub struct ComposedShader {
+    pub source: String,
+    pub files: Vec<String>,
+    /// Indices into `files` and 1-based line numbers, one per output line
+    pub line_origins: Vec<(usize, usize)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShaderError {
+    pub file: String,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl std::fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{line}: {}", self.file, self.message),
+            None => write!(f, "{}: {}", self.file, self.message),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+pub fn shader_key(name: &str, defines: &[&str]) -> ShaderKey {
+    ShaderKey {
+        name: name.to_string(),
+        defines: defines.iter().map(|define| define.to_string()).collect(),
+    }
+}
+
+/// A library containing the built-in shaders
+pub fn builtin_shader_library() -> ShaderLibrary {
+    let mut library = ShaderLibrary::default();
+    for (name, source) in BUILTIN_SHADERS {
+        set_shader_source(&mut library, name, source);
+    }
+    library
+}
+
+pub fn set_shader_source(library: &mut ShaderLibrary, name: &str, source: &str) {
+    library.sources.insert(name.to_string(), source.to_string());
+}
+
+pub fn shader_source<'a>(library: &'a ShaderLibrary, name: &str) -> Option<&'a str> {
+    library.sources.get(name).map(String::as_str)
+}
+
+/// Returns a shader and every file it can include, whatever the defines, starting with the shader.
+/// Files missing from the library are listed but not followed.
+pub fn shader_includes(library: &ShaderLibrary, name: &str) -> Vec<String> {
+    let mut files = vec![name.to_string()];
+    let mut index = 0;
+    while let Some(file) = files.get(index) {
+        let included = library
+            .sources
+            .get(file)
+            .into_iter()
+            .flat_map(|source| source.lines())
+            .filter_map(|line| line.trim_start().strip_prefix("#include"))
+            .filter_map(|argument| {
+                argument
+                    .trim()
+                    .strip_prefix('"')
+                    .and_then(|argument| argument.strip_suffix('"'))
+            })
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        for file in included {
+            if !files.contains(&file) {
+                files.push(file);
+            }
+        }
+        index += 1;
+    }
+    files
+}
+
+/// Resolves the includes and conditional blocks of a shader
+pub fn compose_shader(
+    library: &ShaderLibrary,
+    key: &ShaderKey,
+) -> Result<ComposedShader, ShaderError> {
+    let mut composed = ComposedShader::default();
+    let mut included = HashSet::new();
+    compose_file(
+        library,
+        &key.name,
+        &key.defines,
+        &mut composed,
+        &mut included,
+        &mut Vec::new(),
+    )?;
+    Ok(composed)
+}
+
+/// Tracks one `#ifdef` block while preprocessing
+struct Conditional {
+    line: usize,
+    active: bool,
+    parent_active: bool,
+    seen_else: bool,
+}
+
+fn compose_file(
+    library: &ShaderLibrary,
+    name: &str,
+    defines: &BTreeSet<String>,
+    composed: &mut ComposedShader,
+    included: &mut HashSet<String>,
+    stack: &mut Vec<String>,
+) -> Result<(), ShaderError> {
+    let error = |line: usize, message: String| ShaderError {
+        file: name.to_string(),
+        line: Some(line),
+        message,
+    };
+    let source = library.sources.get(name).ok_or_else(|| ShaderError {
+        file: name.to_string(),
+        line: None,
+        message: "Shader source not found".to_string(),
+    })?;
+    if !included.insert(name.to_string()) {
+        return Ok(());
+    }
+    stack.push(name.to_string());
+    let file_index = composed.files.len();
+    composed.files.push(name.to_string());
+
+    let mut conditionals: Vec<Conditional> = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let active = conditionals
+            .last()
+            .is_none_or(|conditional| conditional.active);
+        let Some(directive) = line.trim_start().strip_prefix('#') else {
+            if active {
+                composed.source.push_str(line);
+                composed.source.push('\n');
+                composed.line_origins.push((file_index, line_number));
+            }
+            continue;
+        };
+
+        let (command, argument) = directive
+            .split_once(char::is_whitespace)
+            .map(|(command, argument)| (command, argument.trim()))
+            .unwrap_or((directive.trim(), ""));
+        match command {
+            "ifdef" | "ifndef" => {
+                if argument.is_empty() {
+                    return Err(error(line_number, format!("#{command} needs a name")));
+                }
+                let defined = defines.contains(argument);
+                conditionals.push(Conditional {
+                    line: line_number,
+                    active: active && (defined == (command == "ifdef")),
+                    parent_active: active,
+                    seen_else: false,
+                });
+            }
+            "else" => {
+                let conditional = conditionals
+                    .last_mut()
+                    .filter(|conditional| !conditional.seen_else)
+                    .ok_or_else(|| error(line_number, "#else without #ifdef".to_string()))?;
+                conditional.seen_else = true;
+                conditional.active = conditional.parent_active && !conditional.active;
+            }
+            "endif" => {
+                conditionals
+                    .pop()
+                    .ok_or_else(|| error(line_number, "#endif without #ifdef".to_string()))?;
+            }
+            "include" => {
+                if !active {
+                    continue;
+                }
+                let included_name = argument
+                    .strip_prefix('"')
+                    .and_then(|argument| argument.strip_suffix('"'))
+                    .ok_or_else(|| {
+                        error(line_number, "#include expects a quoted name".to_string())
+                    })?;
+                if stack.iter().any(|file| file == included_name) {
+                    return Err(error(
+                        line_number,
+                        format!("Including {included_name} forms a cycle"),
+                    ));
+                }
+                compose_file(library, included_name, defines, composed, included, stack).map_err(
+                    |included_error| match included_error.line {
+                        // A missing file is reported where it is included
+                        None => error(
+                            line_number,
+                            format!("{}: {}", included_error.file, included_error.message),
+                        ),
+                        Some(_) => included_error,
+                    },
+                )?;
+            }
+            _ => return Err(error(line_number, format!("Unknown directive #{command}"))),
+        }
+    }
+    if let Some(conditional) = conditionals.last() {
+        return Err(error(conditional.line, "Unterminated #ifdef".to_string()));
+    }
+    stack.pop();
+    Ok(())
+}
+
+/// Composes and validates a shader with naga, without needing a GPU.
+/// Errors point at the file and line of the original source.
+pub fn validate_shader(
+    library: &ShaderLibrary,
+    key: &ShaderKey,
+) -> Result<(ComposedShader, naga::Module), ShaderError> {
+    let composed = compose_shader(library, key)?;
+    let module = naga::front::wgsl::parse_str(&composed.source).map_err(|error| {
+        composed_error(
+            &composed,
+            &key.name,
+            error.location(&composed.source),
+            error.message().to_string(),
+        )
+    })?;
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(&module)
+    .map_err(|error| {
+        composed_error(
+            &composed,
+            &key.name,
+            error.location(&composed.source),
+            error_chain(&error),
+        )
+    })?;
+    Ok((composed, module))
+}
+
+/// Validates every built-in shader permutation the renderer can request
+pub fn validate_builtin_shaders() -> Result<(), ShaderError> {
+    let library = builtin_shader_library();
+    validate_shader(&library, &shader_key("blit.wgsl", &[]))?;
+    validate_shader(&library, &shader_key("debug.wgsl", &[]))?;
+    for defines in [&[][..], &[NORMAL_MAP], &[SKINNING], &[NORMAL_MAP, SKINNING]] {
+        validate_shader(&library, &shader_key("pbr.wgsl", defines))?;
+    }
+    Ok(())
+}
+
+fn composed_error(
+    composed: &ComposedShader,
+    name: &str,
+    location: Option<naga::SourceLocation>,
+    message: String,
+) -> ShaderError {
+    let origin = location.and_then(|location| {
+        composed
+            .line_origins
+            .get(location.line_number.saturating_sub(1) as usize)
+    });
+    match origin {
+        Some((file, line)) => ShaderError {
+            file: composed.files[*file].clone(),
+            line: Some(*line),
+            message,
+        },
+        None => ShaderError {
+            file: name.to_string(),
+            line: None,
+            message,
+        },
+    }
+}
+
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        message.push_str(": ");
+        message.push_str(&error.to_string());
+        source = error.source();
+    }
+    message
+}
+
+/// Compiled shader modules and pipelines, cached by permutation
+#[derive(Default)]
+pub struct ShaderCache {
+    pub library: ShaderLibrary,
+    modules: HashMap<ShaderKey, (wgpu::ShaderModule, Vec<String>)>,
+    pipelines: HashMap<PipelineKey, wgpu::RenderPipeline>,
+}
+
+/// Identifies a render pipeline built from a shader permutation
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    pub shader: ShaderKey,
+    pub color_format: wgpu::TextureFormat,
+    pub sample_count: u32,
+}
+
+pub fn create_shader_cache(library: ShaderLibrary) -> ShaderCache {
+    ShaderCache {
+        library,
+        ..Default::default()
+    }
+}
+
+/// Returns the module for a shader permutation, validating and compiling it on first use
+pub fn shader_module<'a>(
+    cache: &'a mut ShaderCache,
+    device: &wgpu::Device,
+    key: &ShaderKey,
+) -> Result<&'a wgpu::ShaderModule, ShaderError> {
+    if !cache.modules.contains_key(key) {
+        let (composed, _) = validate_shader(&cache.library, key)?;
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(&key.name),
+            source: wgpu::ShaderSource::Wgsl(composed.source.into()),
+        });
+        cache.modules.insert(key.clone(), (module, composed.files));
+    }
+    Ok(&cache.modules[key].0)
+}
+
+/// Returns the cached pipeline for a key, creating it from the shader module if needed
+pub fn cached_pipeline<'a>(
+    cache: &'a mut ShaderCache,
+    device: &wgpu::Device,
+    key: &PipelineKey,
+    create_pipeline: impl FnOnce(&wgpu::ShaderModule) -> Result<wgpu::RenderPipeline, ShaderError>,
+) -> Result<&'a wgpu::RenderPipeline, ShaderError> {
+    if !cache.pipelines.contains_key(key) {
+        let pipeline = create_pipeline(shader_module(cache, device, &key.shader)?)?;
+        cache.pipelines.insert(key.clone(), pipeline);
+    }
+    Ok(&cache.pipelines[key])
+}
+
+pub fn get_pipeline<'a>(
+    cache: &'a ShaderCache,
+    key: &PipelineKey,
+) -> Option<&'a wgpu::RenderPipeline> {
+    cache.pipelines.get(key)
+}
+
+pub fn cached_pipeline_keys(cache: &ShaderCache) -> Vec<PipelineKey> {
+    cache.pipelines.keys().cloned().collect()
+}
+
+/// Replaces a source after checking that every cached permutation still compiles with it.
+/// On failure the previous source stays in place. Modules and pipelines
+/// that include the source are rebuilt on their next use.
+pub fn replace_shader_source(
+    cache: &mut ShaderCache,
+    name: &str,
+    source: &str,
+) -> Result<(), ShaderError> {
+    let mut library = cache.library.clone();
+    set_shader_source(&mut library, name, source);
+    let dependents = cache
+        .modules
+        .iter()
+        .filter(|(_, (_, files))| files.iter().any(|file| file == name))
+        .map(|(key, _)| key.clone())
+        .collect::<Vec<_>>();
+    for key in dependents.iter() {
+        validate_shader(&library, key)?;
+    }
+
+    cache.library = library;
+    cache.modules.retain(|key, _| !dependents.contains(key));
+    cache
+        .pipelines
+        .retain(|key, _| !dependents.contains(&key.shader));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn library(sources: &[(&str, &str)]) -> ShaderLibrary {
+        let mut library = ShaderLibrary::default();
+        for (name, source) in sources {
+            set_shader_source(&mut library, name, source);
+        }
+        library
+    }
+
+    #[test]
+    fn builtin_shaders_validate() {
+        if let Err(error) = validate_builtin_shaders() {
+            panic!("{error}");
+        }
+    }
+
+    #[test]
+    fn includes_are_inlined_once() {
+        let library = library(&[
+            (
+                "main.wgsl",
+                "#include \"a.wgsl\"\n#include \"b.wgsl\"\nmain",
+            ),
+            ("a.wgsl", "#include \"b.wgsl\"\na"),
+            ("b.wgsl", "b"),
+        ]);
+        let composed = compose_shader(&library, &shader_key("main.wgsl", &[])).unwrap();
+        assert_eq!(composed.source, "b\na\nmain\n");
+        assert_eq!(composed.files, vec!["main.wgsl", "a.wgsl", "b.wgsl"]);
+        assert_eq!(composed.line_origins, vec![(2, 1), (1, 2), (0, 3)]);
+    }
+
+    #[test]
+    fn defines_select_conditional_blocks() {
+        let library = library(&[(
+            "main.wgsl",
+            "#ifdef A\na\n#ifndef B\nnot b\n#else\nb\n#endif\n#else\nnot a\n#endif\nend",
+        )]);
+        let compose = |defines: &[&str]| {
+            compose_shader(&library, &shader_key("main.wgsl", defines))
+                .unwrap()
+                .source
+        };
+        assert_eq!(compose(&[]), "not a\nend\n");
+        assert_eq!(compose(&["A"]), "a\nnot b\nend\n");
+        assert_eq!(compose(&["A", "B"]), "a\nb\nend\n");
+        assert_eq!(compose(&["B"]), "not a\nend\n");
+    }
+
+    #[test]
+    fn includes_in_inactive_blocks_are_skipped() {
+        let library = library(&[(
+            "main.wgsl",
+            "#ifdef A\n#include \"missing.wgsl\"\n#endif\nmain",
+        )]);
+        assert!(compose_shader(&library, &shader_key("main.wgsl", &[])).is_ok());
+        let error = compose_shader(&library, &shader_key("main.wgsl", &["A"])).unwrap_err();
+        assert_eq!((error.file.as_str(), error.line), ("main.wgsl", Some(2)));
+    }
+
+    #[test]
+    fn preprocessor_errors_point_at_their_line() {
+        let cases = [
+            ("#include \"main.wgsl\"", 1, "cycle"),
+            ("a\n#endif", 2, "#endif without #ifdef"),
+            ("#ifdef A\n#else\n#else\n#endif", 3, "#else without #ifdef"),
+            ("a\n#ifdef A", 2, "Unterminated"),
+            ("#define A", 1, "Unknown directive"),
+            ("#include missing.wgsl", 1, "quoted"),
+        ];
+        for (source, line, message) in cases {
+            let library = library(&[("main.wgsl", source)]);
+            let error = compose_shader(&library, &shader_key("main.wgsl", &[])).unwrap_err();
+            assert_eq!(error.line, Some(line), "{source}");
+            assert!(error.message.contains(message), "{source}: {error}");
+        }
+    }
+
+    #[test]
+    fn validation_errors_point_at_the_original_file() {
+        let library = library(&[
+            (
+                "main.wgsl",
+                "#include \"helpers.wgsl\"\nfn main_value() -> f32 { return helper(); }",
+            ),
+            (
+                "helpers.wgsl",
+                "fn helper() -> f32 {\n    return 1.0;\n}\nfn broken() -> f32 {\n    return missing;\n}",
+            ),
+        ]);
+        let error = validate_shader(&library, &shader_key("main.wgsl", &[])).unwrap_err();
+        assert_eq!(error.file, "helpers.wgsl");
+        assert_eq!(error.line, Some(5));
+    }
+
+    #[test]
+    fn include_sets_cover_every_permutation() {
+        let library = library(&[
+            (
+                "main.wgsl",
+                "#ifdef A\n#include \"a.wgsl\"\n#endif\n#include \"b.wgsl\"",
+            ),
+            ("a.wgsl", "#include \"b.wgsl\"\n#include \"c.wgsl\""),
+            ("b.wgsl", ""),
+        ]);
+        assert_eq!(
+            shader_includes(&library, "main.wgsl"),
+            vec!["main.wgsl", "a.wgsl", "b.wgsl", "c.wgsl"]
+        );
+        let builtin = shader_includes(&builtin_shader_library(), "pbr.wgsl");
+        for file in ["pbr.wgsl", "brdf.wgsl", "color.wgsl"] {
+            assert!(builtin.iter().any(|name| name == file), "{file}");
+        }
+    }
+}
@@ -0,0 +1,37 @@
+// Set when the surface format is not sRGB, so the shader encodes the output itself
+override OUTPUT_SRGB: bool = false;
+
+#include "color.wgsl"
+
+struct Camera {
+    view_projection: mat4x4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(input: VertexInput) -> VertexOutput {
+    var output: VertexOutput;
+    output.clip_position = camera.view_projection * vec4<f32>(input.position, 1.0);
+    output.color = input.color;
+    return output;
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    var color = input.color.rgb;
+    if OUTPUT_SRGB {
+        color = linear_to_srgb(color);
+    }
+    return vec4<f32>(color, input.color.a);
+}
@@ -0,0 +1,149 @@
+// Set when the surface format is not sRGB, so the shader encodes the output itself
+override OUTPUT_SRGB: bool = false;
+
+#include "brdf.wgsl"
+#include "color.wgsl"
+
+struct Camera {
+    view_projection: mat4x4<f32>,
+    position: vec4<f32>,
+    light_direction: vec4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+
+struct Material {
+    base_color_factor: vec4<f32>,
+    emissive_factor: vec4<f32>,
+    metallic_factor: f32,
+    roughness_factor: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+};
+
+@group(1) @binding(0) var<uniform> material: Material;
+@group(1) @binding(1) var base_color_texture: texture_2d<f32>;
+@group(1) @binding(2) var base_color_sampler: sampler;
+@group(1) @binding(3) var metallic_roughness_texture: texture_2d<f32>;
+@group(1) @binding(4) var metallic_roughness_sampler: sampler;
+@group(1) @binding(5) var normal_texture: texture_2d<f32>;
+@group(1) @binding(6) var normal_sampler: sampler;
+@group(1) @binding(7) var occlusion_texture: texture_2d<f32>;
+@group(1) @binding(8) var occlusion_sampler: sampler;
+@group(1) @binding(9) var emissive_texture: texture_2d<f32>;
+@group(1) @binding(10) var emissive_sampler: sampler;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv_0: vec2<f32>,
+    @location(3) tangent: vec4<f32>,
+};
+
+struct InstanceInput {
+    @location(4) model_0: vec4<f32>,
+    @location(5) model_1: vec4<f32>,
+    @location(6) model_2: vec4<f32>,
+    @location(7) model_3: vec4<f32>,
+    @location(8) normal_0: vec4<f32>,
+    @location(9) normal_1: vec4<f32>,
+    @location(10) normal_2: vec4<f32>,
+};
+
+#ifdef SKINNING
+// Matches MAX_JOINTS in skinning.rs
+struct Skin {
+    joints: array<mat4x4<f32>, 256>,
+};
+
+@group(2) @binding(0) var<uniform> skin: Skin;
+
+struct SkinInput {
+    @location(11) joints: vec4<u32>,
+    @location(12) weights: vec4<f32>,
+};
+#endif
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv_0: vec2<f32>,
+    @location(3) tangent: vec4<f32>,
+};
+
+@vertex
+fn vs_main(
+    vertex: VertexInput,
+    instance: InstanceInput,
+#ifdef SKINNING
+    skinning: SkinInput,
+#endif
+) -> VertexOutput {
+    var model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    var normal_matrix = mat3x3<f32>(instance.normal_0.xyz, instance.normal_1.xyz, instance.normal_2.xyz);
+#ifdef SKINNING
+    let skin_matrix = skin.joints[skinning.joints.x] * skinning.weights.x
+        + skin.joints[skinning.joints.y] * skinning.weights.y
+        + skin.joints[skinning.joints.z] * skinning.weights.z
+        + skin.joints[skinning.joints.w] * skinning.weights.w;
+    model = model * skin_matrix;
+    // Exact for joints without non-uniform scale
+    normal_matrix = normal_matrix * mat3x3<f32>(skin_matrix[0].xyz, skin_matrix[1].xyz, skin_matrix[2].xyz);
+#endif
+    let world_position = model * vec4<f32>(vertex.position, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_projection * world_position;
+    out.world_position = world_position.xyz;
+    out.normal = normalize(normal_matrix * vertex.normal);
+    out.uv_0 = vertex.uv_0;
+    out.tangent = vec4<f32>(normalize((model * vec4<f32>(vertex.tangent.xyz, 0.0)).xyz), vertex.tangent.w);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let base_color = textureSample(base_color_texture, base_color_sampler, in.uv_0) * material.base_color_factor;
+    let metallic_roughness = textureSample(metallic_roughness_texture, metallic_roughness_sampler, in.uv_0);
+    let occlusion_sample = textureSample(occlusion_texture, occlusion_sampler, in.uv_0).r;
+    let emissive = textureSample(emissive_texture, emissive_sampler, in.uv_0).rgb * material.emissive_factor.rgb;
+
+    let roughness = clamp(material.roughness_factor * metallic_roughness.g, 0.04, 1.0);
+    let metallic = clamp(material.metallic_factor * metallic_roughness.b, 0.0, 1.0);
+    let occlusion = mix(1.0, occlusion_sample, material.occlusion_strength);
+
+    let geometric_normal = normalize(in.normal);
+#ifdef NORMAL_MAP
+    let tangent_normal = textureSample(normal_texture, normal_sampler, in.uv_0).xyz * 2.0 - 1.0;
+    let tangent = normalize(in.tangent.xyz - geometric_normal * dot(geometric_normal, in.tangent.xyz));
+    let bitangent = cross(geometric_normal, tangent) * in.tangent.w;
+    let scaled_normal = vec3<f32>(tangent_normal.xy * material.normal_scale, tangent_normal.z);
+    let n = normalize(mat3x3<f32>(tangent, bitangent, geometric_normal) * scaled_normal);
+#else
+    let n = geometric_normal;
+#endif
+
+    let v = normalize(camera.position.xyz - in.world_position);
+    let l = normalize(-camera.light_direction.xyz);
+    let h = normalize(v + l);
+    let n_dot_v = max(dot(n, v), 0.0001);
+    let n_dot_l = max(dot(n, l), 0.0);
+    let n_dot_h = max(dot(n, h), 0.0);
+
+    let f0 = mix(vec3<f32>(0.04), base_color.rgb, metallic);
+    let fresnel = fresnel_schlick(max(dot(h, v), 0.0), f0);
+    let specular = distribution_ggx(n_dot_h, roughness) * geometry_smith(n_dot_v, n_dot_l, roughness) * fresnel
+        / (4.0 * n_dot_v * max(n_dot_l, 0.0001));
+    let diffuse = (1.0 - fresnel) * (1.0 - metallic) * base_color.rgb / PI;
+
+    let light_color = vec3<f32>(1.0, 0.98, 0.95) * 3.0;
+    let ambient = vec3<f32>(0.03) * base_color.rgb * occlusion;
+    var color = (diffuse + specular) * light_color * n_dot_l + ambient + emissive;
+    color = color / (color + 1.0);
+
+    if OUTPUT_SRGB {
+        color = linear_to_srgb(color);
+    }
+    return vec4<f32>(color, base_color.a);
+}
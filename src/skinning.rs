@@ -0,0 +1,181 @@
+use crate::world::{
+    get_component, EntityId, GlobalTransform, SkinWeights, World, GLOBAL_TRANSFORM,
+};
+use nalgebra_glm::{Mat4, Vec3};
+use serde::{Deserialize, Serialize};
+
+/// Joints a skinned mesh can be bound to, the size of its palette on the GPU
+pub const MAX_JOINTS: usize = 256;
+
+/// Deforms the entity's `RenderMesh` by joint entities, typically linked
+/// through `Parent` and moved by an `AnimationPlayer`. The mesh needs
+/// `SkinWeights` for every vertex.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Skin {
+    pub joints: Vec<EntityId>,
+    /// Takes mesh space to each joint's space in the bind pose.
+    /// Missing matrices are identity, as in glTF.
+    pub inverse_bind_matrices: Vec<Mat4>,
+}
+
+/// A matrix per joint taking bind pose positions to the current pose, relative
+/// to the skinned entity's own global transform so the renderer can apply it
+/// afterwards like any other model matrix. Joints without a `GlobalTransform`
+/// stay in the bind pose. At most `MAX_JOINTS` joints are used.
+pub fn joint_palette(world: &World, skin: &Skin, global_transform: &GlobalTransform) -> Vec<Mat4> {
+    let mesh_from_world = global_transform
+        .try_inverse()
+        .unwrap_or_else(Mat4::identity);
+    skin.joints
+        .iter()
+        .take(MAX_JOINTS)
+        .enumerate()
+        .map(|(index, joint)| {
+            let inverse_bind = skin
+                .inverse_bind_matrices
+                .get(index)
+                .copied()
+                .unwrap_or_else(Mat4::identity);
+            match get_component::<GlobalTransform>(world, *joint, GLOBAL_TRANSFORM) {
+                Some(joint_transform) => mesh_from_world * joint_transform * inverse_bind,
+                None => Mat4::identity(),
+            }
+        })
+        .collect()
+}
+
+/// The weighted blend of a vertex's joint matrices, as the skinning shader computes it
+pub fn skin_matrix(palette: &[Mat4], weights: &SkinWeights) -> Mat4 {
+    weights
+        .joints
+        .iter()
+        .zip(weights.weights.iter())
+        .filter(|(_, weight)| **weight != 0.0)
+        .fold(Mat4::zeros(), |matrix, (joint, weight)| {
+            matrix
+                + palette
+                    .get(*joint as usize)
+                    .copied()
+                    .unwrap_or_else(Mat4::identity)
+                    * *weight
+        })
+}
+
+/// Where a bind pose position ends up, in the skinned entity's space
+pub fn skin_position(palette: &[Mat4], position: &Vec3, weights: &SkinWeights) -> Vec3 {
+    (skin_matrix(palette, weights) * position.push(1.0)).xyz()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::spawn_entities;
+
+    fn weights(joints: [u32; 4], weights: [f32; 4]) -> SkinWeights {
+        SkinWeights { joints, weights }
+    }
+
+    fn close(a: &Mat4, b: &Mat4) -> bool {
+        (a - b).abs().max() < 1e-5
+    }
+
+    /// Joints with the given global transforms
+    fn joints(world: &mut World, transforms: &[Mat4]) -> Vec<EntityId> {
+        let joints = spawn_entities(world, GLOBAL_TRANSFORM, transforms.len());
+        for (joint, transform) in joints.iter().zip(transforms) {
+            *crate::world::get_component_mut::<GlobalTransform>(world, *joint, GLOBAL_TRANSFORM)
+                .expect("No global transform!") = *transform;
+        }
+        joints
+    }
+
+    #[test]
+    fn joints_in_the_bind_pose_leave_vertices_in_place() {
+        let mut world = World::default();
+        let bind = nalgebra_glm::translation(&Vec3::new(0.0, 2.0, 0.0));
+        let skin = Skin {
+            joints: joints(&mut world, &[bind]),
+            inverse_bind_matrices: vec![bind.try_inverse().unwrap()],
+        };
+        let palette = joint_palette(&world, &skin, &Mat4::identity());
+        assert!(close(&palette[0], &Mat4::identity()));
+    }
+
+    #[test]
+    fn palettes_are_relative_to_the_skinned_entity() {
+        let mut world = World::default();
+        let joint = nalgebra_glm::translation(&Vec3::new(3.0, 0.0, 0.0))
+            * nalgebra_glm::rotation(std::f32::consts::FRAC_PI_2, &Vec3::z());
+        let skin = Skin {
+            joints: joints(&mut world, &[joint]),
+            inverse_bind_matrices: Vec::new(),
+        };
+        let entity = nalgebra_glm::translation(&Vec3::new(1.0, 0.0, 0.0));
+        let palette = joint_palette(&world, &skin, &entity);
+        let expected = nalgebra_glm::translation(&Vec3::new(2.0, 0.0, 0.0))
+            * nalgebra_glm::rotation(std::f32::consts::FRAC_PI_2, &Vec3::z());
+        assert!(close(&palette[0], &expected));
+
+        // Applying the entity's own transform afterwards gives world space
+        let position = skin_position(&palette, &Vec3::x(), &weights([0; 4], [1.0, 0.0, 0.0, 0.0]));
+        let world_position = (entity * position.push(1.0)).xyz();
+        assert!((world_position - Vec3::new(3.0, 1.0, 0.0)).norm() < 1e-5);
+    }
+
+    #[test]
+    fn missing_joints_and_matrices_fall_back_to_identity() {
+        let mut world = World::default();
+        let moved = nalgebra_glm::translation(&Vec3::new(0.0, 0.0, 4.0));
+        let mut joints = joints(&mut world, &[moved, moved]);
+        joints.push(EntityId {
+            id: 999,
+            generation: 0,
+        });
+        let skin = Skin {
+            joints,
+            inverse_bind_matrices: vec![moved.try_inverse().unwrap()],
+        };
+        let palette = joint_palette(&world, &skin, &Mat4::identity());
+        assert_eq!(palette.len(), 3);
+        assert!(close(&palette[0], &Mat4::identity()));
+        // No inverse bind matrix, so the joint's transform applies as is
+        assert!(close(&palette[1], &moved));
+        // A joint without a global transform stays in the bind pose
+        assert!(close(&palette[2], &Mat4::identity()));
+    }
+
+    #[test]
+    fn palettes_stop_at_the_joint_limit() {
+        let mut world = World::default();
+        let skin = Skin {
+            joints: joints(&mut world, &vec![Mat4::identity(); MAX_JOINTS + 4]),
+            inverse_bind_matrices: Vec::new(),
+        };
+        assert_eq!(
+            joint_palette(&world, &skin, &Mat4::identity()).len(),
+            MAX_JOINTS
+        );
+    }
+
+    #[test]
+    fn vertices_blend_their_weighted_joints() {
+        let palette = [
+            nalgebra_glm::translation(&Vec3::new(2.0, 0.0, 0.0)),
+            nalgebra_glm::translation(&Vec3::new(0.0, 4.0, 0.0)),
+        ];
+        let position = Vec3::new(1.0, 1.0, 1.0);
+        let blended = skin_position(
+            &palette,
+            &position,
+            &weights([0, 1, 0, 0], [0.75, 0.25, 0.0, 0.0]),
+        );
+        assert!((blended - Vec3::new(2.5, 2.0, 1.0)).norm() < 1e-5);
+
+        // Zero weights ignore their joint indices, even out of range ones
+        let single = weights([1, 7, 9, 0], [1.0, 0.0, 0.0, 0.0]);
+        assert!(close(&skin_matrix(&palette, &single), &palette[1]));
+        // Out of range joints with weight count as identity, like missing palette entries
+        let missing = weights([5, 0, 0, 0], [1.0, 0.0, 0.0, 0.0]);
+        assert!(close(&skin_matrix(&palette, &missing), &Mat4::identity()));
+    }
+}
@@ -0,0 +1,971 @@
+use crate::{
+    mipmap::{
+        generate_mipmaps as generate_mipmaps_cpu, generate_mipmaps_gpu, mip_level_count,
+        mip_level_size, supports_gpu_mipmaps, MipFilter, MipmapGenerator,
+    },
+    world::{Image, ImageFormat},
+};
+
+/// How each channel of an `ImageFormat` is stored
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChannelType {
+    Unorm8,
+    Unorm16,
+    Float16,
+    /// 32-bit unsigned normalized, which the GPU can only sample after conversion to float
+    Unorm32,
+    Float32,
+}
+
+pub fn channel_size(channel_type: ChannelType) -> usize {
+    match channel_type {
+        ChannelType::Unorm8 => 1,
+        ChannelType::Unorm16 | ChannelType::Float16 => 2,
+        ChannelType::Unorm32 | ChannelType::Float32 => 4,
+    }
+}
+
+/// Returns the channel count and channel type of an uncompressed format
+pub fn image_format_layout(format: ImageFormat) -> Option<(usize, ChannelType)> {
+    Some(match format {
+        ImageFormat::R8 => (1, ChannelType::Unorm8),
+        ImageFormat::R8G8 => (2, ChannelType::Unorm8),
+        ImageFormat::R8G8B8 | ImageFormat::B8G8R8 => (3, ChannelType::Unorm8),
+        ImageFormat::R8G8B8A8 | ImageFormat::B8G8R8A8 => (4, ChannelType::Unorm8),
+        ImageFormat::R16 => (1, ChannelType::Unorm16),
+        ImageFormat::R16G16 => (2, ChannelType::Unorm16),
+        ImageFormat::R16G16B16 => (3, ChannelType::Unorm16),
+        ImageFormat::R16G16B16A16 => (4, ChannelType::Unorm16),
+        ImageFormat::R16F => (1, ChannelType::Float16),
+        ImageFormat::R16G16F => (2, ChannelType::Float16),
+        ImageFormat::R16G16B16F => (3, ChannelType::Float16),
+        ImageFormat::R16G16B16A16F => (4, ChannelType::Float16),
+        ImageFormat::R32 => (1, ChannelType::Unorm32),
+        ImageFormat::R32G32 => (2, ChannelType::Unorm32),
+        ImageFormat::R32G32B32 => (3, ChannelType::Unorm32),
+        ImageFormat::R32G32B32A32 => (4, ChannelType::Unorm32),
+        ImageFormat::R32F => (1, ChannelType::Float32),
+        ImageFormat::R32G32F => (2, ChannelType::Float32),
+        ImageFormat::R32G32B32F => (3, ChannelType::Float32),
+        ImageFormat::R32G32B32A32F => (4, ChannelType::Float32),
+        ImageFormat::BC1
+        | ImageFormat::BC2
+        | ImageFormat::BC3
+        | ImageFormat::BC4
+        | ImageFormat::BC5
+        | ImageFormat::BC6H
+        | ImageFormat::BC7 => return None,
+    })
+}
+
+/// Returns the bytes per 4x4 block of a block-compressed format
+pub fn block_size(format: ImageFormat) -> Option<usize> {
+    match format {
+        ImageFormat::BC1 | ImageFormat::BC4 => Some(8),
+        ImageFormat::BC2
+        | ImageFormat::BC3
+        | ImageFormat::BC5
+        | ImageFormat::BC6H
+        | ImageFormat::BC7 => Some(16),
+        _ => None,
+    }
+}
+
+pub const BLOCK_DIMENSION: u32 = 4;
+
+/// Returns the bytes per pixel of an uncompressed format
+pub fn bytes_per_pixel(format: ImageFormat) -> Option<usize> {
+    image_format_layout(format)
+        .map(|(channels, channel_type)| channels * channel_size(channel_type))
+}
+
+/// Returns the bytes in one row of pixels, or one row of blocks for compressed formats
+pub fn bytes_per_row(format: ImageFormat, width: u32) -> usize {
+    match block_size(format) {
+        Some(block_size) => width.div_ceil(BLOCK_DIMENSION) as usize * block_size,
+        None => width as usize * bytes_per_pixel(format).unwrap_or_default(),
+    }
+}
+
+/// Returns the number of rows of pixels, or rows of blocks for compressed formats
+pub fn row_count(format: ImageFormat, height: u32) -> u32 {
+    match block_size(format) {
+        Some(_) => height.div_ceil(BLOCK_DIMENSION),
+        None => height,
+    }
+}
+
+/// Returns the expected size of an image's pixel data
+pub fn image_data_size(format: ImageFormat, width: u32, height: u32) -> usize {
+    bytes_per_row(format, width).saturating_mul(row_count(format, height) as usize)
+}
+
+/// Pixels ready to be copied into a texture of `format`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConvertedImage {
+    pub format: wgpu::TextureFormat,
+    pub pixels: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    /// Bytes per row of pixels, or per row of blocks for compressed formats
+    pub bytes_per_row: u32,
+    pub rows: u32,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TextureError {
+    EmptyImage,
+    SizeMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    UnsupportedFormat(ImageFormat),
+    UnalignedBlocks {
+        width: u32,
+        height: u32,
+    },
+    TooManyMipLevels {
+        count: usize,
+        maximum: usize,
+    },
+    TooLarge {
+        width: u32,
+        height: u32,
+        maximum: u32,
+    },
+}
+
+impl std::fmt::Display for TextureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::EmptyImage => write!(f, "Image has a zero width or height"),
+            Self::SizeMismatch { expected, actual } => {
+                write!(f, "Image has {actual} bytes of pixels, expected {expected}")
+            }
+            Self::UnsupportedFormat(format) => {
+                write!(f, "Image format {format:?} is not supported here")
+            }
+            Self::UnalignedBlocks { width, height } => write!(
+                f,
+                "Block-compressed image size {width}x{height} is not a multiple of {BLOCK_DIMENSION}"
+            ),
+            Self::TooManyMipLevels { count, maximum } => {
+                write!(f, "Image has {count} mip levels, at most {maximum} fit")
+            }
+            Self::TooLarge {
+                width,
+                height,
+                maximum,
+            } => write!(
+                f,
+                "Image size {width}x{height} exceeds the device limit of {maximum}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TextureError {}
+
+/// Returns the channel type a format is converted to before upload.
+/// Sampling with a filtering sampler requires formats that are filterable,
+/// so 32-bit floats and 16-bit normalized channels are narrowed to half floats
+/// unless the device supports them.
+pub fn upload_channel_type(channel_type: ChannelType, features: wgpu::Features) -> ChannelType {
+    match channel_type {
+        ChannelType::Unorm16 if features.contains(wgpu::Features::TEXTURE_FORMAT_16BIT_NORM) => {
+            ChannelType::Unorm16
+        }
+        ChannelType::Unorm32 | ChannelType::Float32
+            if features.contains(wgpu::Features::FLOAT32_FILTERABLE) =>
+        {
+            ChannelType::Float32
+        }
+        ChannelType::Unorm8 => ChannelType::Unorm8,
+        _ => ChannelType::Float16,
+    }
+}
+
+/// Maps an image format to the texture format it is uploaded as.
+/// Three channel formats have no GPU equivalent and are uploaded with an opaque alpha channel.
+pub fn wgpu_texture_format(
+    format: ImageFormat,
+    srgb: bool,
+    features: wgpu::Features,
+) -> wgpu::TextureFormat {
+    let Some((channels, channel_type)) = image_format_layout(format) else {
+        return match (format, srgb) {
+            (ImageFormat::BC1, false) => wgpu::TextureFormat::Bc1RgbaUnorm,
+            (ImageFormat::BC1, true) => wgpu::TextureFormat::Bc1RgbaUnormSrgb,
+            (ImageFormat::BC2, false) => wgpu::TextureFormat::Bc2RgbaUnorm,
+            (ImageFormat::BC2, true) => wgpu::TextureFormat::Bc2RgbaUnormSrgb,
+            (ImageFormat::BC3, false) => wgpu::TextureFormat::Bc3RgbaUnorm,
+            (ImageFormat::BC3, true) => wgpu::TextureFormat::Bc3RgbaUnormSrgb,
+            (ImageFormat::BC4, _) => wgpu::TextureFormat::Bc4RUnorm,
+            (ImageFormat::BC5, _) => wgpu::TextureFormat::Bc5RgUnorm,
+            (ImageFormat::BC6H, _) => wgpu::TextureFormat::Bc6hRgbUfloat,
+            (_, false) => wgpu::TextureFormat::Bc7RgbaUnorm,
+            (_, true) => wgpu::TextureFormat::Bc7RgbaUnormSrgb,
+        };
+    };
+    let bgr = matches!(format, ImageFormat::B8G8R8 | ImageFormat::B8G8R8A8);
+    match (upload_channel_type(channel_type, features), channels) {
+        (ChannelType::Unorm8, 1) => wgpu::TextureFormat::R8Unorm,
+        (ChannelType::Unorm8, 2) => wgpu::TextureFormat::Rg8Unorm,
+        (ChannelType::Unorm8, _) if bgr && srgb => wgpu::TextureFormat::Bgra8UnormSrgb,
+        (ChannelType::Unorm8, _) if bgr => wgpu::TextureFormat::Bgra8Unorm,
+        (ChannelType::Unorm8, _) if srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+        (ChannelType::Unorm8, _) => wgpu::TextureFormat::Rgba8Unorm,
+        (ChannelType::Unorm16, 1) => wgpu::TextureFormat::R16Unorm,
+        (ChannelType::Unorm16, 2) => wgpu::TextureFormat::Rg16Unorm,
+        (ChannelType::Unorm16, _) => wgpu::TextureFormat::Rgba16Unorm,
+        (ChannelType::Float32, 1) => wgpu::TextureFormat::R32Float,
+        (ChannelType::Float32, 2) => wgpu::TextureFormat::Rg32Float,
+        (ChannelType::Float32, _) => wgpu::TextureFormat::Rgba32Float,
+        (_, 1) => wgpu::TextureFormat::R16Float,
+        (_, 2) => wgpu::TextureFormat::Rg16Float,
+        (_, _) => wgpu::TextureFormat::Rgba16Float,
+    }
+}
+
+/// Validates an image and converts its pixels to the layout of its upload format.
+/// Block-compressed images are passed through when the device supports
+/// `TEXTURE_COMPRESSION_BC`, and decompressed otherwise.
+pub fn convert_image(
+    image: &Image,
+    srgb: bool,
+    features: wgpu::Features,
+) -> Result<ConvertedImage, TextureError> {
+    if image.width == 0 || image.height == 0 {
+        return Err(TextureError::EmptyImage);
+    }
+    let expected = image_data_size(image.format, image.width, image.height);
+    if image.pixels.len() != expected {
+        return Err(TextureError::SizeMismatch {
+            expected,
+            actual: image.pixels.len(),
+        });
+    }
+
+    let format = wgpu_texture_format(image.format, srgb, features);
+    let Some((channels, source_type)) = image_format_layout(image.format) else {
+        if !features.contains(wgpu::Features::TEXTURE_COMPRESSION_BC) {
+            let (format, pixels) =
+                decompress_blocks(image.format, &image.pixels, image.width, image.height);
+            let decompressed = Image {
+                pixels,
+                format,
+                width: image.width,
+                height: image.height,
+                mip_levels: Vec::new(),
+            };
+            return convert_image(&decompressed, srgb, features);
+        }
+        return Ok(ConvertedImage {
+            format,
+            pixels: image.pixels.clone(),
+            width: image.width,
+            height: image.height,
+            bytes_per_row: bytes_per_row(image.format, image.width) as u32,
+            rows: row_count(image.format, image.height),
+        });
+    };
+
+    let target_type = upload_channel_type(source_type, features);
+    let target_channels = if channels == 3 { 4 } else { channels };
+    let (source_size, target_size) = (channel_size(source_type), channel_size(target_type));
+    let bytes_per_row = (image.width as usize * target_channels * target_size) as u32;
+
+    if target_type == source_type && target_channels == channels {
+        return Ok(ConvertedImage {
+            format,
+            pixels: image.pixels.clone(),
+            width: image.width,
+            height: image.height,
+            bytes_per_row,
+            rows: image.height,
+        });
+    }
+
+    let mut pixels = Vec::with_capacity(bytes_per_row as usize * image.height as usize);
+    for pixel in image.pixels.chunks_exact(channels * source_size) {
+        for channel in pixel.chunks_exact(source_size) {
+            write_channel(&mut pixels, target_type, read_channel(channel, source_type));
+        }
+        if target_channels > channels {
+            write_channel(&mut pixels, target_type, 1.0);
+        }
+    }
+
+    Ok(ConvertedImage {
+        format,
+        pixels,
+        width: image.width,
+        height: image.height,
+        bytes_per_row,
+        rows: image.height,
+    })
+}
+
+/// Returns the uncompressed format a block-compressed format decodes to
+pub fn decompressed_format(format: ImageFormat) -> ImageFormat {
+    match format {
+        ImageFormat::BC4 => ImageFormat::R8,
+        ImageFormat::BC5 => ImageFormat::R8G8,
+        ImageFormat::BC6H => ImageFormat::R16G16B16F,
+        ImageFormat::BC1 | ImageFormat::BC2 | ImageFormat::BC3 | ImageFormat::BC7 => {
+            ImageFormat::R8G8B8A8
+        }
+        format => format,
+    }
+}
+
+/// Decodes the 4x4 blocks of a compressed image with a valid size, for devices without BC support.
+/// Returns the uncompressed format and its pixels, cropped to the image size.
+pub fn decompress_blocks(
+    format: ImageFormat,
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+) -> (ImageFormat, Vec<u8>) {
+    let target_format = decompressed_format(format);
+    let (Some(block_size), Some(pixel_size)) = (block_size(format), bytes_per_pixel(target_format))
+    else {
+        return (format, pixels.to_vec());
+    };
+    let block_dimension = BLOCK_DIMENSION as usize;
+    let (width, height) = (width as usize, height as usize);
+    let blocks_wide = width.div_ceil(block_dimension);
+    let block_pitch = block_dimension * pixel_size;
+    let mut decoded = vec![0; width * height * pixel_size];
+    // The largest decoded block is BC6H, 16 pixels of three half floats
+    let mut block_pixels = [0u8; 96];
+    let mut block_halves = [0u16; 48];
+    for (index, block) in pixels.chunks_exact(block_size).enumerate() {
+        match format {
+            ImageFormat::BC1 => bcdec_rs::bc1(block, &mut block_pixels, block_pitch),
+            ImageFormat::BC2 => bcdec_rs::bc2(block, &mut block_pixels, block_pitch),
+            ImageFormat::BC3 => bcdec_rs::bc3(block, &mut block_pixels, block_pitch),
+            ImageFormat::BC4 => bcdec_rs::bc4(block, &mut block_pixels, block_pitch, false),
+            ImageFormat::BC5 => bcdec_rs::bc5(block, &mut block_pixels, block_pitch, false),
+            ImageFormat::BC6H => {
+                bcdec_rs::bc6h_half(block, &mut block_halves, block_dimension * 3, false);
+                for (target, half) in block_pixels.chunks_exact_mut(2).zip(block_halves.iter()) {
+                    target.copy_from_slice(&half.to_le_bytes());
+                }
+            }
+            _ => bcdec_rs::bc7(block, &mut block_pixels, block_pitch),
+        }
+        // Blocks past the right and bottom edges of small mip levels are cropped
+        let (block_x, block_y) = (index % blocks_wide, index / blocks_wide);
+        for row in 0..block_dimension {
+            let y = block_y * block_dimension + row;
+            let x = block_x * block_dimension;
+            if y >= height {
+                break;
+            }
+            let columns = block_dimension.min(width - x) * pixel_size;
+            let target = (y * width + x) * pixel_size;
+            decoded[target..target + columns]
+                .copy_from_slice(&block_pixels[row * block_pitch..][..columns]);
+        }
+    }
+    (target_format, decoded)
+}
+
+/// Reads a little-endian channel as a normalized or floating point value
+pub fn read_channel(bytes: &[u8], channel_type: ChannelType) -> f32 {
+    match channel_type {
+        ChannelType::Unorm8 => bytes[0] as f32 / u8::MAX as f32,
+        ChannelType::Unorm16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f32 / u16::MAX as f32,
+        ChannelType::Float16 => f16_to_f32(u16::from_le_bytes([bytes[0], bytes[1]])),
+        ChannelType::Unorm32 => {
+            (u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64 / u32::MAX as f64)
+                as f32
+        }
+        ChannelType::Float32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+    }
+}
+
+/// Appends a value as a little-endian channel, clamping normalized types
+pub fn write_channel(pixels: &mut Vec<u8>, channel_type: ChannelType, value: f32) {
+    match channel_type {
+        ChannelType::Unorm8 => pixels.push((value.clamp(0.0, 1.0) * u8::MAX as f32).round() as u8),
+        ChannelType::Unorm16 => pixels.extend_from_slice(
+            &((value.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16).to_le_bytes(),
+        ),
+        ChannelType::Float16 => pixels.extend_from_slice(&f32_to_f16(value).to_le_bytes()),
+        ChannelType::Unorm32 => pixels.extend_from_slice(
+            &((value.clamp(0.0, 1.0) as f64 * u32::MAX as f64).round() as u32).to_le_bytes(),
+        ),
+        ChannelType::Float32 => pixels.extend_from_slice(&value.to_le_bytes()),
+    }
+}
+
+/// Converts to IEEE 754 half precision, rounding to nearest even
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x007f_ffff;
+
+    if exponent == 0xff {
+        let nan = if mantissa != 0 { 0x0200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    if half_exponent <= 0 {
+        if half_exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x0080_0000;
+        let shift = (14 - half_exponent) as u32;
+        let half_mantissa = mantissa >> shift;
+        let remainder = mantissa & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let rounded = if remainder > halfway || (remainder == halfway && half_mantissa & 1 == 1) {
+            half_mantissa + 1
+        } else {
+            half_mantissa
+        };
+        return sign | rounded as u16;
+    }
+
+    let mut half = ((half_exponent as u32) << 10) | (mantissa >> 13);
+    let remainder = mantissa & 0x1fff;
+    if remainder > 0x1000 || (remainder == 0x1000 && half & 1 == 1) {
+        // Carrying into the exponent correctly rounds up to the next power of two or infinity
+        half += 1;
+    }
+    sign | half as u16
+}
+
+pub fn f16_to_f32(half: u16) -> f32 {
+    let sign = ((half & 0x8000) as u32) << 16;
+    let exponent = ((half >> 10) & 0x1f) as u32;
+    let mantissa = (half & 0x03ff) as u32;
+    let bits = match (exponent, mantissa) {
+        (0, 0) => sign,
+        (0, _) => {
+            // Subnormal halves are normal floats
+            let shift = mantissa.leading_zeros() - 21;
+            let mantissa = (mantissa << shift) & 0x03ff;
+            sign | ((127 - 15 + 1 - shift) << 23) | (mantissa << 13)
+        }
+        (0x1f, _) => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 127 - 15) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(bits)
+}
+
+/// Rounds a row up to `COPY_BYTES_PER_ROW_ALIGNMENT` as buffer to texture copies require
+pub fn padded_bytes_per_row(unpadded_bytes_per_row: u32) -> u32 {
+    let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    unpadded_bytes_per_row.div_ceil(alignment) * alignment
+}
+
+/// Copies rows into a buffer whose rows are padded to the copy alignment
+pub fn pad_rows(pixels: &[u8], unpadded_bytes_per_row: u32, height: u32) -> Vec<u8> {
+    let padded_bytes_per_row = padded_bytes_per_row(unpadded_bytes_per_row) as usize;
+    let unpadded_bytes_per_row = unpadded_bytes_per_row as usize;
+    if padded_bytes_per_row == unpadded_bytes_per_row {
+        return pixels.to_vec();
+    }
+    let mut padded = vec![0; padded_bytes_per_row * height as usize];
+    for (source, target) in pixels
+        .chunks_exact(unpadded_bytes_per_row)
+        .zip(padded.chunks_exact_mut(padded_bytes_per_row))
+    {
+        target[..unpadded_bytes_per_row].copy_from_slice(source);
+    }
+    padded
+}
+
+/// Rejects images larger than the device's `max_texture_dimension_2d`
+pub fn check_texture_size(width: u32, height: u32, maximum: u32) -> Result<(), TextureError> {
+    if width > maximum || height > maximum {
+        return Err(TextureError::TooLarge {
+            width,
+            height,
+            maximum,
+        });
+    }
+    Ok(())
+}
+
+/// Converts and uploads an image, returning the created texture.
+/// Pre-built mip levels are uploaded as they are. Otherwise, passing a mipmap generator
+/// allocates a full mip chain, filled on the GPU when the format allows it and on the CPU otherwise.
+pub fn upload_image(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    image: &Image,
+    srgb: bool,
+    mipmaps: Option<&mut MipmapGenerator>,
+) -> Result<wgpu::Texture, TextureError> {
+    check_texture_size(
+        image.width,
+        image.height,
+        device.limits().max_texture_dimension_2d,
+    )?;
+    let converted = convert_image(image, srgb, device.features())?;
+    let compressed = block_size(image.format).is_some();
+    if converted.format.is_compressed()
+        && (!image.width.is_multiple_of(BLOCK_DIMENSION)
+            || !image.height.is_multiple_of(BLOCK_DIMENSION))
+    {
+        return Err(TextureError::UnalignedBlocks {
+            width: image.width,
+            height: image.height,
+        });
+    }
+    let maximum_mip_levels = mip_level_count(image.width, image.height) as usize;
+    if image.mip_levels.len() >= maximum_mip_levels {
+        return Err(TextureError::TooManyMipLevels {
+            count: image.mip_levels.len() + 1,
+            maximum: maximum_mip_levels,
+        });
+    }
+
+    let generate_mipmaps = mipmaps.is_some() && image.mip_levels.is_empty() && !compressed;
+    let gpu_mipmaps = generate_mipmaps && supports_gpu_mipmaps(device, converted.format);
+    let mut usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+    if gpu_mipmaps {
+        usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+    }
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Image Texture"),
+        size: wgpu::Extent3d {
+            width: converted.width,
+            height: converted.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: if generate_mipmaps {
+            maximum_mip_levels as u32
+        } else {
+            1 + image.mip_levels.len() as u32
+        },
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: converted.format,
+        usage,
+        view_formats: &[],
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Image Upload Encoder"),
+    });
+    copy_to_mip_level(device, &mut encoder, &texture, 0, &converted);
+
+    for (level, pixels) in image.mip_levels.iter().enumerate() {
+        let level = level as u32 + 1;
+        let (width, height) = mip_level_size(image.width, image.height, level);
+        let mip = Image {
+            pixels: pixels.clone(),
+            format: image.format,
+            width,
+            height,
+            mip_levels: Vec::new(),
+        };
+        let converted = convert_image(&mip, srgb, device.features())?;
+        copy_to_mip_level(device, &mut encoder, &texture, level, &converted);
+    }
+
+    match mipmaps {
+        Some(generator) if gpu_mipmaps => {
+            generate_mipmaps_gpu(generator, device, &mut encoder, &texture);
+        }
+        Some(_) if generate_mipmaps => {
+            for (level, mip) in generate_mipmaps_cpu(image, srgb, MipFilter::Box)?
+                .iter()
+                .enumerate()
+            {
+                let converted = convert_image(mip, srgb, device.features())?;
+                copy_to_mip_level(device, &mut encoder, &texture, level as u32 + 1, &converted);
+            }
+        }
+        _ => {}
+    }
+    queue.submit(std::iter::once(encoder.finish()));
+
+    Ok(texture)
+}
+
+/// Records a copy of converted pixels into one mip level through a padded staging buffer
+pub fn copy_to_mip_level(
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    texture: &wgpu::Texture,
+    level: u32,
+    converted: &ConvertedImage,
+) {
+    let staging_buffer = wgpu::util::DeviceExt::create_buffer_init(
+        device,
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Image Staging Buffer"),
+            contents: &pad_rows(&converted.pixels, converted.bytes_per_row, converted.rows),
+            usage: wgpu::BufferUsages::COPY_SRC,
+        },
+    );
+    // Copies of compressed mip levels cover whole blocks, even past the edge of small levels
+    let (block_width, block_height) = converted.format.block_dimensions();
+    encoder.copy_buffer_to_texture(
+        wgpu::ImageCopyBuffer {
+            buffer: &staging_buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_bytes_per_row(converted.bytes_per_row)),
+                rows_per_image: Some(converted.rows),
+            },
+        },
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level: level,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        wgpu::Extent3d {
+            width: converted.width.next_multiple_of(block_width),
+            height: converted.height.next_multiple_of(block_height),
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNCOMPRESSED_FORMATS: [ImageFormat; 22] = [
+        ImageFormat::R8,
+        ImageFormat::R8G8,
+        ImageFormat::R8G8B8,
+        ImageFormat::R8G8B8A8,
+        ImageFormat::B8G8R8,
+        ImageFormat::B8G8R8A8,
+        ImageFormat::R16,
+        ImageFormat::R16G16,
+        ImageFormat::R16G16B16,
+        ImageFormat::R16G16B16A16,
+        ImageFormat::R16F,
+        ImageFormat::R16G16F,
+        ImageFormat::R16G16B16F,
+        ImageFormat::R16G16B16A16F,
+        ImageFormat::R32,
+        ImageFormat::R32G32,
+        ImageFormat::R32G32B32,
+        ImageFormat::R32G32B32A32,
+        ImageFormat::R32F,
+        ImageFormat::R32G32F,
+        ImageFormat::R32G32B32F,
+        ImageFormat::R32G32B32A32F,
+    ];
+
+    const COMPRESSED_FORMATS: [ImageFormat; 7] = [
+        ImageFormat::BC1,
+        ImageFormat::BC2,
+        ImageFormat::BC3,
+        ImageFormat::BC4,
+        ImageFormat::BC5,
+        ImageFormat::BC6H,
+        ImageFormat::BC7,
+    ];
+
+    /// Every feature combination that changes how uncompressed formats are uploaded
+    fn feature_sets() -> [wgpu::Features; 4] {
+        [
+            wgpu::Features::empty(),
+            wgpu::Features::TEXTURE_FORMAT_16BIT_NORM,
+            wgpu::Features::FLOAT32_FILTERABLE,
+            wgpu::Features::TEXTURE_FORMAT_16BIT_NORM | wgpu::Features::FLOAT32_FILTERABLE,
+        ]
+    }
+
+    /// Encodes channel values in a format, a pixel at a time
+    fn image_from_values(format: ImageFormat, width: u32, height: u32, values: &[f32]) -> Image {
+        let (_, channel_type) = image_format_layout(format).unwrap();
+        let mut pixels = Vec::new();
+        for value in values {
+            write_channel(&mut pixels, channel_type, *value);
+        }
+        Image {
+            pixels,
+            format,
+            width,
+            height,
+            mip_levels: Vec::new(),
+        }
+    }
+
+    fn read_values(converted: &ConvertedImage, channel_type: ChannelType) -> Vec<f32> {
+        converted
+            .pixels
+            .chunks_exact(channel_size(channel_type))
+            .map(|channel| read_channel(channel, channel_type))
+            .collect()
+    }
+
+    fn texture_channel_type(format: wgpu::TextureFormat) -> ChannelType {
+        use wgpu::TextureFormat::*;
+        match format {
+            R8Unorm | Rg8Unorm | Rgba8Unorm | Rgba8UnormSrgb | Bgra8Unorm | Bgra8UnormSrgb => {
+                ChannelType::Unorm8
+            }
+            R16Unorm | Rg16Unorm | Rgba16Unorm => ChannelType::Unorm16,
+            R16Float | Rg16Float | Rgba16Float => ChannelType::Float16,
+            R32Float | Rg32Float | Rgba32Float => ChannelType::Float32,
+            _ => panic!("Unexpected upload format {format:?}"),
+        }
+    }
+
+    fn texture_channel_count(format: wgpu::TextureFormat) -> usize {
+        format.components() as usize
+    }
+
+    #[test]
+    fn every_uncompressed_format_converts_to_its_upload_layout() {
+        let (width, height) = (3, 2);
+        for features in feature_sets() {
+            for format in UNCOMPRESSED_FORMATS {
+                let (channels, _) = image_format_layout(format).unwrap();
+                let values = (0..width * height * channels as u32)
+                    .map(|index| (index % 5) as f32 / 4.0)
+                    .collect::<Vec<_>>();
+                let image = image_from_values(format, width, height, &values);
+                assert_eq!(image.pixels.len(), image_data_size(format, width, height));
+
+                let converted = convert_image(&image, false, features).unwrap();
+                let target_type = texture_channel_type(converted.format);
+                let target_channels = texture_channel_count(converted.format);
+                assert_eq!(target_channels, if channels == 3 { 4 } else { channels });
+                assert_eq!(
+                    converted.bytes_per_row as usize,
+                    width as usize * target_channels * channel_size(target_type),
+                    "{format:?}"
+                );
+                assert_eq!(converted.rows, height);
+                assert_eq!(
+                    converted.pixels.len(),
+                    converted.bytes_per_row as usize * height as usize
+                );
+
+                let converted_values = read_values(&converted, target_type);
+                for (pixel, expected) in values.chunks_exact(channels).enumerate() {
+                    let actual = &converted_values[pixel * target_channels..][..target_channels];
+                    for (channel, value) in expected.iter().enumerate() {
+                        assert!(
+                            (actual[channel] - value).abs() <= 1.0 / 255.0,
+                            "{format:?} {features:?} pixel {pixel} channel {channel}"
+                        );
+                    }
+                    if channels == 3 {
+                        assert_eq!(actual[3], 1.0, "{format:?} alpha");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn upload_formats_follow_features_and_color_space() {
+        let none = wgpu::Features::empty();
+        assert_eq!(
+            wgpu_texture_format(ImageFormat::R8G8B8, true, none),
+            wgpu::TextureFormat::Rgba8UnormSrgb
+        );
+        assert_eq!(
+            wgpu_texture_format(ImageFormat::B8G8R8, false, none),
+            wgpu::TextureFormat::Bgra8Unorm
+        );
+        assert_eq!(
+            wgpu_texture_format(ImageFormat::B8G8R8A8, true, none),
+            wgpu::TextureFormat::Bgra8UnormSrgb
+        );
+        assert_eq!(
+            wgpu_texture_format(ImageFormat::R16G16B16A16, false, none),
+            wgpu::TextureFormat::Rgba16Float
+        );
+        assert_eq!(
+            wgpu_texture_format(
+                ImageFormat::R16G16B16A16,
+                false,
+                wgpu::Features::TEXTURE_FORMAT_16BIT_NORM
+            ),
+            wgpu::TextureFormat::Rgba16Unorm
+        );
+        assert_eq!(
+            wgpu_texture_format(ImageFormat::R32F, false, none),
+            wgpu::TextureFormat::R16Float
+        );
+        assert_eq!(
+            wgpu_texture_format(ImageFormat::R32F, false, wgpu::Features::FLOAT32_FILTERABLE),
+            wgpu::TextureFormat::R32Float
+        );
+        assert_eq!(
+            wgpu_texture_format(ImageFormat::BC7, true, none),
+            wgpu::TextureFormat::Bc7RgbaUnormSrgb
+        );
+    }
+
+    #[test]
+    fn bgr_channels_keep_their_order() {
+        let image = Image {
+            pixels: vec![10, 20, 30],
+            format: ImageFormat::B8G8R8,
+            width: 1,
+            height: 1,
+            mip_levels: Vec::new(),
+        };
+        let converted = convert_image(&image, false, wgpu::Features::empty()).unwrap();
+        assert_eq!(converted.format, wgpu::TextureFormat::Bgra8Unorm);
+        assert_eq!(converted.pixels, vec![10, 20, 30, 255]);
+    }
+
+    #[test]
+    fn invalid_images_are_rejected() {
+        let features = wgpu::Features::empty();
+        for format in UNCOMPRESSED_FORMATS.into_iter().chain(COMPRESSED_FORMATS) {
+            let expected = image_data_size(format, 4, 4);
+            let short = Image {
+                pixels: vec![0; expected - 1],
+                format,
+                width: 4,
+                height: 4,
+                mip_levels: Vec::new(),
+            };
+            assert_eq!(
+                convert_image(&short, false, features),
+                Err(TextureError::SizeMismatch {
+                    expected,
+                    actual: expected - 1
+                })
+            );
+            let empty = Image {
+                pixels: Vec::new(),
+                format,
+                width: 0,
+                height: 4,
+                mip_levels: Vec::new(),
+            };
+            assert_eq!(
+                convert_image(&empty, false, features),
+                Err(TextureError::EmptyImage)
+            );
+        }
+    }
+
+    #[test]
+    fn compressed_formats_pass_through_with_bc_support() {
+        for format in COMPRESSED_FORMATS {
+            let image = Image {
+                pixels: vec![7; image_data_size(format, 8, 4)],
+                format,
+                width: 8,
+                height: 4,
+                mip_levels: Vec::new(),
+            };
+            let converted =
+                convert_image(&image, false, wgpu::Features::TEXTURE_COMPRESSION_BC).unwrap();
+            assert_eq!(converted.pixels, image.pixels);
+            assert_eq!(converted.rows, 1);
+            assert_eq!(
+                converted.bytes_per_row as usize,
+                2 * block_size(format).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn images_beyond_the_device_limit_are_rejected() {
+        assert_eq!(check_texture_size(4096, 4096, 4096), Ok(()));
+        assert_eq!(
+            check_texture_size(8192, 4096, 4096),
+            Err(TextureError::TooLarge {
+                width: 8192,
+                height: 4096,
+                maximum: 4096
+            })
+        );
+        assert!(check_texture_size(16, 4097, 4096).is_err());
+    }
+
+    #[test]
+    fn compressed_formats_are_decompressed_without_bc_support() {
+        for format in COMPRESSED_FORMATS {
+            // Sizes that are not a multiple of the block size, like small mip levels
+            let image = Image {
+                pixels: vec![7; image_data_size(format, 6, 3)],
+                format,
+                width: 6,
+                height: 3,
+                mip_levels: Vec::new(),
+            };
+            let converted = convert_image(&image, true, wgpu::Features::empty()).unwrap();
+            assert!(!converted.format.is_compressed(), "{format:?}");
+            assert_eq!(
+                (converted.width, converted.height, converted.rows),
+                (6, 3, 3)
+            );
+            assert_eq!(
+                converted.pixels.len(),
+                converted.bytes_per_row as usize * 3,
+                "{format:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn bc1_blocks_decode_to_their_endpoint_colors() {
+        // Endpoint 0 is pure red in RGB565 and every index selects it
+        let red_block = [0x00, 0xf8, 0x1f, 0x00, 0, 0, 0, 0];
+        // Endpoint 1 is pure blue and every index selects it
+        let blue_block = [0x00, 0xf8, 0x1f, 0x00, 0x55, 0x55, 0x55, 0x55];
+        let pixels = [red_block, blue_block].concat();
+        let (format, decoded) = decompress_blocks(ImageFormat::BC1, &pixels, 8, 4);
+        assert_eq!(format, ImageFormat::R8G8B8A8);
+        assert_eq!(decoded.len(), 8 * 4 * 4);
+        for (index, pixel) in decoded.chunks_exact(4).enumerate() {
+            let expected = if index % 8 < 4 {
+                [255, 0, 0, 255]
+            } else {
+                [0, 0, 255, 255]
+            };
+            assert_eq!(pixel, expected, "pixel {index}");
+        }
+    }
+
+    #[test]
+    fn half_floats_round_trip() {
+        for value in [0.0, -0.0, 1.0, -2.5, 0.333, 65504.0, 6.1e-5, 5.96e-8] {
+            let half = f32_to_f16(value);
+            let relative = (f16_to_f32(half) - value).abs() / value.abs().max(1e-7);
+            assert!(relative < 1e-3, "{value} became {}", f16_to_f32(half));
+        }
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(f32::INFINITY), 0x7c00);
+        assert_eq!(f32_to_f16(1e6), 0x7c00);
+        assert!(f16_to_f32(f32_to_f16(f32::NAN)).is_nan());
+        // Ties round to the even mantissa
+        assert_eq!(f32_to_f16(f32::from_bits(0x3f80_1000)), 0x3c00);
+        assert_eq!(f32_to_f16(f32::from_bits(0x3f80_3000)), 0x3c02);
+    }
+
+    #[test]
+    fn rows_are_padded_to_the_copy_alignment() {
+        assert_eq!(padded_bytes_per_row(1), 256);
+        assert_eq!(padded_bytes_per_row(256), 256);
+        assert_eq!(padded_bytes_per_row(257), 512);
+        let pixels = (0..12).collect::<Vec<u8>>();
+        let padded = pad_rows(&pixels, 6, 2);
+        assert_eq!(padded.len(), 512);
+        assert_eq!(&padded[..6], &pixels[..6]);
+        assert_eq!(&padded[256..262], &pixels[6..]);
+        assert!(padded[6..256].iter().all(|byte| *byte == 0));
+        let aligned = vec![1; 512];
+        assert_eq!(pad_rows(&aligned, 256, 2), aligned);
+    }
+}
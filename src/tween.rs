@@ -0,0 +1,607 @@
+use crate::{
+    animation::{mix_property, read_property, write_property, AnimatedProperty},
+    world::{get_component, get_component_mut, query_entities, EntityId, World, TWEEN},
+};
+use nalgebra_glm::Vec4;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+/// Shapes the progress of a tween, mapping 0..1 to 0..1.
+/// Elastic and back curves overshoot in between.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Easing {
+    #[default]
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    ElasticIn,
+    ElasticOut,
+    ElasticInOut,
+    BounceIn,
+    BounceOut,
+    BounceInOut,
+    BackIn,
+    BackOut,
+    BackInOut,
+}
+
+/// Applies an easing curve to a progress clamped to 0..1
+pub fn ease(easing: Easing, progress: f32) -> f32 {
+    let t = progress.clamp(0.0, 1.0);
+    match easing {
+        Easing::Linear => t,
+        Easing::QuadIn => t * t,
+        Easing::QuadOut => 1.0 - (1.0 - t).powi(2),
+        Easing::QuadInOut => {
+            if t < 0.5 {
+                2.0 * t * t
+            } else {
+                1.0 - (-2.0 * t + 2.0).powi(2) / 2.0
+            }
+        }
+        Easing::CubicIn => t * t * t,
+        Easing::CubicOut => 1.0 - (1.0 - t).powi(3),
+        Easing::CubicInOut => {
+            if t < 0.5 {
+                4.0 * t * t * t
+            } else {
+                1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+            }
+        }
+        Easing::ElasticIn => {
+            if t == 0.0 || t == 1.0 {
+                t
+            } else {
+                -(2.0f32).powf(10.0 * t - 10.0) * ((10.0 * t - 10.75) * (2.0 * PI / 3.0)).sin()
+            }
+        }
+        Easing::ElasticOut => {
+            if t == 0.0 || t == 1.0 {
+                t
+            } else {
+                (2.0f32).powf(-10.0 * t) * ((10.0 * t - 0.75) * (2.0 * PI / 3.0)).sin() + 1.0
+            }
+        }
+        Easing::ElasticInOut => {
+            let wave = ((20.0 * t - 11.125) * (2.0 * PI / 4.5)).sin();
+            if t == 0.0 || t == 1.0 {
+                t
+            } else if t < 0.5 {
+                -(2.0f32).powf(20.0 * t - 10.0) * wave / 2.0
+            } else {
+                (2.0f32).powf(-20.0 * t + 10.0) * wave / 2.0 + 1.0
+            }
+        }
+        Easing::BounceIn => 1.0 - bounce_out(1.0 - t),
+        Easing::BounceOut => bounce_out(t),
+        Easing::BounceInOut => {
+            if t < 0.5 {
+                (1.0 - bounce_out(1.0 - 2.0 * t)) / 2.0
+            } else {
+                (1.0 + bounce_out(2.0 * t - 1.0)) / 2.0
+            }
+        }
+        Easing::BackIn => BACK_OVERSHOOT_IN * t * t * t - BACK_OVERSHOOT * t * t,
+        Easing::BackOut => {
+            1.0 + BACK_OVERSHOOT_IN * (t - 1.0).powi(3) + BACK_OVERSHOOT * (t - 1.0).powi(2)
+        }
+        Easing::BackInOut => {
+            let overshoot = BACK_OVERSHOOT * 1.525;
+            if t < 0.5 {
+                (2.0 * t).powi(2) * ((overshoot + 1.0) * 2.0 * t - overshoot) / 2.0
+            } else {
+                ((2.0 * t - 2.0).powi(2) * ((overshoot + 1.0) * (2.0 * t - 2.0) + overshoot) + 2.0)
+                    / 2.0
+            }
+        }
+    }
+}
+
+/// How far back curves pull back, about 10% of the distance
+const BACK_OVERSHOOT: f32 = 1.70158;
+const BACK_OVERSHOOT_IN: f32 = BACK_OVERSHOOT + 1.0;
+
+fn bounce_out(t: f32) -> f32 {
+    const SCALE: f32 = 7.5625;
+    const WIDTH: f32 = 2.75;
+    if t < 1.0 / WIDTH {
+        SCALE * t * t
+    } else if t < 2.0 / WIDTH {
+        let t = t - 1.5 / WIDTH;
+        SCALE * t * t + 0.75
+    } else if t < 2.5 / WIDTH {
+        let t = t - 2.25 / WIDTH;
+        SCALE * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / WIDTH;
+        SCALE * t * t + 0.984375
+    }
+}
+
+/// A step of a tween. Property steps drive the tween's own entity.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TweenNode {
+    /// Moves a property between two values, packed as in `AnimatedProperty`
+    Property {
+        property: AnimatedProperty,
+        from: Vec4,
+        to: Vec4,
+        duration: f32,
+        easing: Easing,
+    },
+    /// Waits, usually inside a sequence
+    Delay(f32),
+    /// Reported in `Tween::events` when the tween passes it
+    Event(String),
+    /// Runs its nodes one after another
+    Sequence(Vec<TweenNode>),
+    /// Runs its nodes together, lasting as long as the longest
+    Parallel(Vec<TweenNode>),
+}
+
+impl Default for TweenNode {
+    fn default() -> Self {
+        Self::Sequence(Vec::new())
+    }
+}
+
+/// How many times a tween plays
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Repeat {
+    Times(u32),
+    Forever,
+}
+
+impl Default for Repeat {
+    fn default() -> Self {
+        Self::Times(1)
+    }
+}
+
+/// Plays a `TweenNode` on its entity, advanced by the frame's delta time
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tween {
+    pub node: TweenNode,
+    /// Seconds into the current play
+    pub time: f32,
+    /// Plays completed so far
+    pub play: u32,
+    pub repeat: Repeat,
+    /// Plays every other repeat backwards
+    pub yoyo: bool,
+    pub paused: bool,
+    /// Set once the last play completes, the tween then stays at its end
+    pub finished: bool,
+    /// Names of the events passed during the last update, in order
+    pub events: Vec<String>,
+}
+
+/// A tween playing a node once
+pub fn tween(node: TweenNode) -> Tween {
+    Tween {
+        node,
+        ..Default::default()
+    }
+}
+
+pub fn tween_property(
+    property: AnimatedProperty,
+    from: Vec4,
+    to: Vec4,
+    duration: f32,
+    easing: Easing,
+) -> TweenNode {
+    TweenNode::Property {
+        property,
+        from,
+        to,
+        duration,
+        easing,
+    }
+}
+
+/// A property step starting from the entity's current value,
+/// or `None` if the entity lacks the property
+pub fn tween_property_to(
+    world: &World,
+    entity: EntityId,
+    property: AnimatedProperty,
+    to: Vec4,
+    duration: f32,
+    easing: Easing,
+) -> Option<TweenNode> {
+    read_property(world, entity, property)
+        .map(|from| tween_property(property, from, to, duration, easing))
+}
+
+/// Plays the tween again from the start
+pub fn restart_tween(tween: &mut Tween) {
+    tween.time = 0.0;
+    tween.play = 0;
+    tween.finished = false;
+    tween.events.clear();
+}
+
+/// Seconds a node takes to play once
+pub fn tween_duration(node: &TweenNode) -> f32 {
+    match node {
+        TweenNode::Property { duration, .. } => duration.max(0.0),
+        TweenNode::Delay(duration) => duration.max(0.0),
+        TweenNode::Event(_) => 0.0,
+        TweenNode::Sequence(nodes) => nodes.iter().map(tween_duration).sum(),
+        TweenNode::Parallel(nodes) => nodes.iter().map(tween_duration).fold(0.0, f32::max),
+    }
+}
+
+/// Advances every `Tween` by the frame's delta time and applies it
+pub fn update_tween_system(world: &mut World) {
+    let delta_time = world.resources.delta_time;
+    for entity in query_entities(world, TWEEN) {
+        let Some(mut tween) = get_component::<Tween>(world, entity, TWEEN).cloned() else {
+            continue;
+        };
+        tween.events.clear();
+        if !tween.paused && !tween.finished {
+            advance_tween(&mut tween, delta_time);
+            apply_node(world, entity, &tween.node, tween_node_time(&tween));
+        }
+        if let Some(component) = get_component_mut::<Tween>(world, entity, TWEEN) {
+            *component = tween;
+        }
+    }
+}
+
+/// Where the current play is in the node's own timeline, accounting for yoyo
+pub fn tween_node_time(tween: &Tween) -> f32 {
+    if playing_backwards(tween) {
+        tween_duration(&tween.node) - tween.time
+    } else {
+        tween.time
+    }
+}
+
+fn playing_backwards(tween: &Tween) -> bool {
+    tween.yoyo && tween.play % 2 == 1
+}
+
+/// Moves the playhead through as many plays as the delta time covers,
+/// collecting the events passed
+fn advance_tween(tween: &mut Tween, delta_time: f32) {
+    let duration = tween_duration(&tween.node);
+    let mut remaining = delta_time.max(0.0);
+    loop {
+        let last = match tween.repeat {
+            Repeat::Times(plays) => tween.play + 1 >= plays,
+            Repeat::Forever => false,
+        };
+        let end = tween.time + remaining;
+        if end < duration {
+            collect_events(tween, end, false);
+            tween.time = end;
+            return;
+        }
+        collect_events(tween, duration, last || duration <= 0.0);
+        if last {
+            tween.time = duration;
+            tween.finished = true;
+            return;
+        }
+        remaining = end - duration;
+        tween.time = 0.0;
+        tween.play += 1;
+        // Empty tweens would otherwise repeat forever within one update
+        if duration <= 0.0 {
+            return;
+        }
+    }
+}
+
+/// Events between the playhead and `end` in the current play. The range includes
+/// where the playhead started and excludes where it ends, unless the tween finishes
+/// there, so each pass fires an event once.
+fn collect_events(tween: &mut Tween, end: f32, finishing: bool) {
+    let mut events = Vec::new();
+    node_events(&tween.node, 0.0, &mut events);
+    let duration = tween_duration(&tween.node);
+    let start = tween.time;
+    let backwards = playing_backwards(tween);
+    let mut passed: Vec<(f32, String)> = events
+        .into_iter()
+        .filter(|(time, _)| {
+            if backwards {
+                let (low, high) = (duration - end, duration - start);
+                (low < *time && *time <= high) || (finishing && *time == low)
+            } else {
+                (start <= *time && *time < end) || (finishing && *time == end)
+            }
+        })
+        .collect();
+    passed.sort_by(|a, b| a.0.total_cmp(&b.0));
+    if backwards {
+        passed.reverse();
+    }
+    tween
+        .events
+        .extend(passed.into_iter().map(|(_, name)| name));
+}
+
+fn node_events(node: &TweenNode, offset: f32, events: &mut Vec<(f32, String)>) {
+    match node {
+        TweenNode::Event(name) => events.push((offset, name.clone())),
+        TweenNode::Sequence(nodes) => {
+            let mut start = offset;
+            for node in nodes {
+                node_events(node, start, events);
+                start += tween_duration(node);
+            }
+        }
+        TweenNode::Parallel(nodes) => {
+            for node in nodes {
+                node_events(node, offset, events);
+            }
+        }
+        TweenNode::Property { .. } | TweenNode::Delay(_) => {}
+    }
+}
+
+/// Writes the properties a node sets at a time in its timeline. Steps that have not
+/// started yet are left alone, so later steps of a sequence do not override earlier ones.
+fn apply_node(world: &mut World, entity: EntityId, node: &TweenNode, time: f32) {
+    match node {
+        TweenNode::Property {
+            property,
+            from,
+            to,
+            duration,
+            easing,
+        } => {
+            let progress = if *duration > 0.0 {
+                time / duration
+            } else {
+                1.0
+            };
+            let value = mix_property(*property, from, to, ease(*easing, progress));
+            write_property(world, entity, *property, value);
+        }
+        TweenNode::Sequence(nodes) => {
+            let mut start = 0.0;
+            for node in nodes {
+                if time < start {
+                    break;
+                }
+                apply_node(world, entity, node, time - start);
+                start += tween_duration(node);
+            }
+        }
+        TweenNode::Parallel(nodes) => {
+            for node in nodes {
+                apply_node(world, entity, node, time);
+            }
+        }
+        TweenNode::Delay(_) | TweenNode::Event(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{spawn_entities, LocalTransform, LOCAL_TRANSFORM};
+
+    const EASINGS: [Easing; 16] = [
+        Easing::Linear,
+        Easing::QuadIn,
+        Easing::QuadOut,
+        Easing::QuadInOut,
+        Easing::CubicIn,
+        Easing::CubicOut,
+        Easing::CubicInOut,
+        Easing::ElasticIn,
+        Easing::ElasticOut,
+        Easing::ElasticInOut,
+        Easing::BounceIn,
+        Easing::BounceOut,
+        Easing::BounceInOut,
+        Easing::BackIn,
+        Easing::BackOut,
+        Easing::BackInOut,
+    ];
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    fn scalar(value: f32) -> Vec4 {
+        Vec4::new(value, 0.0, 0.0, 0.0)
+    }
+
+    fn event(name: &str) -> TweenNode {
+        TweenNode::Event(name.to_string())
+    }
+
+    fn tween_world(tween: Tween) -> (World, EntityId) {
+        let mut world = World::default();
+        let entity = spawn_entities(&mut world, TWEEN | LOCAL_TRANSFORM, 1)[0];
+        *get_component_mut::<Tween>(&mut world, entity, TWEEN).expect("No tween!") = tween;
+        (world, entity)
+    }
+
+    /// Advances the world's tweens and returns the entity's tween and x translation
+    fn update(world: &mut World, entity: EntityId, delta_time: f32) -> (Tween, f32) {
+        world.resources.delta_time = delta_time;
+        update_tween_system(world);
+        let tween = get_component::<Tween>(world, entity, TWEEN)
+            .expect("No tween!")
+            .clone();
+        let x = get_component::<LocalTransform>(world, entity, LOCAL_TRANSFORM)
+            .expect("No local transform!")
+            .translation
+            .x;
+        (tween, x)
+    }
+
+    fn slide(duration: f32) -> TweenNode {
+        tween_property(
+            AnimatedProperty::Translation,
+            scalar(0.0),
+            scalar(1.0),
+            duration,
+            Easing::Linear,
+        )
+    }
+
+    #[test]
+    fn every_easing_starts_at_zero_and_ends_at_one() {
+        for easing in EASINGS {
+            assert!(close(ease(easing, 0.0), 0.0), "{easing:?} at 0");
+            assert!(close(ease(easing, 1.0), 1.0), "{easing:?} at 1");
+        }
+    }
+
+    #[test]
+    fn easing_clamps_progress() {
+        for easing in EASINGS {
+            assert_eq!(ease(easing, -1.0), ease(easing, 0.0), "{easing:?} below 0");
+            assert_eq!(ease(easing, 2.0), ease(easing, 1.0), "{easing:?} above 1");
+        }
+    }
+
+    #[test]
+    fn in_out_easings_pass_through_the_middle() {
+        for easing in [
+            Easing::Linear,
+            Easing::QuadInOut,
+            Easing::CubicInOut,
+            Easing::ElasticInOut,
+            Easing::BounceInOut,
+            Easing::BackInOut,
+        ] {
+            assert!(close(ease(easing, 0.5), 0.5), "{easing:?} at 0.5");
+        }
+    }
+
+    #[test]
+    fn back_easings_overshoot() {
+        assert!(ease(Easing::BackIn, 0.2) < 0.0);
+        assert!(ease(Easing::BackOut, 0.8) > 1.0);
+    }
+
+    #[test]
+    fn sequences_add_durations_and_parallels_take_the_longest() {
+        let node = TweenNode::Sequence(vec![
+            slide(1.0),
+            TweenNode::Delay(0.5),
+            TweenNode::Parallel(vec![slide(2.0), TweenNode::Delay(3.0), event("done")]),
+        ]);
+        assert_eq!(tween_duration(&node), 4.5);
+        assert_eq!(tween_duration(&TweenNode::Delay(-1.0)), 0.0);
+    }
+
+    #[test]
+    fn property_tweens_reach_their_target_and_finish() {
+        let (mut world, entity) = tween_world(tween(slide(1.0)));
+        let (state, x) = update(&mut world, entity, 0.25);
+        assert!(close(x, 0.25));
+        assert!(!state.finished);
+        let (state, x) = update(&mut world, entity, 2.0);
+        assert!(close(x, 1.0));
+        assert!(state.finished);
+        assert_eq!(state.time, 1.0);
+    }
+
+    #[test]
+    fn paused_tweens_hold_their_place() {
+        let (mut world, entity) = tween_world(tween(slide(1.0)));
+        update(&mut world, entity, 0.5);
+        get_component_mut::<Tween>(&mut world, entity, TWEEN)
+            .expect("No tween!")
+            .paused = true;
+        let (state, x) = update(&mut world, entity, 0.25);
+        assert!(close(x, 0.5));
+        assert_eq!(state.time, 0.5);
+    }
+
+    #[test]
+    fn yoyo_tweens_play_every_other_repeat_backwards() {
+        let (mut world, entity) = tween_world(Tween {
+            repeat: Repeat::Times(2),
+            yoyo: true,
+            ..tween(slide(1.0))
+        });
+        let (state, x) = update(&mut world, entity, 1.0);
+        assert_eq!(state.play, 1);
+        assert!(close(x, 1.0));
+        let (_, x) = update(&mut world, entity, 0.25);
+        assert!(close(x, 0.75));
+        let (state, x) = update(&mut world, entity, 1.0);
+        assert!(close(x, 0.0));
+        assert!(state.finished);
+    }
+
+    #[test]
+    fn events_fire_once_as_the_playhead_passes_them() {
+        let (mut world, entity) = tween_world(tween(TweenNode::Sequence(vec![
+            event("start"),
+            TweenNode::Delay(1.0),
+            event("middle"),
+            TweenNode::Delay(1.0),
+            event("end"),
+        ])));
+        let fired: Vec<Vec<String>> = (0..4)
+            .map(|_| update(&mut world, entity, 0.5).0.events)
+            .collect();
+        assert_eq!(
+            fired,
+            vec![
+                vec!["start".to_string()],
+                vec![],
+                vec!["middle".to_string()],
+                vec!["end".to_string()],
+            ]
+        );
+        assert!(update(&mut world, entity, 0.5).0.events.is_empty());
+    }
+
+    #[test]
+    fn yoyo_events_fire_in_reverse_on_the_way_back() {
+        let (mut world, entity) = tween_world(Tween {
+            repeat: Repeat::Times(2),
+            yoyo: true,
+            ..tween(TweenNode::Sequence(vec![
+                event("a"),
+                TweenNode::Delay(1.0),
+                event("b"),
+            ]))
+        });
+        assert_eq!(update(&mut world, entity, 1.0).0.events, vec!["a"]);
+        assert_eq!(update(&mut world, entity, 1.0).0.events, vec!["b", "a"]);
+    }
+
+    #[test]
+    fn long_frames_fire_events_for_every_play_they_cover() {
+        let (mut world, entity) = tween_world(Tween {
+            repeat: Repeat::Times(3),
+            ..tween(TweenNode::Sequence(vec![
+                event("loop"),
+                TweenNode::Delay(1.0),
+            ]))
+        });
+        let (state, _) = update(&mut world, entity, 10.0);
+        assert_eq!(state.events, vec!["loop", "loop", "loop"]);
+        assert_eq!(state.play, 2);
+        assert!(state.finished);
+    }
+
+    #[test]
+    fn restarting_plays_from_the_beginning() {
+        let (mut world, entity) = tween_world(tween(slide(1.0)));
+        update(&mut world, entity, 2.0);
+        restart_tween(get_component_mut::<Tween>(&mut world, entity, TWEEN).expect("No tween!"));
+        let (state, x) = update(&mut world, entity, 0.5);
+        assert!(close(x, 0.5));
+        assert!(!state.finished);
+    }
+}
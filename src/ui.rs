@@ -0,0 +1,111 @@
+use winit::{event::WindowEvent, window::Window};
+
+/// egui state for the window, fed winit events and run once per frame
+pub struct Ui {
+    pub context: egui::Context,
+    state: egui_winit::State,
+}
+
+/// What egui drew in a frame, ready to be rendered over the scene
+#[derive(Default)]
+pub struct UiFrame {
+    pub paint_jobs: Vec<egui::ClippedPrimitive>,
+    pub textures_delta: egui::TexturesDelta,
+    pub pixels_per_point: f32,
+}
+
+pub fn create_ui(window: &Window, max_texture_side: usize) -> Ui {
+    let context = egui::Context::default();
+    let state = egui_winit::State::new(
+        context.clone(),
+        egui::ViewportId::ROOT,
+        window,
+        Some(window.scale_factor() as f32),
+        window.theme(),
+        Some(max_texture_side),
+    );
+    Ui { context, state }
+}
+
+/// Passes an event to egui, returning true when egui consumed it
+/// and it should not reach gameplay input
+pub fn handle_ui_event(ui: &mut Ui, window: &Window, event: &WindowEvent) -> bool {
+    ui.state.on_window_event(window, event).consumed
+}
+
+/// Runs one egui frame, building its windows with the closure
+pub fn run_ui(ui: &mut Ui, window: &Window, build: impl FnMut(&egui::Context)) -> UiFrame {
+    let input = ui.state.take_egui_input(window);
+    let output = ui.context.run(input, build);
+    ui.state
+        .handle_platform_output(window, output.platform_output);
+    UiFrame {
+        paint_jobs: ui
+            .context
+            .tessellate(output.shapes, output.pixels_per_point),
+        textures_delta: output.textures_delta,
+        pixels_per_point: output.pixels_per_point,
+    }
+}
+
+pub fn create_ui_renderer(
+    device: &wgpu::Device,
+    surface_format: wgpu::TextureFormat,
+) -> egui_wgpu::Renderer {
+    egui_wgpu::Renderer::new(device, surface_format, None, 1, false)
+}
+
+/// Records a pass drawing the frame's UI over the view.
+/// Returns command buffers that must be submitted before the encoder.
+#[allow(clippy::too_many_arguments)]
+pub fn render_ui(
+    renderer: &mut egui_wgpu::Renderer,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    encoder: &mut wgpu::CommandEncoder,
+    view: &wgpu::TextureView,
+    (width, height): (u32, u32),
+    frame: &UiFrame,
+    timestamp_writes: Option<wgpu::RenderPassTimestampWrites>,
+) -> Vec<wgpu::CommandBuffer> {
+    let screen_descriptor = egui_wgpu::ScreenDescriptor {
+        size_in_pixels: [width, height],
+        pixels_per_point: frame.pixels_per_point,
+    };
+    for (id, image_delta) in frame.textures_delta.set.iter() {
+        renderer.update_texture(device, queue, *id, image_delta);
+    }
+    let command_buffers = renderer.update_buffers(
+        device,
+        queue,
+        encoder,
+        &frame.paint_jobs,
+        &screen_descriptor,
+    );
+
+    let mut render_pass = encoder
+        .begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("UI Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes,
+            occlusion_query_set: None,
+        })
+        .forget_lifetime();
+    renderer.render(&mut render_pass, &frame.paint_jobs, &screen_descriptor);
+    command_buffers
+}
+
+/// Frees textures egui no longer uses, call after the frame is submitted
+pub fn free_ui_textures(renderer: &mut egui_wgpu::Renderer, frame: &UiFrame) {
+    for id in frame.textures_delta.free.iter() {
+        renderer.free_texture(id);
+    }
+}
@@ -1,8 +1,10 @@
 use freecs::world;
 
-world! {
-    World {
-        components {
+/// Calls `$callback!` with the component list, so everything that needs one entry
+/// per component type is generated from the same list as the world
+macro_rules! for_each_component {
+    ($callback:ident) => {
+        $callback! {
             local_transform: LocalTransform => LOCAL_TRANSFORM,
             global_transform: GlobalTransform => GLOBAL_TRANSFORM,
             parent: Parent => PARENT,
@@ -11,18 +13,57 @@ world! {
             camera: Camera => CAMERA,
             active_camera: ActiveCamera => ACTIVE_CAMERA,
             player: Player => PLAYER,
-        },
-        Resources {
-           delta_time: f32,
-            keyboard: Keyboard,
-            mouse: Mouse,
-            viewport_width: u32,
-            viewport_height: u32,
+            render_mesh: RenderMesh => RENDER_MESH,
+            material: Material => MATERIAL,
+            bounding_box: BoundingBox => BOUNDING_BOX,
+            world_bounds: WorldBounds => WORLD_BOUNDS,
+            hierarchy_bounds: HierarchyBounds => HIERARCHY_BOUNDS,
+            rigid_body: RigidBody => RIGID_BODY,
+            collider: Collider => COLLIDER,
+            character_controller: CharacterController => CHARACTER_CONTROLLER,
+            animation_player: AnimationPlayer => ANIMATION_PLAYER,
+            skin: Skin => SKIN,
+            tween: Tween => TWEEN,
+            audio_source: AudioSource => AUDIO_SOURCE,
+            audio_listener: AudioListener => AUDIO_LISTENER,
         }
-    }
+    };
+}
+pub(crate) use for_each_component;
+
+macro_rules! define_world {
+    ($($name:ident: $type:ty => $mask:ident),* $(,)?) => {
+        world! {
+            World {
+                components {
+                    $($name: $type => $mask,)*
+                },
+                Resources {
+                    delta_time: f32,
+                    keyboard: Keyboard,
+                    mouse: Mouse,
+                    viewport_width: u32,
+                    viewport_height: u32,
+                    assets: crate::asset::AssetServer,
+                    debug_draw: crate::debug_draw::DebugDraw,
+                    profiler: crate::profiler::Profiler,
+                    frame_stats: crate::frame_stats::FrameStats,
+                    mesh_bounds: crate::bounds::MeshBoundsCache,
+                    bvh: crate::bvh::Bvh,
+                    fixed_time: FixedTime,
+                    physics: crate::physics::Physics,
+                    audio: crate::audio::Audio,
+                    input: crate::input::Input,
+                    gamepads: crate::input::Gamepads,
+                }
+            }
+        }
+    };
 }
 
-use components::*;
+for_each_component!(define_world);
+
+pub use components::*;
 mod components {
     use serde::{Deserialize, Serialize};
 
@@ -46,9 +87,36 @@ mod components {
         }
     }
 
+    pub fn transform_matrix(transform: &Transform) -> nalgebra_glm::Mat4 {
+        nalgebra_glm::translation(&transform.translation)
+            * nalgebra_glm::quat_to_mat4(&transform.rotation.normalize())
+            * nalgebra_glm::scaling(&transform.scale)
+    }
+
     #[derive(Default, Debug, Copy, Clone, Serialize, Deserialize, PartialEq)]
     pub struct Parent(pub super::EntityId);
 
+    /// Local-space bounds, computed from the entity's mesh when it has one
+    pub use crate::bounds::BoundingBox;
+
+    /// Bounds in world space, derived from `BoundingBox` and `GlobalTransform`
+    pub type WorldBounds = BoundingBox;
+
+    /// World-space bounds enclosing the entity and all of its descendants
+    pub type HierarchyBounds = BoundingBox;
+
+    pub use crate::physics::{Collider, RigidBody};
+
+    pub use crate::character::CharacterController;
+
+    pub use crate::animation::AnimationPlayer;
+
+    pub use crate::skinning::Skin;
+
+    pub use crate::tween::Tween;
+
+    pub use crate::audio::{AudioListener, AudioSource};
+
     #[derive(Default, Debug, Clone, Serialize, Deserialize)]
     pub struct Name(pub String);
 
@@ -61,6 +129,44 @@ mod components {
     #[derive(Default, Debug, Clone, Serialize, Deserialize)]
     pub struct ActiveCamera;
 
+    #[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+    pub struct RenderMesh(pub crate::asset::Handle<super::Mesh>);
+
+    /// A glTF-style metallic-roughness material
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Material {
+        pub base_color_factor: nalgebra_glm::Vec4,
+        pub base_color_texture: Option<super::Texture>,
+        pub metallic_factor: f32,
+        pub roughness_factor: f32,
+        /// Roughness is read from the green channel and metalness from the blue channel
+        pub metallic_roughness_texture: Option<super::Texture>,
+        pub normal_texture: Option<super::Texture>,
+        pub normal_scale: f32,
+        pub occlusion_texture: Option<super::Texture>,
+        pub occlusion_strength: f32,
+        pub emissive_factor: nalgebra_glm::Vec3,
+        pub emissive_texture: Option<super::Texture>,
+    }
+
+    impl Default for Material {
+        fn default() -> Self {
+            Self {
+                base_color_factor: nalgebra_glm::vec4(1.0, 1.0, 1.0, 1.0),
+                base_color_texture: None,
+                metallic_factor: 1.0,
+                roughness_factor: 1.0,
+                metallic_roughness_texture: None,
+                normal_texture: None,
+                normal_scale: 1.0,
+                occlusion_texture: None,
+                occlusion_strength: 1.0,
+                emissive_factor: nalgebra_glm::Vec3::zeros(),
+                emissive_texture: None,
+            }
+        }
+    }
+
     #[derive(Debug, Serialize, Deserialize, Clone)]
     pub struct Camera {
         pub projection: Projection,
@@ -139,8 +245,9 @@ mod components {
     }
 
     impl OrthographicCamera {
+        /// Maps depth to 0 to 1 like the perspective projections, as wgpu clips it
         pub fn matrix(&self) -> nalgebra_glm::Mat4 {
-            nalgebra_glm::ortho(
+            nalgebra_glm::ortho_rh_zo(
                 -self.x_mag,
                 self.x_mag,
                 -self.y_mag,
@@ -152,7 +259,7 @@ mod components {
     }
 }
 
-use resources::*;
+pub use resources::*;
 mod resources {
     use serde::{Deserialize, Serialize};
 
@@ -162,7 +269,44 @@ mod resources {
             std::collections::HashMap<winit::keyboard::KeyCode, winit::event::ElementState>,
     }
 
-    #[allow(dead_code)]
+    /// Paces the fixed-timestep update, which runs zero or more times per frame
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct FixedTime {
+        /// Seconds simulated by each step
+        pub timestep: f32,
+        /// Steps allowed per frame, so a long frame drops time instead of spiraling
+        pub max_steps: u32,
+        accumulator: f32,
+    }
+
+    impl Default for FixedTime {
+        fn default() -> Self {
+            Self {
+                timestep: 1.0 / 60.0,
+                max_steps: 8,
+                accumulator: 0.0,
+            }
+        }
+    }
+
+    /// Adds a frame's time and returns how many fixed steps are due
+    pub fn advance_fixed_time(fixed_time: &mut FixedTime, delta_time: f32) -> u32 {
+        fixed_time.accumulator += delta_time.max(0.0);
+        let steps = (fixed_time.accumulator / fixed_time.timestep).floor() as u32;
+        let run = steps.min(fixed_time.max_steps);
+        fixed_time.accumulator = if steps > run {
+            0.0
+        } else {
+            fixed_time.accumulator - run as f32 * fixed_time.timestep
+        };
+        run
+    }
+
+    /// How far between fixed steps the frame is, from 0 to 1, for interpolating
+    pub fn fixed_time_alpha(fixed_time: &FixedTime) -> f32 {
+        (fixed_time.accumulator / fixed_time.timestep).clamp(0.0, 1.0)
+    }
+
     pub fn is_key_pressed(keyboard: &Keyboard, keycode: winit::keyboard::KeyCode) -> bool {
         keyboard.keystates.contains_key(&keycode)
             && keyboard.keystates[&keycode] == winit::event::ElementState::Pressed
@@ -194,6 +338,9 @@ mod resources {
         pub format: ImageFormat,
         pub width: u32,
         pub height: u32,
+        /// Pre-built mip levels below the base level, each in `format`
+        #[serde(default)]
+        pub mip_levels: Vec<Vec<u8>>,
     }
 
     #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
@@ -220,9 +367,17 @@ mod resources {
         R32G32F,
         R32G32B32F,
         R32G32B32A32F,
+        /// Block-compressed formats, stored as 4x4 pixel blocks
+        BC1,
+        BC2,
+        BC3,
+        BC4,
+        BC5,
+        BC6H,
+        BC7,
     }
 
-    #[derive(Default, Clone, Debug, Serialize, Deserialize)]
+    #[derive(Default, Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
     pub struct Sampler {
         pub min_filter: MinFilter,
         pub mag_filter: MagFilter,
@@ -230,14 +385,14 @@ mod resources {
         pub wrap_t: WrappingMode,
     }
 
-    #[derive(Default, Clone, Debug, Serialize, Deserialize)]
+    #[derive(Default, Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
     pub enum MagFilter {
         Nearest = 1,
         #[default]
         Linear,
     }
 
-    #[derive(Default, Clone, Debug, Serialize, Deserialize)]
+    #[derive(Default, Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
     pub enum MinFilter {
         Nearest = 1,
         #[default]
@@ -248,7 +403,7 @@ mod resources {
         LinearMipmapLinear,
     }
 
-    #[derive(Default, Clone, Debug, Serialize, Deserialize)]
+    #[derive(Default, Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
     pub enum WrappingMode {
         ClampToEdge,
         MirroredRepeat,
@@ -256,42 +411,287 @@ mod resources {
         Repeat,
     }
 
-    #[derive(Clone, Debug, Serialize, Deserialize)]
+    #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
     pub struct Texture {
-        pub image_index: usize,
-        pub sampler_index: Option<usize>,
+        pub image: crate::asset::Handle<Image>,
+        pub sampler: Sampler,
+    }
+
+    #[repr(C)]
+    #[derive(
+        Default, Debug, Copy, Clone, Serialize, Deserialize, bytemuck::Pod, bytemuck::Zeroable,
+    )]
+    pub struct Vertex {
+        pub position: nalgebra_glm::Vec3,
+        pub normal: nalgebra_glm::Vec3,
+        pub uv_0: nalgebra_glm::Vec2,
+        pub tangent: nalgebra_glm::Vec4,
+    }
+
+    /// Up to four joints influencing a vertex, with weights summing to one
+    #[repr(C)]
+    #[derive(
+        Default, Debug, Copy, Clone, Serialize, Deserialize, bytemuck::Pod, bytemuck::Zeroable,
+    )]
+    pub struct SkinWeights {
+        /// Indices into the `Skin` joints
+        pub joints: [u32; 4],
+        pub weights: [f32; 4],
+    }
+
+    #[derive(Default, Debug, Clone, Serialize, Deserialize)]
+    pub struct Mesh {
+        pub vertices: Vec<Vertex>,
+        pub indices: Vec<u32>,
+        /// One per vertex for meshes deformed by a `Skin`, otherwise empty
+        #[serde(default)]
+        pub skin_weights: Vec<SkinWeights>,
+    }
+
+    /// Creates a unit cube centered on the origin
+    pub fn cube_mesh() -> Mesh {
+        let faces = [
+            (nalgebra_glm::Vec3::x(), nalgebra_glm::Vec3::y()),
+            (-nalgebra_glm::Vec3::x(), nalgebra_glm::Vec3::y()),
+            (nalgebra_glm::Vec3::y(), nalgebra_glm::Vec3::z()),
+            (-nalgebra_glm::Vec3::y(), nalgebra_glm::Vec3::z()),
+            (nalgebra_glm::Vec3::z(), nalgebra_glm::Vec3::y()),
+            (-nalgebra_glm::Vec3::z(), nalgebra_glm::Vec3::y()),
+        ];
+        let mut mesh = Mesh::default();
+        for (normal, up) in faces {
+            let right = up.cross(&normal);
+            let base = mesh.vertices.len() as u32;
+            for (u, v) in [(0.0, 1.0), (1.0, 1.0), (1.0, 0.0), (0.0, 0.0)] {
+                let position = (normal + right * (u * 2.0 - 1.0) + up * (1.0 - v * 2.0)) * 0.5;
+                mesh.vertices.push(Vertex {
+                    position,
+                    normal,
+                    uv_0: nalgebra_glm::vec2(u, v),
+                    tangent: nalgebra_glm::vec4(right.x, right.y, right.z, 1.0),
+                });
+            }
+            mesh.indices
+                .extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+        mesh
+    }
+
+    /// WGSL source, which may use the directives of a `ShaderLibrary`
+    #[derive(Default, Debug, Clone, Serialize, Deserialize)]
+    pub struct Shader {
+        pub source: String,
+    }
+
+    /// A hierarchy of nodes that `spawn_scene` turns into entities
+    #[derive(Default, Debug, Clone, Serialize, Deserialize)]
+    pub struct Scene {
+        pub nodes: Vec<SceneNode>,
+    }
+
+    #[derive(Default, Debug, Clone, Serialize, Deserialize)]
+    #[serde(default)]
+    pub struct SceneNode {
+        pub name: String,
+        pub transform: super::Transform,
+        /// Index of the parent node, which must come earlier in `nodes`
+        pub parent: Option<usize>,
+        pub mesh: Option<crate::asset::Handle<Mesh>>,
+        pub material: Option<crate::asset::Handle<super::Material>>,
     }
 }
 
-use systems::*;
+pub use systems::*;
 mod systems {
     use super::*;
-    use freecs::has_components;
-    use rayon::prelude::*;
+    use crate::profiler::profile;
+    use std::collections::HashMap;
 
     pub fn run_systems(world: &mut World) {
-        let delta_time = world.resources.delta_time;
-        world.tables.par_iter_mut().for_each(|table| {
-            // if has_components!(table, POSITION) {
-            //     update_positions_system(&mut table.positions, &table.velocities, delta_time);
-            // }
+        profile(world, "Asset Server", |world| {
+            crate::asset::update_asset_server(&mut world.resources.assets)
+        });
+        profile(
+            world,
+            "Animation",
+            crate::animation::update_animation_system,
+        );
+        profile(world, "Tweens", crate::tween::update_tween_system);
+        profile(world, "Global Transforms", update_global_transforms_system);
+        profile(world, "Bounds", update_bounds_system);
+        profile(world, "BVH", crate::bvh::update_bvh_system);
+        profile(world, "Audio", crate::audio::update_audio_system);
+        profile(world, "Debug Draw", |world| {
+            crate::debug_draw::update_debug_draw(
+                &mut world.resources.debug_draw,
+                world.resources.delta_time,
+            )
         });
     }
 
-    // // The system itself can also access components in parallel
-    // #[inline]
-    // pub fn update_positions_system(positions: &mut [Position], velocities: &[Velocity], dt: f32) {
-    //     positions
-    //         .par_iter_mut()
-    //         .zip(velocities.par_iter())
-    //         .for_each(|(pos, vel)| {
-    //             pos.x += vel.x * dt;
-    //             pos.y += vel.y * dt;
-    //         });
-    // }
+    /// Clears outputs of the previous frame's fixed steps, before this frame's run
+    pub fn begin_fixed_update(world: &mut World) {
+        crate::physics::clear_collision_events(&mut world.resources.physics);
+    }
+
+    /// Runs once per fixed step, before `run_systems`
+    pub fn run_fixed_systems(world: &mut World) {
+        profile(
+            world,
+            "Characters",
+            crate::character::update_character_controllers_system,
+        );
+        profile(world, "Physics", crate::physics::step_physics_system);
+    }
+
+    /// Composes each local transform with its parent chain into a global transform
+    pub fn update_global_transforms_system(world: &mut World) {
+        let entities = query_entities(world, LOCAL_TRANSFORM | GLOBAL_TRANSFORM);
+        let mut global_transforms = HashMap::with_capacity(entities.len());
+        for entity in entities.iter() {
+            compute_global_transform(world, *entity, &mut global_transforms, 0);
+        }
+        for (entity, global_transform) in global_transforms {
+            if let Some(component) =
+                get_component_mut::<GlobalTransform>(world, entity, GLOBAL_TRANSFORM)
+            {
+                *component = global_transform;
+            }
+        }
+    }
+
+    /// Computes local bounds from meshes, then world and hierarchical bounds from them.
+    /// Runs after global transforms are updated.
+    pub fn update_bounds_system(world: &mut World) {
+        update_local_bounds(world);
+        update_world_bounds(world);
+        update_hierarchy_bounds(world);
+    }
+
+    fn update_local_bounds(world: &mut World) {
+        let cache = &mut world.resources.mesh_bounds;
+        for event in crate::asset::asset_events(&world.resources.assets.meshes) {
+            if let crate::asset::AssetEvent::Modified(mesh)
+            | crate::asset::AssetEvent::Removed(mesh) = event
+            {
+                cache.remove(mesh);
+            }
+        }
+        for entity in query_entities(world, RENDER_MESH | BOUNDING_BOX) {
+            let Some(RenderMesh(mesh)) = get_component::<RenderMesh>(world, entity, RENDER_MESH)
+            else {
+                continue;
+            };
+            let bounds = match world.resources.mesh_bounds.get(&mesh.id) {
+                Some(bounds) => *bounds,
+                None => {
+                    let Some(mesh_data) =
+                        crate::asset::get_asset(&world.resources.assets.meshes, mesh)
+                    else {
+                        continue;
+                    };
+                    let bounds = crate::bounds::mesh_bounds(mesh_data);
+                    world.resources.mesh_bounds.insert(mesh.id, bounds);
+                    bounds
+                }
+            };
+            if let Some(component) = get_component_mut::<BoundingBox>(world, entity, BOUNDING_BOX) {
+                *component = bounds;
+            }
+        }
+    }
+
+    fn update_world_bounds(world: &mut World) {
+        for entity in query_entities(world, BOUNDING_BOX | GLOBAL_TRANSFORM | WORLD_BOUNDS) {
+            let (Some(bounds), Some(global_transform)) = (
+                get_component::<BoundingBox>(world, entity, BOUNDING_BOX),
+                get_component::<GlobalTransform>(world, entity, GLOBAL_TRANSFORM),
+            ) else {
+                continue;
+            };
+            let world_bounds = crate::bounds::transform_bounds(bounds, global_transform);
+            if let Some(component) = get_component_mut::<WorldBounds>(world, entity, WORLD_BOUNDS) {
+                *component = world_bounds;
+            }
+        }
+    }
+
+    /// Merges each entity's world bounds into itself and every ancestor
+    fn update_hierarchy_bounds(world: &mut World) {
+        let mut merged: HashMap<EntityId, BoundingBox> = HashMap::new();
+        for entity in query_entities(world, WORLD_BOUNDS) {
+            let Some(bounds) = get_component::<WorldBounds>(world, entity, WORLD_BOUNDS).copied()
+            else {
+                continue;
+            };
+            let mut current = Some(entity);
+            for _ in 0..MAX_HIERARCHY_DEPTH {
+                let Some(ancestor) = current else {
+                    break;
+                };
+                let entry = merged.entry(ancestor).or_default();
+                *entry = crate::bounds::merge_bounds(entry, &bounds);
+                current =
+                    get_component::<Parent>(world, ancestor, PARENT).map(|Parent(parent)| *parent);
+            }
+        }
+        for entity in query_entities(world, HIERARCHY_BOUNDS) {
+            if let Some(component) =
+                get_component_mut::<HierarchyBounds>(world, entity, HIERARCHY_BOUNDS)
+            {
+                *component = merged.get(&entity).copied().unwrap_or_default();
+            }
+        }
+    }
+
+    /// Rebuilds entity locations and the table registry from the tables.
+    /// freecs leaves stale locations behind when `add_components`, `remove_components`
+    /// or `despawn_entities` move entities or tables, so call this after them.
+    pub fn repair_entity_locations(world: &mut World) {
+        world.table_registry = world
+            .tables
+            .iter()
+            .enumerate()
+            .map(|(index, table)| (table.mask, index))
+            .collect();
+        for (table_index, table) in world.tables.iter().enumerate() {
+            for (array_index, entity) in table.entity_indices.iter().enumerate() {
+                if let Some(location) = world.entity_locations.locations.get_mut(entity.id as usize)
+                {
+                    *location = Some((table_index, array_index));
+                }
+            }
+        }
+    }
+
+    /// Guards against parent cycles
+    const MAX_HIERARCHY_DEPTH: usize = 256;
+
+    fn compute_global_transform(
+        world: &World,
+        entity: EntityId,
+        global_transforms: &mut HashMap<EntityId, GlobalTransform>,
+        depth: usize,
+    ) -> GlobalTransform {
+        if let Some(global_transform) = global_transforms.get(&entity) {
+            return *global_transform;
+        }
+        let local_matrix = get_component::<LocalTransform>(world, entity, LOCAL_TRANSFORM)
+            .map(transform_matrix)
+            .unwrap_or_else(nalgebra_glm::Mat4::identity);
+        let global_transform = match get_component::<Parent>(world, entity, PARENT) {
+            Some(Parent(parent)) if depth < MAX_HIERARCHY_DEPTH => {
+                compute_global_transform(world, *parent, global_transforms, depth + 1)
+                    * local_matrix
+            }
+            _ => local_matrix,
+        };
+        global_transforms.insert(entity, global_transform);
+        global_transform
+    }
 }
 
-use queries::*;
+pub use queries::*;
 mod queries {
     use super::*;
 
@@ -302,15 +702,52 @@ mod queries {
         pub view: nalgebra_glm::Mat4,
     }
 
+    #[derive(Default, Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+    pub struct Ray {
+        pub origin: nalgebra_glm::Vec3,
+        /// Normalized
+        pub direction: nalgebra_glm::Vec3,
+    }
+
+    /// The global transform of the entity's parent, identity for root entities
+    pub fn parent_global_transform(world: &World, entity: EntityId) -> nalgebra_glm::Mat4 {
+        get_component::<Parent>(world, entity, PARENT)
+            .and_then(|Parent(parent)| {
+                get_component::<GlobalTransform>(world, *parent, GLOBAL_TRANSFORM)
+            })
+            .copied()
+            .unwrap_or_else(nalgebra_glm::Mat4::identity)
+    }
+
+    /// The world-space ray through a point on the viewport, in pixels from the top left
+    pub fn screen_to_world_ray(
+        camera_matrices: &CameraMatrices,
+        viewport_size: nalgebra_glm::Vec2,
+        screen_position: nalgebra_glm::Vec2,
+    ) -> Option<Ray> {
+        let ndc = nalgebra_glm::vec2(
+            screen_position.x / viewport_size.x.max(1.0) * 2.0 - 1.0,
+            1.0 - screen_position.y / viewport_size.y.max(1.0) * 2.0,
+        );
+        let inverse = (camera_matrices.projection * camera_matrices.view).try_inverse()?;
+        // Every projection maps the near plane to depth 0, and depth 0.5 is further
+        // along the ray even with an infinite far plane
+        let unproject = |depth: f32| {
+            let point = inverse * nalgebra_glm::vec4(ndc.x, ndc.y, depth, 1.0);
+            point.xyz() / point.w
+        };
+        let (near, far) = (unproject(0.0), unproject(0.5));
+        Some(Ray {
+            origin: near,
+            direction: (far - near).try_normalize(f32::EPSILON)?,
+        })
+    }
+
     pub fn query_active_camera_matrices(
         world: &World,
         resources: &Resources,
     ) -> Option<(EntityId, CameraMatrices)> {
-        let Some(camera_entity) =
-            query_first_entity(&world, ACTIVE_CAMERA | CAMERA | LOCAL_TRANSFORM)
-        else {
-            return None;
-        };
+        let camera_entity = query_first_entity(world, ACTIVE_CAMERA | CAMERA | LOCAL_TRANSFORM)?;
 
         let (Some(camera), Some(local_transform), Some(global_transform)) = (
             get_component::<Camera>(world, camera_entity, CAMERA),
@@ -338,3 +775,72 @@ mod queries {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera_matrices(projection: Projection) -> CameraMatrices {
+        let camera_position = nalgebra_glm::vec3(0.0, 0.0, 10.0);
+        let camera = Camera {
+            projection,
+            ..Default::default()
+        };
+        CameraMatrices {
+            camera_position,
+            projection: projection_matrix(&camera, 1.0),
+            view: nalgebra_glm::look_at(
+                &camera_position,
+                &nalgebra_glm::Vec3::zeros(),
+                &nalgebra_glm::Vec3::y(),
+            ),
+        }
+    }
+
+    fn center_ray(projection: Projection) -> Ray {
+        let viewport = nalgebra_glm::vec2(200.0, 200.0);
+        screen_to_world_ray(&camera_matrices(projection), viewport, viewport * 0.5)
+            .expect("No ray through the viewport!")
+    }
+
+    #[test]
+    fn rays_start_at_the_near_plane() {
+        let orthographic = Projection::Orthographic(OrthographicCamera {
+            x_mag: 5.0,
+            y_mag: 5.0,
+            z_far: 100.0,
+            z_near: 0.5,
+        });
+        let perspective = Projection::Perspective(PerspectiveCamera {
+            z_near: 0.5,
+            z_far: Some(100.0),
+            ..Default::default()
+        });
+        let infinite = Projection::Perspective(PerspectiveCamera {
+            z_near: 0.5,
+            z_far: None,
+            ..Default::default()
+        });
+        for projection in [orthographic, perspective, infinite] {
+            let ray = center_ray(projection);
+            assert!((ray.origin - nalgebra_glm::vec3(0.0, 0.0, 9.5)).norm() < 1e-3);
+            assert!((ray.direction - nalgebra_glm::vec3(0.0, 0.0, -1.0)).norm() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let projection = Projection::Orthographic(OrthographicCamera {
+            x_mag: 5.0,
+            y_mag: 5.0,
+            z_far: 100.0,
+            z_near: 0.01,
+        });
+        let matrices = camera_matrices(projection);
+        let viewport = nalgebra_glm::vec2(200.0, 200.0);
+        let corner = screen_to_world_ray(&matrices, viewport, nalgebra_glm::vec2(0.0, 0.0))
+            .expect("No ray through the viewport!");
+        assert!((corner.origin.xy() - nalgebra_glm::vec2(-5.0, 5.0)).norm() < 1e-3);
+        assert!((corner.direction - nalgebra_glm::vec3(0.0, 0.0, -1.0)).norm() < 1e-4);
+    }
+}